@@ -1,54 +1,235 @@
 pub type Number = rust_decimal::Decimal;
 pub use rust_decimal_macros::dec as num;
 
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+// What an account needs from the type holding its balances. Other decimal
+// crates, fixed-point types or integer minor units can be plugged into
+// `Account` and `AccountError` by implementing this, to keep balances of
+// their own with the same rules. Only those two are generic: transactions,
+// the ledger, journals and reports work on `Number`, and rely on it for
+// normalized amounts, signatures, rounding and formatting.
+pub trait Money:
+    Copy
+    + Debug
+    + Default
+    + Ord
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+{
+    const ZERO: Self;
+    const MAX: Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+}
+
+impl Money for Number {
+    const ZERO: Self = Number::ZERO;
+    const MAX: Self = Number::MAX;
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Number::checked_add(self, other)
+    }
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        Number::checked_sub(self, other)
+    }
+}
+
+// Amounts counted in minor units, cents for instance.
+impl Money for i64 {
+    const ZERO: Self = 0;
+    const MAX: Self = i64::MAX;
+    fn checked_add(self, other: Self) -> Option<Self> {
+        i64::checked_add(self, other)
+    }
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        i64::checked_sub(self, other)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone, Default)]
 pub struct ClientId(pub u16);
 
-#[derive(Debug, PartialEq)]
-pub enum AccountError {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AmountError {
+    Invalid(String),
+    // More significant digits than a `Number` holds, or a value past
+    // `Number::MAX`.
+    AmountPrecisionExceeded(String),
+}
+
+impl std::fmt::Display for AmountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AmountError::Invalid(amount) => write!(f, "invalid amount '{amount}'"),
+            AmountError::AmountPrecisionExceeded(amount) => write!(
+                f,
+                "amount '{amount}' has more precision than the 28 digits an amount holds"
+            ),
+        }
+    }
+}
+
+// Parses an amount without rounding: where `str::parse` quietly drops the
+// digits past the 28th, this rejects the amount.
+pub fn parse_amount(amount: &str) -> Result<Number, AmountError> {
+    if let Ok(number) = Number::from_str_exact(amount) {
+        return Ok(number);
+    }
+    let digits = amount.strip_prefix(['-', '+']).unwrap_or(amount);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let numeric = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if !digits.is_empty() && digits != "." && numeric(integer) && numeric(fraction) {
+        Err(AmountError::AmountPrecisionExceeded(amount.to_string()))
+    } else {
+        Err(AmountError::Invalid(amount.to_string()))
+    }
+}
+
+// The ways amounts are written in a region, for input read leniently:
+// `1,234.56` and `1.234,56`. Digits may also be grouped with spaces,
+// including the thin and non-breaking ones printed figures use.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AmountLocale {
+    #[default]
+    English,
+    European,
+}
+
+impl AmountLocale {
+    pub fn decimal_separator(&self) -> char {
+        match self {
+            AmountLocale::English => '.',
+            AmountLocale::European => ',',
+        }
+    }
+
+    fn is_group_separator(&self, character: char) -> bool {
+        let separator = match self {
+            AmountLocale::English => ',',
+            AmountLocale::European => '.',
+        };
+        character == separator || matches!(character, ' ' | '\u{a0}' | '\u{2009}' | '\u{202f}')
+    }
+}
+
+impl std::str::FromStr for AmountLocale {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "en" => Ok(AmountLocale::English),
+            "eu" => Ok(AmountLocale::European),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+// Reads an amount written the locale's way. Groups have to be three digits
+// long, except the first, so a misplaced separator like the one in `1,5`
+// read as English is rejected rather than dropped.
+pub fn parse_amount_in(amount: &str, locale: AmountLocale) -> Result<Number, AmountError> {
+    let invalid = || AmountError::Invalid(amount.to_string());
+    let (integer, fraction) = match amount.split_once(locale.decimal_separator()) {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (amount, None),
+    };
+    let (sign, integer) = match integer.strip_prefix(['-', '+']) {
+        Some(digits) => (&integer[..1], digits),
+        None => ("", integer),
+    };
+    let groups: Vec<&str> = integer
+        .split(|character| locale.is_group_separator(character))
+        .collect();
+    let grouped = groups.len() == 1
+        || groups.iter().enumerate().all(|(i, group)| match i {
+            0 => (1..=3).contains(&group.len()),
+            _ => group.len() == 3,
+        });
+    if !grouped {
+        return Err(invalid());
+    }
+    let mut canonical = format!("{sign}{}", groups.concat());
+    if let Some(fraction) = fraction {
+        canonical.push('.');
+        canonical.push_str(fraction);
+    }
+    parse_amount(&canonical).map_err(|err| match err {
+        AmountError::Invalid(_) => invalid(),
+        AmountError::AmountPrecisionExceeded(_) => {
+            AmountError::AmountPrecisionExceeded(amount.to_string())
+        }
+    })
+}
+
+// For amount columns of records read with serde.
+pub(crate) fn deserialize_amount<'de, D>(deserializer: D) -> Result<Option<Number>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let amount: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    amount
+        .filter(|amount| !amount.is_empty())
+        .map(|amount| parse_amount(&amount))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountError<M: Money = Number> {
     Overflow {
-        available: Number,
-        held: Number,
-        transaction_amount: Number,
+        available: M,
+        held: M,
+        transaction_amount: M,
     },
     Underflow {
-        available: Number,
-        held: Number,
-        transaction_amount: Number,
+        available: M,
+        held: M,
+        transaction_amount: M,
     },
-    FrozenAccount(Account),
+    FrozenAccount(Account<M>),
 }
 
-pub type AccountResult = Result<(), AccountError>;
+pub type AccountResult<M = Number> = Result<(), AccountError<M>>;
 
-#[derive(Copy, Clone, Default, Debug, PartialEq)]
-pub struct Account {
-    available: Number,
-    held: Number,
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Account<M: Money = Number> {
+    available: M,
+    held: M,
     locked: bool,
 }
 
-impl Account {
-    pub fn total(&self) -> Number {
+impl<M: Money> Account<M> {
+    pub(crate) fn from_parts(available: M, held: M, locked: bool) -> Self {
+        Self {
+            available,
+            held,
+            locked,
+        }
+    }
+    pub fn total(&self) -> M {
         self.available + self.held
     }
-    pub fn available(&self) -> Number {
+    pub fn available(&self) -> M {
         self.available
     }
-    pub fn held(&self) -> Number {
+    pub fn held(&self) -> M {
         self.held
     }
     pub fn locked(&self) -> bool {
         self.locked
     }
-    pub fn check_locked(&mut self) -> AccountResult {
+    pub fn check_locked(&mut self) -> AccountResult<M> {
         if self.locked {
             Err(AccountError::FrozenAccount(*self))
         } else {
             Ok(())
         }
     }
-    pub fn deposit(&mut self, amount: Number) -> AccountResult {
+    pub fn deposit(&mut self, amount: M) -> AccountResult<M> {
         self.available = self
             .available
             .checked_add(amount)
@@ -59,19 +240,54 @@ impl Account {
             })?;
         Ok(())
     }
-    pub fn withdraw(&mut self, amount: Number) -> AccountResult {
+    // Credits as much of the amount as fits, returning how much that was.
+    pub fn deposit_saturating(&mut self, amount: M) -> M {
+        match self.available.checked_add(amount) {
+            Some(available) => {
+                self.available = available;
+                amount
+            }
+            None => {
+                let credited = M::MAX - self.available;
+                self.available = M::MAX;
+                credited
+            }
+        }
+    }
+    pub fn withdraw(&mut self, amount: M) -> AccountResult<M> {
+        self.overdraw(amount, M::ZERO)
+    }
+    // A withdrawal allowed to take the available funds down to `-overdraft`.
+    pub fn overdraw(&mut self, amount: M, overdraft: M) -> AccountResult<M> {
         self.check_locked()?;
-        if self.available < amount {
+        self.available = self
+            .available
+            .checked_sub(amount)
+            .filter(|available| *available >= -overdraft)
+            .ok_or(AccountError::Underflow {
+                available: self.available,
+                held: self.held,
+                transaction_amount: amount,
+            })?;
+        Ok(())
+    }
+    // Corrections move available funds in either direction and go through
+    // even on frozen accounts, but never take the balance below zero.
+    pub fn adjust(&mut self, amount: M) -> AccountResult<M> {
+        if amount >= M::ZERO {
+            return self.deposit(amount);
+        }
+        if self.available < -amount {
             return Err(AccountError::Underflow {
                 available: self.available,
                 held: self.held,
                 transaction_amount: amount,
             });
         }
-        self.available -= amount;
+        self.available += amount;
         Ok(())
     }
-    pub fn dispute(&mut self, amount: Number) -> AccountResult {
+    pub fn dispute(&mut self, amount: M) -> AccountResult<M> {
         let available = self
             .available
             .checked_sub(amount)
@@ -92,7 +308,7 @@ impl Account {
         self.held = held;
         Ok(())
     }
-    pub fn resolve(&mut self, amount: Number) -> AccountResult {
+    pub fn resolve(&mut self, amount: M) -> AccountResult<M> {
         let available = self
             .available
             .checked_add(amount)
@@ -114,16 +330,47 @@ impl Account {
         self.held = held;
         Ok(())
     }
-    pub fn chargeback(&mut self, amount: Number) {
+    pub fn chargeback(&mut self, amount: M) {
         self.held -= amount;
         self.locked = true;
     }
+    // Lifts the lock a chargeback left, see `Ledger::unlock_account`.
+    pub(crate) fn unlock(&mut self) {
+        self.locked = false;
+    }
+    // Holds the amount of a disputed withdrawal as a pending credit, leaving
+    // the available funds alone.
+    pub fn dispute_withdrawal(&mut self, amount: M) -> AccountResult<M> {
+        self.held = self
+            .held
+            .checked_add(amount)
+            .ok_or(AccountError::Overflow {
+                available: self.available,
+                held: self.held,
+                transaction_amount: amount,
+            })?;
+        Ok(())
+    }
+    // The withdrawal stands: the pending credit is dropped.
+    pub fn resolve_withdrawal(&mut self, amount: M) -> AccountResult<M> {
+        self.held = self
+            .held
+            .checked_sub(amount)
+            .ok_or(AccountError::Underflow {
+                available: self.available,
+                held: self.held,
+                transaction_amount: amount,
+            })?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod account_tests {
     use super::num;
-    use super::Number;
+    use super::{
+        parse_amount, parse_amount_in, Account, AccountError, AmountError, AmountLocale, Number,
+    };
 
     #[test]
     fn verify_precision() {
@@ -137,4 +384,69 @@ mod account_tests {
         }
         assert_eq!(a, Number::ZERO);
     }
+
+    #[test]
+    fn reject_amounts_past_the_precision() {
+        assert_eq!(parse_amount("-1.5000"), Ok(num!(-1.5)));
+        assert_eq!(
+            parse_amount("0.12345678901234567890123456789"),
+            Err(AmountError::AmountPrecisionExceeded(
+                "0.12345678901234567890123456789".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse_amount("79228162514264337593543950336"),
+            Err(AmountError::AmountPrecisionExceeded(_))
+        ));
+        assert_eq!(
+            parse_amount("1.2.3"),
+            Err(AmountError::Invalid("1.2.3".to_string()))
+        );
+    }
+
+    #[test]
+    fn amounts_in_regional_formats() {
+        let european = AmountLocale::European;
+        assert_eq!(parse_amount_in("1.234,56", european), Ok(num!(1234.56)));
+        assert_eq!(
+            parse_amount_in("-1\u{2009}234\u{2009}567,5", european),
+            Ok(num!(-1234567.5))
+        );
+        assert_eq!(parse_amount_in("0,25", european), Ok(num!(0.25)));
+        assert_eq!(
+            parse_amount_in("1,234.56", AmountLocale::English),
+            Ok(num!(1234.56))
+        );
+        for amount in ["1,5", "12,34.5", "1,234,5"] {
+            assert_eq!(
+                parse_amount_in(amount, AmountLocale::English),
+                Err(AmountError::Invalid(amount.to_string()))
+            );
+        }
+        assert!(parse_amount_in("1.234.56", european).is_err());
+    }
+
+    #[test]
+    fn accounts_in_minor_units() {
+        let mut account = Account::<i64>::default();
+        account.deposit(1_000).unwrap();
+        account.withdraw(250).unwrap();
+        account.dispute(500).unwrap();
+        assert_eq!((account.available(), account.held()), (250, 500));
+        assert_eq!(account.total(), 750);
+        assert!(matches!(
+            account.withdraw(300),
+            Err(AccountError::Underflow { .. })
+        ));
+        account.deposit(i64::MAX - 250).unwrap();
+        assert!(matches!(
+            account.deposit(1),
+            Err(AccountError::Overflow { .. })
+        ));
+        account.chargeback(500);
+        assert_eq!(
+            account.withdraw(1),
+            Err(AccountError::FrozenAccount(account))
+        );
+    }
 }
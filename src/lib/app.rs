@@ -1,116 +1,1581 @@
-use std::{fs, io, sync::mpsc, thread};
+use std::{collections::BTreeMap, fs, io, sync::mpsc, sync::Arc, thread, time::Duration};
 
-use super::account::{ClientId, Number};
-use super::ledger::Ledger;
-use super::transactions::{Operation, Transaction, TransactionId};
+use super::account::{deserialize_amount, parse_amount_in, AmountLocale, ClientId, Number};
+use super::checkpoint::{write_sealed, SnapshotSchedule, Snapshotter};
+use super::config::GroupConfig;
+use super::digest;
+use super::encryption::Keyring;
+use super::enrichment::Enricher;
+use super::fixed_width::Layout;
+use super::health::HealthMonitor;
+use super::import;
+use super::ledger::{
+    AccountFilter, AccountSettings, CustomOperation, CustomTransaction, Finalizer, Ledger,
+    OperationRegistry, Policy,
+};
+use super::outbox::{OutboxConfig, OutboxWorker, Webhook};
+use super::rate_limit::RateLimiter;
+use super::report::{self, ReportOptions};
+use super::seen::{AtMostOnce, SeenIds};
+#[cfg(feature = "templates")]
+use super::template::Template;
+use super::transactions::{
+    IdGenerator, Operation, Signature, Transaction, TransactionError, TransactionId,
+};
+use super::wire;
 
-fn create_reader(path: &String) -> csv::Reader<io::BufReader<fs::File>> {
-    let file = fs::File::open(path).unwrap();
-    let reader = io::BufReader::new(file);
-    csv::Reader::from_reader(reader)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    // Fixed-width records laid out like this.
+    FixedWidth(Layout),
+    // Length-delimited protobuf `Transaction` messages, see wire.rs.
+    Protobuf,
 }
 
-#[derive(serde::Deserialize)]
-#[serde(rename_all = "lowercase")]
-enum TransactionType {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    Chargeback,
-}
-
-impl From<TransactionType> for Operation {
-    fn from(value: TransactionType) -> Self {
-        match value {
-            TransactionType::Deposit => Operation::Deposit,
-            TransactionType::Withdrawal => Operation::Withdrawal,
-            TransactionType::Dispute => Operation::Dispute,
-            TransactionType::Resolve => Operation::Resolve,
-            TransactionType::Chargeback => Operation::Chargeback,
+// CSV fields are separated by commas unless `delimiter` says otherwise.
+fn create_reader(
+    path: &String,
+    format: &InputFormat,
+    delimiter: Option<u8>,
+    locale: Option<AmountLocale>,
+) -> io::Result<Box<dyn Iterator<Item = Row>>> {
+    let reader = io::BufReader::new(fs::File::open(path)?);
+    Ok(match format {
+        InputFormat::Csv => {
+            let reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter.unwrap_or(b','))
+                .from_reader(reader);
+            Box::new(read_rows_in(reader, path, locale))
         }
-    }
+        InputFormat::FixedWidth(layout) => {
+            Box::new(read_rows_in(layout.csv_reader(reader), path, locale))
+        }
+        InputFormat::Protobuf => Box::new(read_messages(reader)),
+    })
 }
 
 #[derive(serde::Deserialize)]
 struct CsvTransactionRecord {
+    // One of the built-in operations or a name from the operation registry.
     #[serde(rename = "type")]
-    tx_type: TransactionType,
+    tx_type: String,
     client: u16,
-    tx: u32,
+    // Feeds without ids get one derived from the file, line and contents.
+    tx: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_amount")]
     amount: Option<Number>,
+    #[serde(default)]
+    signature: Option<String>,
+    // Settings of `open` rows.
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    tier: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    overdraft_limit: Option<Number>,
+    // Recipient of `transfer` rows.
+    #[serde(default)]
+    to: Option<u16>,
 }
 
-#[derive(serde::Serialize)]
-struct CsvAccountRecord {
-    client: u16,
-    available: String,
-    held: String,
-    total: String,
-    locked: bool,
+impl CsvTransactionRecord {
+    fn settings(&self) -> Result<Option<AccountSettings>, String> {
+        let present = |field: &Option<String>| field.clone().filter(|value| !value.is_empty());
+        let settings = AccountSettings {
+            currency: present(&self.currency)
+                .map(|currency| currency.parse())
+                .transpose()
+                .map_err(|currency| format!("invalid currency '{currency}'"))?,
+            tier: present(&self.tier)
+                .map(|tier| tier.parse())
+                .transpose()
+                .map_err(|tier| format!("invalid tier '{tier}'"))?,
+            overdraft_limit: self.overdraft_limit,
+        };
+        Ok((settings != AccountSettings::default()).then_some(settings))
+    }
+
+    // Ids are filled in by `read_rows` before this is called. Signatures are
+    // only read when the ledger verifies them, and otherwise ignored.
+    fn into_transaction(
+        self,
+        operation: Operation,
+        line: u64,
+        verify_signatures: bool,
+    ) -> Result<(TransactionId, Transaction), RowError> {
+        let malformed = |message: String| RowError::Malformed { line, message };
+        let amount = self.amount.unwrap_or_default();
+        let client_id = ClientId(self.client);
+        let operation = operation.with_recipient(self.to.map(ClientId));
+        let mut transaction = Transaction::new(client_id, amount, operation);
+        if let Some(settings) = self.settings().map_err(malformed)? {
+            transaction = transaction.with_settings(settings);
+        }
+        let signature = self.signature.as_deref().filter(|s| !s.is_empty());
+        if let Some(signature) = signature.filter(|_| verify_signatures) {
+            let signature =
+                Signature::from_hex(signature).ok_or(RowError::InvalidSignature { line })?;
+            transaction = transaction.with_signature(signature);
+        }
+        let transaction_id = self
+            .tx
+            .ok_or_else(|| malformed("missing transaction id".to_string()))?;
+        Ok((TransactionId(transaction_id), transaction))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RowError {
+    Malformed { line: u64, message: String },
+    InvalidSignature { line: u64 },
+    Rejected { line: u64, error: TransactionError },
 }
 
-fn process(
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowError::Malformed { line, message } => write!(f, "line {line}: {message}"),
+            RowError::InvalidSignature { line } => write!(f, "line {line}: invalid signature"),
+            RowError::Rejected { line, error } => write!(f, "line {line}: {:?}", error),
+        }
+    }
+}
+
+// A row as read from the input, kept alongside its parsed form so that
+// rejected rows can be reported as they were received.
+pub(crate) struct Row {
+    line: u64,
+    fields: csv::StringRecord,
+    record: Result<CsvTransactionRecord, String>,
+}
+
+impl CsvTransactionRecord {
+    fn from_transaction(transaction_id: TransactionId, transaction: &Transaction) -> Self {
+        let settings = transaction.settings();
+        let has_amount = matches!(
+            transaction.operation(),
+            Operation::Deposit
+                | Operation::Withdrawal
+                | Operation::Adjustment
+                | Operation::Transfer { .. }
+        );
+        CsvTransactionRecord {
+            tx_type: transaction.operation().name().to_string(),
+            client: transaction.client_id().0,
+            tx: Some(transaction_id.0),
+            amount: has_amount.then_some(transaction.amount()),
+            signature: transaction
+                .signature()
+                .map(|signature| digest::to_hex(&signature.0)),
+            currency: settings
+                .and_then(|settings| settings.currency)
+                .map(|currency| currency.to_string()),
+            tier: settings
+                .and_then(|settings| settings.tier)
+                .map(|tier| tier.name().to_string()),
+            overdraft_limit: settings.and_then(|settings| settings.overdraft_limit),
+            to: transaction.operation().recipient().map(|to| to.0),
+        }
+    }
+
+    // The record as a CSV row with every column, as dead letters write it.
+    fn to_fields(&self) -> csv::StringRecord {
+        let text = |field: Option<String>| field.unwrap_or_default();
+        csv::StringRecord::from(vec![
+            self.tx_type.clone(),
+            self.client.to_string(),
+            text(self.tx.map(|tx| tx.to_string())),
+            text(self.amount.map(|amount| amount.to_string())),
+            text(self.signature.clone()),
+            text(self.currency.clone()),
+            text(self.tier.clone()),
+            text(self.overdraft_limit.map(|limit| limit.to_string())),
+            text(self.to.map(|to| to.to_string())),
+        ])
+    }
+}
+
+// Messages are numbered from 1 in place of lines, and dead letters get them
+// back as CSV. Reading stops at the first message that can't be read off the
+// stream, since the ones after it can't be found.
+fn read_messages<R: io::Read>(reader: R) -> impl Iterator<Item = Row> {
+    let mut messages = wire::DelimitedReader::new(reader);
+    let (mut line, mut done) = (0, false);
+    std::iter::from_fn(move || {
+        let message = messages.next().filter(|_| !done)?;
+        line += 1;
+        let decoded = message.map_err(|err| {
+            done = true;
+            err.to_string()
+        });
+        let decoded = decoded.and_then(|message| {
+            wire::decode_transaction(&message).map_err(|err| format!("invalid message: {err:?}"))
+        });
+        Some(match decoded {
+            Ok((transaction_id, transaction)) => {
+                let record = CsvTransactionRecord::from_transaction(transaction_id, &transaction);
+                Row {
+                    line,
+                    fields: record.to_fields(),
+                    record: Ok(record),
+                }
+            }
+            Err(message) => Row {
+                line,
+                fields: csv::StringRecord::new(),
+                record: Err(message),
+            },
+        })
+    })
+}
+
+impl Row {
+    // Reads the amount as a whole number of minor units of the account's
+    // currency.
+    fn in_minor_units(mut self, ledger: &Ledger) -> Self {
+        if let Ok(record) = &mut self.record {
+            let Some(amount) = record.amount else {
+                return self;
+            };
+            match i64::try_from(amount.mantissa()) {
+                Ok(units) if amount.scale() == 0 => {
+                    record.amount =
+                        Some(ledger.amount_from_minor_units(ClientId(record.client), units))
+                }
+                _ => self.record = Err(format!("amount {amount} is not a count of minor units")),
+            }
+        }
+        self
+    }
+}
+
+pub(crate) fn read_rows<R: io::Read>(
+    reader: csv::Reader<R>,
+    source: &str,
+) -> impl Iterator<Item = Row> {
+    read_rows_in(reader, source, None)
+}
+
+// Rewrites the amount columns of a row written the locale's way as plain
+// decimals.
+fn localize(
+    fields: &csv::StringRecord,
+    headers: &csv::StringRecord,
+    locale: AmountLocale,
+) -> Result<csv::StringRecord, String> {
+    fields
+        .iter()
+        .zip(headers.iter().chain(std::iter::repeat("")))
+        .map(|(field, header)| match header {
+            "amount" | "overdraft_limit" if !field.is_empty() => parse_amount_in(field, locale)
+                .map(|amount| amount.to_string())
+                .map_err(|err| err.to_string()),
+            _ => Ok(field.to_string()),
+        })
+        .collect()
+}
+
+// With a locale, amounts are read leniently, written the way it writes them.
+pub(crate) fn read_rows_in<R: io::Read>(
+    mut reader: csv::Reader<R>,
+    source: &str,
+    locale: Option<AmountLocale>,
+) -> impl Iterator<Item = Row> {
+    let headers = reader.headers().cloned().unwrap_or_default();
+    let source = source.to_string();
+    let mut ids = IdGenerator::new();
+    reader.into_records().map(move |fields| match fields {
+        Ok(fields) => {
+            let line = fields.position().map_or(0, |position| position.line());
+            let localized = match locale {
+                Some(locale) => localize(&fields, &headers, locale),
+                None => Ok(fields.clone()),
+            };
+            let record = localized
+                .and_then(|localized| {
+                    localized
+                        .deserialize::<CsvTransactionRecord>(Some(&headers))
+                        .map_err(|err| err.to_string())
+                })
+                .and_then(|mut record| {
+                    if record.tx.is_none() {
+                        let contents = fields.iter().collect::<Vec<_>>().join(",");
+                        let id = ids
+                            .assign(&source, line, contents.as_bytes())
+                            .map_err(|err| err.to_string())?;
+                        record.tx = Some(id.0);
+                    }
+                    Ok(record)
+                });
+            Row {
+                line,
+                fields,
+                record,
+            }
+        }
+        Err(err) => Row {
+            line: err.position().map_or(0, |position| position.line()),
+            fields: csv::StringRecord::new(),
+            record: Err(err.to_string()),
+        },
+    })
+}
+
+// How often a row turned down with a retryable error is applied again before
+// it's given up on, waiting `backoff` after the first failure and twice as
+// long after each following one. Rows aren't retried by default.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RowRetry {
+    pub max_retries: u32,
+    pub backoff: Duration,
+}
+
+impl RowRetry {
+    fn apply<T>(
+        &self,
+        mut apply: impl FnMut() -> Result<T, TransactionError>,
+    ) -> Result<T, TransactionError> {
+        let mut backoff = self.backoff;
+        let mut retries = 0;
+        loop {
+            match apply() {
+                Err(err) if err.is_retryable() && retries < self.max_retries => {
+                    retries += 1;
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+pub(crate) fn apply_row(
     ledger: &mut Ledger,
-    transaction_id: TransactionId,
-    transaction: &Transaction,
-    print_error: bool,
-) {
-    match ledger.apply_transaction(transaction_id, transaction) {
-        Ok(()) => {}
-        Err(err) => {
-            if print_error {
-                eprintln!("error: {:?}", err);
+    row: Row,
+    rate_limiter: Option<&mut RateLimiter>,
+    operations: &OperationRegistry,
+) -> Result<(), RowError> {
+    apply_row_with(ledger, row, rate_limiter, operations, RowRetry::default())
+}
+
+fn apply_row_with(
+    ledger: &mut Ledger,
+    row: Row,
+    rate_limiter: Option<&mut RateLimiter>,
+    operations: &OperationRegistry,
+    retry: RowRetry,
+) -> Result<(), RowError> {
+    let line = row.line;
+    let record = row
+        .record
+        .map_err(|message| RowError::Malformed { line, message })?;
+    let operation = match record.tx_type.parse::<Operation>() {
+        Ok(operation) => operation,
+        Err(name) => match operations.get(&name) {
+            Some(CustomOperation::Alias(operation)) => *operation,
+            Some(CustomOperation::Handler(handler)) => {
+                let transaction_id = TransactionId(record.tx.unwrap_or_default());
+                let transaction = CustomTransaction {
+                    client_id: ClientId(record.client),
+                    amount: record.amount.unwrap_or_default(),
+                };
+                if let Some(rate_limiter) = rate_limiter {
+                    rate_limiter.acquire();
+                }
+                return retry
+                    .apply(|| handler(ledger, transaction_id, &transaction))
+                    .map(|_| ())
+                    .map_err(|error| RowError::Rejected { line, error });
             }
+            None => {
+                return Err(RowError::Malformed {
+                    line,
+                    message: format!("unknown transaction type '{name}'"),
+                })
+            }
+        },
+    };
+    if record.to.is_none() && operation.recipient().is_some() {
+        return Err(RowError::Malformed {
+            line,
+            message: "transfer without a recipient".to_string(),
+        });
+    }
+    let (transaction_id, transaction) =
+        record.into_transaction(operation, line, ledger.verifies_signatures())?;
+    if let Some(rate_limiter) = rate_limiter {
+        rate_limiter.acquire();
+    }
+    retry
+        .apply(|| ledger.apply_transaction(transaction_id, &transaction))
+        .map(|_| ())
+        .map_err(|error| RowError::Rejected { line, error })
+}
+
+#[derive(Default)]
+pub struct ProcessOptions {
+    pub debug: bool,
+    pub rate_limiter: Option<RateLimiter>,
+    // Rows that can't be applied are written here as received, followed by
+    // the reason they failed, instead of being dropped.
+    pub dead_letters: Option<Box<dyn io::Write + Send>>,
+    pub retry: RowRetry,
+    pub health: Option<Arc<HealthMonitor>>,
+    pub operations: OperationRegistry,
+    // Bounds the queue between the reader and the ledger. When it's full the
+    // reader waits, or drops the row if `drop_when_full` is set.
+    pub queue_capacity: Option<usize>,
+    pub drop_when_full: bool,
+    pub queue_observer: Option<Box<dyn FnMut(QueueEvent) + Send>>,
+    pub snapshotter: Option<Snapshotter>,
+    pub input_format: InputFormat,
+    // Separates the fields of CSV input, a comma by default. European files
+    // often use a semicolon, their decimal separator being the comma.
+    pub delimiter: Option<u8>,
+    // Amounts are whole numbers of minor units of each account's currency.
+    pub minor_units: bool,
+    // Amounts may be written the way this locale writes them, see
+    // `parse_amount_in`. Without one only plain decimals are accepted.
+    pub amount_locale: Option<AmountLocale>,
+    // Looks each row's client up before it's applied.
+    pub enricher: Option<Arc<Enricher>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QueueEvent {
+    HighWaterMark(usize),
+    Dropped { line: u64, dropped: u64 },
+}
+
+enum RowSender {
+    Unbounded(mpsc::Sender<Row>),
+    Bounded(mpsc::SyncSender<Row>),
+    Dropping(mpsc::SyncSender<Row>),
+}
+
+impl RowSender {
+    fn channel(capacity: Option<usize>, drop_when_full: bool) -> (Self, mpsc::Receiver<Row>) {
+        match capacity {
+            None => {
+                let (tx, rx) = mpsc::channel();
+                (RowSender::Unbounded(tx), rx)
+            }
+            Some(capacity) => {
+                let (tx, rx) = mpsc::sync_channel(capacity);
+                match drop_when_full {
+                    true => (RowSender::Dropping(tx), rx),
+                    false => (RowSender::Bounded(tx), rx),
+                }
+            }
+        }
+    }
+
+    // Hands back rows that were dropped because the queue was full.
+    fn send(&self, row: Row) -> Option<Row> {
+        match self {
+            RowSender::Unbounded(tx) => tx.send(row).err().map(|err| err.0),
+            RowSender::Bounded(tx) => tx.send(row).err().map(|err| err.0),
+            RowSender::Dropping(tx) => match tx.try_send(row) {
+                Ok(()) => None,
+                Err(mpsc::TrySendError::Full(row) | mpsc::TrySendError::Disconnected(row)) => {
+                    Some(row)
+                }
+            },
         }
+    }
+}
+
+// Applies a row as a run does: amounts in minor units are converted and the
+// client is looked up before the row itself is applied.
+fn apply_processed_row(
+    ledger: &mut Ledger,
+    row: Row,
+    options: &ProcessOptions,
+    rate_limiter: Option<&mut RateLimiter>,
+) -> Result<(), RowError> {
+    let row = match options.minor_units {
+        true => row.in_minor_units(ledger),
+        false => row,
     };
+    if let (Some(enricher), Ok(record)) = (&options.enricher, &row.record) {
+        enricher
+            .enrich(ledger, ClientId(record.client))
+            .map_err(|error| RowError::Rejected {
+                line: row.line,
+                error,
+            })?;
+    }
+    apply_row_with(
+        ledger,
+        row,
+        rate_limiter,
+        &options.operations,
+        options.retry,
+    )
 }
 
 fn process_transactions(
-    rx_channel: mpsc::Receiver<CsvTransactionRecord>,
-    debug: bool,
+    rx_channel: mpsc::Receiver<Row>,
     ledger: &mut Ledger,
+    mut options: ProcessOptions,
 ) {
-    while let Ok(record) = rx_channel.recv() {
-        let transaction_id = TransactionId(record.tx);
-        let amount = record.amount.unwrap_or_default();
-        let client_id = ClientId(record.client);
-        let operation = Operation::from(record.tx_type);
-        process(
-            ledger,
-            transaction_id,
-            &Transaction::new(client_id, amount, operation),
-            debug,
-        )
+    let debug = options.debug;
+    let mut rate_limiter = options.rate_limiter.take();
+    let health = options.health.take();
+    let mut snapshotter = options.snapshotter.take();
+    let mut dead_letters = options
+        .dead_letters
+        .take()
+        .map(|writer| csv::WriterBuilder::new().flexible(true).from_writer(writer));
+    while let Ok(row) = rx_channel.recv() {
+        let fields = dead_letters.as_ref().map(|_| row.fields.clone());
+        let result = apply_processed_row(ledger, row, &options, rate_limiter.as_mut());
+        if let Some(health) = &health {
+            health.processed(
+                ledger
+                    .journal()
+                    .map_or(0, |journal| journal.last_sequence()),
+            );
+            if health.take_export_request() {
+                health.publish_accounts(ledger.accounts_snapshot(), false);
+            }
+            if let Some(policy) = health.take_reloaded_policy() {
+                ledger.set_policy(policy);
+            }
+        }
+        if let Some(snapshotter) = &mut snapshotter {
+            if let Err(err) = snapshotter.tick(ledger) {
+                eprintln!("error: failed to take snapshot: {err}");
+            }
+        }
+        let Err(err) = result else {
+            continue;
+        };
+        if debug {
+            eprintln!("error: {err}");
+        }
+        if let (Some(writer), Some(mut fields)) = (&mut dead_letters, fields) {
+            fields.push_field(&err.to_string());
+            if let Err(err) = writer.write_record(&fields) {
+                eprintln!("error: failed to write dead letter: {err}");
+            }
+        }
+    }
+    if let Some(writer) = &mut dead_letters {
+        let _ = writer.flush();
+    }
+    if let Some(Err(err)) = snapshotter.map(Snapshotter::finish) {
+        eprintln!("error: failed to take snapshot: {err}");
     }
 }
 
 pub fn process_file(filename: &String, debug: bool) -> Ledger {
-    let mut reader = create_reader(filename);
-    let (tx, rx) = mpsc::channel();
+    process_file_into(filename, debug, Ledger::new())
+}
+
+pub fn process_file_into(filename: &String, debug: bool, ledger: Ledger) -> Ledger {
+    let options = ProcessOptions {
+        debug,
+        ..ProcessOptions::default()
+    };
+    process_file_with(filename, ledger, options)
+}
+
+pub fn process_file_with(
+    filename: &String,
+    mut ledger: Ledger,
+    mut options: ProcessOptions,
+) -> Ledger {
+    let rows = create_reader(
+        filename,
+        &options.input_format,
+        options.delimiter,
+        options.amount_locale,
+    )
+    .unwrap();
+    let health = options
+        .health
+        .get_or_insert_with(|| Arc::new(HealthMonitor::default()))
+        .clone();
+    let mut observer = options.queue_observer.take();
+    let (tx, rx) = RowSender::channel(options.queue_capacity, options.drop_when_full);
     let handler = thread::spawn(move || {
-        let mut ledger = Ledger::new();
-        process_transactions(rx, debug, &mut ledger);
+        process_transactions(rx, &mut ledger, options);
         ledger
     });
-    for record in reader.deserialize::<CsvTransactionRecord>().flatten() {
-        let _ = tx.send(record);
+    for row in rows {
+        if health.shutdown_requested() {
+            break;
+        }
+        let line = row.line;
+        if let Some(high_water_mark) = health.enqueued() {
+            if let Some(observer) = &mut observer {
+                observer(QueueEvent::HighWaterMark(high_water_mark));
+            }
+        }
+        if tx.send(row).is_some() {
+            let dropped = health.dropped();
+            if let Some(observer) = &mut observer {
+                observer(QueueEvent::Dropped { line, dropped });
+            }
+        }
     }
     drop(tx);
     handler.join().unwrap()
 }
 
-pub fn app(filename: &String, debug: bool) {
-    let ledger = process_file(filename, debug);
-    let mut writer = csv::WriterBuilder::new().from_writer(io::BufWriter::new(io::stdout()));
-    for (key, account) in ledger {
-        let val = CsvAccountRecord {
-            client: key.0,
-            available: format!("{:.4}", account.available()),
-            held: format!("{:.4}", account.held()),
-            total: format!("{:.4}", account.total()),
-            locked: account.locked(),
+// The part of a partitioned run a single worker handled. `start` and `end`
+// are the byte range actually processed, moved forward to record boundaries.
+// Workers only read and parse their range: whether a row applies depends on
+// everything before it, locks and chargebacks in earlier ranges included, so
+// the parsed transactions are kept in input order and applied by
+// `merge_partitions`.
+pub struct Partition {
+    pub start: u64,
+    pub end: u64,
+    pub rows: u64,
+    pub rejected: u64,
+    pub transactions: Vec<(TransactionId, Transaction)>,
+}
+
+// Moves `offset` to the start of the next record. Offset 0 skips the header.
+// A newline inside a quoted field doesn't end a record, and only the quotes
+// before `offset` tell whether it's inside one, so they're counted from the
+// start of the input. Escaped quotes come in pairs and leave the count even.
+fn align_to_record<R: io::BufRead + io::Seek>(reader: &mut R, offset: u64) -> io::Result<u64> {
+    let len = reader.seek(io::SeekFrom::End(0))?;
+    if offset >= len {
+        return Ok(len);
+    }
+    let from = offset.saturating_sub(1);
+    reader.seek(io::SeekFrom::Start(0))?;
+    let mut quoted = false;
+    for (position, byte) in (0..).zip(io::Read::bytes(&mut *reader)) {
+        match byte? {
+            b'"' => quoted = !quoted,
+            b'\n' if !quoted && position >= from => return Ok(position + 1),
+            _ => {}
+        }
+    }
+    Ok(len)
+}
+
+// Reads the records starting in `start..end` of a CSV input. Rows without an
+// id get one derived from the range rather than the whole file.
+pub fn process_range<R: io::BufRead + io::Seek>(
+    mut reader: R,
+    source: &str,
+    start: u64,
+    end: u64,
+    verify_signatures: bool,
+) -> io::Result<Partition> {
+    let header_len = align_to_record(&mut reader, 0)?;
+    let start = align_to_record(&mut reader, start)?.max(header_len);
+    let end = align_to_record(&mut reader, end)?.max(start);
+    let mut header = Vec::new();
+    reader.seek(io::SeekFrom::Start(0))?;
+    io::Read::read_to_end(&mut io::Read::take(&mut reader, header_len), &mut header)?;
+    reader.seek(io::SeekFrom::Start(start))?;
+    let input = io::Read::chain(io::Cursor::new(header), reader.take(end - start));
+    let (mut rows, mut rejected, mut transactions) = (0, 0, Vec::new());
+    let source = format!("{source}@{start}");
+    for row in read_rows(csv::Reader::from_reader(input), &source) {
+        rows += 1;
+        let parsed = row.record.ok().and_then(|record| {
+            let operation = record.tx_type.parse().ok()?;
+            record
+                .into_transaction(operation, row.line, verify_signatures)
+                .ok()
+        });
+        match parsed {
+            Some(transaction) => transactions.push(transaction),
+            None => rejected += 1,
+        }
+    }
+    Ok(Partition {
+        start,
+        end,
+        rows,
+        rejected,
+        transactions,
+    })
+}
+
+// The reduce step of a partitioned run: the transactions of every partition
+// are applied in input order, exactly as a run over the whole file would.
+pub fn merge_partitions(partitions: Vec<Partition>, mut ledger: Ledger) -> Ledger {
+    for partition in partitions {
+        for (transaction_id, transaction) in partition.transactions {
+            let _ = ledger.apply_transaction(transaction_id, &transaction);
+        }
+    }
+    ledger
+}
+
+// Runs every row through a scratch ledger and reports the ones that would be
+// skipped or rejected by a real run.
+pub fn validate_reader<R: io::Read>(reader: R, source: &str, mut ledger: Ledger) -> Vec<RowError> {
+    read_rows(csv::Reader::from_reader(reader), source)
+        .filter_map(|row| apply_row(&mut ledger, row, None, &OperationRegistry::new()).err())
+        .collect()
+}
+
+// Reads and applies the file with the same ledger, reader and row handling
+// as a run, without writing anything out.
+pub fn validate_file(filename: &String, options: RunOptions) -> io::Result<Vec<RowError>> {
+    let (mut ledger, _) = prepare_ledger(&options, true)?;
+    let process = options.process;
+    let rows = create_reader(
+        filename,
+        &process.input_format,
+        process.delimiter,
+        process.amount_locale,
+    )?;
+    Ok(rows
+        .filter_map(|row| apply_processed_row(&mut ledger, row, &process, None).err())
+        .collect())
+}
+
+// Everything a full run of the binary needs besides the input file.
+#[derive(Default)]
+pub struct RunOptions {
+    pub process: ProcessOptions,
+    pub report: ReportOptions,
+    pub report_threads: usize,
+    pub policy: Policy,
+    pub journal: Option<String>,
+    pub snapshot: Option<String>,
+    // Encrypts the journal and snapshots. Files under a previous key are
+    // read and written back under the current one.
+    pub keyring: Option<Keyring>,
+    // Also take snapshots while running, not only at the end.
+    pub snapshot_schedule: SnapshotSchedule,
+    // Start from the snapshot and the journal tail written after it instead
+    // of an empty ledger.
+    pub recover: bool,
+    pub risk_scores: Option<String>,
+    pub groups: Vec<GroupConfig>,
+    pub alerts: Option<String>,
+    pub held_aging: Option<String>,
+    pub dispute_letters: Option<String>,
+    // A client,portfolio mapping, and where to write totals per portfolio.
+    pub portfolios: Option<String>,
+    pub portfolio_report: Option<String>,
+    pub disputes: Option<String>,
+    pub reconciliation: Option<String>,
+    // Delivered from the journal file after it's written.
+    pub outbox: Option<OutboxConfig>,
+    // Reject ids applied by any earlier run, kept in this file.
+    pub seen_ids: Option<String>,
+    // Render the report through this template instead of as CSV or JSON.
+    pub template: Option<String>,
+    // Run once the input and imported disputes are applied, before anything
+    // is written out.
+    pub finalizers: Vec<Finalizer>,
+}
+
+pub fn deliver(journal: &str, outbox: &OutboxConfig) -> io::Result<usize> {
+    let webhook = Webhook::new(&outbox.webhook)?;
+    OutboxWorker::open(&outbox.state, webhook, outbox.retry)?.deliver_file(journal)
+}
+
+#[cfg(feature = "templates")]
+fn read_template(path: &str) -> io::Result<Template> {
+    Template::read(path).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid template {path}: {err:?}"),
+        )
+    })
+}
+
+// Stands in for the template engine in builds without it, so `--template`
+// fails before the run instead of being ignored.
+#[cfg(not(feature = "templates"))]
+enum Template {}
+
+#[cfg(not(feature = "templates"))]
+impl Template {
+    fn render<W: io::Write>(&self, _: W, _: &Ledger, _: &ReportOptions) -> io::Result<()> {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "templates"))]
+fn read_template(_: &str) -> io::Result<Template> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "templates need a build with the templates feature",
+    ))
+}
+
+// A missing snapshot means none was taken yet and the whole journal is
+// replayed.
+fn recover(options: &RunOptions) -> io::Result<Ledger> {
+    let journal = options
+        .journal
+        .as_ref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "recovering needs a journal"))?;
+    let snapshot = match options.snapshot.as_ref().map(fs::File::open) {
+        Some(Err(err)) if err.kind() == io::ErrorKind::NotFound => None,
+        snapshot => snapshot.transpose()?,
+    };
+    let journal = fs::File::open(journal)?;
+    match &options.keyring {
+        Some(keyring) => Ledger::recover(
+            snapshot
+                .map(|snapshot| keyring.open(snapshot).map(io::Cursor::new))
+                .transpose()?,
+            io::Cursor::new(keyring.open(journal)?),
+            options.policy,
+        ),
+        None => Ledger::recover(snapshot, journal, options.policy),
+    }
+}
+
+// The ledger a run starts from, with its policy, groups, journal and
+// seen-id check. Validating leaves the seen ids as they were.
+fn prepare_ledger(
+    options: &RunOptions,
+    validating: bool,
+) -> io::Result<(Ledger, Option<Arc<AtMostOnce>>)> {
+    let mut ledger = match options.recover {
+        true => recover(options)?,
+        false => Ledger::with_policy(options.policy),
+    };
+    // Recovered ledgers already have their groups.
+    let groups: Vec<_> = options
+        .groups
+        .iter()
+        .filter(|group| ledger.group(group.id).is_none())
+        .collect();
+    for group in groups {
+        let created = ledger.create_group(group.id, group.holder).and_then(|()| {
+            group
+                .members
+                .iter()
+                .try_for_each(|member| ledger.add_member(group.id, *member, group.withdrawal_limit))
+        });
+        created.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("{err:?}")))?;
+    }
+    if options.journal.is_some() {
+        ledger.enable_journal();
+    }
+    let at_most_once = match &options.seen_ids {
+        Some(path) if validating => Some(Arc::new(AtMostOnce::new(SeenIds::open_read_only(path)?))),
+        Some(path) => Some(Arc::new(AtMostOnce::new(SeenIds::open(path)?))),
+        None => None,
+    };
+    if let Some(at_most_once) = &at_most_once {
+        ledger.add_middleware(at_most_once.clone());
+    }
+    Ok((ledger, at_most_once))
+}
+
+pub fn app(filename: &String, mut options: RunOptions) -> io::Result<()> {
+    if let Some(outbox) = &options.outbox {
+        if options.journal.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the outbox needs a journal",
+            ));
+        }
+        if options.keyring.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the outbox can't deliver from an encrypted journal",
+            ));
+        }
+        Webhook::new(&outbox.webhook)?;
+    }
+    let template = options.template.as_deref().map(read_template).transpose()?;
+    let (ledger, at_most_once) = prepare_ledger(&options, false)?;
+    if let (Some(path), true) = (&options.snapshot, options.snapshot_schedule.is_enabled()) {
+        let snapshotter =
+            Snapshotter::new(path, options.journal.as_deref(), options.snapshot_schedule)
+                .with_keyring(options.keyring.clone());
+        options.process.snapshotter = Some(match (options.recover, ledger.journal()) {
+            (true, Some(journal)) => snapshotter.resume_journal(journal.last_sequence()),
+            _ => snapshotter,
+        });
+    }
+    let health = options.process.health.clone();
+    let mut ledger = process_file_with(filename, ledger, options.process);
+    if let Some(path) = &options.disputes {
+        let summary =
+            import::import_disputes(io::BufReader::new(fs::File::open(path)?), &mut ledger);
+        match &options.reconciliation {
+            Some(path) => import::write_reconciliation(fs::File::create(path)?, &summary)?,
+            None if !summary.failures.is_empty() => {
+                import::write_reconciliation(io::stderr().lock(), &summary)?
+            }
+            None => {}
+        }
+    }
+    for finalizer in &options.finalizers {
+        ledger.add_finalizer(*finalizer);
+    }
+    ledger.finalize();
+    if let Some(health) = &health {
+        health.publish_accounts(ledger.accounts_snapshot(), true);
+    }
+    if let Some(at_most_once) = &at_most_once {
+        at_most_once.sync()?;
+    }
+    if let (Some(path), Some(journal)) = (&options.journal, ledger.journal()) {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        write_sealed(options.keyring.as_ref(), &mut file, |writer| {
+            journal.write_csv(writer)
+        })?;
+        file.into_inner()?.sync_all()?;
+        if let Some(health) = &health {
+            health.flushed(journal.last_sequence());
+        }
+        if let Some(outbox) = &options.outbox {
+            // Undelivered entries stay in the journal for the next attempt.
+            if let Err(err) = deliver(path, outbox) {
+                eprintln!("error: {err}");
+            }
+        }
+    }
+    if let Some(path) = &options.snapshot {
+        let mut file = fs::File::create(path)?;
+        write_sealed(options.keyring.as_ref(), &mut file, |writer| {
+            ledger.write_snapshot(writer)
+        })?;
+        file.sync_all()?;
+    }
+    if let Some(path) = &options.alerts {
+        let alerts = ledger.alerts(&options.report.alerts);
+        report::write_alerts(fs::File::create(path)?, &alerts, &options.report)?;
+    }
+    if let Some(path) = &options.held_aging {
+        let aging = ledger.held_aging();
+        report::write_held_aging(fs::File::create(path)?, &aging, &options.report)?;
+    }
+    if let Some(path) = &options.dispute_letters {
+        let letters = ledger.dispute_letters();
+        report::write_dispute_letters(fs::File::create(path)?, &letters, &options.report)?;
+    }
+    if let Some(path) = &options.portfolio_report {
+        let portfolios = match &options.portfolios {
+            Some(mapping) => report::read_portfolios(fs::File::open(mapping)?)?,
+            None => BTreeMap::new(),
         };
-        let _ = writer.serialize(val);
+        let rollups =
+            report::portfolio_rollups(ledger.accounts(&AccountFilter::default()), &portfolios);
+        report::write_portfolio_rollups(fs::File::create(path)?, &rollups, &options.report)?;
+    }
+    if let Some(path) = &options.risk_scores {
+        ledger.write_risk_scores(fs::File::create(path)?)?;
+    }
+    if let Some(health) = health.as_ref().filter(|health| health.shutdown_requested()) {
+        let report = health.report();
+        eprintln!(
+            "shut down after {} rows, last journal sequence {}",
+            report.last_applied,
+            ledger
+                .journal()
+                .map_or(0, |journal| journal.last_sequence())
+        );
+    }
+    let result = if let Some(template) = &template {
+        template.render(io::stdout().lock(), &ledger, &options.report)
+    } else if options.report_threads > 1 {
+        let accounts: Vec<_> = ledger.into_iter().collect();
+        report::write_report_parallel(
+            io::stdout().lock(),
+            &accounts,
+            &options.report,
+            options.report_threads,
+        )
+    } else {
+        report::write_report(io::stdout().lock(), ledger, &options.report)
+    };
+    if let Some(health) = &health {
+        health.finish();
+    }
+    result
+}
+
+#[cfg(test)]
+mod app_tests {
+    use super::{
+        apply_row, merge_partitions, process_file_with, process_range, read_messages, read_rows,
+        read_rows_in, validate_file, validate_reader, wire, ProcessOptions, QueueEvent, RowError,
+        RowRetry, RunOptions,
+    };
+    use crate::account::{num, AccountError, AmountLocale, ClientId};
+    use crate::health::HealthMonitor;
+    use crate::ledger::{Ledger, OperationRegistry, Policy, ScreeningOutcome, ScreeningProvider};
+    use crate::transactions::{
+        Operation, Signature, SignatureVerifier, Transaction, TransactionError, TransactionId,
+    };
+    use std::fs;
+    use std::io;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn validate_lists_offending_rows() {
+        let input = "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            withdrawal,1,2,5.0\n\
+            refund,1,3,1.0\n\
+            deposit,1,1,2.0\n";
+        let errors = validate_reader(input.as_bytes(), "input.csv", Ledger::new());
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors[0],
+            RowError::Rejected {
+                line: 3,
+                error: TransactionError::AccountError(
+                    ClientId(1),
+                    AccountError::Underflow {
+                        available: num!(1.0),
+                        held: num!(0),
+                        transaction_amount: num!(5.0)
+                    }
+                )
+            }
+        );
+        assert!(matches!(errors[1], RowError::Malformed { line: 4, .. }));
+        assert_eq!(
+            errors[2],
+            RowError::Rejected {
+                line: 5,
+                error: TransactionError::RepeatedTransactionId(TransactionId(1))
+            }
+        );
+        assert!(validate_reader(
+            "type,client,tx,amount\ndeposit,1,1,1.0\n".as_bytes(),
+            "input.csv",
+            Ledger::new()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn validate_with_the_run_options() {
+        let dir = std::env::temp_dir().join(format!("crab-validate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv").to_string_lossy().into_owned();
+        let seen = dir.join("seen.ids").to_string_lossy().into_owned();
+        fs::write(
+            &input,
+            "type;client;tx;amount\ndeposit;1;1;1.0\nadjustment;1;2;-0.5\n",
+        )
+        .unwrap();
+        let options = || RunOptions {
+            process: ProcessOptions {
+                delimiter: Some(b';'),
+                ..ProcessOptions::default()
+            },
+            policy: Policy {
+                allow_adjustments: true,
+                ..Policy::default()
+            },
+            seen_ids: Some(seen.clone()),
+            ..RunOptions::default()
+        };
+        assert_eq!(validate_file(&input, options()).unwrap(), []);
+        // nothing was marked as seen, so validating again passes too
+        assert_eq!(validate_file(&input, options()).unwrap(), []);
+        assert!(!fs::exists(&seen).unwrap());
+
+        let errors = validate_file(&input, RunOptions::default()).unwrap();
+        assert!(matches!(
+            errors[..],
+            [RowError::Malformed { line: 2, .. }, ..]
+        ));
+        assert!(validate_file(
+            &dir.join("missing.csv").to_string_lossy().into_owned(),
+            options()
+        )
+        .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    struct AnySignature;
+
+    impl SignatureVerifier for AnySignature {
+        fn verify(&self, _: TransactionId, _: &Transaction, _: &Signature) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn signatures_are_read_when_verified() {
+        let signature = "01".repeat(32);
+        let input = format!(
+            "type,client,tx,amount,signature\n\
+            deposit,1,1,1.0,{signature}\n\
+            deposit,1,2,1.0,not-hex\n"
+        );
+        // Without a verifier the column is ignored.
+        assert!(validate_reader(input.as_bytes(), "input.csv", Ledger::new()).is_empty());
+
+        let mut ledger = Ledger::new();
+        ledger.set_signature_verifier(AnySignature);
+        let errors = validate_reader(input.as_bytes(), "input.csv", ledger);
+        assert_eq!(errors, [RowError::InvalidSignature { line: 3 }]);
+
+        let input = "type,client,tx\ndeposit,1,1\n";
+        let mut rows = read_rows(csv::Reader::from_reader(input.as_bytes()), "input.csv");
+        let record = rows.next().unwrap().record.unwrap();
+        let record = super::CsvTransactionRecord { tx: None, ..record };
+        assert_eq!(
+            record.into_transaction(Operation::Deposit, 2, false),
+            Err(RowError::Malformed {
+                line: 2,
+                message: "missing transaction id".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn report_amounts_past_the_precision() {
+        let input = "type,client,tx,amount\n\
+            deposit,1,1,0.12345678901234567890123456789\n";
+        let errors = validate_reader(input.as_bytes(), "input.csv", Ledger::new());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .contains("has more precision than the 28 digits an amount holds"));
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dead_letters_keep_processing() {
+        let dead_letters = SharedBuffer::default();
+        let options = ProcessOptions {
+            dead_letters: Some(Box::new(dead_letters.clone())),
+            ..ProcessOptions::default()
+        };
+        let ledger = process_file_with(
+            &"tests/data/01-bad_record-input.csv".to_string(),
+            Ledger::new(),
+            options,
+        );
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(1.0));
+        let dead_letters = String::from_utf8(dead_letters.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = dead_letters.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].starts_with("deposits,1,2,2.0,line 3: unknown transaction type 'deposits'")
+        );
+        assert!(lines[1].starts_with("\"line 4: CSV error"));
+        assert!(lines[2].starts_with("withdrawal,1,3,3.0,\"line 5: AccountError"));
+    }
+
+    // Unavailable for the first `failures` screenings, clear afterwards.
+    struct FlakyScreening {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    impl ScreeningProvider for FlakyScreening {
+        fn screen(&self, _: TransactionId, _: &Transaction) -> ScreeningOutcome {
+            match self.calls.fetch_add(1, Ordering::Relaxed) < self.failures {
+                true => ScreeningOutcome::Unavailable,
+                false => ScreeningOutcome::Clear,
+            }
+        }
+    }
+
+    #[test]
+    fn retry_rows_after_transient_failures() {
+        let process = |max_retries| {
+            let mut ledger = Ledger::new();
+            ledger.set_screening_provider(FlakyScreening {
+                failures: 2,
+                calls: AtomicU32::new(0),
+            });
+            let dead_letters = SharedBuffer::default();
+            let options = ProcessOptions {
+                dead_letters: Some(Box::new(dead_letters.clone())),
+                retry: RowRetry {
+                    max_retries,
+                    backoff: Duration::ZERO,
+                },
+                ..ProcessOptions::default()
+            };
+            let ledger = process_file_with(
+                &"tests/data/02-sample-input.csv".to_string(),
+                ledger,
+                options,
+            );
+            let dead_letters = String::from_utf8(dead_letters.0.lock().unwrap().clone()).unwrap();
+            (ledger, dead_letters)
+        };
+
+        let (ledger, dead_letters) = process(2);
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(1.5));
+        assert!(dead_letters.starts_with("withdrawal,2,5,3.0,"));
+        assert_eq!(dead_letters.lines().count(), 1);
+
+        // one retry isn't enough for the first deposit
+        let (ledger, dead_letters) = process(1);
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(0.5));
+        assert!(dead_letters.starts_with("deposit,1,1,1.0,line 2: ScreeningUnavailable"));
+    }
+
+    #[test]
+    fn stop_reading_after_shutdown() {
+        let health = Arc::new(HealthMonitor::default());
+        health.request_shutdown();
+        let options = ProcessOptions {
+            health: Some(health.clone()),
+            ..ProcessOptions::default()
+        };
+        let ledger = process_file_with(
+            &"tests/data/02-sample-input.csv".to_string(),
+            Ledger::new(),
+            options,
+        );
+        assert!(ledger.account(ClientId(1)).is_none());
+        assert_eq!(health.report().last_applied, 0);
+    }
+
+    #[test]
+    fn read_protobuf_messages() {
+        let mut input = Vec::new();
+        let deposit = Transaction::new(ClientId(1), num!(2.5), Operation::Deposit);
+        let dispute = Transaction::new(ClientId(1), num!(0), Operation::Dispute);
+        for (id, transaction) in [(1, deposit), (1, dispute)] {
+            let message = wire::encode_transaction(TransactionId(id), &transaction);
+            wire::write_delimited(&mut input, &message).unwrap();
+        }
+        wire::write_delimited(&mut input, &[0x08, 0x09]).unwrap();
+        // a length running past the end of the stream
+        input.push(0x10);
+
+        let mut ledger = Ledger::new();
+        let results: Vec<_> = read_messages(input.as_slice())
+            .map(|row| apply_row(&mut ledger, row, None, &OperationRegistry::new()))
+            .collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[..2], [Ok(()), Ok(())]);
+        assert!(matches!(
+            results[2],
+            Err(RowError::Malformed { line: 3, .. })
+        ));
+        assert!(matches!(
+            results[3],
+            Err(RowError::Malformed { line: 4, .. })
+        ));
+        assert_eq!(ledger.account(ClientId(1)).unwrap().held(), num!(2.5));
+    }
+
+    #[test]
+    fn protobuf_dead_letters_keep_every_column() {
+        let transfer = Transaction::new(
+            ClientId(1),
+            num!(2.5),
+            Operation::Transfer { to: ClientId(2) },
+        )
+        .with_signature(Signature([0xab; 32]));
+        let mut input = Vec::new();
+        let message = wire::encode_transaction(TransactionId(7), &transfer);
+        wire::write_delimited(&mut input, &message).unwrap();
+        let row = read_messages(input.as_slice()).next().unwrap();
+        let signature = "ab".repeat(32);
+        assert_eq!(
+            row.fields,
+            vec!["transfer", "1", "7", "2.5", &signature, "", "", "", "2"]
+        );
+    }
+
+    #[test]
+    fn amounts_in_minor_units() {
+        let input = "type,client,tx,amount,currency\n\
+            open,1,1,,JPY\n\
+            open,2,2,,KWD\n\
+            deposit,1,3,1500,\n\
+            deposit,2,4,1500,\n\
+            deposit,3,5,1500,\n\
+            deposit,3,6,15.00,\n";
+        let mut ledger = Ledger::new();
+        let results: Vec<_> = read_rows(csv::Reader::from_reader(input.as_bytes()), "feed.csv")
+            .map(|row| {
+                let row = row.in_minor_units(&ledger);
+                apply_row(&mut ledger, row, None, &OperationRegistry::new())
+            })
+            .collect();
+        assert!(matches!(
+            results[5],
+            Err(RowError::Malformed { line: 7, .. })
+        ));
+        let available = |client| ledger.account(ClientId(client)).unwrap().available();
+        assert_eq!(available(1), num!(1500));
+        assert_eq!(available(2), num!(1.5));
+        assert_eq!(available(3), num!(15));
+    }
+
+    #[test]
+    fn amounts_in_a_locale() {
+        let input = "type;client;tx;amount\n\
+            deposit;1;1;1.234,50\n\
+            deposit;1;2;1\u{2009}000\n\
+            withdrawal;1;3;0,5\n\
+            deposit;1;4;1,2,3\n";
+        let reader = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .from_reader(input.as_bytes());
+        let mut ledger = Ledger::new();
+        let results: Vec<_> = read_rows_in(reader, "feed.csv", Some(AmountLocale::European))
+            .map(|row| apply_row(&mut ledger, row, None, &OperationRegistry::new()))
+            .collect();
+        assert_eq!(
+            results[3],
+            Err(RowError::Malformed {
+                line: 5,
+                message: "invalid amount '1,2,3'".to_string()
+            })
+        );
+        let account = ledger.account(ClientId(1)).unwrap();
+        assert_eq!(account.available(), num!(2234));
+
+        // The locale leaves the delimiter alone.
+        let path = std::env::temp_dir().join(format!("crab-locale-{}.csv", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        for (input, delimiter) in [
+            ("type,client,tx,amount\ndeposit,1,1,\"1.234,50\"\n", None),
+            ("type;client;tx;amount\ndeposit;1;1;1.234,50\n", Some(b';')),
+        ] {
+            std::fs::write(&path, input).unwrap();
+            let options = ProcessOptions {
+                delimiter,
+                amount_locale: Some(AmountLocale::European),
+                ..ProcessOptions::default()
+            };
+            let ledger = process_file_with(&path, Ledger::new(), options);
+            let account = ledger.account(ClientId(1)).unwrap();
+            assert_eq!(account.available(), num!(1234.5));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn derive_missing_ids() {
+        let input = "type,client,tx,amount\ndeposit,1,,1.0\ndeposit,1,,1.0\n";
+        let mut ledger = Ledger::new();
+        assert!(validate_reader(input.as_bytes(), "feed.csv", Ledger::new()).is_empty());
+        for row in read_rows(csv::Reader::from_reader(input.as_bytes()), "feed.csv") {
+            assert_eq!(
+                apply_row(&mut ledger, row, None, &OperationRegistry::new()),
+                Ok(())
+            );
+        }
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(2.0));
+        // replaying the same feed derives the same ids, so nothing is applied twice
+        for row in read_rows(csv::Reader::from_reader(input.as_bytes()), "feed.csv") {
+            assert_eq!(
+                apply_row(&mut ledger, row, None, &OperationRegistry::new()),
+                Ok(())
+            );
+        }
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(2.0));
+    }
+
+    // The merged ledger and the range each partition ended up with.
+    fn run_partitioned(input: &str, parts: u64) -> (Vec<(u64, u64)>, Ledger) {
+        let len = input.len() as u64;
+        let partitions: Vec<_> = (0..parts)
+            .map(|part| {
+                let reader = io::Cursor::new(input.as_bytes());
+                let (start, end) = (part * len / parts, (part + 1) * len / parts);
+                process_range(reader, "input.csv", start, end, false).unwrap()
+            })
+            .collect();
+        let ranges = partitions.iter().map(|p| (p.start, p.end)).collect();
+        (ranges, merge_partitions(partitions, Ledger::new()))
+    }
+
+    fn run_sequential(input: &str) -> Ledger {
+        let mut ledger = Ledger::new();
+        for row in read_rows(csv::Reader::from_reader(input.as_bytes()), "input.csv") {
+            let _ = apply_row(&mut ledger, row, None, &OperationRegistry::new());
+        }
+        ledger
+    }
+
+    #[test]
+    fn partitioned_run_matches_full_run() {
+        let input = "type,client,tx,amount\n\
+            deposit,1,1,10.0\n\
+            deposit,2,2,4.0\n\
+            withdrawal,1,3,3.0\n\
+            dispute,1,1,\n\
+            deposit,2,4,1.0\n\
+            chargeback,1,1,\n";
+        let len = input.len() as u64;
+        let partitions: Vec<_> = [0, len / 3, 2 * len / 3, len]
+            .windows(2)
+            .map(|range| {
+                let reader = io::Cursor::new(input.as_bytes());
+                process_range(reader, "input.csv", range[0], range[1], false).unwrap()
+            })
+            .collect();
+        assert_eq!(partitions.iter().map(|p| p.rows).sum::<u64>(), 6);
+        assert_eq!(partitions[0].end, partitions[1].start);
+        assert_eq!(partitions[2].end, len);
+
+        let merged = merge_partitions(partitions, Ledger::new());
+        let full = run_sequential(input);
+        for client in [1, 2] {
+            assert_eq!(
+                merged.account(ClientId(client)),
+                full.account(ClientId(client))
+            );
+        }
+        assert!(merged.account(ClientId(1)).unwrap().locked());
+    }
+
+    #[test]
+    fn partitioned_run_keeps_input_order_across_partitions() {
+        // Client 1 is locked by a chargeback before it deposits and
+        // withdraws again, and client 2 withdraws more than it has before its
+        // deposit arrives, each across a partition boundary. The memo of the
+        // last deposit has a newline in it.
+        let input = "type,client,tx,amount,memo\n\
+            deposit,1,1,10.0,\n\
+            deposit,1,2,5.0,\n\
+            withdrawal,2,3,5.0,\n\
+            dispute,1,1,,\n\
+            chargeback,1,1,,\n\
+            deposit,2,4,4.0,\n\
+            deposit,1,5,3.0,\n\
+            withdrawal,1,6,3.0,\n\
+            deposit,2,7,2.0,\"two\nlines, \"\"quoted\"\"\"\n";
+        let full = run_sequential(input);
+        for parts in 1..=8 {
+            let (ranges, merged) = run_partitioned(input, parts);
+            for window in ranges.windows(2) {
+                assert_eq!(window[0].1, window[1].0);
+            }
+            for client in [1, 2] {
+                assert_eq!(
+                    merged.account(ClientId(client)),
+                    full.account(ClientId(client)),
+                    "{parts} partitions"
+                );
+            }
+        }
+        assert!(full.account(ClientId(1)).unwrap().locked());
+        assert_eq!(full.account(ClientId(1)).unwrap().available(), num!(8.0));
+        assert_eq!(full.account(ClientId(2)).unwrap().available(), num!(6.0));
+    }
+
+    #[test]
+    fn custom_operations() {
+        let mut operations = OperationRegistry::new();
+        operations.alias("credit", Operation::Deposit);
+        // a bonus is only paid to existing accounts, as a deposit of the amount
+        operations.register("bonus", |ledger, transaction_id, bonus| {
+            ledger
+                .account(bonus.client_id)
+                .ok_or(TransactionError::UnknownClientId(bonus.client_id))?;
+            let deposit = Transaction::new(bonus.client_id, bonus.amount, Operation::Deposit);
+            ledger.apply_transaction(transaction_id, &deposit)
+        });
+        let input = "type,client,tx,amount\n\
+            bonus,1,1,5.0\n\
+            credit,1,2,2.0\n\
+            bonus,1,3,5.0\n\
+            cashback,1,4,1.0\n";
+        let mut ledger = Ledger::new();
+        let errors: Vec<_> = read_rows(csv::Reader::from_reader(input.as_bytes()), "input.csv")
+            .filter_map(|row| apply_row(&mut ledger, row, None, &operations).err())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                RowError::Rejected {
+                    line: 2,
+                    error: TransactionError::UnknownClientId(ClientId(1)),
+                },
+                RowError::Malformed {
+                    line: 5,
+                    message: "unknown transaction type 'cashback'".to_string(),
+                },
+            ]
+        );
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(7.0));
+    }
+
+    #[test]
+    fn queue_statistics() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let health = Arc::new(HealthMonitor::default());
+        let options = ProcessOptions {
+            health: Some(health.clone()),
+            queue_capacity: Some(1),
+            drop_when_full: true,
+            queue_observer: Some(Box::new(move |event| recorded.lock().unwrap().push(event))),
+            ..ProcessOptions::default()
+        };
+        process_file_with(
+            &"tests/data/03-10k_records-input.csv".to_string(),
+            Ledger::new(),
+            options,
+        );
+        let report = health.report();
+        let events = events.lock().unwrap();
+        assert_eq!(report.queue_depth, 0);
+        assert_eq!(report.last_applied + report.dropped, 99987);
+        let dropped = events
+            .iter()
+            .filter(|event| matches!(event, QueueEvent::Dropped { .. }))
+            .count();
+        assert_eq!(dropped as u64, report.dropped);
+        assert_eq!(events.first(), Some(&QueueEvent::HighWaterMark(1)));
+        let high_water_mark = events.iter().rev().find_map(|event| match event {
+            QueueEvent::HighWaterMark(depth) => Some(*depth),
+            _ => None,
+        });
+        assert_eq!(high_water_mark, Some(report.high_water_mark));
+    }
+
+    #[test]
+    fn open_rows_carry_settings() {
+        let input = "type,client,tx,amount,currency,tier,overdraft_limit\n\
+            open,1,1,,eur,premium,50\n\
+            withdrawal,1,2,20.0,,,\n\
+            open,2,3,,EURO,,\n";
+        let mut ledger = Ledger::new();
+        let errors: Vec<_> = read_rows(csv::Reader::from_reader(input.as_bytes()), "input.csv")
+            .filter_map(|row| apply_row(&mut ledger, row, None, &OperationRegistry::new()).err())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![RowError::Malformed {
+                line: 4,
+                message: "invalid currency 'EURO'".to_string()
+            }]
+        );
+        let settings = ledger.account_settings(ClientId(1)).unwrap();
+        assert_eq!(settings.currency.unwrap().as_str(), "EUR");
+        assert_eq!(settings.overdraft_limit, Some(num!(50)));
+        assert_eq!(
+            ledger.account(ClientId(1)).unwrap().available(),
+            num!(-20.0)
+        );
     }
 }
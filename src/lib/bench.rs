@@ -0,0 +1,225 @@
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use super::app::{apply_row, read_rows};
+use super::ledger::{Ledger, OperationRegistry};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BenchOptions {
+    pub clients: u16,
+    pub transactions: u64,
+    // The share of rows disputing an earlier deposit. Half as many resolve
+    // one of those disputes.
+    pub dispute_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            clients: 1000,
+            transactions: 1_000_000,
+            dispute_rate: 0.01,
+            seed: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchReport {
+    pub transactions: u64,
+    pub rejected: u64,
+    pub elapsed: Duration,
+    // Time to read and apply one row, at the 50th, 90th, 99th and 100th
+    // percentiles.
+    pub latencies: [Duration; 4],
+}
+
+impl BenchReport {
+    pub fn throughput(&self) -> f64 {
+        self.transactions as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "transactions  {}", self.transactions)?;
+        writeln!(f, "rejected      {}", self.rejected)?;
+        writeln!(f, "elapsed       {:.3?}", self.elapsed)?;
+        writeln!(f, "throughput    {:.0}/s", self.throughput())?;
+        for (name, latency) in ["p50", "p90", "p99", "max"].iter().zip(self.latencies) {
+            writeln!(f, "latency {name}   {latency:.1?}")?;
+        }
+        Ok(())
+    }
+}
+
+// xorshift64*: enough to spread load over clients, and the same for a seed.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound.max(1)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64 <= probability
+    }
+}
+
+// Synthetic transactions as CSV, produced a line at a time as they're read
+// so runs of any size fit in memory: mostly deposits, some withdrawals, and
+// disputes and resolves of earlier deposits at the requested rate.
+pub struct Generator {
+    options: BenchOptions,
+    random: Random,
+    written: u64,
+    // The last deposit of each client, and the last one disputed.
+    deposits: Vec<Option<u32>>,
+    disputed: Vec<Option<u32>>,
+    line: Vec<u8>,
+    position: usize,
+}
+
+impl Generator {
+    pub fn new(options: BenchOptions) -> Self {
+        let clients = usize::from(options.clients.max(1));
+        Self {
+            options,
+            random: Random(options.seed.max(1)),
+            written: 0,
+            deposits: vec![None; clients],
+            disputed: vec![None; clients],
+            line: b"type,client,tx,amount\n".to_vec(),
+            position: 0,
+        }
+    }
+
+    fn next_line(&mut self) {
+        let id = self.written as u32 + 1;
+        let client = self.random.below(self.deposits.len() as u64) as usize;
+        let rate = self.options.dispute_rate;
+        let (dispute, resolve) = (self.random.chance(rate), self.random.chance(rate / 2.0));
+        self.line.clear();
+        let line = match (self.deposits[client], self.disputed[client]) {
+            (Some(deposit), _) if dispute => {
+                self.deposits[client] = None;
+                self.disputed[client] = Some(deposit);
+                format!("dispute,{client},{deposit},\n")
+            }
+            (_, Some(disputed)) if resolve => {
+                self.disputed[client] = None;
+                format!("resolve,{client},{disputed},\n")
+            }
+            _ if self.random.below(4) == 0 => {
+                let amount = self.random.below(5_000);
+                format!(
+                    "withdrawal,{client},{id},{}.{:02}\n",
+                    amount / 100,
+                    amount % 100
+                )
+            }
+            _ => {
+                let amount = self.random.below(10_000) + 1;
+                self.deposits[client] = Some(id);
+                format!(
+                    "deposit,{client},{id},{}.{:02}\n",
+                    amount / 100,
+                    amount % 100
+                )
+            }
+        };
+        self.line.extend_from_slice(line.as_bytes());
+        self.written += 1;
+    }
+}
+
+impl io::Read for Generator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.line.len() {
+            if self.written == self.options.transactions {
+                return Ok(0);
+            }
+            self.next_line();
+            self.position = 0;
+        }
+        let count = buf.len().min(self.line.len() - self.position);
+        buf[..count].copy_from_slice(&self.line[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+// Reads and applies generated rows the way a run of the binary does, timing
+// each one.
+pub fn run(options: BenchOptions, mut ledger: Ledger) -> BenchReport {
+    let operations = OperationRegistry::new();
+    let mut rows = read_rows(csv::Reader::from_reader(Generator::new(options)), "bench");
+    let mut latencies = Vec::with_capacity(options.transactions as usize);
+    let mut rejected = 0;
+    let started = Instant::now();
+    loop {
+        let start = Instant::now();
+        let Some(row) = rows.next() else {
+            break;
+        };
+        if apply_row(&mut ledger, row, None, &operations).is_err() {
+            rejected += 1;
+        }
+        latencies.push(start.elapsed());
+    }
+    let elapsed = started.elapsed();
+    latencies.sort_unstable();
+    let percentile = |p: usize| match latencies.len() {
+        0 => Duration::ZERO,
+        len => latencies[(len * p / 100).min(len - 1)],
+    };
+    BenchReport {
+        transactions: latencies.len() as u64,
+        rejected,
+        elapsed,
+        latencies: [
+            percentile(50),
+            percentile(90),
+            percentile(99),
+            percentile(100),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use super::{run, BenchOptions, Generator};
+    use crate::ledger::Ledger;
+    use std::io::Read;
+
+    #[test]
+    fn generated_load() {
+        let options = BenchOptions {
+            clients: 10,
+            transactions: 500,
+            dispute_rate: 0.2,
+            seed: 7,
+        };
+        let mut input = String::new();
+        Generator::new(options).read_to_string(&mut input).unwrap();
+        let lines: Vec<&str> = input.lines().collect();
+        assert_eq!(lines.len(), 501);
+        assert_eq!(lines[0], "type,client,tx,amount");
+        assert!(lines.iter().any(|line| line.starts_with("dispute,")));
+        assert!(lines.iter().any(|line| line.starts_with("resolve,")));
+
+        let report = run(options, Ledger::new());
+        assert_eq!(report.transactions, 500);
+        assert!(report.rejected < 500);
+        assert!(report.latencies.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
@@ -0,0 +1,257 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::encryption::Keyring;
+use super::ledger::Ledger;
+
+// When to take a snapshot while processing: every `every` rows, every
+// `interval`, or whichever comes first when both are set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SnapshotSchedule {
+    pub every: Option<u64>,
+    pub interval: Option<Duration>,
+}
+
+impl SnapshotSchedule {
+    pub fn is_enabled(&self) -> bool {
+        self.every.is_some() || self.interval.is_some()
+    }
+}
+
+// Takes snapshots of a ledger as it runs so a restart only has to replay the
+// journal written since the last one. The journal file is brought up to date
+// first, then a copy of the ledger is written out on another thread while
+// processing carries on. Snapshots are written to a temporary file and moved
+// into place, so a crash never leaves a partial one behind.
+pub struct Snapshotter {
+    snapshot: String,
+    journal: Option<String>,
+    schedule: SnapshotSchedule,
+    rows: u64,
+    last: Instant,
+    // The last sequence in the journal file, none until this run creates it.
+    journaled: Option<u64>,
+    writer: Option<thread::JoinHandle<io::Result<()>>>,
+    taken: u64,
+    keyring: Option<Keyring>,
+}
+
+impl Snapshotter {
+    pub fn new(snapshot: &str, journal: Option<&str>, schedule: SnapshotSchedule) -> Self {
+        Snapshotter {
+            snapshot: snapshot.to_string(),
+            journal: journal.map(str::to_string),
+            schedule,
+            rows: 0,
+            last: Instant::now(),
+            journaled: None,
+            writer: None,
+            taken: 0,
+            keyring: None,
+        }
+    }
+
+    // Encrypts the snapshot and the journal. While the keyring is rotating,
+    // the first checkpoint rewrites the whole journal under the new key
+    // instead of appending to it, and the previous key is retired.
+    pub fn with_keyring(mut self, keyring: Option<Keyring>) -> Self {
+        self.keyring = keyring;
+        self
+    }
+
+    // The journal file already holds every entry up to `sequence`, as after
+    // recovering from it, and is appended to rather than replaced.
+    pub fn resume_journal(mut self, sequence: u64) -> Self {
+        self.journaled = Some(sequence);
+        self
+    }
+
+    // Called after every row. Returns whether a snapshot was started.
+    pub fn tick(&mut self, ledger: &Ledger) -> io::Result<bool> {
+        self.rows += 1;
+        let due = self.schedule.every.is_some_and(|every| self.rows >= every)
+            || self
+                .schedule
+                .interval
+                .is_some_and(|interval| self.last.elapsed() >= interval);
+        if due {
+            self.checkpoint(ledger)?;
+        }
+        Ok(due)
+    }
+
+    pub fn checkpoint(&mut self, ledger: &Ledger) -> io::Result<()> {
+        self.rows = 0;
+        self.last = Instant::now();
+        self.wait()?;
+        let rotating = self.keyring.as_ref().is_some_and(Keyring::is_rotating);
+        if let (Some(path), Some(journal)) = (&self.journal, ledger.journal()) {
+            let keyring = self.keyring.as_ref();
+            let file = match self.journaled.filter(|_| !rotating) {
+                Some(sequence) => {
+                    let mut file = OpenOptions::new().append(true).open(path)?;
+                    write_sealed(keyring, &mut file, |writer| {
+                        journal.append_csv(writer, sequence)
+                    })?;
+                    file
+                }
+                None => {
+                    let mut file = fs::File::create(path)?;
+                    write_sealed(keyring, &mut file, |writer| journal.write_csv(writer))?;
+                    file
+                }
+            };
+            file.sync_all()?;
+            self.journaled = Some(journal.last_sequence());
+        }
+        let state = ledger.clone_state();
+        let path = self.snapshot.clone();
+        let keyring = self.keyring.clone();
+        self.writer = Some(thread::spawn(move || {
+            write_snapshot(&path, &state, keyring.as_ref())
+        }));
+        if let Some(keyring) = self.keyring.as_mut().filter(|_| rotating) {
+            keyring.retire();
+        }
+        self.taken += 1;
+        Ok(())
+    }
+
+    pub fn taken(&self) -> u64 {
+        self.taken
+    }
+
+    // Waits for the snapshot being written, if any, and returns how many
+    // were taken.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.wait()?;
+        Ok(self.taken)
+    }
+
+    fn wait(&mut self) -> io::Result<()> {
+        match self.writer.take() {
+            Some(writer) => writer
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("snapshot writer panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+// Writes what `write` produces as it is, or sealed in one frame with the
+// keyring's current key.
+pub(crate) fn write_sealed<W: Write>(
+    keyring: Option<&Keyring>,
+    mut writer: W,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    match keyring {
+        Some(keyring) => {
+            let mut plaintext = Vec::new();
+            write(&mut plaintext)?;
+            keyring.seal(writer, &plaintext)
+        }
+        None => write(&mut writer),
+    }
+}
+
+fn write_snapshot(path: &str, ledger: &Ledger, keyring: Option<&Keyring>) -> io::Result<()> {
+    let partial = format!("{path}.tmp");
+    let mut file = fs::File::create(&partial)?;
+    write_sealed(keyring, &mut file, |writer| ledger.write_snapshot(writer))?;
+    file.flush()?;
+    file.sync_all()?;
+    fs::rename(partial, path)
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use super::{SnapshotSchedule, Snapshotter};
+    use crate::account::{num, ClientId};
+    use crate::ledger::{Ledger, Policy};
+    use crate::transactions::{Operation, Transaction, TransactionId};
+    use std::fs;
+
+    #[test]
+    fn snapshots_every_n_rows() {
+        let dir = std::env::temp_dir().join(format!("crab-checkpoint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let snapshot = dir.join("ledger.snapshot").to_string_lossy().into_owned();
+        let journal = dir.join("journal.csv").to_string_lossy().into_owned();
+        let schedule = SnapshotSchedule {
+            every: Some(2),
+            interval: None,
+        };
+        let mut snapshotter = Snapshotter::new(&snapshot, Some(&journal), schedule);
+        let mut ledger = Ledger::new();
+        ledger.enable_journal();
+        for id in 1..=5 {
+            let deposit = Transaction::new(ClientId(1), num!(1), Operation::Deposit);
+            assert!(ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .is_ok());
+            assert_eq!(snapshotter.tick(&ledger).unwrap(), id % 2 == 0);
+        }
+        assert_eq!(snapshotter.finish().unwrap(), 2);
+
+        // The fifth deposit came after the last checkpoint and is lost, the
+        // others are recovered from the snapshot and the journal.
+        let recovered = Ledger::recover(
+            Some(fs::File::open(&snapshot).unwrap()),
+            fs::File::open(&journal).unwrap(),
+            Policy::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.account(ClientId(1)).unwrap().available(), num!(4));
+        assert_eq!(recovered.journal().unwrap().last_sequence(), 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn rotate_keys_on_snapshot() {
+        use crate::encryption::Keyring;
+
+        let dir = std::env::temp_dir().join(format!("crab-rotation-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let snapshot = dir.join("ledger.snapshot").to_string_lossy().into_owned();
+        let journal = dir.join("journal.csv").to_string_lossy().into_owned();
+        let mut ledger = Ledger::new();
+        ledger.enable_journal();
+        let deposit = Transaction::new(ClientId(1), num!(1), Operation::Deposit);
+        let old = Keyring::new([1; 32]);
+        let mut snapshotter =
+            Snapshotter::new(&snapshot, Some(&journal), SnapshotSchedule::default())
+                .with_keyring(Some(old.clone()));
+        assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+        snapshotter.checkpoint(&ledger).unwrap();
+        snapshotter.finish().unwrap();
+
+        // The journal written under the old key is rewritten whole under
+        // the new one, rather than appended to.
+        let mut rotating = old.clone();
+        rotating.rotate([2; 32]);
+        let mut snapshotter =
+            Snapshotter::new(&snapshot, Some(&journal), SnapshotSchedule::default())
+                .with_keyring(Some(rotating))
+                .resume_journal(1);
+        assert!(ledger.apply_transaction(TransactionId(2), &deposit).is_ok());
+        snapshotter.checkpoint(&ledger).unwrap();
+        snapshotter.finish().unwrap();
+
+        let new = Keyring::new([2; 32]);
+        let recovered = Ledger::recover(
+            Some(std::io::Cursor::new(
+                new.open(fs::File::open(&snapshot).unwrap()).unwrap(),
+            )),
+            std::io::Cursor::new(new.open(fs::File::open(&journal).unwrap()).unwrap()),
+            Policy::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.account(ClientId(1)).unwrap().available(), num!(2));
+        assert!(old.open(fs::File::open(&journal).unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
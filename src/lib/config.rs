@@ -0,0 +1,602 @@
+use std::fs;
+
+use toml_edit::{Document, Item};
+
+use super::account::{AmountLocale, ClientId, Number};
+use super::app::InputFormat;
+use super::ledger::{
+    AlertRules, AmountRange, ChargebackExcess, Finalizer, GroupId, HeldAccrual, IdleSweep,
+    OverflowPolicy, Policy, TierLimits,
+};
+use super::report::{ClientIdFormat, ReportFormat};
+use super::transactions::Operation;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    Io(String),
+    Syntax(String),
+    InvalidValue(String),
+}
+
+// Settings read from a TOML file. Every field is optional so that command
+// line flags can take precedence over whatever the file sets:
+//
+//   debug = false
+//   [input]
+//   max_rate = 1000
+//   dead_letters = "rejected.csv"
+//   max_retries = 3
+//   retry_backoff_ms = 100
+//   queue_capacity = 10000
+//   drop_when_full = false
+//   format = "csv"
+//   layout = "core-banking.layout.toml"
+//   minor_units = false
+//   amount_locale = "eu"
+//   delimiter = ";"
+//   [input.aliases]
+//   credit = "deposit"
+//   [policy]
+//   max_open_disputes = 5
+//   max_transactions_per_client = 1000
+//   allow_adjustments = true
+//   allow_unlocks = true
+//   dormant_after_days = 365
+//   deposit_overflow = "review"
+//   chargeback_excess = "clamp"
+//   require_open_account = true
+//   reject_redisputes = true
+//   dispute_withdrawals = true
+//   screening_fail_open = false
+//   replay_window = 100000
+//   [policy.basic]
+//   max_transaction = "10000"
+//   [policy.held_accrual]
+//   after_days = 30
+//   daily_rate = "-0.001"
+//   [policy.idle_sweep]
+//   after_days = 1095
+//   sweep_account = 9999
+//   [policy.withdrawal]
+//   min_amount = "0.01"
+//   max_amount = "10000"
+//   [output]
+//   format = "json"
+//   decimal_separator = ","
+//   thousands_separator = "."
+//   client_ids = "hashed"
+//   client_id_key = "secret"
+//   threads = 4
+//   template = "report.tmpl"
+//   held_aging = "held-aging.csv"
+//   dispute_letters = "dispute-letters.csv"
+//   [journal]
+//   path = "journal.csv"
+//   [snapshot]
+//   path = "ledger.snapshot"
+//   every = 100000
+//   interval = 300
+//   [encryption]
+//   key = "<64 hex digits>"
+//   previous_key = "<64 hex digits>"
+//   [health]
+//   addr = "127.0.0.1:8080"
+//   max_queue_depth = 10000
+//   max_journal_lag = 100000
+//   [outbox]
+//   webhook = "http://127.0.0.1:9000/events"
+//   state = "journal.outbox"
+//   max_attempts = 5
+//   [seen_ids]
+//   path = "seen.ids"
+//   [finalize]
+//   resolve_disputes_after_days = 90
+//   accrue_held_funds = true
+//   sweep_idle_accounts = true
+//   close_period = true
+//   [portfolios]
+//   mapping = "portfolios.csv"
+//   report = "portfolio-totals.csv"
+//   [alerts]
+//   path = "alerts.csv"
+//   negative_available = true
+//   negative_held = true
+//   locked = true
+//   frozen = true
+//   held_above = "1000"
+//   dormant_days = 90
+//   [[groups]]
+//   id = 1
+//   holder = 10
+//   members = [11, 12]
+//   withdrawal_limit = "500"
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub debug: Option<bool>,
+    pub max_rate: Option<u32>,
+    pub dead_letters: Option<String>,
+    // Rows turned down with a retryable error are applied again up to
+    // `max_retries` times, backing off from `retry_backoff_ms`.
+    pub max_retries: Option<u32>,
+    pub retry_backoff_ms: Option<u64>,
+    pub queue_capacity: Option<usize>,
+    pub drop_when_full: Option<bool>,
+    // CSV or protobuf; fixed-width input is set with `layout` instead.
+    pub input_format: Option<InputFormat>,
+    // A fixed-width record layout, see fixed_width.rs.
+    pub layout: Option<String>,
+    pub minor_units: Option<bool>,
+    pub amount_locale: Option<AmountLocale>,
+    // Of CSV input, an ASCII character.
+    pub delimiter: Option<u8>,
+    pub policy: Policy,
+    pub format: Option<ReportFormat>,
+    pub decimal_separator: Option<char>,
+    pub thousands_separator: Option<char>,
+    pub client_ids: Option<ClientIdFormat>,
+    pub report_threads: Option<usize>,
+    pub template: Option<String>,
+    pub journal: Option<String>,
+    pub snapshot: Option<String>,
+    pub snapshot_every: Option<u64>,
+    // In seconds.
+    pub snapshot_interval: Option<u64>,
+    // Hex keys for encrypted snapshots and journals; files under the
+    // previous one are still read and rewritten under the new one.
+    pub encryption_key: Option<String>,
+    pub previous_encryption_key: Option<String>,
+    pub health_addr: Option<String>,
+    pub max_queue_depth: Option<usize>,
+    pub max_journal_lag: Option<u64>,
+    pub outbox_webhook: Option<String>,
+    pub outbox_state: Option<String>,
+    pub outbox_max_attempts: Option<u32>,
+    pub seen_ids: Option<String>,
+    // Run at the end of the run in this order: expired disputes, accrual,
+    // idle sweep, period close.
+    pub finalizers: Vec<Finalizer>,
+    pub groups: Vec<GroupConfig>,
+    pub alerts: Option<String>,
+    pub held_aging: Option<String>,
+    pub dispute_letters: Option<String>,
+    pub portfolios: Option<String>,
+    pub portfolio_report: Option<String>,
+    pub alert_rules: AlertRules,
+    pub operation_aliases: Vec<(String, Operation)>,
+}
+
+// An account group and the members to add to it, each with the same
+// withdrawal limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupConfig {
+    pub id: GroupId,
+    pub holder: ClientId,
+    pub members: Vec<ClientId>,
+    pub withdrawal_limit: Option<Number>,
+}
+
+static MISSING: Item = Item::None;
+
+pub(crate) fn section<'a>(item: &'a Item, key: &str) -> &'a Item {
+    item.get(key).unwrap_or(&MISSING)
+}
+
+pub(crate) fn invalid(key: &str) -> ConfigError {
+    ConfigError::InvalidValue(key.to_string())
+}
+
+pub(crate) fn string(item: &Item, key: &str) -> Result<Option<String>, ConfigError> {
+    match item.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(|value| Some(value.to_string()))
+            .ok_or_else(|| invalid(key)),
+    }
+}
+
+pub(crate) fn boolean(item: &Item, key: &str) -> Result<Option<bool>, ConfigError> {
+    match item.get(key) {
+        None => Ok(None),
+        Some(value) => value.as_bool().map(Some).ok_or_else(|| invalid(key)),
+    }
+}
+
+pub(crate) fn integer<T: TryFrom<i64>>(item: &Item, key: &str) -> Result<Option<T>, ConfigError> {
+    match item.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_integer()
+            .and_then(|value| T::try_from(value).ok())
+            .map(Some)
+            .ok_or_else(|| invalid(key)),
+    }
+}
+
+fn character(item: &Item, key: &str) -> Result<Option<char>, ConfigError> {
+    let Some(value) = string(item, key)? else {
+        return Ok(None);
+    };
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(character), None) => Ok(Some(character)),
+        _ => Err(invalid(key)),
+    }
+}
+
+// Amounts are written as strings so they keep their exact decimal value, but
+// plain integers are accepted too.
+pub(crate) fn amount(item: &Item, key: &str) -> Result<Option<Number>, ConfigError> {
+    let Some(value) = item.get(key) else {
+        return Ok(None);
+    };
+    let amount = match (value.as_str(), value.as_integer()) {
+        (Some(amount), _) => amount.parse().ok(),
+        (_, Some(amount)) => Some(Number::from(amount)),
+        _ => None,
+    };
+    amount.map(Some).ok_or_else(|| invalid(key))
+}
+
+fn tier_limits(item: &Item) -> Result<TierLimits, ConfigError> {
+    Ok(TierLimits {
+        max_transaction: amount(item, "max_transaction")?,
+        max_daily_volume: amount(item, "max_daily_volume")?,
+        max_rolling_withdrawals: amount(item, "max_rolling_withdrawals")?,
+        max_balance: amount(item, "max_balance")?,
+    })
+}
+
+fn amount_range(item: &Item) -> Result<AmountRange, ConfigError> {
+    Ok(AmountRange {
+        min: amount(item, "min_amount")?,
+        max: amount(item, "max_amount")?,
+    })
+}
+
+// Both keys or neither.
+fn held_accrual(item: &Item) -> Result<Option<HeldAccrual>, ConfigError> {
+    let after_days = integer(item, "after_days")?;
+    let daily_rate = amount(item, "daily_rate")?;
+    match (after_days, daily_rate) {
+        (Some(after_days), Some(daily_rate)) => Ok(Some(HeldAccrual {
+            after_days,
+            daily_rate,
+        })),
+        (None, None) => Ok(None),
+        (None, Some(_)) => Err(invalid("after_days")),
+        (Some(_), None) => Err(invalid("daily_rate")),
+    }
+}
+
+// Both keys or neither.
+fn idle_sweep(item: &Item) -> Result<Option<IdleSweep>, ConfigError> {
+    let after_days = integer(item, "after_days")?;
+    let sweep_account = integer(item, "sweep_account")?;
+    match (after_days, sweep_account) {
+        (Some(after_days), Some(sweep_account)) => Ok(Some(IdleSweep {
+            after_days,
+            sweep_account: ClientId(sweep_account),
+        })),
+        (None, None) => Ok(None),
+        (None, Some(_)) => Err(invalid("after_days")),
+        (Some(_), None) => Err(invalid("sweep_account")),
+    }
+}
+
+fn finalizers(item: &Item) -> Result<Vec<Finalizer>, ConfigError> {
+    let mut finalizers = Vec::new();
+    if let Some(after_days) = integer(item, "resolve_disputes_after_days")? {
+        finalizers.push(Finalizer::ResolveExpiredDisputes { after_days });
+    }
+    if boolean(item, "accrue_held_funds")?.unwrap_or_default() {
+        finalizers.push(Finalizer::AccrueHeldFunds);
+    }
+    if boolean(item, "sweep_idle_accounts")?.unwrap_or_default() {
+        finalizers.push(Finalizer::SweepIdleAccounts);
+    }
+    if boolean(item, "close_period")?.unwrap_or_default() {
+        finalizers.push(Finalizer::ClosePeriod);
+    }
+    Ok(finalizers)
+}
+
+fn group(item: &Item) -> Result<GroupConfig, ConfigError> {
+    let members = match item.get("members") {
+        None => Vec::new(),
+        Some(members) => members
+            .as_array()
+            .ok_or_else(|| invalid("members"))?
+            .iter()
+            .map(|member| {
+                member
+                    .as_integer()
+                    .and_then(|member| u16::try_from(member).ok())
+                    .map(ClientId)
+                    .ok_or_else(|| invalid("members"))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(GroupConfig {
+        id: GroupId(integer(item, "id")?.ok_or_else(|| invalid("id"))?),
+        holder: ClientId(integer(item, "holder")?.ok_or_else(|| invalid("holder"))?),
+        members,
+        withdrawal_limit: amount(item, "withdrawal_limit")?,
+    })
+}
+
+// Other names accepted for the built-in operations in the input.
+fn aliases(item: &Item) -> Result<Vec<(String, Operation)>, ConfigError> {
+    let Some(aliases) = item.get("aliases") else {
+        return Ok(Vec::new());
+    };
+    aliases
+        .as_table_like()
+        .ok_or_else(|| invalid("aliases"))?
+        .iter()
+        .map(|(name, operation)| {
+            operation
+                .as_str()
+                .and_then(|operation| operation.parse().ok())
+                .map(|operation| (name.to_string(), operation))
+                .ok_or_else(|| invalid(name))
+        })
+        .collect()
+}
+
+fn groups(item: &Item) -> Result<Vec<GroupConfig>, ConfigError> {
+    let Some(groups) = item.get("groups") else {
+        return Ok(Vec::new());
+    };
+    groups
+        .as_array_of_tables()
+        .ok_or_else(|| invalid("groups"))?
+        .iter()
+        .map(|table| group(&Item::Table(table.clone())))
+        .collect()
+}
+
+impl Config {
+    pub fn read(path: &str) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|err| ConfigError::Io(err.to_string()))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let document: Document = text
+            .parse()
+            .map_err(|err: toml_edit::TomlError| ConfigError::Syntax(err.to_string()))?;
+        let root = document.as_item();
+        let input = section(root, "input");
+        let policy = section(root, "policy");
+        let alerts = section(root, "alerts");
+        let output = section(root, "output");
+
+        let format = match string(output, "format")?.as_deref() {
+            None => None,
+            Some("csv") => Some(ReportFormat::Csv),
+            Some("json") => Some(ReportFormat::Json),
+            Some(_) => return Err(invalid("format")),
+        };
+        let input_format = match string(input, "format")?.as_deref() {
+            None => None,
+            Some("csv") => Some(InputFormat::Csv),
+            Some("protobuf") => Some(InputFormat::Protobuf),
+            Some(_) => return Err(invalid("format")),
+        };
+        let deposit_overflow = match string(policy, "deposit_overflow")?.as_deref() {
+            None | Some("reject") => OverflowPolicy::Reject,
+            Some("saturate") => OverflowPolicy::Saturate,
+            Some("review") => OverflowPolicy::Review,
+            Some(_) => return Err(invalid("deposit_overflow")),
+        };
+        let chargeback_excess = match string(policy, "chargeback_excess")?.as_deref() {
+            None | Some("allow") => ChargebackExcess::Allow,
+            Some("clamp") => ChargebackExcess::Clamp,
+            Some("reject") => ChargebackExcess::Reject,
+            Some(_) => return Err(invalid("chargeback_excess")),
+        };
+        let client_ids = match string(output, "client_ids")?.as_deref() {
+            None => None,
+            Some("plain") => Some(ClientIdFormat::Plain),
+            Some("masked") => Some(ClientIdFormat::Masked),
+            Some("hashed") => {
+                let key =
+                    string(output, "client_id_key")?.ok_or_else(|| invalid("client_id_key"))?;
+                Some(ClientIdFormat::Hashed(key.into_bytes()))
+            }
+            Some(_) => return Err(invalid("client_ids")),
+        };
+
+        Ok(Config {
+            debug: boolean(root, "debug")?,
+            max_rate: integer(input, "max_rate")?,
+            dead_letters: string(input, "dead_letters")?,
+            max_retries: integer(input, "max_retries")?,
+            retry_backoff_ms: integer(input, "retry_backoff_ms")?,
+            queue_capacity: integer(input, "queue_capacity")?,
+            drop_when_full: boolean(input, "drop_when_full")?,
+            input_format,
+            layout: string(input, "layout")?,
+            minor_units: boolean(input, "minor_units")?,
+            amount_locale: string(input, "amount_locale")?
+                .map(|locale| locale.parse())
+                .transpose()
+                .map_err(|_| invalid("amount_locale"))?,
+            delimiter: character(input, "delimiter")?
+                .map(u8::try_from)
+                .transpose()
+                .map_err(|_| invalid("delimiter"))?,
+            policy: Policy {
+                max_open_disputes: integer(policy, "max_open_disputes")?,
+                max_transactions_per_client: integer(policy, "max_transactions_per_client")?,
+                allow_adjustments: boolean(policy, "allow_adjustments")?.unwrap_or_default(),
+                allow_unlocks: boolean(policy, "allow_unlocks")?.unwrap_or_default(),
+                dormant_after_days: integer(policy, "dormant_after_days")?,
+                basic: tier_limits(section(policy, "basic"))?,
+                verified: tier_limits(section(policy, "verified"))?,
+                premium: tier_limits(section(policy, "premium"))?,
+                deposit_amounts: amount_range(section(policy, "deposit"))?,
+                withdrawal_amounts: amount_range(section(policy, "withdrawal"))?,
+                adjustment_amounts: amount_range(section(policy, "adjustment"))?,
+                deposit_overflow,
+                chargeback_excess,
+                require_open_account: boolean(policy, "require_open_account")?.unwrap_or_default(),
+                reject_redisputes: boolean(policy, "reject_redisputes")?.unwrap_or_default(),
+                dispute_withdrawals: boolean(policy, "dispute_withdrawals")?.unwrap_or_default(),
+                screening_fail_open: boolean(policy, "screening_fail_open")?.unwrap_or_default(),
+                replay_window: integer(policy, "replay_window")?,
+                held_accrual: held_accrual(section(policy, "held_accrual"))?,
+                idle_sweep: idle_sweep(section(policy, "idle_sweep"))?,
+            },
+            format,
+            decimal_separator: character(output, "decimal_separator")?,
+            thousands_separator: character(output, "thousands_separator")?,
+            client_ids,
+            report_threads: integer(output, "threads")?,
+            template: string(output, "template")?,
+            journal: string(section(root, "journal"), "path")?,
+            snapshot: string(section(root, "snapshot"), "path")?,
+            snapshot_every: integer(section(root, "snapshot"), "every")?,
+            snapshot_interval: integer(section(root, "snapshot"), "interval")?,
+            encryption_key: string(section(root, "encryption"), "key")?,
+            previous_encryption_key: string(section(root, "encryption"), "previous_key")?,
+            health_addr: string(section(root, "health"), "addr")?,
+            max_queue_depth: integer(section(root, "health"), "max_queue_depth")?,
+            max_journal_lag: integer(section(root, "health"), "max_journal_lag")?,
+            outbox_webhook: string(section(root, "outbox"), "webhook")?,
+            outbox_state: string(section(root, "outbox"), "state")?,
+            outbox_max_attempts: integer(section(root, "outbox"), "max_attempts")?,
+            seen_ids: string(section(root, "seen_ids"), "path")?,
+            finalizers: finalizers(section(root, "finalize"))?,
+            groups: groups(root)?,
+            alerts: string(alerts, "path")?,
+            held_aging: string(output, "held_aging")?,
+            dispute_letters: string(output, "dispute_letters")?,
+            portfolios: string(section(root, "portfolios"), "mapping")?,
+            portfolio_report: string(section(root, "portfolios"), "report")?,
+            alert_rules: AlertRules {
+                negative_available: boolean(alerts, "negative_available")?.unwrap_or(true),
+                negative_held: boolean(alerts, "negative_held")?.unwrap_or(true),
+                locked: boolean(alerts, "locked")?.unwrap_or(true),
+                frozen: boolean(alerts, "frozen")?.unwrap_or(true),
+                held_above: amount(alerts, "held_above")?,
+                dormant_days: integer(alerts, "dormant_days")?,
+            },
+            operation_aliases: aliases(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::{Config, ConfigError, GroupConfig};
+    use crate::account::{num, ClientId};
+    use crate::ledger::{AmountRange, GroupId, OverflowPolicy, Policy, TierLimits};
+    use crate::report::{ClientIdFormat, ReportFormat};
+    use crate::transactions::Operation;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::parse(
+            r#"
+            debug = true
+            [input]
+            max_rate = 500
+            max_retries = 3
+            delimiter = ";"
+            [input.aliases]
+            credit = "deposit"
+            [policy]
+            max_open_disputes = 3
+            allow_adjustments = true
+            deposit_overflow = "saturate"
+            [policy.withdrawal]
+            min_amount = "0.01"
+            [policy.verified]
+            max_transaction = "2500.50"
+            max_balance = 10000
+            [output]
+            format = "json"
+            decimal_separator = ","
+            client_ids = "hashed"
+            client_id_key = "secret"
+            threads = 4
+            [journal]
+            path = "journal.csv"
+            [[groups]]
+            id = 1
+            holder = 10
+            members = [11, 12]
+            withdrawal_limit = "500"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                debug: Some(true),
+                max_rate: Some(500),
+                max_retries: Some(3),
+                delimiter: Some(b';'),
+                operation_aliases: vec![("credit".to_string(), Operation::Deposit)],
+                policy: Policy {
+                    max_open_disputes: Some(3),
+                    allow_adjustments: true,
+                    deposit_overflow: OverflowPolicy::Saturate,
+                    withdrawal_amounts: AmountRange {
+                        min: Some(num!(0.01)),
+                        max: None,
+                    },
+                    verified: TierLimits {
+                        max_transaction: Some(num!(2500.50)),
+                        max_balance: Some(num!(10000)),
+                        ..TierLimits::default()
+                    },
+                    ..Policy::default()
+                },
+                format: Some(ReportFormat::Json),
+                decimal_separator: Some(','),
+                client_ids: Some(ClientIdFormat::Hashed(b"secret".to_vec())),
+                report_threads: Some(4),
+                journal: Some("journal.csv".to_string()),
+                groups: vec![GroupConfig {
+                    id: GroupId(1),
+                    holder: ClientId(10),
+                    members: vec![ClientId(11), ClientId(12)],
+                    withdrawal_limit: Some(num!(500)),
+                }],
+                ..Config::default()
+            }
+        );
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+    }
+
+    #[test]
+    fn reject_invalid_values() {
+        assert_eq!(
+            Config::parse("[output]\nformat = \"xml\""),
+            Err(ConfigError::InvalidValue("format".to_string()))
+        );
+        assert_eq!(
+            Config::parse("[input]\nmax_rate = -1"),
+            Err(ConfigError::InvalidValue("max_rate".to_string()))
+        );
+        assert_eq!(
+            Config::parse("[output]\nclient_ids = \"hashed\""),
+            Err(ConfigError::InvalidValue("client_id_key".to_string()))
+        );
+        assert_eq!(
+            Config::parse("[policy.held_accrual]\nafter_days = 3"),
+            Err(ConfigError::InvalidValue("daily_rate".to_string()))
+        );
+        assert_eq!(
+            Config::parse("[[groups]]\nid = 1\nmembers = [2]"),
+            Err(ConfigError::InvalidValue("holder".to_string()))
+        );
+        assert!(matches!(
+            Config::parse("[output"),
+            Err(ConfigError::Syntax(_))
+        ));
+    }
+}
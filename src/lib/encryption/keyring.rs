@@ -0,0 +1,215 @@
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
+#[derive(Clone)]
+pub struct Keyring {
+    // The first key seals new frames, all of them open old ones.
+    keys: Vec<Key>,
+}
+
+pub type Key = [u8; 32];
+
+const MAGIC: &[u8; 8] = b"crabenc1";
+
+const FRAME_HEADER: usize = 8 + 8 + 12 + 4;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn key_id(key: &Key) -> [u8; 8] {
+    let mut id = [0; 8];
+    id.copy_from_slice(&Sha256::digest(key)[..8]);
+    id
+}
+
+fn parse_key(hex: &str) -> io::Result<Key> {
+    let digits: Option<Vec<u8>> = hex
+        .trim()
+        .chars()
+        .map(|digit| digit.to_digit(16).map(|digit| digit as u8))
+        .collect();
+    let mut key = [0; 32];
+    match digits {
+        Some(digits) if digits.len() == 2 * key.len() => {
+            for (byte, pair) in key.iter_mut().zip(digits.chunks(2)) {
+                *byte = pair[0] << 4 | pair[1];
+            }
+            Ok(key)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "encryption keys are 64 hex digits",
+        )),
+    }
+}
+
+impl Keyring {
+    pub fn new(key: Key) -> Self {
+        Keyring { keys: vec![key] }
+    }
+
+    // The current key and, while rotating away from it, the previous one.
+    pub fn from_hex(key: &str, previous: Option<&str>) -> io::Result<Self> {
+        let mut keyring = Keyring::new(parse_key(key)?);
+        if let Some(previous) = previous {
+            keyring.keys.push(parse_key(previous)?);
+        }
+        Ok(keyring)
+    }
+
+    // Seals with `key` from now on. Files sealed with the keys before still
+    // open until they are retired.
+    pub fn rotate(&mut self, key: Key) {
+        self.keys.insert(0, key);
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    // Forgets every key but the current one, once nothing sealed with them
+    // is left.
+    pub fn retire(&mut self) {
+        self.keys.truncate(1);
+    }
+
+    // Writes `plaintext` as one frame sealed with the current key.
+    pub fn seal<W: Write>(&self, mut writer: W, plaintext: &[u8]) -> io::Result<()> {
+        use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+        use aes_gcm::Aes256Gcm;
+
+        let key = &self.keys[0];
+        let length = u32::try_from(plaintext.len() + 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut header = Vec::with_capacity(FRAME_HEADER);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&key_id(key));
+        header.extend_from_slice(&nonce);
+        header.extend_from_slice(&length.to_be_bytes());
+        // The header is authenticated along with the contents.
+        let sealed = Aes256Gcm::new(key.into())
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        writer.write_all(&header)?;
+        writer.write_all(&sealed)?;
+        writer.flush()
+    }
+
+    // Reads every frame of `reader` and returns their contents joined
+    // together. A frame that doesn't open with any of the keys, has been
+    // tampered with or is cut short fails the whole read.
+    pub fn open<R: Read>(&self, mut reader: R) -> io::Result<Vec<u8>> {
+        use aes_gcm::aead::{Aead, KeyInit, Payload};
+        use aes_gcm::{Aes256Gcm, Nonce};
+
+        let mut contents = Vec::new();
+        loop {
+            let mut header = [0; FRAME_HEADER];
+            match reader.read(&mut header[..1])? {
+                0 => return Ok(contents),
+                _ => reader
+                    .read_exact(&mut header[1..])
+                    .map_err(|err| match err.kind() {
+                        io::ErrorKind::UnexpectedEof => invalid("encrypted file is cut short"),
+                        _ => err,
+                    })?,
+            }
+            if &header[..8] != MAGIC {
+                return Err(invalid("not an encrypted file"));
+            }
+            let key = self
+                .keys
+                .iter()
+                .find(|key| key_id(key)[..] == header[8..16])
+                .ok_or_else(|| invalid("encrypted with a key that isn't in the keyring"))?;
+            let nonce = Nonce::from_slice(&header[16..28]);
+            let length = u32::from_be_bytes(header[28..32].try_into().unwrap_or_default());
+            let mut sealed = Vec::new();
+            reader
+                .by_ref()
+                .take(u64::from(length))
+                .read_to_end(&mut sealed)?;
+            if sealed.len() != length as usize {
+                return Err(invalid("encrypted file is cut short"));
+            }
+            let plaintext = Aes256Gcm::new(key.into())
+                .decrypt(
+                    nonce,
+                    Payload {
+                        msg: &sealed,
+                        aad: &header,
+                    },
+                )
+                .map_err(|_| invalid("encrypted file failed authentication"))?;
+            contents.extend_from_slice(&plaintext);
+        }
+    }
+}
+
+#[cfg(test)]
+mod encryption_tests {
+    use super::Keyring;
+    use crate::account::{num, ClientId};
+    use crate::ledger::{Ledger, Policy};
+    use crate::transactions::{Operation, Transaction, TransactionId};
+    use std::io;
+
+    #[test]
+    fn frames_open_with_any_kept_key() {
+        let mut keyring = Keyring::new([1; 32]);
+        let mut file = Vec::new();
+        keyring.seal(&mut file, b"type,client\n").unwrap();
+        keyring.rotate([2; 32]);
+        keyring.seal(&mut file, b"deposit,1\n").unwrap();
+        assert!(!file
+            .windows(b"deposit".len())
+            .any(|window| window == b"deposit"));
+        assert_eq!(
+            keyring.open(&file[..]).unwrap(),
+            b"type,client\ndeposit,1\n"
+        );
+
+        keyring.retire();
+        let err = keyring.open(&file[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut tampered = Vec::new();
+        keyring.seal(&mut tampered, b"deposit,1\n").unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(keyring.open(&tampered[..]).is_err());
+        assert!(keyring.open(&tampered[..10]).is_err());
+        assert!(Keyring::from_hex("abc", None).is_err());
+    }
+
+    #[test]
+    fn recover_from_encrypted_files() {
+        let keyring = Keyring::new([7; 32]);
+        let mut ledger = Ledger::new();
+        ledger.enable_journal();
+        let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+        assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+        let (mut snapshot, mut journal) = (Vec::new(), Vec::new());
+        ledger.write_snapshot(&mut snapshot).unwrap();
+        ledger.journal().unwrap().write_csv(&mut journal).unwrap();
+        let (mut sealed_snapshot, mut sealed_journal) = (Vec::new(), Vec::new());
+        keyring.seal(&mut sealed_snapshot, &snapshot).unwrap();
+        keyring.seal(&mut sealed_journal, &journal).unwrap();
+
+        let recovered = Ledger::recover(
+            Some(io::Cursor::new(keyring.open(&sealed_snapshot[..]).unwrap())),
+            io::Cursor::new(keyring.open(&sealed_journal[..]).unwrap()),
+            Policy::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.account(ClientId(1)).unwrap().available(), num!(5));
+    }
+}
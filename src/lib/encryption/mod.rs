@@ -0,0 +1,15 @@
+// Snapshots and journals encrypted with AES-256-GCM under keys the caller
+// provides, as 64 hex digits. A file is a series of frames, each sealed on
+// its own, so journal entries can still be appended to an encrypted journal:
+//
+//   b"crabenc1" | key id (8) | nonce (12) | length (4, big endian) | sealed
+//
+// The key id is the start of the key's SHA-256, which tells the keyring which
+// of its keys opens the frame. Keys are rotated by writing with a new key
+// while the previous one is still kept for reading, see `Keyring::rotate`.
+// Enabled with the `encryption` feature; without it the keyring can't be
+// built and asking for one fails up front.
+#[cfg_attr(not(feature = "encryption"), path = "disabled.rs")]
+mod keyring;
+
+pub use keyring::{Key, Keyring};
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use super::account::ClientId;
+use super::ledger::{FreezeReason, Ledger, Tier};
+use super::transactions::{TransactionError, TransactionResult};
+
+// What an external service (a KYC system, a sanctions list) knows about a
+// client.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientProfile {
+    pub tier: Option<Tier>,
+    pub sanctioned: bool,
+}
+
+pub type Lookup = Pin<Box<dyn Future<Output = Option<ClientProfile>> + Send>>;
+
+// Looks a client up without blocking, for services reached over the network.
+// `None` means the service had nothing to say and the client is left as is.
+pub trait ClientLookup: Send + Sync {
+    fn lookup(&self, client_id: ClientId) -> Lookup;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnrichmentStats {
+    pub hits: u64,
+    pub lookups: u64,
+    pub timeouts: u64,
+    // Lookups skipped because `max_in_flight` were already running.
+    pub shed: u64,
+}
+
+// Runs before each row is applied: the client's profile, from the cache or
+// the lookup, sets its tier and freezes sanctioned clients. Profiles are
+// kept for `ttl`. A lookup that takes longer than `timeout`, or that would
+// exceed `max_in_flight` lookups across every pipeline sharing the enricher,
+// is given up on and the expired profile, if any, is used instead, so a slow
+// service slows the run down by at most `timeout` per row.
+pub struct Enricher {
+    lookup: Arc<dyn ClientLookup>,
+    ttl: Duration,
+    timeout: Duration,
+    max_in_flight: usize,
+    in_flight: AtomicUsize,
+    cache: Mutex<HashMap<ClientId, (Instant, Option<ClientProfile>)>>,
+    stats: Mutex<EnrichmentStats>,
+}
+
+impl Enricher {
+    pub fn new(lookup: Arc<dyn ClientLookup>, ttl: Duration, timeout: Duration) -> Self {
+        Enricher {
+            lookup,
+            ttl,
+            timeout,
+            max_in_flight: usize::MAX,
+            in_flight: AtomicUsize::new(0),
+            cache: Mutex::new(HashMap::new()),
+            stats: Mutex::new(EnrichmentStats::default()),
+        }
+    }
+
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    pub fn stats(&self) -> EnrichmentStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub fn profile(&self, client_id: ClientId) -> Option<ClientProfile> {
+        self.profile_at(client_id, Instant::now())
+    }
+
+    pub fn profile_at(&self, client_id: ClientId, now: Instant) -> Option<ClientProfile> {
+        let cached = self.cache.lock().unwrap().get(&client_id).copied();
+        if let Some((fetched_at, profile)) = cached {
+            if now.saturating_duration_since(fetched_at) < self.ttl {
+                self.stats.lock().unwrap().hits += 1;
+                return profile;
+            }
+        }
+        let stale = cached.and_then(|(_, profile)| profile);
+        if self.in_flight.fetch_add(1, Ordering::AcqRel) >= self.max_in_flight {
+            self.in_flight.fetch_sub(1, Ordering::AcqRel);
+            self.stats.lock().unwrap().shed += 1;
+            return stale;
+        }
+        let result = block_on_timeout(self.lookup.lookup(client_id), self.timeout);
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+        let mut stats = self.stats.lock().unwrap();
+        stats.lookups += 1;
+        match result {
+            Some(profile) => {
+                self.cache.lock().unwrap().insert(client_id, (now, profile));
+                profile
+            }
+            None => {
+                stats.timeouts += 1;
+                stale
+            }
+        }
+    }
+
+    // Clients found sanctioned are frozen, and stay frozen until someone
+    // unfreezes them, whatever later lookups say. Clients without an account
+    // can't be frozen yet, so their row is rejected instead.
+    pub fn enrich(&self, ledger: &mut Ledger, client_id: ClientId) -> TransactionResult {
+        let Some(profile) = self.profile(client_id) else {
+            return Ok(());
+        };
+        if let Some(tier) = profile.tier {
+            if ledger.tier(client_id) != tier {
+                ledger.set_tier(client_id, tier);
+            }
+        }
+        if profile.sanctioned && !ledger.is_frozen(client_id) {
+            ledger
+                .freeze(client_id, FreezeReason::Sanctions)
+                .map_err(|_| TransactionError::FrozenAccount(client_id))?;
+        }
+        Ok(())
+    }
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Polls the future on this thread until it completes or `timeout` passes.
+fn block_on_timeout<F: Future>(future: F, timeout: Duration) -> Option<F::Output> {
+    let deadline = Instant::now() + timeout;
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return Some(output);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
+#[cfg(test)]
+mod enrichment_tests {
+    use super::{ClientLookup, ClientProfile, Enricher, EnrichmentStats, Lookup};
+    use crate::account::{num, ClientId};
+    use crate::ledger::{FreezeReason, Ledger, Tier};
+    use crate::transactions::{Operation, Transaction, TransactionError, TransactionId};
+    use std::future::{pending, ready};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    // Client 1 is premium, client 2 sanctioned and client 3 never answers.
+    struct Directory;
+
+    impl ClientLookup for Directory {
+        fn lookup(&self, client_id: ClientId) -> Lookup {
+            match client_id.0 {
+                1 => Box::pin(ready(Some(ClientProfile {
+                    tier: Some(Tier::Premium),
+                    sanctioned: false,
+                }))),
+                2 => Box::pin(ready(Some(ClientProfile {
+                    tier: None,
+                    sanctioned: true,
+                }))),
+                _ => Box::pin(pending()),
+            }
+        }
+    }
+
+    #[test]
+    fn cached_lookups_with_timeouts() {
+        let enricher = Enricher::new(
+            Arc::new(Directory),
+            Duration::from_secs(60),
+            Duration::from_millis(10),
+        );
+        let mut ledger = Ledger::new();
+        assert_eq!(
+            enricher.enrich(&mut ledger, ClientId(2)),
+            Err(TransactionError::FrozenAccount(ClientId(2)))
+        );
+        let deposit = Transaction::new(ClientId(2), num!(1), Operation::Deposit);
+        assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+        assert_eq!(enricher.enrich(&mut ledger, ClientId(2)), Ok(()));
+        assert_eq!(enricher.enrich(&mut ledger, ClientId(1)), Ok(()));
+        assert_eq!(enricher.enrich(&mut ledger, ClientId(3)), Ok(()));
+        assert_eq!(ledger.tier(ClientId(1)), Tier::Premium);
+        assert_eq!(
+            ledger.freeze_reason(ClientId(2)),
+            Some(FreezeReason::Sanctions)
+        );
+        assert_eq!(ledger.tier(ClientId(3)), Tier::Basic);
+
+        let now = Instant::now();
+        assert!(enricher.profile_at(ClientId(1), now).is_some());
+        // expired, looked up again
+        assert!(enricher
+            .profile_at(ClientId(1), now + Duration::from_secs(61))
+            .is_some());
+        assert_eq!(
+            enricher.stats(),
+            EnrichmentStats {
+                hits: 2,
+                lookups: 4,
+                timeouts: 1,
+                shed: 0,
+            }
+        );
+
+        let shedding = Enricher::new(
+            Arc::new(Directory),
+            Duration::from_secs(60),
+            Duration::from_millis(10),
+        )
+        .max_in_flight(0);
+        assert_eq!(shedding.profile(ClientId(1)), None);
+        assert_eq!(shedding.stats().shed, 1);
+    }
+}
@@ -0,0 +1,319 @@
+use std::fs;
+use std::io::{self, BufRead, Read};
+
+use toml_edit::Document;
+
+use super::config::{integer, invalid, section, string, ConfigError};
+
+// Reads feeds made of fixed-width records, like the ones legacy core banking
+// systems export, by turning each record into a CSV row with the layout's
+// field names as headers. Everything downstream of the CSV reader works
+// unchanged, and a field that can't be converted is passed on as it was so
+// the row is rejected as malformed like any bad CSV row.
+//
+// The layout is a TOML file naming where each field sits in a record:
+//
+//   # Records are separated by newlines unless they all have this length.
+//   record_length = 40
+//   [[fields]]
+//   name = "type"
+//   offset = 0
+//   length = 10
+//   [[fields]]
+//   name = "amount"
+//   offset = 26
+//   length = 14
+//   kind = "zoned"
+//   scale = 4
+//
+// Kinds are `text` (the default, with surrounding spaces removed),
+// `integer` (leading zeros removed), `decimal` (digits with an optional
+// leading sign and `scale` implied decimal places) and `zoned` (like
+// decimal, with the sign overpunched on the last digit).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub record_length: Option<usize>,
+    pub fields: Vec<LayoutField>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayoutField {
+    pub name: String,
+    pub offset: usize,
+    pub length: usize,
+    pub kind: FieldKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    Text,
+    Integer,
+    Decimal { scale: u32 },
+    Zoned { scale: u32 },
+}
+
+impl Layout {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let document: Document = text
+            .parse()
+            .map_err(|err: toml_edit::TomlError| ConfigError::Syntax(err.to_string()))?;
+        let root = document.as_item();
+        let tables = section(root, "fields")
+            .as_array_of_tables()
+            .ok_or_else(|| invalid("fields"))?;
+        let mut fields = Vec::new();
+        for table in tables {
+            let item = toml_edit::Item::Table(table.clone());
+            let scale = integer(&item, "scale")?.unwrap_or(0);
+            let kind = match string(&item, "kind")?.as_deref() {
+                None | Some("text") => FieldKind::Text,
+                Some("integer") => FieldKind::Integer,
+                Some("decimal") => FieldKind::Decimal { scale },
+                Some("zoned") => FieldKind::Zoned { scale },
+                Some(_) => return Err(invalid("kind")),
+            };
+            fields.push(LayoutField {
+                name: string(&item, "name")?.ok_or_else(|| invalid("name"))?,
+                offset: integer(&item, "offset")?.ok_or_else(|| invalid("offset"))?,
+                length: integer(&item, "length")?.ok_or_else(|| invalid("length"))?,
+                kind,
+            });
+        }
+        for required in ["type", "client"] {
+            if !fields.iter().any(|field| field.name == required) {
+                return Err(invalid(required));
+            }
+        }
+        let record_length = integer(root, "record_length")?;
+        if record_length == Some(0) {
+            return Err(invalid("record_length"));
+        }
+        Ok(Layout {
+            record_length,
+            fields,
+        })
+    }
+
+    pub fn read(path: &str) -> Result<Self, ConfigError> {
+        Self::parse(&fs::read_to_string(path).map_err(|err| ConfigError::Io(err.to_string()))?)
+    }
+
+    // The converted rows follow a header line made of the field names, so
+    // record n is reported as line n + 1, as it would be in a CSV file.
+    pub fn csv_reader<R: BufRead>(&self, reader: R) -> csv::Reader<FixedWidthReader<R>> {
+        let mut header = csv::Writer::from_writer(Vec::new());
+        // Writing to a vector can't fail.
+        header
+            .write_record(self.fields.iter().map(|field| field.name.as_str()))
+            .unwrap();
+        csv::Reader::from_reader(FixedWidthReader {
+            inner: reader,
+            layout: self.clone(),
+            pending: header.into_inner().unwrap(),
+            position: 0,
+        })
+    }
+
+    fn to_csv(&self, record: &[u8]) -> Vec<u8> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let values = self.fields.iter().map(|field| {
+            let start = field.offset.min(record.len());
+            let end = (field.offset + field.length).min(record.len());
+            field
+                .kind
+                .convert(String::from_utf8_lossy(&record[start..end]).trim())
+        });
+        // Writing to a vector can't fail.
+        writer.write_record(values).unwrap();
+        writer.into_inner().unwrap()
+    }
+}
+
+impl FieldKind {
+    fn convert(self, value: &str) -> String {
+        let converted = match self {
+            FieldKind::Text => Some(value.to_string()),
+            FieldKind::Integer => integer_digits(value).map(str::to_string),
+            FieldKind::Decimal { scale } => {
+                let (sign, digits) = match value.strip_prefix('-') {
+                    Some(digits) => ("-", digits),
+                    None => ("", value.strip_prefix('+').unwrap_or(value)),
+                };
+                integer_digits(digits).map(|digits| with_scale(sign, digits, scale))
+            }
+            FieldKind::Zoned { scale } => unpunch(value).and_then(|(sign, digits)| {
+                integer_digits(&digits).map(|digits| with_scale(sign, digits, scale))
+            }),
+        };
+        converted.unwrap_or_else(|| value.to_string())
+    }
+}
+
+// Digits without their leading zeros, keeping a single zero. Empty fields
+// stay empty, for optional columns.
+fn integer_digits(value: &str) -> Option<&str> {
+    if !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let trimmed = value.trim_start_matches('0');
+    Some(match (trimmed.is_empty(), value.is_empty()) {
+        (true, false) => "0",
+        _ => trimmed,
+    })
+}
+
+fn with_scale(sign: &str, digits: &str, scale: u32) -> String {
+    if digits.is_empty() {
+        return String::new();
+    }
+    let scale = scale as usize;
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    match scale {
+        0 => format!("{sign}{integer}"),
+        _ => format!("{sign}{integer}.{fraction}"),
+    }
+}
+
+// Zoned decimals carry the sign in the last digit: `{` and `A` to `I` are a
+// positive 0 to 9, `}` and `J` to `R` a negative one. Plain digits are
+// unsigned.
+fn unpunch(value: &str) -> Option<(&'static str, String)> {
+    let split = value.len().checked_sub(1)?;
+    if !value.is_char_boundary(split) {
+        return None;
+    }
+    let (head, last) = value.split_at(split);
+    let (sign, digit) = match last.as_bytes()[0] {
+        digit @ b'0'..=b'9' => ("", digit),
+        b'{' => ("", b'0'),
+        punched @ b'A'..=b'I' => ("", punched - b'A' + b'1'),
+        b'}' => ("-", b'0'),
+        punched @ b'J'..=b'R' => ("-", punched - b'J' + b'1'),
+        _ => return None,
+    };
+    Some((sign, format!("{head}{}", digit as char)))
+}
+
+// Serves the CSV rows converted from the fixed-width records, one record at
+// a time.
+pub struct FixedWidthReader<R> {
+    inner: R,
+    layout: Layout,
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl<R: BufRead> FixedWidthReader<R> {
+    fn next_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut record = Vec::new();
+        match self.layout.record_length {
+            Some(length) => {
+                (&mut self.inner)
+                    .take(length as u64)
+                    .read_to_end(&mut record)?;
+            }
+            None => {
+                self.inner.read_until(b'\n', &mut record)?;
+                while record
+                    .last()
+                    .is_some_and(|byte| *byte == b'\n' || *byte == b'\r')
+                {
+                    record.pop();
+                }
+            }
+        }
+        Ok((!record.is_empty()).then_some(record))
+    }
+}
+
+impl<R: BufRead> Read for FixedWidthReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.pending.len() {
+            let Some(record) = self.next_record()? else {
+                return Ok(0);
+            };
+            self.pending = self.layout.to_csv(&record);
+            self.position = 0;
+        }
+        let count = buf.len().min(self.pending.len() - self.position);
+        buf[..count].copy_from_slice(&self.pending[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod fixed_width_tests {
+    use super::{FieldKind, Layout};
+    use crate::config::ConfigError;
+
+    const LAYOUT: &str = r#"
+        [[fields]]
+        name = "type"
+        offset = 0
+        length = 10
+        [[fields]]
+        name = "client"
+        offset = 10
+        length = 5
+        kind = "integer"
+        [[fields]]
+        name = "tx"
+        offset = 15
+        length = 8
+        kind = "integer"
+        [[fields]]
+        name = "amount"
+        offset = 23
+        length = 10
+        kind = "zoned"
+        scale = 4
+    "#;
+
+    #[test]
+    fn fixed_width_records_become_csv_rows() {
+        let layout = Layout::parse(LAYOUT).unwrap();
+        let input = "deposit   0000100000001000150000{\n\
+                     withdrawal0000100000002000025000{\n\
+                     dispute   0000100000001          \n";
+        let mut reader = layout.csv_reader(input.as_bytes());
+        let rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|record| record.unwrap().iter().map(str::to_string).collect())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ["deposit", "1", "1", "150.0000"],
+                ["withdrawal", "1", "2", "25.0000"],
+                ["dispute", "1", "1", ""],
+            ]
+        );
+        assert_eq!(
+            reader.headers().unwrap().iter().collect::<Vec<_>>(),
+            ["type", "client", "tx", "amount"]
+        );
+
+        // without newlines between records
+        let layout = Layout {
+            record_length: Some(33),
+            ..layout
+        };
+        let input = "deposit   0000200000003000000012Edeposit   0000200000004000000000{";
+        let amounts: Vec<String> = layout
+            .csv_reader(input.as_bytes())
+            .records()
+            .map(|record| record.unwrap()[3].to_string())
+            .collect();
+        assert_eq!(amounts, ["0.0125", "0.0000"]);
+
+        assert_eq!(FieldKind::Decimal { scale: 2 }.convert("-00012"), "-0.12");
+        assert_eq!(FieldKind::Zoned { scale: 2 }.convert("1234}"), "-123.40");
+        assert_eq!(FieldKind::Zoned { scale: 0 }.convert("12X"), "12X");
+        assert_eq!(
+            Layout::parse("[[fields]]\nname = \"type\"\noffset = 0\nlength = 1"),
+            Err(ConfigError::InvalidValue("client".to_string()))
+        );
+    }
+}
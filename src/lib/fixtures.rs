@@ -0,0 +1,202 @@
+use super::account::{num, ClientId, Number};
+use super::ledger::Ledger;
+use super::transactions::{Operation, Transaction, TransactionId};
+
+// Ledgers in common states for tests, built through the same transactions a
+// real input would carry. Enabled with the `fixtures` feature.
+//
+// Every step panics if the ledger rejects it: a fixture that doesn't build is
+// a broken test, not a case to handle.
+pub struct Scenario {
+    ledger: Ledger,
+    next_id: u32,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::with_ledger(Ledger::new())
+    }
+
+    // Transaction ids are handed out from 1 upwards, so the ledger shouldn't
+    // already use them.
+    pub fn with_ledger(ledger: Ledger) -> Self {
+        Scenario { ledger, next_id: 1 }
+    }
+
+    pub fn deposit(self, client_id: ClientId, amount: Number) -> Self {
+        self.new_transaction(client_id, amount, Operation::Deposit)
+    }
+
+    pub fn withdraw(self, client_id: ClientId, amount: Number) -> Self {
+        self.new_transaction(client_id, amount, Operation::Withdrawal)
+    }
+
+    pub fn dispute(self, transaction_id: TransactionId) -> Self {
+        self.refer_to(transaction_id, Operation::Dispute)
+    }
+
+    pub fn resolve(self, transaction_id: TransactionId) -> Self {
+        self.refer_to(transaction_id, Operation::Resolve)
+    }
+
+    pub fn chargeback(self, transaction_id: TransactionId) -> Self {
+        self.refer_to(transaction_id, Operation::Chargeback)
+    }
+
+    // The id given to the last deposit or withdrawal.
+    pub fn last_id(&self) -> TransactionId {
+        TransactionId(self.next_id - 1)
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn into_ledger(self) -> Ledger {
+        self.ledger
+    }
+
+    fn new_transaction(
+        mut self,
+        client_id: ClientId,
+        amount: Number,
+        operation: Operation,
+    ) -> Self {
+        let transaction_id = TransactionId(self.next_id);
+        self.next_id += 1;
+        self.apply(
+            transaction_id,
+            Transaction::new(client_id, amount, operation),
+        )
+    }
+
+    fn refer_to(self, transaction_id: TransactionId, operation: Operation) -> Self {
+        let client_id = self
+            .ledger
+            .transaction(transaction_id)
+            .unwrap_or_else(|| panic!("fixture refers to unknown {transaction_id:?}"))
+            .client_id();
+        self.apply(
+            transaction_id,
+            Transaction::new(client_id, Number::ZERO, operation),
+        )
+    }
+
+    fn apply(mut self, transaction_id: TransactionId, transaction: Transaction) -> Self {
+        if let Err(err) = self.ledger.apply_transaction(transaction_id, &transaction) {
+            panic!("fixture {transaction_id:?} {transaction:?} was rejected: {err:?}");
+        }
+        self
+    }
+}
+
+// Client 1 with 100 available.
+pub fn funded_account() -> Ledger {
+    Scenario::new()
+        .deposit(ClientId(1), num!(100))
+        .into_ledger()
+}
+
+// Client 1 deposited 100 and then 50, and disputed the 50: 100 available and
+// 50 held. Returns the disputed deposit's id.
+pub fn open_dispute() -> (Ledger, TransactionId) {
+    let scenario = Scenario::new()
+        .deposit(ClientId(1), num!(100))
+        .deposit(ClientId(1), num!(50));
+    let disputed = scenario.last_id();
+    (scenario.dispute(disputed).into_ledger(), disputed)
+}
+
+// Client 1 had its 50 deposit charged back: 100 available, locked.
+pub fn locked_account() -> Ledger {
+    let (ledger, disputed) = open_dispute();
+    Scenario {
+        ledger,
+        next_id: disputed.0 + 1,
+    }
+    .chargeback(disputed)
+    .into_ledger()
+}
+
+// One client in each state:
+//
+//   client 1: deposited 100, 100 available
+//   client 2: deposited 80 and withdrew 30, 50 available
+//   client 3: deposited 40 and 20, the 20 disputed: 40 available, 20 held
+//   client 4: deposited 60 and 10, the 10 charged back: 60 available, locked
+//   client 5: deposited 25, disputed and resolved, 25 available
+pub fn multi_client_mix() -> Ledger {
+    let scenario = Scenario::new()
+        .deposit(ClientId(1), num!(100))
+        .deposit(ClientId(2), num!(80))
+        .withdraw(ClientId(2), num!(30))
+        .deposit(ClientId(3), num!(40))
+        .deposit(ClientId(3), num!(20));
+    let disputed = scenario.last_id();
+    let scenario = scenario
+        .dispute(disputed)
+        .deposit(ClientId(4), num!(60))
+        .deposit(ClientId(4), num!(10));
+    let charged_back = scenario.last_id();
+    let scenario = scenario
+        .dispute(charged_back)
+        .chargeback(charged_back)
+        .deposit(ClientId(5), num!(25));
+    let resolved = scenario.last_id();
+    scenario.dispute(resolved).resolve(resolved).into_ledger()
+}
+
+#[cfg(test)]
+mod fixtures_tests {
+    use super::{funded_account, locked_account, multi_client_mix, open_dispute};
+    use crate::account::{num, ClientId, Number};
+    use crate::transactions::TransactionState;
+
+    #[test]
+    fn fixtures_build() {
+        let ledger = funded_account();
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(100));
+
+        let (ledger, disputed) = open_dispute();
+        assert_eq!(
+            ledger.transaction(disputed).unwrap().state(),
+            TransactionState::Disputed
+        );
+        assert_eq!(ledger.account(ClientId(1)).unwrap().held(), num!(50));
+
+        let account = locked_account().account(ClientId(1)).unwrap();
+        assert!(account.locked());
+        assert_eq!(
+            (account.available(), account.held()),
+            (num!(100), Number::ZERO)
+        );
+
+        let balances: Vec<_> = multi_client_mix()
+            .into_iter()
+            .map(|(client_id, account)| {
+                (
+                    client_id.0,
+                    account.available(),
+                    account.held(),
+                    account.locked(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            balances,
+            [
+                (1, num!(100), num!(0), false),
+                (2, num!(50), num!(0), false),
+                (3, num!(40), num!(20), false),
+                (4, num!(60), num!(0), true),
+                (5, num!(25), num!(0), false),
+            ]
+        );
+    }
+}
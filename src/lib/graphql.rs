@@ -0,0 +1,369 @@
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context as TaskContext, Poll, Waker};
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Object, Request, Response, Result, Schema,
+    SimpleObject,
+};
+
+use super::account::{Account, ClientId, Number};
+use super::ledger::{AccountFilter, Ledger, LedgerStats, TransactionFilter};
+use super::transactions::{Operation, Transaction, TransactionId};
+
+// The ledger's accounts, transactions, disputes and stats over GraphQL, for
+// dashboards that would rather not parse reports. Read only: the ledger is
+// shared with whatever keeps applying transactions to it and every query
+// sees it as it stands. Amounts are strings, so no precision is lost on the
+// way to JavaScript. Enabled with the `graphql` feature.
+//
+//   {
+//     accounts(locked: true, first: 10) { items { client available } next }
+//     transactions(client: 1, type: "deposit") { items { id amount state } }
+//     disputes { items { id client amount } }
+//     stats { accounts openDisputes held }
+//   }
+//
+// Lists come in pages of `first` items, 100 unless asked otherwise, after
+// the `after` cursor; a page's `next` is the cursor of the one after it.
+pub type LedgerSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const DEFAULT_PAGE: usize = 100;
+
+pub fn schema(ledger: Arc<Mutex<Ledger>>) -> LedgerSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(ledger)
+        .finish()
+}
+
+// Resolvers never wait on anything, so a query runs to completion without
+// an async runtime.
+pub fn execute(schema: &LedgerSchema, request: impl Into<Request>) -> Response {
+    let mut future = pin!(schema.execute(request));
+    let mut context = TaskContext::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(response) = future.as_mut().poll(&mut context) {
+            return response;
+        }
+    }
+}
+
+fn ledger<'a>(context: &Context<'a>) -> MutexGuard<'a, Ledger> {
+    context
+        .data_unchecked::<Arc<Mutex<Ledger>>>()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+fn amount(value: Option<String>) -> Result<Option<Number>> {
+    Ok(value.map(|value| value.parse()).transpose()?)
+}
+
+// The first `first` items after `after`, and the cursor of the next page.
+fn page<K: Copy + PartialOrd, T, O>(
+    items: impl Iterator<Item = (K, T)>,
+    after: Option<K>,
+    first: Option<usize>,
+    object: impl Fn(K, T) -> O,
+) -> (Vec<O>, Option<K>) {
+    let first = first.unwrap_or(DEFAULT_PAGE);
+    let mut items = items.skip_while(|(key, _)| after.is_some_and(|after| *key <= after));
+    let mut page = Vec::new();
+    let mut last = None;
+    for (key, item) in items.by_ref().take(first) {
+        last = Some(key);
+        page.push(object(key, item));
+    }
+    let next = match items.next() {
+        Some(_) => last,
+        None => None,
+    };
+    (page, next)
+}
+
+#[derive(SimpleObject)]
+pub struct AccountObject {
+    client: u16,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+impl AccountObject {
+    fn new(client_id: ClientId, account: Account) -> Self {
+        AccountObject {
+            client: client_id.0,
+            available: account.available().to_string(),
+            held: account.held().to_string(),
+            total: account.total().to_string(),
+            locked: account.locked(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct TransactionObject {
+    id: u32,
+    client: u16,
+    #[graphql(name = "type")]
+    operation: String,
+    // Of transfers.
+    to: Option<u16>,
+    amount: String,
+    state: String,
+}
+
+impl TransactionObject {
+    fn new(transaction_id: TransactionId, transaction: &Transaction) -> Self {
+        TransactionObject {
+            id: transaction_id.0,
+            client: transaction.client_id().0,
+            operation: transaction.operation().name().to_string(),
+            to: transaction.operation().recipient().map(|to| to.0),
+            amount: transaction.amount().to_string(),
+            state: transaction.state().name().to_string(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct AccountPage {
+    items: Vec<AccountObject>,
+    next: Option<u16>,
+}
+
+#[derive(SimpleObject)]
+pub struct TransactionPage {
+    items: Vec<TransactionObject>,
+    next: Option<u32>,
+}
+
+#[derive(SimpleObject)]
+pub struct StatsObject {
+    accounts: usize,
+    locked_accounts: usize,
+    transactions: usize,
+    open_disputes: usize,
+    chargebacks: usize,
+    resolved_disputes: usize,
+    erased_clients: usize,
+    available: String,
+    held: String,
+    escrowed: String,
+    watermark: Option<u32>,
+}
+
+impl From<LedgerStats> for StatsObject {
+    fn from(stats: LedgerStats) -> Self {
+        StatsObject {
+            accounts: stats.accounts,
+            locked_accounts: stats.locked_accounts,
+            transactions: stats.transactions,
+            open_disputes: stats.open_disputes,
+            chargebacks: stats.chargebacks,
+            resolved_disputes: stats.resolved_disputes,
+            erased_clients: stats.erased_clients,
+            available: stats.available.to_string(),
+            held: stats.held.to_string(),
+            escrowed: stats.escrowed.to_string(),
+            watermark: stats.watermark.map(|watermark| watermark.0),
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn account(&self, context: &Context<'_>, client: u16) -> Option<AccountObject> {
+        let client_id = ClientId(client);
+        ledger(context)
+            .account(client_id)
+            .map(|account| AccountObject::new(client_id, account))
+    }
+
+    async fn accounts(
+        &self,
+        context: &Context<'_>,
+        locked: Option<bool>,
+        min_total: Option<String>,
+        max_total: Option<String>,
+        after: Option<u16>,
+        first: Option<usize>,
+    ) -> Result<AccountPage> {
+        let filter = AccountFilter {
+            locked,
+            min_total: amount(min_total)?,
+            max_total: amount(max_total)?,
+        };
+        let ledger = ledger(context);
+        let (items, next) = page(
+            ledger.accounts(&filter),
+            after.map(ClientId),
+            first,
+            AccountObject::new,
+        );
+        Ok(AccountPage {
+            items,
+            next: next.map(|client_id| client_id.0),
+        })
+    }
+
+    async fn transaction(&self, context: &Context<'_>, id: u32) -> Option<TransactionObject> {
+        let transaction_id = TransactionId(id);
+        ledger(context)
+            .transaction(transaction_id)
+            .map(|transaction| TransactionObject::new(transaction_id, transaction))
+    }
+
+    // `type` and `state` take the names used in the input and in snapshots,
+    // like "deposit" and "disputed".
+    async fn transactions(
+        &self,
+        context: &Context<'_>,
+        client: Option<u16>,
+        #[graphql(name = "type")] operation: Option<String>,
+        state: Option<String>,
+        after: Option<u32>,
+        first: Option<usize>,
+    ) -> Result<TransactionPage> {
+        let operation = operation
+            .map(|name| name.parse::<Operation>())
+            .transpose()
+            .map_err(|name| format!("unknown transaction type '{name}'"))?;
+        let filter = TransactionFilter {
+            client_id: client.map(ClientId),
+            ..TransactionFilter::default()
+        };
+        let ledger = ledger(context);
+        let transactions = ledger.transactions(&filter).filter(|(_, transaction)| {
+            operation.is_none_or(|operation| transaction.operation().name() == operation.name())
+                && state
+                    .as_deref()
+                    .is_none_or(|state| transaction.state().name() == state)
+        });
+        let (items, next) = page(
+            transactions,
+            after.map(TransactionId),
+            first,
+            TransactionObject::new,
+        );
+        Ok(TransactionPage {
+            items,
+            next: next.map(|transaction_id| transaction_id.0),
+        })
+    }
+
+    // Deposits under an open dispute.
+    async fn disputes(
+        &self,
+        context: &Context<'_>,
+        client: Option<u16>,
+        after: Option<u32>,
+        first: Option<usize>,
+    ) -> TransactionPage {
+        let ledger = ledger(context);
+        let disputes = ledger.disputes().filter(|(_, transaction)| {
+            client.is_none_or(|client| transaction.client_id() == ClientId(client))
+        });
+        let (items, next) = page(
+            disputes,
+            after.map(TransactionId),
+            first,
+            TransactionObject::new,
+        );
+        TransactionPage {
+            items,
+            next: next.map(|transaction_id| transaction_id.0),
+        }
+    }
+
+    async fn stats(&self, context: &Context<'_>) -> StatsObject {
+        ledger(context).stats().into()
+    }
+}
+
+#[cfg(test)]
+mod graphql_tests {
+    use super::{execute, schema};
+    use crate::account::{num, ClientId};
+    use crate::ledger::Ledger;
+    use crate::transactions::{Operation, Transaction, TransactionId};
+    use async_graphql::value;
+    use std::sync::{Arc, Mutex};
+
+    fn ledger() -> Arc<Mutex<Ledger>> {
+        let mut ledger = Ledger::new();
+        for (id, client) in [(1, 1), (2, 2), (3, 3)] {
+            let deposit = Transaction::new(ClientId(client), num!(10), Operation::Deposit);
+            assert!(ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .is_ok());
+        }
+        let dispute = Transaction::new(ClientId(2), num!(0), Operation::Dispute);
+        assert!(ledger.apply_transaction(TransactionId(2), &dispute).is_ok());
+        Arc::new(Mutex::new(ledger))
+    }
+
+    #[test]
+    fn page_through_accounts() {
+        let schema = schema(ledger());
+        let response = execute(
+            &schema,
+            "{ accounts(first: 2) { items { client available } next } }",
+        );
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data,
+            value!({"accounts": {
+                "items": [
+                    {"client": 1, "available": "10"},
+                    {"client": 2, "available": "0"},
+                ],
+                "next": 2,
+            }})
+        );
+        let response = execute(&schema, "{ accounts(after: 2) { items { client } next } }");
+        assert_eq!(
+            response.data,
+            value!({"accounts": {"items": [{"client": 3}], "next": null}})
+        );
+    }
+
+    #[test]
+    fn filter_transactions_and_disputes() {
+        let ledger = ledger();
+        let schema = schema(ledger.clone());
+        let response = execute(
+            &schema,
+            r#"{
+                transactions(type: "deposit", state: "disputed") { items { id client } }
+                disputes { items { id amount state } }
+                stats { accounts openDisputes held }
+            }"#,
+        );
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data,
+            value!({
+                "transactions": {"items": [{"id": 2, "client": 2}]},
+                "disputes": {"items": [{"id": 2, "amount": "10", "state": "disputed"}]},
+                "stats": {"accounts": 3, "openDisputes": 1, "held": "10"},
+            })
+        );
+
+        // Queries see the ledger as it is when they run.
+        let resolve = Transaction::new(ClientId(2), num!(0), Operation::Resolve);
+        assert!(ledger
+            .lock()
+            .unwrap()
+            .apply_transaction(TransactionId(2), &resolve)
+            .is_ok());
+        let response = execute(&schema, "{ disputes { items { id } } }");
+        assert_eq!(response.data, value!({"disputes": {"items": []}}));
+        let response = execute(&schema, r#"{ transactions(type: "bonus") { next } }"#);
+        assert_eq!(response.errors.len(), 1);
+    }
+}
@@ -0,0 +1,515 @@
+use std::io::{self, BufRead, Write};
+use std::net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+use super::config::{Config, ConfigError};
+use super::ledger::{AccountsSnapshot, Policy};
+use super::report::{ReportFormat, ReportOptions, ReportWriter};
+
+// How long an export waits for the ledger thread to hand over its accounts.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The accounts last handed over by the ledger thread. `generation` goes up
+// with each one, and `last` is set once the input is done and no newer ones
+// will come. Until then they're only kept while `waiting` exports still have
+// to pick them up.
+#[derive(Debug, Default)]
+struct Published {
+    generation: u64,
+    accounts: Option<AccountsSnapshot>,
+    last: bool,
+    waiting: usize,
+}
+
+// Progress counters shared between the reader, the ledger thread and the
+// health endpoints. Updates are relaxed: readers only need a recent value.
+#[derive(Debug, Default)]
+pub struct HealthMonitor {
+    queued: AtomicUsize,
+    high_water_mark: AtomicUsize,
+    dropped: AtomicU64,
+    processed: AtomicU64,
+    journaled: AtomicU64,
+    flushed: AtomicU64,
+    finished: AtomicBool,
+    // Shared with the signal handlers.
+    shutdown: Arc<AtomicBool>,
+    max_queue_depth: Option<usize>,
+    max_journal_lag: Option<u64>,
+    export_requested: AtomicBool,
+    published: Mutex<Published>,
+    published_changed: Condvar,
+    export_options: ReportOptions,
+    // The file `POST /reload` reads the policy from, and the policy it read
+    // until the ledger thread takes it.
+    config: Option<String>,
+    reloaded_policy: Mutex<Option<Policy>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct HealthReport {
+    pub ready: bool,
+    pub queue_depth: usize,
+    pub high_water_mark: usize,
+    pub dropped: u64,
+    pub last_applied: u64,
+    pub journal_lag: u64,
+    pub finished: bool,
+}
+
+impl HealthReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl HealthMonitor {
+    pub fn new(max_queue_depth: Option<usize>, max_journal_lag: Option<u64>) -> Self {
+        Self {
+            max_queue_depth,
+            max_journal_lag,
+            ..Self::default()
+        }
+    }
+
+    // Returns the new high-water mark when this row raised it.
+    pub fn enqueued(&self) -> Option<usize> {
+        let depth = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        let previous = self.high_water_mark.fetch_max(depth, Ordering::Relaxed);
+        (depth > previous).then_some(depth)
+    }
+
+    // A queued row the reader gave up on because the queue was full. Returns
+    // how many have been dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        self.dropped.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn processed(&self, journal_sequence: u64) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.journaled.store(journal_sequence, Ordering::Relaxed);
+    }
+
+    pub fn flushed(&self, journal_sequence: u64) {
+        self.flushed.store(journal_sequence, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    // Asks the reader to stop taking new rows. Rows already queued are still
+    // applied before the run ends.
+    pub fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    // SIGTERM and SIGINT request a shutdown as `POST /shutdown` does. A second
+    // one while the queue drains ends the process right away.
+    pub fn shutdown_on_signals(&self) -> io::Result<()> {
+        for signal in [SIGTERM, SIGINT] {
+            flag::register_conditional_shutdown(signal, 1, self.shutdown.clone())?;
+            flag::register(signal, self.shutdown.clone())?;
+        }
+        Ok(())
+    }
+
+    // Exports are written like the report, apart from their format.
+    pub fn with_export_options(mut self, options: ReportOptions) -> Self {
+        self.export_options = options;
+        self
+    }
+
+    pub fn with_config(mut self, path: impl Into<String>) -> Self {
+        self.config = Some(path.into());
+        self
+    }
+
+    // Reads the config file again. Its policy is applied by the ledger thread
+    // before the next row, without touching the accounts or transactions.
+    pub fn request_reload(&self) -> Result<Policy, ConfigError> {
+        let path = self
+            .config
+            .as_deref()
+            .ok_or_else(|| ConfigError::Io("no config file to reload".to_string()))?;
+        let policy = Config::read(path)?.policy;
+        *self
+            .reloaded_policy
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = Some(policy);
+        Ok(policy)
+    }
+
+    // Polled by the ledger thread between rows.
+    pub fn take_reloaded_policy(&self) -> Option<Policy> {
+        self.reloaded_policy
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+    }
+
+    // Polled by the ledger thread between rows: true when an export is
+    // waiting for `publish_accounts`.
+    pub fn take_export_request(&self) -> bool {
+        self.export_requested.swap(false, Ordering::Relaxed)
+    }
+
+    // `last` tells exports started afterwards not to wait for newer accounts.
+    pub fn publish_accounts(&self, accounts: AccountsSnapshot, last: bool) {
+        let mut published = self.published.lock().unwrap_or_else(|err| err.into_inner());
+        published.generation += 1;
+        published.accounts = Some(accounts);
+        published.last |= last;
+        self.published_changed.notify_all();
+    }
+
+    // Asks the ledger thread for its current accounts, or returns the last
+    // ones it published once it's done. None if it didn't answer in time.
+    // The last export to pick up accounts published mid-run takes them, so
+    // they're dropped once it's done streaming them.
+    fn request_accounts(&self, timeout: Duration) -> Option<AccountsSnapshot> {
+        let mut published = self.published.lock().unwrap_or_else(|err| err.into_inner());
+        if published.last {
+            return published.accounts.clone();
+        }
+        let generation = published.generation;
+        published.waiting += 1;
+        self.export_requested.store(true, Ordering::Relaxed);
+        let (mut published, _) = self
+            .published_changed
+            .wait_timeout_while(published, timeout, |published| {
+                published.generation == generation
+            })
+            .unwrap_or_else(|err| err.into_inner());
+        published.waiting -= 1;
+        if published.generation == generation {
+            None
+        } else if published.waiting == 0 && !published.last {
+            published.accounts.take()
+        } else {
+            published.accounts.clone()
+        }
+    }
+
+    pub fn report(&self) -> HealthReport {
+        let queue_depth = self.queued.load(Ordering::Relaxed);
+        let journal_lag = self
+            .journaled
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.flushed.load(Ordering::Relaxed));
+        HealthReport {
+            ready: !self.shutdown_requested()
+                && self.max_queue_depth.is_none_or(|max| queue_depth <= max)
+                && self.max_journal_lag.is_none_or(|max| journal_lag <= max),
+            queue_depth,
+            high_water_mark: self.high_water_mark.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            last_applied: self.processed.load(Ordering::Relaxed),
+            journal_lag,
+            finished: self.finished.load(Ordering::Relaxed),
+        }
+    }
+
+    // Returns the status code and body for a request. Liveness only needs the
+    // process to answer; readiness also checks the thresholds.
+    pub fn respond(&self, method: &str, path: &str) -> (u16, String) {
+        match (method, path) {
+            ("POST", "/shutdown") => {
+                self.request_shutdown();
+                (202, self.report().to_json())
+            }
+            ("POST", "/reload") => match self.request_reload() {
+                Ok(_) => (202, self.report().to_json()),
+                Err(err) => (
+                    400,
+                    serde_json::json!({ "error": format!("{err:?}") }).to_string(),
+                ),
+            },
+            (_, "/healthz") => (200, self.report().to_json()),
+            (_, "/readyz") if self.report().ready => (200, self.report().to_json()),
+            (_, "/readyz") => (503, self.report().to_json()),
+            _ => (404, String::new()),
+        }
+    }
+}
+
+// Frames everything written to it as one HTTP chunk, so a body can be sent
+// as it's produced without knowing its length.
+struct ChunkedWriter<W: Write>(W);
+
+impl<W: Write> ChunkedWriter<W> {
+    fn finish(mut self) -> io::Result<()> {
+        self.0.write_all(b"0\r\n\r\n")?;
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            write!(self.0, "{:X}\r\n", buf.len())?;
+            self.0.write_all(buf)?;
+            self.0.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// `GET /accounts/export?format=jsonl` streams the accounts as JSON lines, and
+// CSV otherwise. Rows are sent in chunks as the report writer fills its
+// buffer, straight from the accounts the ledger thread handed over.
+fn export(stream: &TcpStream, monitor: &HealthMonitor, query: &str) -> io::Result<()> {
+    let Some(accounts) = monitor.request_accounts(EXPORT_TIMEOUT) else {
+        return write_response(stream, 503, "");
+    };
+    let mut options = monitor.export_options.clone();
+    options.format = match query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("format="))
+    {
+        Some("jsonl") | Some("json") => ReportFormat::Json,
+        _ => ReportFormat::Csv,
+    };
+    let content_type = match options.format {
+        ReportFormat::Csv => "text/csv",
+        ReportFormat::Json => "application/x-ndjson",
+    };
+    write!(
+        &*stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut writer = ReportWriter::new(ChunkedWriter(stream), &options);
+    writer.write_accounts(accounts.iter())?;
+    writer.flush()?;
+    writer.into_inner()?.finish()
+}
+
+fn write_response(stream: &TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        503 => "Service Unavailable",
+        _ => "Not Found",
+    };
+    write!(
+        &*stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+// Anything that changes the run, like `POST /shutdown` or `POST /reload`, is
+// only taken from this host; the probes and exports answer anyone.
+fn allowed(method: &str, peer: IpAddr) -> bool {
+    method != "POST" || peer.is_loopback()
+}
+
+fn handle(stream: TcpStream, monitor: &HealthMonitor) -> io::Result<()> {
+    let mut request = String::new();
+    io::BufReader::new(&stream).read_line(&mut request)?;
+    let mut request = request.split_whitespace();
+    let (method, path) = (
+        request.next().unwrap_or_default(),
+        request.next().unwrap_or_default(),
+    );
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    if !allowed(method, stream.peer_addr()?.ip()) {
+        return write_response(&stream, 403, "");
+    }
+    if (method, path) == ("GET", "/accounts/export") {
+        return export(&stream, monitor, query);
+    }
+    let (status, body) = monitor.respond(method, path);
+    write_response(&stream, status, &body)
+}
+
+// Answers /healthz, /readyz, POST /shutdown, POST /reload and
+// GET /accounts/export on background threads for as long as the process runs,
+// one per connection so a long export doesn't hold up the others.
+pub fn serve(addr: impl ToSocketAddrs, monitor: Arc<HealthMonitor>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let monitor = monitor.clone();
+            thread::spawn(move || handle(stream, &monitor));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod health_tests {
+    use super::{allowed, serve, HealthMonitor};
+    use crate::account::{num, ClientId, Number};
+    use crate::ledger::Ledger;
+    use crate::transactions::{Operation, Transaction, TransactionId};
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn readiness_thresholds() {
+        let monitor = HealthMonitor::new(Some(1), Some(2));
+        monitor.enqueued();
+        monitor.enqueued();
+        assert_eq!(monitor.respond("GET", "/readyz").0, 503);
+        assert_eq!(monitor.respond("GET", "/healthz").0, 200);
+        monitor.processed(1);
+        monitor.processed(3);
+        let report = monitor.report();
+        assert_eq!(report.queue_depth, 0);
+        assert_eq!(report.high_water_mark, 2);
+        assert_eq!(report.last_applied, 2);
+        assert_eq!(report.journal_lag, 3);
+        assert!(!report.ready);
+        monitor.flushed(3);
+        assert_eq!(
+            monitor.respond("GET", "/readyz"),
+            (
+                200,
+                "{\"ready\":true,\"queue_depth\":0,\"high_water_mark\":2,\"dropped\":0,\"last_applied\":2,\"journal_lag\":0,\"finished\":false}"
+                    .to_string()
+            )
+        );
+        assert_eq!(monitor.respond("GET", "/metrics").0, 404);
+        assert_eq!(monitor.respond("GET", "/shutdown").0, 404);
+        assert_eq!(monitor.respond("POST", "/shutdown").0, 202);
+        assert!(monitor.shutdown_requested());
+        assert_eq!(monitor.respond("GET", "/readyz").0, 503);
+    }
+
+    #[test]
+    fn reload_policy() {
+        let path = std::env::temp_dir().join(format!("crab-reload-{}.toml", std::process::id()));
+        fs::write(&path, "[policy]\nallow_adjustments = true\n").unwrap();
+        assert_eq!(HealthMonitor::default().respond("POST", "/reload").0, 400);
+        let monitor = HealthMonitor::default().with_config(path.to_str().unwrap());
+        assert_eq!(monitor.take_reloaded_policy(), None);
+        assert_eq!(monitor.respond("GET", "/reload").0, 404);
+        assert_eq!(monitor.respond("POST", "/reload").0, 202);
+        let policy = monitor.take_reloaded_policy().unwrap();
+        assert!(policy.allow_adjustments);
+        assert_eq!(monitor.take_reloaded_policy(), None);
+
+        // a broken file leaves the running policy alone
+        fs::write(&path, "[policy]\nallow_adjustments = 1\n").unwrap();
+        let (status, body) = monitor.respond("POST", "/reload");
+        assert_eq!(status, 400);
+        assert!(body.starts_with("{\"error\":"));
+        assert_eq!(monitor.take_reloaded_policy(), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn admin_requests_only_from_this_host() {
+        let remote = "10.0.0.7".parse().unwrap();
+        assert!(allowed("GET", remote));
+        assert!(!allowed("POST", remote));
+        assert!(allowed("POST", "127.0.0.1".parse().unwrap()));
+        assert!(allowed("POST", "::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn shutdown_on_sigterm() {
+        let monitor = HealthMonitor::default();
+        monitor.shutdown_on_signals().unwrap();
+        assert!(!monitor.shutdown_requested());
+        signal_hook::low_level::raise(signal_hook::consts::SIGTERM).unwrap();
+        assert!(monitor.shutdown_requested());
+    }
+
+    #[test]
+    fn serve_over_http() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        serve(addr, Arc::new(HealthMonitor::default())).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /healthz HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\"finished\":false}"));
+    }
+
+    #[test]
+    fn export_accounts_in_chunks() {
+        let mut ledger = Ledger::new();
+        for client in 1..=3 {
+            let deposit = Transaction::new(ClientId(client), num!(10), Operation::Deposit);
+            ledger
+                .apply_transaction(TransactionId(client.into()), &deposit)
+                .unwrap();
+        }
+        ledger.archive_account(ClientId(2)).unwrap();
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let monitor = Arc::new(HealthMonitor::default());
+        serve(addr, monitor.clone()).unwrap();
+
+        // the ledger thread hands its accounts over between rows
+        let ledger_thread = {
+            let monitor = monitor.clone();
+            let accounts = ledger.accounts_snapshot();
+            thread::spawn(move || {
+                while !monitor.take_export_request() {
+                    thread::yield_now();
+                }
+                monitor.publish_accounts(accounts, false);
+            })
+        };
+        let response = get(addr, "/accounts/export?format=jsonl");
+        ledger_thread.join().unwrap();
+        // nothing holds on to the accounts once they're exported
+        assert!(monitor.published.lock().unwrap().accounts.is_none());
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Transfer-Encoding: chunked"));
+        let rows = "{\"client\":1,\"available\":\"10.0000\",\"held\":\"0.0000\",\"total\":\"10.0000\",\"locked\":false}\n\
+            {\"client\":3,\"available\":\"10.0000\",\"held\":\"0.0000\",\"total\":\"10.0000\",\"locked\":false}\n";
+        assert_eq!(body, format!("{:X}\r\n{rows}\r\n0\r\n\r\n", rows.len()));
+
+        // once the input is done, exports use the last accounts right away
+        let deposit = Transaction::new(ClientId(4), Number::ONE, Operation::Deposit);
+        ledger
+            .apply_transaction(TransactionId(4), &deposit)
+            .unwrap();
+        monitor.publish_accounts(ledger.accounts_snapshot(), true);
+        let response = get(addr, "/accounts/export");
+        assert!(response.contains("text/csv"));
+        assert!(response.contains("\r\nclient,available,held,total,locked\n1,"));
+        assert!(response.contains("\n4,1.0000,0.0000,1.0000,false\n"));
+    }
+}
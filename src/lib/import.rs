@@ -0,0 +1,169 @@
+use std::io::{self, Write};
+
+use super::account::Number;
+use super::ledger::Ledger;
+use super::transactions::{Operation, Transaction, TransactionId};
+
+#[derive(Copy, Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DisputeAction {
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+impl From<DisputeAction> for Operation {
+    fn from(value: DisputeAction) -> Self {
+        match value {
+            DisputeAction::Dispute => Operation::Dispute,
+            DisputeAction::Resolve => Operation::Resolve,
+            DisputeAction::Chargeback => Operation::Chargeback,
+        }
+    }
+}
+
+// One row of the case-management export: tx,reason,case,action. Rows don't
+// carry the client, it is taken from the transaction they refer to.
+#[derive(serde::Deserialize)]
+struct DisputeRecord {
+    tx: u32,
+    #[serde(default)]
+    reason: String,
+    #[serde(default)]
+    case: String,
+    action: DisputeAction,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportFailure {
+    pub line: u64,
+    pub transaction_id: Option<TransactionId>,
+    pub case: String,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub applied: usize,
+    pub failures: Vec<ImportFailure>,
+}
+
+pub fn import_disputes<R: io::Read>(reader: R, ledger: &mut Ledger) -> ImportSummary {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let headers = reader.headers().cloned().unwrap_or_default();
+    let mut summary = ImportSummary::default();
+    for fields in reader.records() {
+        let (line, result) = match fields {
+            Ok(fields) => (
+                fields.position().map_or(0, |position| position.line()),
+                fields.deserialize::<DisputeRecord>(Some(&headers)),
+            ),
+            Err(err) => (
+                err.position().map_or(0, |position| position.line()),
+                Err(err),
+            ),
+        };
+        let record = match result {
+            Ok(record) => record,
+            Err(err) => {
+                summary.failures.push(ImportFailure {
+                    line,
+                    transaction_id: None,
+                    case: String::new(),
+                    error: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let transaction_id = TransactionId(record.tx);
+        let applied = match ledger.transaction(transaction_id) {
+            None => Err("unknown transaction".to_string()),
+            Some(disputed) => {
+                let transaction =
+                    Transaction::new(disputed.client_id(), Number::ZERO, record.action.into());
+                ledger
+                    .apply_transaction(transaction_id, &transaction)
+                    .map(|_| ())
+                    .map_err(|err| format!("{:?}", err))
+            }
+        };
+        match applied {
+            Ok(()) => summary.applied += 1,
+            Err(error) => summary.failures.push(ImportFailure {
+                line,
+                transaction_id: Some(transaction_id),
+                case: record.case,
+                error: if record.reason.is_empty() {
+                    error
+                } else {
+                    format!("{error} ({})", record.reason)
+                },
+            }),
+        }
+    }
+    summary
+}
+
+// Rows that couldn't be applied: line,tx,case,error.
+pub fn write_reconciliation<W: Write>(writer: W, summary: &ImportSummary) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["line", "tx", "case", "error"])?;
+    for failure in &summary.failures {
+        writer.write_record([
+            failure.line.to_string(),
+            failure
+                .transaction_id
+                .map_or(String::new(), |transaction_id| transaction_id.0.to_string()),
+            failure.case.clone(),
+            failure.error.clone(),
+        ])?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::{import_disputes, write_reconciliation};
+    use crate::account::{num, ClientId, Number};
+    use crate::ledger::Ledger;
+    use crate::transactions::{Operation, Transaction, TransactionId};
+
+    #[test]
+    fn import_and_reconcile() {
+        let mut ledger = Ledger::new();
+        for id in 1..=2 {
+            let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+            ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .unwrap();
+        }
+        let input = "tx,reason,case,action
+1,fraud,C-1,dispute
+2,fraud,C-1,dispute
+2,,C-1,chargeback
+1,duplicate,C-1,dispute
+9,fraud,C-2,dispute
+1,fraud,C-1,refund
+";
+        let summary = import_disputes(input.as_bytes(), &mut ledger);
+        assert_eq!(summary.applied, 3);
+        let account = ledger.account(ClientId(1)).unwrap();
+        assert_eq!(account.available(), Number::ZERO);
+        assert_eq!(account.held(), num!(5));
+        assert!(account.locked());
+
+        let mut report = Vec::new();
+        write_reconciliation(&mut report, &summary).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[1],
+            "5,1,C-1,AlreadyDisputed(TransactionId(1)) (duplicate)"
+        );
+        assert_eq!(lines[2], "6,9,C-2,unknown transaction (fraud)");
+        assert!(lines[3].starts_with("7,,,"));
+    }
+}
@@ -0,0 +1,208 @@
+use super::ranking::BalanceIndex;
+use crate::account::{Account, ClientId, Number};
+use imbl::Vector;
+use std::fmt;
+
+const EMPTY: u32 = u32::MAX;
+
+// Balances are stored column-wise and only for clients that had at least one
+// successful transaction. `slots` maps a client id to its row and only grows up
+// to the highest client id seen. Columns are persistent vectors, so a clone
+// shares them and a write only copies the chunks it touches.
+#[derive(Clone, Default)]
+pub struct AccountTable {
+    slots: Vector<u32>,
+    ids: Vector<ClientId>,
+    available: Vector<Number>,
+    held: Vector<Number>,
+    locked: Vector<u64>,
+    index: Option<BalanceIndex>,
+}
+
+impl AccountTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.available.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.available.is_empty()
+    }
+
+    fn slot(&self, client_id: &ClientId) -> Option<usize> {
+        match self.slots.get(client_id.0 as usize) {
+            Some(&slot) if slot != EMPTY => Some(slot as usize),
+            _ => None,
+        }
+    }
+
+    fn load(&self, slot: usize) -> Account {
+        let locked = self.locked[slot / 64] & (1 << (slot % 64)) != 0;
+        Account::from_parts(self.available[slot], self.held[slot], locked)
+    }
+
+    pub fn get(&self, client_id: &ClientId) -> Option<Account> {
+        self.slot(client_id).map(|slot| self.load(slot))
+    }
+
+    pub fn insert(&mut self, client_id: ClientId, account: Account) {
+        let previous = self.index.as_ref().and_then(|_| self.get(&client_id));
+        if let Some(index) = &mut self.index {
+            index.update(client_id, previous, Some(account));
+        }
+        let slot = match self.slot(&client_id) {
+            Some(slot) => slot,
+            None => {
+                let index = client_id.0 as usize;
+                if self.slots.len() <= index {
+                    let missing = index + 1 - self.slots.len();
+                    self.slots.extend(std::iter::repeat_n(EMPTY, missing));
+                }
+                let slot = self.available.len();
+                self.slots[index] = slot as u32;
+                self.ids.push_back(client_id);
+                self.available.push_back(Number::ZERO);
+                self.held.push_back(Number::ZERO);
+                if slot.is_multiple_of(64) {
+                    self.locked.push_back(0);
+                }
+                slot
+            }
+        };
+        self.store(slot, &account);
+    }
+
+    fn store(&mut self, slot: usize, account: &Account) {
+        self.available[slot] = account.available();
+        self.held[slot] = account.held();
+        if account.locked() {
+            self.locked[slot / 64] |= 1 << (slot % 64);
+        } else {
+            self.locked[slot / 64] &= !(1 << (slot % 64));
+        }
+    }
+
+    // Moves the last row into the removed one to keep the columns dense.
+    pub fn remove(&mut self, client_id: &ClientId) -> Option<Account> {
+        let slot = self.slot(client_id)?;
+        let account = self.load(slot);
+        if let Some(index) = &mut self.index {
+            index.update(*client_id, Some(account), None);
+        }
+        let last = self.len() - 1;
+        if slot != last {
+            let moved = self.load(last);
+            let moved_id = self.ids[last];
+            self.store(slot, &moved);
+            self.ids[slot] = moved_id;
+            self.slots[moved_id.0 as usize] = slot as u32;
+        }
+        self.store(last, &Account::default());
+        self.ids.pop_back();
+        self.available.pop_back();
+        self.held.pop_back();
+        if last.is_multiple_of(64) {
+            self.locked.pop_back();
+        }
+        self.slots[client_id.0 as usize] = EMPTY;
+        Some(account)
+    }
+
+    pub fn enable_index(&mut self) {
+        if self.index.is_none() {
+            let mut index = BalanceIndex::default();
+            for (client_id, account) in self.iter() {
+                index.update(client_id, None, Some(account));
+            }
+            self.index = Some(index);
+        }
+    }
+
+    pub fn index(&self) -> Option<&BalanceIndex> {
+        self.index.as_ref()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(0)
+    }
+
+    pub fn iter_after(&self, cursor: Option<ClientId>) -> Iter<'_> {
+        self.iter_from(cursor.map_or(0, |client_id| client_id.0 as usize + 1))
+    }
+
+    fn iter_from(&self, next: usize) -> Iter<'_> {
+        Iter { table: self, next }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = Account> + '_ {
+        (0..self.len()).map(|slot| self.load(slot))
+    }
+
+    fn next_from(&self, next: &mut usize) -> Option<(ClientId, Account)> {
+        while *next < self.slots.len() {
+            let index = *next;
+            *next += 1;
+            if self.slots[index] != EMPTY {
+                let account = self.load(self.slots[index] as usize);
+                return Some((ClientId(index as u16), account));
+            }
+        }
+        None
+    }
+}
+
+// Tables are equal when they hold the same accounts, wherever their rows are.
+impl PartialEq for AccountTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for AccountTable {}
+
+impl fmt::Debug for AccountTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a> {
+    table: &'a AccountTable,
+    next: usize,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (ClientId, Account);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.table.next_from(&mut self.next)
+    }
+}
+
+pub struct IntoIter {
+    table: AccountTable,
+    next: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = (ClientId, Account);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.table.next_from(&mut self.next)
+    }
+}
+
+impl IntoIterator for AccountTable {
+    type Item = (ClientId, Account);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            table: self,
+            next: 0,
+        }
+    }
+}
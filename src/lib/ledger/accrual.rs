@@ -0,0 +1,93 @@
+use super::{clock::SECONDS_PER_DAY, AuditEvent, Ledger, Timestamp};
+use crate::{
+    account::ClientId, account::Number, transactions::TransactionId, transactions::TransactionState,
+};
+
+// An amount credited to, or with a negative amount taken from, the account of
+// a client whose deposit stayed disputed past `HeldAccrual::after_days`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Accrual {
+    pub transaction_id: TransactionId,
+    pub client_id: ClientId,
+    pub timestamp: Timestamp,
+    pub days: u64,
+    pub amount: Number,
+}
+
+impl Ledger {
+    // The accruals linked to a disputed transaction, oldest first.
+    pub fn accruals(&self, transaction_id: TransactionId) -> impl Iterator<Item = &Accrual> {
+        self.accruals
+            .iter()
+            .filter(move |accrual| accrual.transaction_id == transaction_id)
+    }
+
+    // Called when the clock moves to another day. Each open dispute accrues
+    // `daily_rate` of its held amount for every full day past `after_days`
+    // it hasn't accrued for yet, counted from when it was last opened.
+    // Disputes restored from a snapshot don't know when that was and accrue
+    // nothing.
+    pub(super) fn accrue_held_funds(&mut self) {
+        let Some(rate) = self.policy.held_accrual else {
+            return;
+        };
+        let due: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|(_, transaction)| transaction.state() == TransactionState::Disputed)
+            .filter_map(|(transaction_id, transaction)| {
+                let opened_at = self.dispute_opened_at(*transaction_id)?;
+                let days = (self.now.0.saturating_sub(opened_at.0) / SECONDS_PER_DAY)
+                    .saturating_sub(rate.after_days);
+                let accrued = match self.accrued_days.get(transaction_id) {
+                    Some((since, accrued)) if *since == opened_at => *accrued,
+                    _ => 0,
+                };
+                (days > accrued).then_some((
+                    *transaction_id,
+                    *transaction,
+                    opened_at,
+                    days,
+                    accrued,
+                ))
+            })
+            .collect();
+        for (transaction_id, transaction, opened_at, days, accrued) in due {
+            self.accrued_days.insert(transaction_id, (opened_at, days));
+            let days = days - accrued;
+            let amount = (transaction.amount() * rate.daily_rate * Number::from(days)).round_dp(4);
+            self.apply_accrual(transaction_id, transaction.client_id(), days, amount);
+        }
+    }
+
+    // Penalties stop at the available funds rather than overdrawing them.
+    fn apply_accrual(
+        &mut self,
+        transaction_id: TransactionId,
+        client_id: ClientId,
+        days: u64,
+        amount: Number,
+    ) {
+        let holder = self.account_holder(client_id);
+        let Some(mut account) = self.accounts.get(&holder) else {
+            return;
+        };
+        let amount = amount.max(-account.available());
+        if amount == Number::ZERO || account.adjust(amount).is_err() {
+            return;
+        }
+        self.accounts.insert(holder, account);
+        self.accruals.push_back(Accrual {
+            transaction_id,
+            client_id,
+            timestamp: self.now,
+            days,
+            amount,
+        });
+        self.audit(AuditEvent::HeldFundsAccrued {
+            client_id,
+            transaction_id,
+            amount,
+        });
+    }
+}
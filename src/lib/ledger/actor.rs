@@ -0,0 +1,151 @@
+use super::{CommandOutput, Ledger, LedgerCommand};
+use crate::transactions::{Outcome, Transaction, TransactionId, TransactionResult};
+
+use std::sync::mpsc;
+use std::thread;
+
+type Job = Box<dyn FnOnce(&mut Ledger) + Send>;
+
+enum Message {
+    Run(Job),
+    Stop,
+}
+
+// The actor's thread is gone, because it was stopped or a command panicked.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ActorStopped;
+
+// Owns a ledger on a thread of its own, which runs the commands sent to it
+// one at a time in the order they arrive. Any number of threads can hold a
+// handle and wait on their own command's result, without the ledger ever
+// being shared.
+pub struct LedgerActor {
+    mailbox: mpsc::Sender<Message>,
+    worker: Option<thread::JoinHandle<Ledger>>,
+}
+
+#[derive(Clone)]
+pub struct LedgerHandle {
+    mailbox: mpsc::Sender<Message>,
+}
+
+impl LedgerActor {
+    pub fn spawn(mut ledger: Ledger) -> Self {
+        let (mailbox, messages) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            for message in messages {
+                match message {
+                    Message::Run(job) => job(&mut ledger),
+                    Message::Stop => break,
+                }
+            }
+            ledger
+        });
+        Self {
+            mailbox,
+            worker: Some(worker),
+        }
+    }
+
+    pub fn handle(&self) -> LedgerHandle {
+        LedgerHandle {
+            mailbox: self.mailbox.clone(),
+        }
+    }
+
+    // Runs the commands already sent and hands the ledger back. Commands
+    // sent afterwards fail with `ActorStopped`.
+    pub fn stop(mut self) -> Result<Ledger, ActorStopped> {
+        let _ = self.mailbox.send(Message::Stop);
+        let worker = self.worker.take().ok_or(ActorStopped)?;
+        worker.join().map_err(|_| ActorStopped)
+    }
+}
+
+impl Drop for LedgerActor {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = self.mailbox.send(Message::Stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+impl LedgerHandle {
+    // Runs `command` on the actor's thread and waits for what it returns.
+    pub fn call<R, F>(&self, command: F) -> Result<R, ActorStopped>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Ledger) -> R + Send + 'static,
+    {
+        let (reply, result) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |ledger| {
+            let _ = reply.send(command(ledger));
+        });
+        self.mailbox
+            .send(Message::Run(job))
+            .map_err(|_| ActorStopped)?;
+        result.recv().map_err(|_| ActorStopped)
+    }
+
+    pub fn apply_transaction(
+        &self,
+        transaction_id: TransactionId,
+        transaction: Transaction,
+    ) -> Result<TransactionResult<Outcome>, ActorStopped> {
+        self.call(move |ledger| ledger.apply_transaction(transaction_id, &transaction))
+    }
+
+    pub fn execute(
+        &self,
+        command: LedgerCommand,
+    ) -> Result<TransactionResult<CommandOutput>, ActorStopped> {
+        self.call(move |ledger| ledger.execute(command))
+    }
+}
+
+#[cfg(test)]
+mod actor_tests {
+    use super::{ActorStopped, LedgerActor};
+    use crate::{
+        account::num, account::ClientId, ledger::Ledger, transactions::Operation,
+        transactions::Transaction, transactions::TransactionError, transactions::TransactionId,
+    };
+    use std::thread;
+
+    #[test]
+    fn commands_from_many_threads() {
+        let actor = LedgerActor::spawn(Ledger::new());
+        thread::scope(|scope| {
+            for client in 0..4u16 {
+                let handle = actor.handle();
+                scope.spawn(move || {
+                    for i in 0..50u32 {
+                        let id = TransactionId(client as u32 * 50 + i);
+                        let deposit =
+                            Transaction::new(ClientId(client), num!(0.5), Operation::Deposit);
+                        assert!(handle.apply_transaction(id, deposit).unwrap().is_ok());
+                    }
+                });
+            }
+        });
+        let handle = actor.handle();
+        let deposit = Transaction::new(ClientId(9), num!(1), Operation::Deposit);
+        assert_eq!(
+            handle.apply_transaction(TransactionId(0), deposit),
+            Ok(Err(TransactionError::RepeatedTransactionId(TransactionId(
+                0
+            ))))
+        );
+        assert_eq!(
+            handle.call(|ledger| ledger
+                .account(ClientId(3))
+                .map(|account| account.available())),
+            Ok(Some(num!(25)))
+        );
+
+        let ledger = actor.stop().unwrap();
+        assert_eq!(ledger.into_iter().count(), 4);
+        assert_eq!(handle.call(|_| ()), Err(ActorStopped));
+    }
+}
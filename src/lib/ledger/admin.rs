@@ -0,0 +1,216 @@
+use super::{AuditEvent, Change, EscrowState, Ledger};
+use crate::{
+    account::ClientId, account::Number, transactions::Operation, transactions::Transaction,
+    transactions::TransactionError, transactions::TransactionId, transactions::TransactionResult,
+    transactions::TransactionState,
+};
+use imbl::Vector;
+
+// What remains of an erased client: its balances, so ledger-wide totals stay
+// intact, without anything tying them back to the client.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tombstone {
+    pub available: Number,
+    pub held: Number,
+    pub locked: bool,
+    pub transactions: usize,
+}
+
+impl Ledger {
+    fn account_exists(&self, client_id: ClientId) -> TransactionResult {
+        match self.accounts.get(&client_id) {
+            Some(_) => Ok(()),
+            None => Err(TransactionError::UnknownClientId(client_id)),
+        }
+    }
+
+    pub fn archive_account(&mut self, client_id: ClientId) -> TransactionResult {
+        self.account_exists(client_id)?;
+        if self.archived.insert(client_id).is_some() {
+            return Err(TransactionError::ArchivedAccount(client_id));
+        }
+        self.audit(AuditEvent::AccountArchived(client_id));
+        Ok(())
+    }
+
+    pub fn restore_account(&mut self, client_id: ClientId) -> TransactionResult {
+        self.account_exists(client_id)?;
+        if self.archived.remove(&client_id).is_some() {
+            self.audit(AuditEvent::AccountRestored(client_id));
+        }
+        Ok(())
+    }
+
+    // Lets a client locked by a chargeback deposit and withdraw again once
+    // someone has reviewed the account. Only allowed with
+    // `Policy::allow_unlocks`; balances and disputes are left as they are.
+    // Applied as an unlock of the client's latest chargeback, so it's
+    // journaled and replayed like any other transaction.
+    pub fn unlock_account(&mut self, client_id: ClientId) -> TransactionResult {
+        if !self.policy.allow_unlocks {
+            return Err(TransactionError::UnlocksDisabled(client_id));
+        }
+        let account = self
+            .accounts
+            .get(&client_id)
+            .ok_or(TransactionError::UnknownClientId(client_id))?;
+        if !account.locked() {
+            return Ok(());
+        }
+        let (transaction_id, charged_back) = self
+            .transactions
+            .iter()
+            .rev()
+            .find(|(_, transaction)| {
+                transaction.state() == TransactionState::Chargedback
+                    && self.account_holder(transaction.client_id()) == client_id
+            })
+            .ok_or(TransactionError::NotChargedBack(client_id))?;
+        let unlock = Transaction::new(charged_back.client_id(), Number::ZERO, Operation::Unlock);
+        self.apply_transaction(*transaction_id, &unlock).map(|_| ())
+    }
+
+    pub(super) fn prepare_unlock(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> Result<Change, TransactionError> {
+        if !self.policy.allow_unlocks {
+            return Err(TransactionError::UnlocksDisabled(transaction.client_id()));
+        }
+        self.prepare_disputed_transaction(
+            transaction_id,
+            transaction,
+            TransactionError::NotChargedBack(transaction.client_id()),
+            |_, account| {
+                account.unlock();
+                Ok(())
+            },
+        )
+    }
+
+    pub(super) fn audit_unlock(&mut self, transaction: &Transaction, change: &Change) {
+        if transaction.operation() == Operation::Unlock {
+            self.audit(AuditEvent::AccountUnlocked(change.client_id));
+        }
+    }
+
+    // Removes everything that ties the ledger to the client: its account,
+    // transactions, journal entries, reviews and escrows. Funds still in an
+    // open escrow have nowhere to go once the client is gone, so those have
+    // to be released or refunded first.
+    pub fn erase_client(&mut self, client_id: ClientId) -> Result<usize, TransactionError> {
+        self.account_exists(client_id)?;
+        if self.escrows.values().any(|escrow| {
+            escrow.state == EscrowState::Open
+                && (escrow.depositor == client_id || escrow.beneficiary == client_id)
+        }) {
+            return Err(TransactionError::OpenEscrow(client_id));
+        }
+        let account = self
+            .accounts
+            .remove(&client_id)
+            .ok_or(TransactionError::UnknownClientId(client_id))?;
+        let transaction_ids: Vec<TransactionId> = self
+            .transactions
+            .iter()
+            .filter(|(_, transaction)| transaction.client_id() == client_id)
+            .map(|(transaction_id, _)| *transaction_id)
+            .collect();
+        let review_ids: Vec<TransactionId> = self
+            .overflow_reviews
+            .iter()
+            .filter(|(_, transaction)| transaction.client_id() == client_id)
+            .map(|(transaction_id, _)| *transaction_id)
+            .collect();
+        for transaction_id in &transaction_ids {
+            self.transactions.remove(transaction_id);
+            self.history.remove(transaction_id);
+            self.accrued_days.remove(transaction_id);
+            self.screenings.remove(transaction_id);
+        }
+        self.accruals
+            .retain(|accrual| accrual.client_id != client_id);
+        self.sweeps.retain(|sweep| sweep.client_id != client_id);
+        let archived_ids: Vec<TransactionId> = self
+            .archived_transactions
+            .iter()
+            .filter(|(_, owner)| **owner == client_id)
+            .map(|(transaction_id, _)| *transaction_id)
+            .collect();
+        for transaction_id in &archived_ids {
+            self.archived_transactions.remove(transaction_id);
+        }
+        for transaction_id in &review_ids {
+            self.overflow_reviews.remove(transaction_id);
+        }
+        // erased ids stay reserved so resubmitted records can't be applied again
+        self.erased_transactions.extend(
+            transaction_ids
+                .iter()
+                .chain(&archived_ids)
+                .chain(&review_ids)
+                .copied(),
+        );
+        if let Some(timeline) = &mut self.timeline {
+            *timeline = std::mem::take(timeline)
+                .into_iter()
+                .filter(|(_, transaction_id)| !self.erased_transactions.contains(transaction_id))
+                .collect();
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.erase_client(client_id);
+        }
+        self.escrows = std::mem::take(&mut self.escrows)
+            .into_iter()
+            .filter(|(_, escrow)| escrow.depositor != client_id && escrow.beneficiary != client_id)
+            .collect();
+        self.retained.remove(&client_id);
+        self.risk.remove(&client_id);
+        self.quarantined.remove(&client_id);
+        self.frozen.remove(&client_id);
+        self.reviews.remove(&client_id);
+        self.remove_from_groups(client_id);
+        self.last_activity.remove(&client_id);
+        self.dormant.remove(&client_id);
+        self.cases = std::mem::take(&mut self.cases)
+            .into_iter()
+            .filter(|(_, case)| case.client_id != client_id)
+            .collect();
+        self.archived.remove(&client_id);
+        self.open_disputes.remove(&client_id);
+        self.tiers.remove(&client_id);
+        self.opened.remove(&client_id);
+        self.settings.remove(&client_id);
+        self.daily_volume.remove(&client_id);
+        self.withdrawals.remove(&client_id);
+        let tombstone = self.tombstones.len();
+        self.tombstones.push_back(Tombstone {
+            available: account.available(),
+            held: account.held(),
+            locked: account.locked(),
+            transactions: transaction_ids.len(),
+        });
+        self.audit(AuditEvent::ClientErased {
+            tombstone,
+            transactions: transaction_ids.len(),
+        });
+        Ok(tombstone)
+    }
+
+    pub fn tombstones(&self) -> &Vector<Tombstone> {
+        &self.tombstones
+    }
+
+    pub fn is_archived(&self, client_id: ClientId) -> bool {
+        self.archived.contains(&client_id)
+    }
+
+    pub(super) fn check_archived(&self, client_id: ClientId) -> TransactionResult {
+        if self.is_archived(client_id) {
+            Err(TransactionError::ArchivedAccount(client_id))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use super::{clock::SECONDS_PER_DAY, Ledger};
+use crate::account::{ClientId, Number};
+
+// Names of the dispute age buckets, in days.
+pub const AGE_BUCKETS: [&str; 4] = ["0-30", "31-60", "61-90", "90+"];
+
+// Held funds by how long their dispute has been open, for provisioning
+// chargeback losses.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeldAging {
+    pub buckets: [Number; 4],
+}
+
+impl HeldAging {
+    fn add(&mut self, days: u64, amount: Number) {
+        let bucket = match days {
+            0..=30 => 0,
+            31..=60 => 1,
+            61..=90 => 2,
+            _ => 3,
+        };
+        self.buckets[bucket] += amount;
+    }
+
+    pub fn total(&self) -> Number {
+        self.buckets.iter().sum()
+    }
+
+    pub fn sum<'a>(agings: impl IntoIterator<Item = &'a HeldAging>) -> HeldAging {
+        let mut sum = HeldAging::default();
+        for aging in agings {
+            for (total, held) in sum.buckets.iter_mut().zip(aging.buckets) {
+                *total += held;
+            }
+        }
+        sum
+    }
+}
+
+impl Ledger {
+    // One entry per client with funds held in dispute, by client id. Ages
+    // follow the ledger's clock; disputes restored from a snapshot don't
+    // know when they were opened and count as the oldest.
+    pub fn held_aging(&self) -> Vec<(ClientId, HeldAging)> {
+        let mut aging: Vec<(ClientId, HeldAging)> = Vec::new();
+        for (transaction_id, transaction) in self.disputes() {
+            let days = self
+                .dispute_opened_at(transaction_id)
+                .map_or(u64::MAX, |opened_at| {
+                    self.now.0.saturating_sub(opened_at.0) / SECONDS_PER_DAY
+                });
+            let client_id = transaction.client_id();
+            let index = match aging.binary_search_by_key(&client_id, |(client_id, _)| *client_id) {
+                Ok(index) => index,
+                Err(index) => {
+                    aging.insert(index, (client_id, HeldAging::default()));
+                    index
+                }
+            };
+            aging[index].1.add(days, transaction.amount());
+        }
+        aging
+    }
+}
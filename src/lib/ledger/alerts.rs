@@ -0,0 +1,73 @@
+use super::{FreezeReason, Ledger};
+use crate::account::{ClientId, Number};
+
+// Which standing conditions are reported. Negative balances, locked and
+// frozen accounts are reported unless turned off; the thresholds only when
+// set.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AlertRules {
+    pub negative_available: bool,
+    // Left by chargebacks of more than was held, see `ChargebackExcess`.
+    pub negative_held: bool,
+    pub locked: bool,
+    pub frozen: bool,
+    pub held_above: Option<Number>,
+    pub dormant_days: Option<u64>,
+}
+
+impl Default for AlertRules {
+    fn default() -> Self {
+        Self {
+            negative_available: true,
+            negative_held: true,
+            locked: true,
+            frozen: true,
+            held_above: None,
+            dormant_days: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Alert {
+    NegativeAvailable(Number),
+    NegativeHeld(Number),
+    Locked,
+    Frozen(FreezeReason),
+    HeldAbove(Number),
+    Dormant { days: u64 },
+}
+
+impl Ledger {
+    // Accounts matching any of the rules, ordered by client id. An account
+    // matching several rules appears once for each.
+    pub fn alerts(&self, rules: &AlertRules) -> Vec<(ClientId, Alert)> {
+        let mut alerts = Vec::new();
+        for (client_id, account) in self.accounts.iter() {
+            if self.is_archived(client_id) {
+                continue;
+            }
+            if rules.negative_available && account.available() < Number::ZERO {
+                alerts.push((client_id, Alert::NegativeAvailable(account.available())));
+            }
+            if rules.negative_held && account.held() < Number::ZERO {
+                alerts.push((client_id, Alert::NegativeHeld(account.held())));
+            }
+            if rules.locked && account.locked() {
+                alerts.push((client_id, Alert::Locked));
+            }
+            match self.freeze_reason(client_id) {
+                Some(reason) if rules.frozen => alerts.push((client_id, Alert::Frozen(reason))),
+                _ => {}
+            }
+            if rules.held_above.is_some_and(|max| account.held() > max) {
+                alerts.push((client_id, Alert::HeldAbove(account.held())));
+            }
+            let idle = self.idle_days(client_id);
+            if rules.dormant_days.is_some_and(|days| idle >= days) {
+                alerts.push((client_id, Alert::Dormant { days: idle }));
+            }
+        }
+        alerts
+    }
+}
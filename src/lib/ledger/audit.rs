@@ -0,0 +1,85 @@
+use super::{events::LedgerEvent, FreezeReason, Ledger, ScreeningOutcome, Timestamp};
+use crate::account::{ClientId, Number};
+use crate::transactions::TransactionId;
+use imbl::Vector;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuditEvent {
+    AccountArchived(ClientId),
+    AccountRestored(ClientId),
+    AccountQuarantined(ClientId),
+    AccountReactivated(ClientId),
+    AccountFrozen {
+        client_id: ClientId,
+        reason: FreezeReason,
+    },
+    AccountUnfrozen(ClientId),
+    // The chargeback lock lifted by an operator.
+    AccountUnlocked(ClientId),
+    QuarantineReleased {
+        client_id: ClientId,
+        released: Number,
+    },
+    // Only the tombstone, the client id is gone with everything else.
+    ClientErased {
+        tombstone: usize,
+        transactions: usize,
+    },
+    // A deposit credited only in part because the balance hit its limit.
+    DepositSaturated {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        excess: Number,
+    },
+    // A chargeback that took only what was held.
+    ChargebackClamped {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        excess: Number,
+    },
+    DepositHeldForReview {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    },
+    HeldFundsAccrued {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        amount: Number,
+    },
+    PeriodClosed(Timestamp),
+    ScreeningRejected {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        outcome: ScreeningOutcome,
+    },
+    // One for each side of a sweep: taken from the idle account, credited to
+    // the sweep account.
+    BalanceSwept {
+        sweep: u64,
+        client_id: ClientId,
+        amount: Number,
+    },
+    TransactionFlagged {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    },
+    ReviewApproved {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    },
+    ReviewRejected {
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    },
+}
+
+impl Ledger {
+    pub fn audit_log(&self) -> &Vector<AuditEvent> {
+        &self.audit_log
+    }
+
+    pub(super) fn audit(&mut self, event: AuditEvent) {
+        self.audit_log.push_back(event);
+        self.events.publish(LedgerEvent::Audit(event));
+    }
+}
@@ -0,0 +1,168 @@
+use super::{AccountStore, Journal, JournalEntry, Ledger, TransactionStore};
+use crate::account::{Account, ClientId};
+use crate::transactions::{Outcome, Transaction, TransactionId, TransactionResult};
+use imbl::Vector;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+pub const DEFAULT_BATCH_SIZE: usize = 256;
+
+// Buffers writes to a persistent store and flushes them in batches. Repeated
+// writes to the same account or transaction are coalesced into one.
+//
+// Callers journal a transaction before handing its writes over, so the store
+// never holds state the journal doesn't. Within a flush transactions go out
+// before accounts and the high-water mark moves last: after a crash the store
+// is consistent up to `flushed_sequence` and the journal entries after it are
+// replayed on top.
+pub struct BatchedStore<A, T> {
+    accounts: A,
+    transactions: T,
+    batch_size: usize,
+    pending_accounts: BTreeMap<ClientId, Option<Account>>,
+    pending_transactions: BTreeMap<TransactionId, Transaction>,
+    pending_sequence: u64,
+    flushed_sequence: u64,
+    flushes: u64,
+}
+
+impl<A: AccountStore, T: TransactionStore> BatchedStore<A, T> {
+    pub fn new(accounts: A, transactions: T, batch_size: usize) -> Self {
+        Self {
+            accounts,
+            transactions,
+            batch_size: batch_size.max(1),
+            pending_accounts: BTreeMap::new(),
+            pending_transactions: BTreeMap::new(),
+            pending_sequence: 0,
+            flushed_sequence: 0,
+            flushes: 0,
+        }
+    }
+
+    // Writes that belong to journal entry `sequence`.
+    pub fn write(
+        &mut self,
+        sequence: u64,
+        transaction_id: TransactionId,
+        transaction: Transaction,
+        client_id: ClientId,
+        account: Account,
+    ) {
+        self.pending_transactions
+            .insert(transaction_id, transaction);
+        self.pending_accounts.insert(client_id, Some(account));
+        self.pending_sequence = self.pending_sequence.max(sequence);
+        if self.pending() >= self.batch_size {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if self.pending() == 0 {
+            self.flushed_sequence = self.flushed_sequence.max(self.pending_sequence);
+            return;
+        }
+        for (transaction_id, transaction) in std::mem::take(&mut self.pending_transactions) {
+            self.transactions
+                .put_transaction(transaction_id, transaction);
+        }
+        for (client_id, account) in std::mem::take(&mut self.pending_accounts) {
+            match account {
+                Some(account) => self.accounts.put_account(client_id, account),
+                None => {
+                    self.accounts.remove_account(client_id);
+                }
+            }
+        }
+        self.flushed_sequence = self.flushed_sequence.max(self.pending_sequence);
+        self.flushes += 1;
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending_accounts.len() + self.pending_transactions.len()
+    }
+
+    pub fn flushes(&self) -> u64 {
+        self.flushes
+    }
+
+    pub fn flushed_sequence(&self) -> u64 {
+        self.flushed_sequence
+    }
+
+    // Journal entries the store doesn't reflect yet.
+    pub fn unflushed(&self, journal: &Journal) -> Vector<JournalEntry> {
+        let entries = journal.entries();
+        let start = entries
+            .binary_search_by(|entry| match entry.sequence <= self.flushed_sequence {
+                true => Ordering::Less,
+                false => Ordering::Greater,
+            })
+            .unwrap_or_else(|start| start);
+        entries.skip(start)
+    }
+
+    pub fn into_inner(mut self) -> (A, T) {
+        self.flush();
+        (self.accounts, self.transactions)
+    }
+}
+
+impl<A: AccountStore, T: TransactionStore> AccountStore for BatchedStore<A, T> {
+    fn get_account(&self, client_id: ClientId) -> Option<Account> {
+        match self.pending_accounts.get(&client_id) {
+            Some(account) => *account,
+            None => self.accounts.get_account(client_id),
+        }
+    }
+
+    fn put_account(&mut self, client_id: ClientId, account: Account) {
+        self.pending_accounts.insert(client_id, Some(account));
+    }
+
+    fn remove_account(&mut self, client_id: ClientId) -> Option<Account> {
+        let account = AccountStore::get_account(self, client_id);
+        self.pending_accounts.insert(client_id, None);
+        account
+    }
+}
+
+impl<A: AccountStore, T: TransactionStore> TransactionStore for BatchedStore<A, T> {
+    fn get_transaction(&self, transaction_id: TransactionId) -> Option<Transaction> {
+        self.pending_transactions
+            .get(&transaction_id)
+            .copied()
+            .or_else(|| self.transactions.get_transaction(transaction_id))
+    }
+
+    fn put_transaction(&mut self, transaction_id: TransactionId, transaction: Transaction) {
+        self.pending_transactions
+            .insert(transaction_id, transaction);
+    }
+}
+
+impl Ledger {
+    // Applies a transaction and hands the resulting writes to `store`. With a
+    // journal enabled the writes are tagged with its sequence so the store can
+    // tell which entries still need replaying.
+    pub fn apply_batched<A: AccountStore, T: TransactionStore>(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        store: &mut BatchedStore<A, T>,
+    ) -> TransactionResult<Outcome> {
+        let outcome = self.apply_transaction(transaction_id, transaction)?;
+        if let Outcome::Applied { .. } = outcome {
+            let client_id = self.account_holder(transaction.client_id());
+            let sequence = self.journal.as_ref().map_or(0, Journal::last_sequence);
+            if let (Some(stored), Some(account)) = (
+                self.transactions.get(&transaction_id),
+                self.accounts.get(&client_id),
+            ) {
+                store.write(sequence, transaction_id, *stored, client_id, account);
+            }
+        }
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,127 @@
+use super::{events, Ledger};
+use std::fmt;
+
+impl Ledger {
+    // A copy of the ledger to branch from. Its state is held in persistent
+    // collections, so the copy shares them with the original and a write on
+    // either side only copies the few nodes it touches. Event subscribers
+    // stay with the original.
+    pub fn clone_state(&self) -> Ledger {
+        Ledger {
+            accounts: self.accounts.clone(),
+            transactions: self.transactions.clone(),
+            archived: self.archived.clone(),
+            erased_transactions: self.erased_transactions.clone(),
+            tombstones: self.tombstones.clone(),
+            audit_log: self.audit_log.clone(),
+            signature_verifier: self.signature_verifier.clone(),
+            screening_provider: self.screening_provider.clone(),
+            policy: self.policy,
+            open_disputes: self.open_disputes.clone(),
+            now: self.now,
+            tiers: self.tiers.clone(),
+            daily_volume: self.daily_volume.clone(),
+            withdrawals: self.withdrawals.clone(),
+            journal: self.journal.clone(),
+            history: self.history.clone(),
+            retained: self.retained.clone(),
+            archived_transactions: self.archived_transactions.clone(),
+            cases: self.cases.clone(),
+            risk: self.risk.clone(),
+            quarantined: self.quarantined.clone(),
+            groups: self.groups.clone(),
+            escrows: self.escrows.clone(),
+            last_activity: self.last_activity.clone(),
+            dormant: self.dormant.clone(),
+            overflow_reviews: self.overflow_reviews.clone(),
+            reviews: self.reviews.clone(),
+            timeline: self.timeline.clone(),
+            opened: self.opened.clone(),
+            settings: self.settings.clone(),
+            accruals: self.accruals.clone(),
+            sweeps: self.sweeps.clone(),
+            screenings: self.screenings.clone(),
+            totals: self.totals,
+            frozen: self.frozen.clone(),
+            accrued_days: self.accrued_days.clone(),
+            events: events::EventBus::default(),
+            middlewares: self.middlewares.clone(),
+            finalizers: self.finalizers.clone(),
+            paranoid: self.paranoid,
+        }
+    }
+}
+
+impl Clone for Ledger {
+    fn clone(&self) -> Self {
+        self.clone_state()
+    }
+}
+
+// Ledgers are equal when their state is: the signature verifier, screening
+// provider, event subscribers, middlewares and finalizers aren't compared.
+impl PartialEq for Ledger {
+    fn eq(&self, other: &Self) -> bool {
+        self.accounts == other.accounts
+            && self.transactions == other.transactions
+            && self.archived == other.archived
+            && self.erased_transactions == other.erased_transactions
+            && self.tombstones == other.tombstones
+            && self.audit_log == other.audit_log
+            && self.policy == other.policy
+            && self.open_disputes == other.open_disputes
+            && self.now == other.now
+            && self.tiers == other.tiers
+            && self.daily_volume == other.daily_volume
+            && self.withdrawals == other.withdrawals
+            && self.journal == other.journal
+            && self.history == other.history
+            && self.retained == other.retained
+            && self.archived_transactions == other.archived_transactions
+            && self.cases == other.cases
+            && self.risk == other.risk
+            && self.quarantined == other.quarantined
+            && self.groups == other.groups
+            && self.escrows == other.escrows
+            && self.last_activity == other.last_activity
+            && self.dormant == other.dormant
+            && self.overflow_reviews == other.overflow_reviews
+            && self.reviews == other.reviews
+            && self.timeline == other.timeline
+            && self.opened == other.opened
+            && self.settings == other.settings
+            && self.accruals == other.accruals
+            && self.sweeps == other.sweeps
+            && self.screenings == other.screenings
+            && self.totals == other.totals
+            && self.frozen == other.frozen
+            && self.accrued_days == other.accrued_days
+    }
+}
+
+impl Eq for Ledger {}
+
+impl fmt::Debug for Ledger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ledger")
+            .field("accounts", &self.accounts)
+            .field("transactions", &self.transactions)
+            .field("archived", &self.archived)
+            .field("erased_transactions", &self.erased_transactions)
+            .field("tombstones", &self.tombstones)
+            .field("policy", &self.policy)
+            .field("now", &self.now)
+            .field("tiers", &self.tiers)
+            .field("journal", &self.journal)
+            .field("archived_transactions", &self.archived_transactions)
+            .field("cases", &self.cases)
+            .field("quarantined", &self.quarantined)
+            .field("groups", &self.groups)
+            .field("escrows", &self.escrows)
+            .field("dormant", &self.dormant)
+            .field("frozen", &self.frozen)
+            .field("overflow_reviews", &self.overflow_reviews)
+            .field("reviews", &self.reviews)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,125 @@
+use super::Ledger;
+use crate::{
+    account::ClientId, account::Number, transactions::Operation, transactions::Transaction,
+    transactions::TransactionError, transactions::TransactionId,
+};
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub struct CaseId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaseState {
+    Open,
+    Resolved,
+    Chargedback,
+}
+
+// Disputed transactions that belong together, e.g. every installment of one
+// purchase, and are settled in one go.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    pub client_id: ClientId,
+    pub transactions: Vec<TransactionId>,
+    pub state: CaseState,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaseError {
+    RepeatedCase(CaseId),
+    UnknownCase(CaseId),
+    EmptyCase(CaseId),
+    ClosedCase(CaseId),
+    AlreadyInCase(TransactionId, CaseId),
+    Transaction(TransactionError),
+}
+
+impl From<TransactionError> for CaseError {
+    fn from(err: TransactionError) -> Self {
+        CaseError::Transaction(err)
+    }
+}
+
+impl Ledger {
+    pub fn case(&self, case_id: CaseId) -> Option<&Case> {
+        self.cases.get(&case_id)
+    }
+
+    pub fn cases(&self, client_id: ClientId) -> impl Iterator<Item = (CaseId, &Case)> {
+        self.cases
+            .iter()
+            .filter(move |(_, case)| case.client_id == client_id)
+            .map(|(case_id, case)| (*case_id, case))
+    }
+
+    // Transactions must already be disputed by the client and can only be
+    // part of one open case at a time.
+    pub fn open_case(
+        &mut self,
+        case_id: CaseId,
+        client_id: ClientId,
+        transactions: &[TransactionId],
+    ) -> Result<(), CaseError> {
+        if self.cases.contains_key(&case_id) {
+            return Err(CaseError::RepeatedCase(case_id));
+        }
+        if transactions.is_empty() {
+            return Err(CaseError::EmptyCase(case_id));
+        }
+        for transaction_id in transactions {
+            if let Some((other, _)) = self.cases.iter().find(|(_, case)| {
+                case.state == CaseState::Open && case.transactions.contains(transaction_id)
+            }) {
+                return Err(CaseError::AlreadyInCase(*transaction_id, *other));
+            }
+            let resolve = Transaction::new(client_id, Number::ZERO, Operation::Resolve);
+            self.validate(&resolve, *transaction_id)?;
+        }
+        self.cases.insert(
+            case_id,
+            Case {
+                client_id,
+                transactions: transactions.to_vec(),
+                state: CaseState::Open,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn resolve_case(&mut self, case_id: CaseId) -> Result<(), CaseError> {
+        self.close_case(case_id, Operation::Resolve, CaseState::Resolved)
+    }
+
+    pub fn chargeback_case(&mut self, case_id: CaseId) -> Result<(), CaseError> {
+        self.close_case(case_id, Operation::Chargeback, CaseState::Chargedback)
+    }
+
+    // Every transaction is checked before any is applied. The held funds
+    // cover each disputed amount, so once a resolve or chargeback validates
+    // on its own, applying the others first can't make it fail.
+    fn close_case(
+        &mut self,
+        case_id: CaseId,
+        operation: Operation,
+        state: CaseState,
+    ) -> Result<(), CaseError> {
+        let case = self
+            .cases
+            .get(&case_id)
+            .ok_or(CaseError::UnknownCase(case_id))?;
+        if case.state != CaseState::Open {
+            return Err(CaseError::ClosedCase(case_id));
+        }
+        let transaction = Transaction::new(case.client_id, Number::ZERO, operation);
+        let transactions = case.transactions.clone();
+        for transaction_id in &transactions {
+            self.validate(&transaction, *transaction_id)?;
+        }
+        for transaction_id in transactions {
+            self.apply_transaction(transaction_id, &transaction)?;
+        }
+        if let Some(case) = self.cases.get_mut(&case_id) {
+            case.state = state;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use super::Ledger;
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    pub fn day(&self) -> u64 {
+        self.0 / SECONDS_PER_DAY
+    }
+
+    // The calendar date, as YYYY-MM-DD, taking timestamps as seconds since
+    // the Unix epoch.
+    pub fn date(&self) -> String {
+        // civil_from_days, from Howard Hinnant's date algorithms
+        let days = self.day() + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + u64::from(month <= 2);
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+}
+
+impl Ledger {
+    pub fn now(&self) -> Timestamp {
+        self.now
+    }
+
+    pub fn set_time(&mut self, now: Timestamp) {
+        let day = self.now.day();
+        self.now = now;
+        if now.day() != day {
+            self.accrue_held_funds();
+        }
+    }
+
+    pub fn advance_time(&mut self, seconds: u64) {
+        self.set_time(Timestamp(self.now.0.saturating_add(seconds)));
+    }
+}
@@ -0,0 +1,99 @@
+use super::{Finalization, FreezeReason, Ledger, Policy, Released, Tier};
+use crate::{
+    account::ClientId, account::Number, transactions::Outcome, transactions::Transaction,
+    transactions::TransactionId, transactions::TransactionResult,
+};
+
+// Every change that can be made to a ledger, as a value that can be queued,
+// sent to another thread or written down before it is run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LedgerCommand {
+    // Deposits, withdrawals, adjustments, disputes and account openings.
+    ApplyTransaction(TransactionId, Transaction),
+    Freeze(ClientId, FreezeReason),
+    Unfreeze(ClientId),
+    // Lifts a chargeback lock, see `Ledger::unlock_account`.
+    Unlock(ClientId),
+    Quarantine(ClientId),
+    ReleaseQuarantine(ClientId),
+    Reactivate(ClientId),
+    SetTier(ClientId, Tier),
+    // Archives the account, see `Ledger::archive_account`.
+    CloseAccount(ClientId),
+    ReopenAccount(ClientId),
+    EraseClient(ClientId),
+    ApproveReview(TransactionId),
+    RejectReview(TransactionId),
+    SetPolicy(Box<Policy>),
+    AdvanceTime(u64),
+    Finalize,
+    Snapshot,
+}
+
+// What a command gave back, by command:
+// - `ApplyTransaction`: `Applied`
+// - `ReleaseQuarantine`: `Released` with the amount released
+// - `EraseClient`: `Erased` with the index of the client's tombstone
+// - `ApproveReview`, `RejectReview`: `Reviewed` with what was applied
+// - `Finalize`: `Finalized`
+// - `Snapshot`: `Snapshot` with the snapshot as `write_snapshot` writes it
+// - anything else: `Done`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandOutput {
+    Done,
+    Applied(Outcome),
+    Released(Number),
+    Erased(usize),
+    Reviewed(Released),
+    Finalized(Finalization),
+    Snapshot(Vec<u8>),
+}
+
+impl Ledger {
+    pub fn execute(&mut self, command: LedgerCommand) -> TransactionResult<CommandOutput> {
+        let done = |result: TransactionResult| result.map(|_| CommandOutput::Done);
+        match command {
+            LedgerCommand::ApplyTransaction(transaction_id, transaction) => self
+                .apply_transaction(transaction_id, &transaction)
+                .map(CommandOutput::Applied),
+            LedgerCommand::Freeze(client_id, reason) => done(self.freeze(client_id, reason)),
+            LedgerCommand::Unfreeze(client_id) => done(self.unfreeze(client_id)),
+            LedgerCommand::Unlock(client_id) => done(self.unlock_account(client_id)),
+            LedgerCommand::Quarantine(client_id) => done(self.quarantine(client_id)),
+            LedgerCommand::ReleaseQuarantine(client_id) => self
+                .release_quarantine(client_id)
+                .map(CommandOutput::Released),
+            LedgerCommand::Reactivate(client_id) => done(self.reactivate(client_id)),
+            LedgerCommand::SetTier(client_id, tier) => {
+                self.set_tier(client_id, tier);
+                Ok(CommandOutput::Done)
+            }
+            LedgerCommand::CloseAccount(client_id) => done(self.archive_account(client_id)),
+            LedgerCommand::ReopenAccount(client_id) => done(self.restore_account(client_id)),
+            LedgerCommand::EraseClient(client_id) => {
+                self.erase_client(client_id).map(CommandOutput::Erased)
+            }
+            LedgerCommand::ApproveReview(transaction_id) => self
+                .approve_review(transaction_id)
+                .map(CommandOutput::Reviewed),
+            LedgerCommand::RejectReview(transaction_id) => self
+                .reject_review(transaction_id)
+                .map(CommandOutput::Reviewed),
+            LedgerCommand::SetPolicy(policy) => {
+                self.set_policy(*policy);
+                Ok(CommandOutput::Done)
+            }
+            LedgerCommand::AdvanceTime(seconds) => {
+                self.advance_time(seconds);
+                Ok(CommandOutput::Done)
+            }
+            LedgerCommand::Finalize => Ok(CommandOutput::Finalized(self.finalize())),
+            LedgerCommand::Snapshot => {
+                let mut snapshot = Vec::new();
+                self.write_snapshot(&mut snapshot)
+                    .expect("snapshots are written to memory");
+                Ok(CommandOutput::Snapshot(snapshot))
+            }
+        }
+    }
+}
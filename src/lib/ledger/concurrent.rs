@@ -0,0 +1,387 @@
+use super::{Ledger, Policy};
+use crate::{
+    account::Account, account::ClientId, transactions::Operation, transactions::Outcome,
+    transactions::Transaction, transactions::TransactionError, transactions::TransactionId,
+    transactions::TransactionResult,
+};
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+const DEFAULT_STRIPES: usize = 64;
+
+// Clients are spread over independently locked ledgers, so operations on
+// clients living in different stripes never wait on each other. Transaction
+// ids are global, so their owner is tracked in a separate set of stripes keyed
+// by id. Locks are always taken client stripes first, in ascending index
+// order, and id stripes last, which keeps every lock acquisition ordered.
+pub struct ConcurrentLedger {
+    stripes: Vec<Mutex<Ledger>>,
+    owners: Vec<Mutex<HashMap<TransactionId, ClientId>>>,
+}
+
+impl Default for ConcurrentLedger {
+    fn default() -> Self {
+        Self::new(DEFAULT_STRIPES)
+    }
+}
+
+impl ConcurrentLedger {
+    pub fn new(stripes: usize) -> Self {
+        let stripes = stripes.max(1);
+        Self {
+            stripes: (0..stripes).map(|_| Mutex::new(Ledger::new())).collect(),
+            owners: (0..stripes).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    fn stripe_index(&self, client_id: ClientId) -> usize {
+        client_id.0 as usize % self.stripes.len()
+    }
+
+    fn lock_stripe(&self, index: usize) -> MutexGuard<'_, Ledger> {
+        self.stripes[index]
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn lock_owners(
+        &self,
+        transaction_id: TransactionId,
+    ) -> MutexGuard<'_, HashMap<TransactionId, ClientId>> {
+        self.owners[transaction_id.0 as usize % self.owners.len()]
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn lock_client(&self, client_id: ClientId) -> MutexGuard<'_, Ledger> {
+        self.lock_stripe(self.stripe_index(client_id))
+    }
+
+    // Locks the stripes of two clients for cross-client operations. The
+    // second guard is `None` when both clients share a stripe.
+    pub fn lock_clients(
+        &self,
+        first: ClientId,
+        second: ClientId,
+    ) -> (MutexGuard<'_, Ledger>, Option<MutexGuard<'_, Ledger>>) {
+        let (first, second) = (self.stripe_index(first), self.stripe_index(second));
+        if first == second {
+            (self.lock_stripe(first), None)
+        } else if first < second {
+            let first = self.lock_stripe(first);
+            (first, Some(self.lock_stripe(second)))
+        } else {
+            let second = self.lock_stripe(second);
+            (self.lock_stripe(first), Some(second))
+        }
+    }
+
+    pub fn apply_transaction(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        let mut ledger = self.lock_client(transaction.client_id());
+        let mut owners = self.lock_owners(transaction_id);
+        match transaction.operation() {
+            Operation::Deposit
+            | Operation::Withdrawal
+            | Operation::Adjustment
+            | Operation::OpenAccount => {
+                // the owner's ledger tells a duplicate from a conflicting id
+                match owners.get(&transaction_id) {
+                    Some(owner) if *owner != transaction.client_id() => {
+                        return Err(TransactionError::RepeatedTransactionId(transaction_id));
+                    }
+                    Some(_) => return ledger.apply_transaction(transaction_id, transaction),
+                    None => {}
+                }
+                let outcome = ledger.apply_transaction(transaction_id, transaction)?;
+                owners.insert(transaction_id, transaction.client_id());
+                Ok(outcome)
+            }
+            Operation::Transfer { to } => {
+                drop((ledger, owners));
+                self.transfer(transaction_id, transaction, to)
+            }
+            Operation::Dispute | Operation::Resolve | Operation::Chargeback | Operation::Unlock => {
+                match owners.get(&transaction_id) {
+                    None => Err(TransactionError::UnknownTransactionId(transaction_id)),
+                    Some(owner) if *owner != transaction.client_id() => Err(
+                        TransactionError::MismatchedClientId(transaction.client_id(), *owner),
+                    ),
+                    Some(_) => ledger.apply_transaction(transaction_id, transaction),
+                }
+            }
+        }
+    }
+
+    // Within a stripe its ledger moves the funds. Across stripes, with both
+    // locked, the sender's ledger stores the transfer and debits the sender
+    // while the recipient's only credits the recipient. Both sides are
+    // prepared before either is committed, so a transfer turned down on
+    // either side changes nothing. Middlewares and reviews only see
+    // transfers within a stripe.
+    fn transfer(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        to: ClientId,
+    ) -> TransactionResult<Outcome> {
+        let (mut sender, recipient) = self.lock_clients(transaction.client_id(), to);
+        let mut owners = self.lock_owners(transaction_id);
+        if owners.contains_key(&transaction_id) {
+            return Err(TransactionError::RepeatedTransactionId(transaction_id));
+        }
+        let outcome = match recipient {
+            None => sender.apply_transaction(transaction_id, transaction)?,
+            Some(mut recipient) => {
+                let debit = sender.prepare_transfer_out(transaction_id, transaction)?;
+                let credit = recipient.prepare_transfer_in(transaction_id, transaction, to)?;
+                recipient.commit_transfer_in(credit);
+                sender.commit_transfer_out(transaction_id, transaction, debit)
+            }
+        };
+        owners.insert(transaction_id, transaction.client_id());
+        Ok(outcome)
+    }
+
+    // Every stripe is locked, in index order, before any of them changes, so
+    // no transaction ever runs under a mix of the old and new policies.
+    pub fn set_policy(&self, policy: Policy) {
+        let mut stripes: Vec<MutexGuard<'_, Ledger>> = (0..self.stripes.len())
+            .map(|index| self.lock_stripe(index))
+            .collect();
+        for stripe in &mut stripes {
+            stripe.set_policy(policy);
+        }
+    }
+
+    pub fn account(&self, client_id: ClientId) -> Option<Account> {
+        self.lock_client(client_id).account(client_id)
+    }
+
+    pub fn into_accounts(self) -> Vec<(ClientId, Account)> {
+        let mut accounts: Vec<(ClientId, Account)> = self
+            .stripes
+            .into_iter()
+            .flat_map(|stripe| stripe.into_inner().unwrap_or_else(|err| err.into_inner()))
+            .collect();
+        accounts.sort_by_key(|(client_id, _)| *client_id);
+        accounts
+    }
+}
+
+#[cfg(test)]
+mod concurrent_tests {
+    use super::ConcurrentLedger;
+    use crate::{
+        account::num, account::ClientId, account::Number, ledger::LedgerEvent, ledger::Policy,
+        transactions::Operation, transactions::Transaction, transactions::TransactionError,
+        transactions::TransactionId,
+    };
+    use std::thread;
+
+    #[test]
+    fn transaction_ids_are_global() {
+        let ledger = ConcurrentLedger::new(4);
+        let deposit = Transaction::new(ClientId(1), Number::ONE, Operation::Deposit);
+        assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+        let deposit = Transaction::new(ClientId(2), Number::ONE, Operation::Deposit);
+        assert_eq!(
+            ledger.apply_transaction(TransactionId(1), &deposit),
+            Err(TransactionError::RepeatedTransactionId(TransactionId(1)))
+        );
+        let dispute = Transaction::new(ClientId(2), Number::ZERO, Operation::Dispute);
+        assert_eq!(
+            ledger.apply_transaction(TransactionId(1), &dispute),
+            Err(TransactionError::MismatchedClientId(
+                ClientId(2),
+                ClientId(1)
+            ))
+        );
+        let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+        assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+        assert_eq!(ledger.account(ClientId(1)).unwrap().held(), Number::ONE);
+        assert!(ledger.account(ClientId(2)).is_none());
+    }
+
+    #[test]
+    fn transfers_across_stripes_move_both_sides_or_neither() {
+        let ledger = ConcurrentLedger::new(4);
+        for (id, client) in [(1, 1), (2, 2), (3, 3)] {
+            let deposit = Transaction::new(ClientId(client), num!(10), Operation::Deposit);
+            assert!(ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .is_ok());
+        }
+        for operation in [Operation::Dispute, Operation::Chargeback] {
+            let row = Transaction::new(ClientId(2), Number::ZERO, operation);
+            assert!(ledger.apply_transaction(TransactionId(2), &row).is_ok());
+        }
+        let mut events = ledger.lock_client(ClientId(1)).event_stream();
+
+        // Client 2 is locked by the chargeback, which still lets deposits in.
+        let transfer = Transaction::new(
+            ClientId(1),
+            num!(4),
+            Operation::Transfer { to: ClientId(2) },
+        );
+        assert!(ledger
+            .apply_transaction(TransactionId(4), &transfer)
+            .is_ok());
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(6));
+        assert_eq!(ledger.account(ClientId(2)).unwrap().available(), num!(4));
+        assert!(ledger.account(ClientId(2)).unwrap().locked());
+        assert!(matches!(
+            events.try_next(),
+            Some(LedgerEvent::Applied {
+                transaction_id: TransactionId(4),
+                ..
+            })
+        ));
+
+        // Either side turning the transfer down leaves both as they were.
+        assert!(ledger
+            .lock_client(ClientId(3))
+            .archive_account(ClientId(3))
+            .is_ok());
+        let transfer = Transaction::new(
+            ClientId(1),
+            num!(4),
+            Operation::Transfer { to: ClientId(3) },
+        );
+        assert_eq!(
+            ledger.apply_transaction(TransactionId(5), &transfer),
+            Err(TransactionError::ArchivedAccount(ClientId(3)))
+        );
+        let overdrawn = Transaction::new(
+            ClientId(1),
+            num!(40),
+            Operation::Transfer { to: ClientId(2) },
+        );
+        assert!(matches!(
+            ledger.apply_transaction(TransactionId(6), &overdrawn),
+            Err(TransactionError::AccountError(ClientId(1), _))
+        ));
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(6));
+        assert_eq!(ledger.account(ClientId(2)).unwrap().available(), num!(4));
+        assert_eq!(ledger.account(ClientId(3)).unwrap().available(), num!(10));
+        assert!(ledger
+            .lock_client(ClientId(1))
+            .transaction(TransactionId(5))
+            .is_none());
+        assert_eq!(events.try_next(), None);
+
+        assert!(ledger
+            .lock_client(ClientId(3))
+            .restore_account(ClientId(3))
+            .is_ok());
+        assert!(ledger
+            .apply_transaction(TransactionId(5), &transfer)
+            .is_ok());
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(2));
+        assert_eq!(ledger.account(ClientId(3)).unwrap().available(), num!(14));
+        assert!(matches!(
+            events.try_next(),
+            Some(LedgerEvent::Applied {
+                transaction_id: TransactionId(5),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn transfers_across_stripes_cannot_be_disputed() {
+        let ledger = ConcurrentLedger::new(4);
+        ledger.set_policy(Policy {
+            dispute_withdrawals: true,
+            ..Policy::default()
+        });
+        let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+        assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+        let transfer = Transaction::new(
+            ClientId(1),
+            num!(4),
+            Operation::Transfer { to: ClientId(2) },
+        );
+        assert!(ledger
+            .apply_transaction(TransactionId(2), &transfer)
+            .is_ok());
+        assert_eq!(
+            ledger
+                .lock_client(ClientId(1))
+                .transaction(TransactionId(2))
+                .map(|stored| stored.operation()),
+            Some(Operation::Transfer { to: ClientId(2) })
+        );
+        assert!(ledger
+            .lock_client(ClientId(2))
+            .transaction(TransactionId(2))
+            .is_none());
+        let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+        assert!(ledger
+            .apply_transaction(TransactionId(2), &dispute)
+            .is_err());
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(6));
+        assert_eq!(ledger.account(ClientId(1)).unwrap().held(), Number::ZERO);
+        assert_eq!(ledger.account(ClientId(2)).unwrap().available(), num!(4));
+    }
+
+    #[test]
+    fn lock_clients_in_any_order() {
+        let ledger = ConcurrentLedger::new(4);
+        let guards = ledger.lock_clients(ClientId(3), ClientId(1));
+        assert!(guards.1.is_some());
+        drop(guards);
+        let guards = ledger.lock_clients(ClientId(1), ClientId(5));
+        assert!(guards.1.is_none());
+    }
+
+    #[test]
+    fn policy_changes_on_every_stripe_at_once() {
+        let ledger = ConcurrentLedger::new(2);
+        let policy = Policy {
+            dispute_withdrawals: true,
+            ..Policy::default()
+        };
+        let held = ledger.lock_client(ClientId(1));
+        thread::scope(|scope| {
+            scope.spawn(|| ledger.set_policy(policy));
+            // the first stripe stays locked under the old policy until the
+            // second one is free
+            while ledger.stripes[0].try_lock().is_ok() {
+                thread::yield_now();
+            }
+            assert_eq!(*held.policy(), Policy::default());
+            drop(held);
+        });
+        for client in 0..2 {
+            assert_eq!(*ledger.lock_client(ClientId(client)).policy(), policy);
+        }
+    }
+
+    #[test]
+    fn parallel_deposits() {
+        let ledger = ConcurrentLedger::new(8);
+        thread::scope(|scope| {
+            for client in 0..8u16 {
+                let ledger = &ledger;
+                scope.spawn(move || {
+                    for i in 0..100u32 {
+                        let id = TransactionId(client as u32 * 100 + i);
+                        let deposit =
+                            Transaction::new(ClientId(client), num!(0.5), Operation::Deposit);
+                        assert!(ledger.apply_transaction(id, &deposit).is_ok());
+                    }
+                });
+            }
+        });
+        let accounts = ledger.into_accounts();
+        assert_eq!(accounts.len(), 8);
+        for (_, account) in accounts {
+            assert_eq!(account.available(), num!(50));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use super::{AuditEvent, Change, Ledger, Timestamp};
+use crate::{
+    account::ClientId, account::Number, transactions::Operation, transactions::TransactionId,
+    transactions::TransactionState,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DisputeEvent {
+    pub timestamp: Timestamp,
+    pub operation: Operation,
+    pub state: TransactionState,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisputeInfo {
+    pub transaction_id: TransactionId,
+    pub client_id: ClientId,
+    pub state: TransactionState,
+    pub held: Number,
+    pub opened_at: Timestamp,
+    pub history: Vec<DisputeEvent>,
+}
+
+// A dispute opened, resolved or charged back during the run, as the client
+// is told about it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DisputeLetter {
+    pub transaction_id: TransactionId,
+    pub client_id: ClientId,
+    pub status: TransactionState,
+    pub amount: Number,
+    pub opened_at: Timestamp,
+    pub changed_at: Timestamp,
+}
+
+impl Ledger {
+    // Every dispute status change the ledger saw, oldest first. Transactions
+    // archived or erased since are left out, and restored snapshots carry no
+    // history, so only changes made since the ledger was loaded are listed.
+    pub fn dispute_letters(&self) -> Vec<DisputeLetter> {
+        let mut letters = Vec::new();
+        for (transaction_id, history) in &self.history {
+            let Some(transaction) = self.transactions.get(transaction_id) else {
+                continue;
+            };
+            let mut opened_at = None;
+            for change in history.iter().filter(|change| change.from.is_some()) {
+                if change.operation == Operation::Dispute {
+                    opened_at = Some(change.timestamp);
+                }
+                letters.push(DisputeLetter {
+                    transaction_id: *transaction_id,
+                    client_id: transaction.client_id(),
+                    status: change.to,
+                    amount: transaction.amount(),
+                    opened_at: opened_at.unwrap_or(change.timestamp),
+                    changed_at: change.timestamp,
+                });
+            }
+        }
+        letters.sort_by_key(|letter| (letter.changed_at, letter.transaction_id));
+        letters
+    }
+
+    // A chargeback that took less than the disputed amount because less was
+    // held, see `ChargebackExcess::Clamp`.
+    pub(super) fn audit_clamped_chargeback(&mut self, change: &Change) {
+        if change.transaction.state() != TransactionState::Chargedback {
+            return;
+        }
+        let Some(stored) = self.transactions.get(&change.transaction_id) else {
+            return;
+        };
+        if stored.state() == TransactionState::Disputed
+            && change.transaction.amount() < stored.amount()
+        {
+            self.audit(AuditEvent::ChargebackClamped {
+                client_id: change.client_id,
+                transaction_id: change.transaction_id,
+                excess: stored.amount() - change.transaction.amount(),
+            });
+        }
+    }
+
+    // When the transaction was last disputed, if the ledger saw it happen.
+    pub(super) fn dispute_opened_at(&self, transaction_id: TransactionId) -> Option<Timestamp> {
+        self.history
+            .get(&transaction_id)?
+            .iter()
+            .rev()
+            .find(|change| change.operation == Operation::Dispute)
+            .map(|change| change.timestamp)
+    }
+
+    // Returns None for transactions that were never disputed.
+    pub fn dispute_details(&self, transaction_id: TransactionId) -> Option<DisputeInfo> {
+        let stored = self.stored_transaction(transaction_id)?;
+        let transaction = stored.transaction();
+        let history: Vec<DisputeEvent> = stored
+            .history()
+            .iter()
+            .filter(|change| change.from.is_some())
+            .map(|change| DisputeEvent {
+                timestamp: change.timestamp,
+                operation: change.operation,
+                state: change.to,
+            })
+            .collect();
+        let opened_at = self.dispute_opened_at(transaction_id)?;
+        let held = match transaction.state() {
+            state if state.is_disputed() => transaction.amount(),
+            _ => Number::ZERO,
+        };
+        Some(DisputeInfo {
+            transaction_id,
+            client_id: transaction.client_id(),
+            state: transaction.state(),
+            held,
+            opened_at,
+            history,
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use super::{clock::SECONDS_PER_DAY, AuditEvent, Ledger, Timestamp};
+use crate::{account::ClientId, transactions::TransactionError, transactions::TransactionResult};
+
+impl Ledger {
+    // With `Policy::dormant_after_days` set, an account that comes back after
+    // that long without activity is flagged dormant. Deposits still go
+    // through, but withdrawals wait until it is reactivated.
+    pub(super) fn record_activity(&mut self, client_id: ClientId) {
+        if self.idle_days(client_id) >= self.policy.dormant_after_days.unwrap_or(u64::MAX) {
+            self.dormant.insert(client_id);
+        }
+        self.last_activity.insert(client_id, self.now);
+    }
+
+    pub(super) fn idle_days(&self, client_id: ClientId) -> u64 {
+        self.last_activity.get(&client_id).map_or(0, |last| {
+            self.now.0.saturating_sub(last.0) / SECONDS_PER_DAY
+        })
+    }
+
+    pub fn last_activity(&self, client_id: ClientId) -> Option<Timestamp> {
+        self.last_activity.get(&client_id).copied()
+    }
+
+    // Accounts whose last activity happened before `since`, oldest first.
+    pub fn dormant_accounts(&self, since: Timestamp) -> Vec<(ClientId, Timestamp)> {
+        let mut dormant: Vec<_> = self
+            .last_activity
+            .iter()
+            .filter(|(client_id, last)| **last < since && self.accounts.get(client_id).is_some())
+            .map(|(client_id, last)| (*client_id, *last))
+            .collect();
+        dormant.sort_by_key(|(client_id, last)| (*last, *client_id));
+        dormant
+    }
+
+    pub fn is_dormant(&self, client_id: ClientId) -> bool {
+        self.dormant.contains(&client_id)
+            || self
+                .policy
+                .dormant_after_days
+                .is_some_and(|days| self.idle_days(client_id) >= days)
+    }
+
+    pub fn reactivate(&mut self, client_id: ClientId) -> TransactionResult {
+        self.accounts
+            .get(&client_id)
+            .ok_or(TransactionError::UnknownClientId(client_id))?;
+        self.dormant.remove(&client_id);
+        self.last_activity.insert(client_id, self.now);
+        self.audit(AuditEvent::AccountReactivated(client_id));
+        Ok(())
+    }
+
+    pub(super) fn check_dormant(&self, client_id: ClientId) -> TransactionResult {
+        if self.is_dormant(client_id) {
+            Err(TransactionError::DormantAccount(client_id))
+        } else {
+            Ok(())
+        }
+    }
+}
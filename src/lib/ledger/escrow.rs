@@ -0,0 +1,113 @@
+use super::Ledger;
+use crate::{account::ClientId, account::Number, transactions::TransactionError};
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub struct EscrowId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EscrowState {
+    Open,
+    Released,
+    Refunded,
+}
+
+// Funds deposited for a beneficiary but kept out of every account until they
+// are released to it or refunded to the depositor. They never show up as
+// held, which stays reserved for disputes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Escrow {
+    pub depositor: ClientId,
+    pub beneficiary: ClientId,
+    pub amount: Number,
+    pub state: EscrowState,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EscrowError {
+    RepeatedEscrow(EscrowId),
+    UnknownEscrow(EscrowId),
+    ClosedEscrow(EscrowId),
+    InvalidAmount(EscrowId, Number),
+    Transaction(TransactionError),
+}
+
+impl From<TransactionError> for EscrowError {
+    fn from(err: TransactionError) -> Self {
+        EscrowError::Transaction(err)
+    }
+}
+
+impl Ledger {
+    pub fn escrow(&self, escrow_id: EscrowId) -> Option<&Escrow> {
+        self.escrows.get(&escrow_id)
+    }
+
+    // Open escrows waiting to be released to the client.
+    pub fn escrowed(&self, beneficiary: ClientId) -> Number {
+        self.escrows
+            .values()
+            .filter(|escrow| escrow.state == EscrowState::Open && escrow.beneficiary == beneficiary)
+            .map(|escrow| escrow.amount)
+            .sum()
+    }
+
+    pub fn deposit_escrow(
+        &mut self,
+        escrow_id: EscrowId,
+        depositor: ClientId,
+        beneficiary: ClientId,
+        amount: Number,
+    ) -> Result<(), EscrowError> {
+        if self.escrows.contains_key(&escrow_id) {
+            return Err(EscrowError::RepeatedEscrow(escrow_id));
+        }
+        if amount <= Number::ZERO {
+            return Err(EscrowError::InvalidAmount(escrow_id, amount));
+        }
+        self.check_archived(depositor)?;
+        self.check_archived(beneficiary)?;
+        self.escrows.insert(
+            escrow_id,
+            Escrow {
+                depositor,
+                beneficiary,
+                amount,
+                state: EscrowState::Open,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn release_escrow(&mut self, escrow_id: EscrowId) -> Result<(), EscrowError> {
+        self.close_escrow(escrow_id, EscrowState::Released)
+    }
+
+    pub fn refund_escrow(&mut self, escrow_id: EscrowId) -> Result<(), EscrowError> {
+        self.close_escrow(escrow_id, EscrowState::Refunded)
+    }
+
+    fn close_escrow(&mut self, escrow_id: EscrowId, state: EscrowState) -> Result<(), EscrowError> {
+        let escrow = *self
+            .escrows
+            .get(&escrow_id)
+            .ok_or(EscrowError::UnknownEscrow(escrow_id))?;
+        if escrow.state != EscrowState::Open {
+            return Err(EscrowError::ClosedEscrow(escrow_id));
+        }
+        let client_id = match state {
+            EscrowState::Refunded => escrow.depositor,
+            _ => escrow.beneficiary,
+        };
+        self.check_archived(client_id)?;
+        let holder = self.account_holder(client_id);
+        let mut account = self.accounts.get(&holder).unwrap_or_default();
+        account
+            .deposit(escrow.amount)
+            .map_err(|err| TransactionError::AccountError(client_id, err))?;
+        self.accounts.insert(holder, account);
+        if let Some(escrow) = self.escrows.get_mut(&escrow_id) {
+            escrow.state = state;
+        }
+        Ok(())
+    }
+}
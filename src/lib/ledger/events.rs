@@ -0,0 +1,216 @@
+use std::collections::{HashMap, VecDeque};
+use std::future;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use super::{AuditEvent, Ledger};
+use crate::{
+    account::Account, account::ClientId, account::Number, transactions::Transaction,
+    transactions::TransactionId,
+};
+
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LedgerEvent {
+    // A transaction was accepted; `account` is the balance it left behind.
+    Applied {
+        transaction_id: TransactionId,
+        transaction: Transaction,
+        account: Account,
+    },
+    Audit(AuditEvent),
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    events: VecDeque<LedgerEvent>,
+    lagged: u64,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+// Which `Applied` events a subscriber hears about, for consumers that can't
+// keep up with one per transaction, such as a queue fed during a backfill.
+// Audit events are always delivered.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    // At most one event per client in this long: the first change in each
+    // interval is delivered and the ones after it are dropped.
+    pub debounce: Option<Duration>,
+    // Only changes taking a client's total balance to the other side of this
+    // amount from where it was at the last delivered event. With a debounce
+    // too, a crossing dropped in one interval is delivered with the first
+    // change after it that still stands on the other side.
+    pub threshold: Option<Number>,
+}
+
+#[derive(Debug)]
+struct Filter {
+    filter: EventFilter,
+    delivered_at: HashMap<ClientId, Instant>,
+    totals: HashMap<ClientId, Number>,
+}
+
+impl Filter {
+    fn accepts(&mut self, event: &LedgerEvent) -> bool {
+        let LedgerEvent::Applied {
+            transaction,
+            account,
+            ..
+        } = event
+        else {
+            return true;
+        };
+        let client_id = transaction.client_id();
+        let total = account.total();
+        let before = self.totals.get(&client_id).copied().unwrap_or_default();
+        let crossed = self
+            .filter
+            .threshold
+            .is_none_or(|threshold| (before < threshold) != (total < threshold));
+        let now = Instant::now();
+        let due = match (self.filter.debounce, self.delivered_at.get(&client_id)) {
+            (Some(debounce), Some(delivered_at)) => now.duration_since(*delivered_at) >= debounce,
+            _ => true,
+        };
+        if crossed && due {
+            self.delivered_at.insert(client_id, now);
+            self.totals.insert(client_id, total);
+        }
+        crossed && due
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    queue: Arc<Mutex<Queue>>,
+    capacity: usize,
+    filter: Option<Filter>,
+}
+
+// Hands every event to each subscriber's bounded queue. Slow subscribers lose
+// their oldest events rather than holding up the ledger.
+#[derive(Debug, Default)]
+pub(super) struct EventBus {
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventBus {
+    fn subscribe(&mut self, capacity: usize, filter: Option<EventFilter>) -> EventStream {
+        let queue = Arc::new(Mutex::new(Queue::default()));
+        self.subscribers.push(Subscriber {
+            queue: queue.clone(),
+            capacity: capacity.max(1),
+            filter: filter.map(|filter| Filter {
+                filter,
+                delivered_at: HashMap::new(),
+                totals: HashMap::new(),
+            }),
+        });
+        EventStream { queue }
+    }
+
+    pub(super) fn publish(&mut self, event: LedgerEvent) {
+        self.subscribers
+            .retain(|subscriber| Arc::strong_count(&subscriber.queue) > 1);
+        for subscriber in &mut self.subscribers {
+            if let Some(filter) = &mut subscriber.filter {
+                if !filter.accepts(&event) {
+                    continue;
+                }
+            }
+            let mut queue = subscriber
+                .queue
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            if queue.events.len() >= subscriber.capacity {
+                queue.events.pop_front();
+                queue.lagged += 1;
+            }
+            queue.events.push_back(event);
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+// Streams end once the ledger is dropped and their queue is drained.
+impl Drop for EventBus {
+    fn drop(&mut self) {
+        for subscriber in &self.subscribers {
+            let mut queue = subscriber
+                .queue
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            queue.closed = true;
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+// One subscriber's view of the events, usable from any executor through
+// `poll_next` or `next`, or polled without waiting with `try_next`. With the
+// `tokio` feature it's also a `futures::Stream`.
+#[derive(Debug)]
+pub struct EventStream {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl EventStream {
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<LedgerEvent>> {
+        let mut queue = self.queue.lock().unwrap_or_else(|err| err.into_inner());
+        match queue.events.pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None if queue.closed => Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub async fn next(&mut self) -> Option<LedgerEvent> {
+        future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+
+    pub fn try_next(&mut self) -> Option<LedgerEvent> {
+        let mut queue = self.queue.lock().unwrap_or_else(|err| err.into_inner());
+        queue.events.pop_front()
+    }
+
+    // Events dropped because the queue was full.
+    pub fn lagged(&self) -> u64 {
+        self.queue.lock().map_or(0, |queue| queue.lagged)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl futures::Stream for EventStream {
+    type Item = LedgerEvent;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<LedgerEvent>> {
+        EventStream::poll_next(self.get_mut(), cx)
+    }
+}
+
+impl Ledger {
+    pub fn event_stream(&mut self) -> EventStream {
+        self.event_stream_with_capacity(DEFAULT_EVENT_CAPACITY)
+    }
+
+    pub fn event_stream_with_capacity(&mut self, capacity: usize) -> EventStream {
+        self.events.subscribe(capacity, None)
+    }
+
+    pub fn filtered_event_stream(&mut self, filter: EventFilter) -> EventStream {
+        self.events.subscribe(DEFAULT_EVENT_CAPACITY, Some(filter))
+    }
+}
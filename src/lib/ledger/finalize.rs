@@ -0,0 +1,93 @@
+use super::{clock::SECONDS_PER_DAY, AuditEvent, Ledger, LedgerStats, Sweep, Timestamp};
+use crate::transactions::{Operation, Transaction, TransactionId};
+
+// Steps run by `Ledger::finalize` at the end of a batch, in the order they
+// were added.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Finalizer {
+    // Resolves disputes opened more than this many days ago. Disputes
+    // restored from a snapshot don't know when they were opened and stay.
+    ResolveExpiredDisputes { after_days: u64 },
+    // Accrues what `Policy::held_accrual` has due up to the ledger's clock.
+    AccrueHeldFunds,
+    // Records the end of the period with the ledger's totals at that point.
+    ClosePeriod,
+    // Sweeps idle accounts as `Policy::idle_sweep` says.
+    SweepIdleAccounts,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeriodClose {
+    pub closed_at: Timestamp,
+    pub stats: LedgerStats,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Finalization {
+    pub resolved: Vec<TransactionId>,
+    pub accruals: usize,
+    pub sweeps: Vec<Sweep>,
+    pub period: Option<PeriodClose>,
+}
+
+impl Ledger {
+    pub fn add_finalizer(&mut self, finalizer: Finalizer) {
+        self.finalizers.push(finalizer);
+    }
+
+    pub fn finalize(&mut self) -> Finalization {
+        let mut finalization = Finalization::default();
+        for finalizer in self.finalizers.clone() {
+            match finalizer {
+                Finalizer::ResolveExpiredDisputes { after_days } => {
+                    let resolved = self.resolve_expired_disputes(after_days);
+                    finalization.resolved.extend(resolved);
+                }
+                Finalizer::AccrueHeldFunds => {
+                    let before = self.accruals.len();
+                    self.accrue_held_funds();
+                    finalization.accruals += self.accruals.len() - before;
+                }
+                Finalizer::SweepIdleAccounts => {
+                    let sweeps = self.sweep_idle_accounts();
+                    finalization.sweeps.extend(sweeps);
+                }
+                Finalizer::ClosePeriod => {
+                    let closed_at = self.now;
+                    finalization.period = Some(PeriodClose {
+                        closed_at,
+                        stats: self.stats(),
+                    });
+                    self.audit(AuditEvent::PeriodClosed(closed_at));
+                }
+            }
+        }
+        finalization
+    }
+
+    // Through `apply_transaction`, so the resolves are journaled and seen by
+    // middlewares like any other.
+    fn resolve_expired_disputes(&mut self, after_days: u64) -> Vec<TransactionId> {
+        let expired: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|(_, transaction)| transaction.state().is_disputed())
+            .filter(|(transaction_id, _)| {
+                self.dispute_opened_at(**transaction_id)
+                    .is_some_and(|opened_at| {
+                        self.now.0.saturating_sub(opened_at.0)
+                            > after_days.saturating_mul(SECONDS_PER_DAY)
+                    })
+            })
+            .map(|(transaction_id, transaction)| (*transaction_id, transaction.client_id()))
+            .collect();
+        expired
+            .into_iter()
+            .filter(|(transaction_id, client_id)| {
+                let resolve = Transaction::new(*client_id, Default::default(), Operation::Resolve);
+                self.apply_transaction(*transaction_id, &resolve).is_ok()
+            })
+            .map(|(transaction_id, _)| transaction_id)
+            .collect()
+    }
+}
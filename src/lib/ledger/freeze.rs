@@ -0,0 +1,75 @@
+use super::{AuditEvent, Ledger};
+use crate::{account::ClientId, transactions::TransactionError, transactions::TransactionResult};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FreezeReason {
+    Compliance,
+    Sanctions,
+    LegalOrder,
+    CustomerRequest,
+}
+
+impl FreezeReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FreezeReason::Compliance => "compliance",
+            FreezeReason::Sanctions => "sanctions",
+            FreezeReason::LegalOrder => "legal_order",
+            FreezeReason::CustomerRequest => "customer_request",
+        }
+    }
+}
+
+impl std::str::FromStr for FreezeReason {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "compliance" => Ok(FreezeReason::Compliance),
+            "sanctions" => Ok(FreezeReason::Sanctions),
+            "legal_order" => Ok(FreezeReason::LegalOrder),
+            "customer_request" => Ok(FreezeReason::CustomerRequest),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+// An administrative freeze stops the client's deposits and withdrawals until
+// it is lifted with `unfreeze`. It is kept apart from the lock a chargeback
+// leaves on the account: the balance isn't touched, the account reports as
+// unlocked, and transactions are rejected with their own error.
+impl Ledger {
+    pub fn freeze(&mut self, client_id: ClientId, reason: FreezeReason) -> TransactionResult {
+        self.account(client_id)
+            .ok_or(TransactionError::UnknownClientId(client_id))?;
+        if self.frozen.contains_key(&client_id) {
+            return Err(TransactionError::FrozenAccount(client_id));
+        }
+        self.frozen.insert(client_id, reason);
+        self.audit(AuditEvent::AccountFrozen { client_id, reason });
+        Ok(())
+    }
+
+    pub fn unfreeze(&mut self, client_id: ClientId) -> TransactionResult {
+        if self.frozen.remove(&client_id).is_some() {
+            self.audit(AuditEvent::AccountUnfrozen(client_id));
+        }
+        Ok(())
+    }
+
+    pub fn freeze_reason(&self, client_id: ClientId) -> Option<FreezeReason> {
+        self.frozen.get(&client_id).copied()
+    }
+
+    pub fn is_frozen(&self, client_id: ClientId) -> bool {
+        self.frozen.contains_key(&client_id)
+    }
+
+    pub(super) fn check_frozen(&self, client_id: ClientId) -> TransactionResult {
+        if self.is_frozen(client_id) {
+            Err(TransactionError::FrozenAccount(client_id))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use super::Ledger;
+use crate::{
+    account::ClientId, account::Number, transactions::Transaction, transactions::TransactionError,
+    transactions::TransactionResult,
+};
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub struct GroupId(pub u32);
+
+// Clients sharing one balance, kept in the holder's account. Members' deposits
+// and withdrawals are applied to that account, and each member may have a
+// cap on what it withdraws over 24 hours. The holder has no cap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    pub holder: ClientId,
+    pub members: BTreeMap<ClientId, Option<Number>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GroupError {
+    RepeatedGroup(GroupId),
+    UnknownGroup(GroupId),
+    AlreadyGrouped(ClientId, GroupId),
+    // Clients with their own balance can't join, it would no longer be
+    // reachable.
+    OwnBalance(ClientId),
+}
+
+impl Ledger {
+    pub fn group(&self, group_id: GroupId) -> Option<&Group> {
+        self.groups.get(&group_id)
+    }
+
+    pub fn group_of(&self, client_id: ClientId) -> Option<GroupId> {
+        self.groups
+            .iter()
+            .find(|(_, group)| group.holder == client_id || group.members.contains_key(&client_id))
+            .map(|(group_id, _)| *group_id)
+    }
+
+    fn check_ungrouped(&self, client_id: ClientId) -> Result<(), GroupError> {
+        match self.group_of(client_id) {
+            Some(group_id) => Err(GroupError::AlreadyGrouped(client_id, group_id)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn create_group(&mut self, group_id: GroupId, holder: ClientId) -> Result<(), GroupError> {
+        if self.groups.contains_key(&group_id) {
+            return Err(GroupError::RepeatedGroup(group_id));
+        }
+        self.check_ungrouped(holder)?;
+        self.groups.insert(
+            group_id,
+            Group {
+                holder,
+                members: BTreeMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn add_member(
+        &mut self,
+        group_id: GroupId,
+        client_id: ClientId,
+        withdrawal_limit: Option<Number>,
+    ) -> Result<(), GroupError> {
+        if !self.groups.contains_key(&group_id) {
+            return Err(GroupError::UnknownGroup(group_id));
+        }
+        self.check_ungrouped(client_id)?;
+        if self
+            .accounts
+            .get(&client_id)
+            .is_some_and(|account| account.total() != Number::ZERO)
+        {
+            return Err(GroupError::OwnBalance(client_id));
+        }
+        if let Some(group) = self.groups.get_mut(&group_id) {
+            group.members.insert(client_id, withdrawal_limit);
+        }
+        Ok(())
+    }
+
+    // The member starts over with an empty balance of its own.
+    pub fn remove_member(
+        &mut self,
+        group_id: GroupId,
+        client_id: ClientId,
+    ) -> Result<(), GroupError> {
+        let group = self
+            .groups
+            .get_mut(&group_id)
+            .ok_or(GroupError::UnknownGroup(group_id))?;
+        group.members.remove(&client_id);
+        Ok(())
+    }
+
+    // Whose account a client's transactions are applied to.
+    pub(super) fn account_holder(&self, client_id: ClientId) -> ClientId {
+        self.groups
+            .values()
+            .find(|group| group.members.contains_key(&client_id))
+            .map_or(client_id, |group| group.holder)
+    }
+
+    pub(super) fn check_group_limit(&self, transaction: &Transaction) -> TransactionResult {
+        let client_id = transaction.client_id();
+        let limit = self
+            .groups
+            .values()
+            .find_map(|group| group.members.get(&client_id).copied().flatten());
+        match limit {
+            Some(limit) if self.rolling_withdrawals(client_id) + transaction.amount() > limit => {
+                Err(TransactionError::GroupLimitExceeded(client_id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn remove_from_groups(&mut self, client_id: ClientId) {
+        self.groups = std::mem::take(&mut self.groups)
+            .into_iter()
+            .filter(|(_, group)| group.holder != client_id)
+            .map(|(group_id, mut group)| {
+                group.members.remove(&client_id);
+                (group_id, group)
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,108 @@
+use std::io;
+
+use super::Ledger;
+use crate::{account::Number, transactions::Operation};
+
+// Bucket `i` holds the amounts no larger than `bounds[i]` (and larger than the
+// previous bound); the last bucket holds everything above the highest bound.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    pub bounds: Vec<Number>,
+    pub counts: Vec<usize>,
+    pub totals: Vec<Number>,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[Number]) -> Self {
+        let mut bounds = bounds.to_vec();
+        bounds.sort();
+        bounds.dedup();
+        Self {
+            counts: vec![0; bounds.len() + 1],
+            totals: vec![Number::ZERO; bounds.len() + 1],
+            bounds,
+        }
+    }
+
+    pub fn add(&mut self, amount: Number) {
+        let bucket = self.bounds.partition_point(|bound| *bound < amount);
+        self.counts[bucket] += 1;
+        self.totals[bucket] += amount;
+    }
+
+    pub fn count(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn write_csv<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["upper_bound", "count", "total"])?;
+        for (bucket, (count, total)) in self.counts.iter().zip(&self.totals).enumerate() {
+            let bound = self
+                .bounds
+                .get(bucket)
+                .map(|bound| bound.to_string())
+                .unwrap_or_default();
+            writer.write_record([bound, count.to_string(), total.to_string()])?;
+        }
+        writer.flush()
+    }
+}
+
+impl Ledger {
+    // Deposits, withdrawals, adjustments, openings and transfers are counted
+    // once each, transfers whoever they went to. Disputes, resolves,
+    // chargebacks and unlocks are counted every time they were applied, with
+    // the amount of the transaction they refer to.
+    pub fn amount_distribution(&self, operation: Operation, bounds: &[Number]) -> Histogram {
+        let mut histogram = Histogram::new(bounds);
+        match operation {
+            Operation::Deposit
+            | Operation::Withdrawal
+            | Operation::Adjustment
+            | Operation::OpenAccount
+            | Operation::Transfer { .. } => self
+                .transactions
+                .values()
+                .filter(|transaction| transaction.operation().name() == operation.name())
+                .for_each(|transaction| histogram.add(transaction.amount())),
+            Operation::Dispute | Operation::Resolve | Operation::Chargeback | Operation::Unlock => {
+                for (transaction_id, history) in &self.history {
+                    let Some(transaction) = self.transactions.get(transaction_id) else {
+                        continue;
+                    };
+                    history
+                        .iter()
+                        .filter(|event| event.operation == operation)
+                        .for_each(|_| histogram.add(transaction.amount()));
+                }
+            }
+        }
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod histogram_tests {
+    use super::Histogram;
+    use crate::account::num;
+
+    #[test]
+    fn bucket_amounts() {
+        let mut histogram = Histogram::new(&[num!(100), num!(10), num!(10)]);
+        for amount in [num!(1), num!(10), num!(10.5), num!(100), num!(1000)] {
+            histogram.add(amount);
+        }
+        assert_eq!(histogram.bounds, vec![num!(10), num!(100)]);
+        assert_eq!(histogram.counts, vec![2, 2, 1]);
+        assert_eq!(histogram.totals, vec![num!(11), num!(110.5), num!(1000)]);
+        assert_eq!(histogram.count(), 5);
+
+        let mut csv = Vec::new();
+        histogram.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "upper_bound,count,total\n10,2,11\n100,2,110.5\n,1,1000\n"
+        );
+    }
+}
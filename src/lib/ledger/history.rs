@@ -0,0 +1,68 @@
+use super::{Ledger, Timestamp};
+use crate::transactions::{Operation, Transaction, TransactionId, TransactionState};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StateChange {
+    pub timestamp: Timestamp,
+    // The row that caused the change: the deposit, withdrawal, adjustment or
+    // opening itself, then the disputes, resolves and chargebacks referring to it.
+    pub operation: Operation,
+    // None when the transaction was stored.
+    pub from: Option<TransactionState>,
+    pub to: TransactionState,
+    // The journal entry of that row, when the ledger keeps a journal.
+    pub sequence: Option<u64>,
+}
+
+// A stored transaction along with the state changes the ledger saw. Restored
+// snapshots only carry the current state, so their history starts empty.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StoredTransaction<'a> {
+    pub transaction_id: TransactionId,
+    transaction: &'a Transaction,
+    history: &'a [StateChange],
+}
+
+impl<'a> StoredTransaction<'a> {
+    pub fn transaction(&self) -> &'a Transaction {
+        self.transaction
+    }
+
+    pub fn history(&self) -> &'a [StateChange] {
+        self.history
+    }
+}
+
+impl Ledger {
+    pub fn stored_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Option<StoredTransaction<'_>> {
+        let transaction = self.transactions.get(&transaction_id)?;
+        Some(StoredTransaction {
+            transaction_id,
+            transaction,
+            history: self.history.get(&transaction_id).map_or(&[], Vec::as_slice),
+        })
+    }
+
+    pub(super) fn record_state_change(
+        &mut self,
+        transaction_id: TransactionId,
+        operation: Operation,
+        from: Option<TransactionState>,
+        to: TransactionState,
+        sequence: Option<u64>,
+    ) {
+        self.history
+            .entry(transaction_id)
+            .or_default()
+            .push(StateChange {
+                timestamp: self.now,
+                operation,
+                from,
+                to,
+                sequence,
+            });
+    }
+}
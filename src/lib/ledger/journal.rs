@@ -0,0 +1,339 @@
+use imbl::Vector;
+use std::io::{self, BufRead};
+
+use super::{AccountSettings, Currency, Ledger, Policy, Tier, Timestamp};
+use crate::{
+    account::ClientId, account::Number, manifest::Manifest, manifest::ManifestError,
+    transactions::Operation, transactions::Signature, transactions::Transaction,
+    transactions::TransactionId,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    pub timestamp: Timestamp,
+    pub transaction_id: TransactionId,
+    pub transaction: Transaction,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReplayPoint {
+    Sequence(u64),
+    Time(Timestamp),
+}
+
+impl ReplayPoint {
+    fn includes(&self, entry: &JournalEntry) -> bool {
+        match self {
+            ReplayPoint::Sequence(sequence) => entry.sequence <= *sequence,
+            ReplayPoint::Time(timestamp) => entry.timestamp <= *timestamp,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JournalRecord {
+    sequence: u64,
+    timestamp: u64,
+    #[serde(rename = "type")]
+    operation: Operation,
+    client: u16,
+    tx: u32,
+    amount: Number,
+    // Settings of account openings, since version 3.
+    #[serde(default)]
+    currency: Option<Currency>,
+    #[serde(default)]
+    tier: Option<Tier>,
+    #[serde(default)]
+    overdraft_limit: Option<Number>,
+    // Recipient of transfers, since version 4.
+    #[serde(default)]
+    to: Option<u16>,
+    // Hex signature of signed transactions.
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+// Entries were all accepted when they were journaled, so one the ledger turns
+// down on replay means the journal doesn't match the ledger it came from.
+fn replay(ledger: &mut Ledger, entry: &JournalEntry) -> io::Result<()> {
+    ledger.set_time(entry.timestamp);
+    ledger
+        .apply_transaction(entry.transaction_id, &entry.transaction)
+        .map(|_| ())
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("journal entry {} failed: {err:?}", entry.sequence),
+            )
+        })
+}
+
+const HEADER: &str = "# crab-journal ";
+
+pub const JOURNAL_VERSION: u32 = 4;
+
+// Journals start with a `# crab-journal <version>` line; version 1 had none
+// and is otherwise read the same way. Version 3 added the settings columns
+// and version 4 the recipient column.
+fn read_version(first_line: &str) -> io::Result<Option<u32>> {
+    let Some(version) = first_line.trim_end().strip_prefix(HEADER) else {
+        return Ok(None);
+    };
+    match version.parse() {
+        Ok(version) if version > JOURNAL_VERSION => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("journal version {version} is newer than the supported {JOURNAL_VERSION}"),
+        )),
+        Ok(version) if version > 0 => Ok(Some(version)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("malformed journal header: {}", first_line.trim_end()),
+        )),
+    }
+}
+
+// Every transaction the ledger accepted, in the order it was applied. A
+// journal picked up from a snapshot only holds what came after it, numbered
+// on from `start`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Journal {
+    entries: Vector<JournalEntry>,
+    start: u64,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn starting_after(sequence: u64) -> Self {
+        Journal {
+            entries: Vector::new(),
+            start: sequence,
+        }
+    }
+
+    pub fn entries(&self) -> &Vector<JournalEntry> {
+        &self.entries
+    }
+
+    // Never below `start`, which erasure moves up past the entries it drops.
+    pub fn last_sequence(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(self.start, |entry| entry.sequence.max(self.start))
+    }
+
+    // Drops the client's entries. Their sequences aren't handed out again, so
+    // whatever already consumed them doesn't skip the entries that follow.
+    pub(super) fn erase_client(&mut self, client_id: ClientId) {
+        self.start = self.last_sequence();
+        self.entries
+            .retain(|entry| entry.transaction.client_id() != client_id);
+    }
+
+    pub fn append(
+        &mut self,
+        timestamp: Timestamp,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> u64 {
+        let sequence = self.last_sequence() + 1;
+        self.entries.push_back(JournalEntry {
+            sequence,
+            timestamp,
+            transaction_id,
+            transaction: *transaction,
+        });
+        sequence
+    }
+
+    pub fn replay_until(&self, point: ReplayPoint, policy: Policy) -> io::Result<Ledger> {
+        let mut ledger = Ledger::with_policy(policy);
+        for entry in self
+            .entries
+            .iter()
+            .take_while(|entry| point.includes(entry))
+        {
+            replay(&mut ledger, entry)?;
+        }
+        Ok(ledger)
+    }
+
+    pub fn write_csv<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{HEADER}{JOURNAL_VERSION}")?;
+        self.write_records(csv::Writer::from_writer(writer), 0)
+    }
+
+    // Writes the entries after `sequence` with no headers, to be added to the
+    // end of a journal file holding the ones before.
+    pub fn append_csv<W: io::Write>(&self, writer: W, sequence: u64) -> io::Result<()> {
+        let writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        self.write_records(writer, sequence)
+    }
+
+    fn write_records<W: io::Write>(
+        &self,
+        mut writer: csv::Writer<W>,
+        after: u64,
+    ) -> io::Result<()> {
+        for entry in self.entries.iter().filter(|entry| entry.sequence > after) {
+            let settings = entry.transaction.settings().copied().unwrap_or_default();
+            writer.serialize(JournalRecord {
+                sequence: entry.sequence,
+                timestamp: entry.timestamp.0,
+                operation: entry.transaction.operation(),
+                client: entry.transaction.client_id().0,
+                tx: entry.transaction_id.0,
+                amount: entry.transaction.amount(),
+                currency: settings.currency,
+                tier: settings.tier,
+                overdraft_limit: settings.overdraft_limit,
+                to: entry.transaction.operation().recipient().map(|to| to.0),
+                signature: entry.transaction.signature().map(Signature::to_hex),
+            })?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_csv<R: io::Read>(reader: R) -> io::Result<Self> {
+        let mut reader = io::BufReader::new(reader);
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line)?;
+        if read_version(&first_line)?.is_some() {
+            first_line.clear();
+        }
+        let reader = io::Read::chain(io::Cursor::new(first_line), reader);
+        let mut journal = Journal::new();
+        for record in csv::Reader::from_reader(reader).deserialize::<JournalRecord>() {
+            let record = record?;
+            let operation = record.operation.with_recipient(record.to.map(ClientId));
+            let mut transaction =
+                Transaction::new(ClientId(record.client), record.amount, operation);
+            let settings = AccountSettings {
+                currency: record.currency,
+                tier: record.tier,
+                overdraft_limit: record.overdraft_limit,
+            };
+            if settings != AccountSettings::default() {
+                transaction = transaction.with_settings(settings);
+            }
+            if let Some(hex) = record.signature.filter(|hex| !hex.is_empty()) {
+                let signature = Signature::from_hex(&hex).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid signature in journal entry {}", record.sequence),
+                    )
+                })?;
+                transaction = transaction.with_signature(signature);
+            }
+            journal.entries.push_back(JournalEntry {
+                sequence: record.sequence,
+                timestamp: Timestamp(record.timestamp),
+                transaction_id: TransactionId(record.tx),
+                transaction,
+            });
+        }
+        Ok(journal)
+    }
+}
+
+impl Journal {
+    // Rewrites a journal written by an older version in the current format
+    // and returns the version it was written in.
+    pub fn migrate<R: io::Read, W: io::Write>(reader: R, writer: W) -> io::Result<u32> {
+        let mut reader = io::BufReader::new(reader);
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line)?;
+        let version = read_version(&first_line)?.unwrap_or(1);
+        let reader = io::Read::chain(io::Cursor::new(first_line), reader);
+        Journal::read_csv(reader)?.write_csv(writer)?;
+        Ok(version)
+    }
+
+    pub fn export(&self, name: &str, key: &[u8]) -> io::Result<(Vec<u8>, Manifest)> {
+        let mut contents = Vec::new();
+        self.write_csv(&mut contents)?;
+        let mut manifest = Manifest::new();
+        manifest.add_file(name, &contents);
+        manifest.sign(key);
+        Ok((contents, manifest))
+    }
+
+    pub fn import(
+        name: &str,
+        contents: &[u8],
+        manifest: &Manifest,
+        key: &[u8],
+    ) -> Result<Self, ManifestError> {
+        manifest.verify_signature(key)?;
+        manifest.verify_file(name, contents)?;
+        Self::read_csv(contents).map_err(|err| ManifestError::Malformed(err.to_string()))
+    }
+}
+
+impl Ledger {
+    pub fn enable_journal(&mut self) {
+        self.journal.get_or_insert_with(Journal::new);
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    // Restores a ledger from its latest snapshot, if any, and the journal
+    // written alongside it. Only the entries after the snapshot are replayed;
+    // the ledger keeps the whole journal to carry on appending to it.
+    pub fn recover<R: io::Read>(
+        snapshot: Option<R>,
+        journal: R,
+        policy: Policy,
+    ) -> io::Result<Ledger> {
+        let mut ledger = match snapshot {
+            Some(snapshot) => {
+                let ledger = Ledger::read_snapshot(snapshot, policy)?;
+                if ledger.journal.is_none() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "snapshot was taken without a journal",
+                    ));
+                }
+                ledger
+            }
+            None => Ledger::with_policy(policy),
+        };
+        let covered = ledger
+            .journal
+            .take()
+            .map_or(0, |journal| journal.last_sequence());
+        let journal = Journal::read_csv(journal)?;
+        if journal.last_sequence() < covered {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "journal ends at {} but the snapshot covers up to {covered}",
+                    journal.last_sequence()
+                ),
+            ));
+        }
+        for entry in journal
+            .entries
+            .iter()
+            .filter(|entry| entry.sequence > covered)
+        {
+            replay(&mut ledger, entry)?;
+        }
+        ledger.journal = Some(journal);
+        Ok(ledger)
+    }
+
+    pub fn state_at(&self, point: ReplayPoint) -> Option<io::Result<Ledger>> {
+        self.journal
+            .as_ref()
+            .map(|journal| journal.replay_until(point, self.policy))
+    }
+}
@@ -0,0 +1,118 @@
+use super::{Ledger, Tier, TierLimit};
+use crate::{
+    account::Account, account::ClientId, account::Number, transactions::Operation,
+    transactions::Transaction, transactions::TransactionError, transactions::TransactionId,
+    transactions::TransactionResult,
+};
+
+impl Ledger {
+    pub fn tier(&self, client_id: ClientId) -> Tier {
+        self.tiers.get(&client_id).copied().unwrap_or_default()
+    }
+
+    pub fn set_tier(&mut self, client_id: ClientId, tier: Tier) {
+        if tier == Tier::default() {
+            self.tiers.remove(&client_id);
+        } else {
+            self.tiers.insert(client_id, tier);
+        }
+    }
+
+    pub fn daily_volume(&self, client_id: ClientId) -> Number {
+        match self.daily_volume.get(&client_id) {
+            Some((day, volume)) if *day == self.now.day() => *volume,
+            _ => Number::ZERO,
+        }
+    }
+
+    pub fn rolling_withdrawals(&self, client_id: ClientId) -> Number {
+        self.withdrawals
+            .get(&client_id)
+            .map_or(Number::ZERO, |window| window.sum(self.now))
+    }
+
+    pub(super) fn check_amount_range(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult {
+        let Some(range) = self.policy.amount_range(transaction.operation()) else {
+            return Ok(());
+        };
+        let amount = transaction.amount().abs();
+        if range.min.is_some_and(|min| amount < min) {
+            return Err(TransactionError::AmountBelowMinimum(
+                transaction_id,
+                transaction.amount(),
+            ));
+        }
+        if range.max.is_some_and(|max| amount > max) {
+            return Err(TransactionError::AmountAboveMaximum(
+                transaction_id,
+                transaction.amount(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(super) fn check_tier_limits(
+        &self,
+        transaction: &Transaction,
+        account: &Account,
+    ) -> TransactionResult {
+        // Adjustments are corrections made by operators, not client activity.
+        if transaction.operation() == Operation::Adjustment {
+            return Ok(());
+        }
+        let client_id = transaction.client_id();
+        let limits = self.policy.limits(self.tier(client_id));
+        let exceeded = |limit| Err(TransactionError::TierLimitExceeded(client_id, limit));
+        if limits
+            .max_transaction
+            .is_some_and(|max| transaction.amount() > max)
+        {
+            return exceeded(TierLimit::TransactionAmount);
+        }
+        if limits.max_daily_volume.is_some_and(|max| {
+            self.daily_volume(client_id)
+                .saturating_add(transaction.amount())
+                > max
+        }) {
+            return exceeded(TierLimit::DailyVolume);
+        }
+        if transaction.operation() == Operation::Withdrawal
+            && limits.max_rolling_withdrawals.is_some_and(|max| {
+                self.rolling_withdrawals(client_id)
+                    .saturating_add(transaction.amount())
+                    > max
+            })
+        {
+            return exceeded(TierLimit::RollingWithdrawals);
+        }
+        if transaction.operation() == Operation::Deposit
+            && limits.max_balance.is_some_and(|max| account.total() > max)
+        {
+            return exceeded(TierLimit::Balance);
+        }
+        Ok(())
+    }
+
+    pub(super) fn record_volume(&mut self, transaction: &Transaction) {
+        if transaction.operation() == Operation::Adjustment {
+            return;
+        }
+        // Volumes past what a `Number` holds stay at the limit rather than
+        // failing a transaction that was already applied.
+        let volume = self
+            .daily_volume(transaction.client_id())
+            .saturating_add(transaction.amount());
+        self.daily_volume
+            .insert(transaction.client_id(), (self.now.day(), volume));
+        if transaction.operation() == Operation::Withdrawal {
+            self.withdrawals
+                .entry(transaction.client_id())
+                .or_default()
+                .add(self.now, transaction.amount());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::Ledger;
+use crate::account::{Account, ClientId};
+use crate::transactions::TransactionId;
+
+// How to combine the account of a client present in both ledgers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    // Balances are summed and a lock on either side locks the result, which
+    // is right when the input was split by position rather than by client.
+    #[default]
+    Sum,
+    // Clients should never span partitions; refuse to merge if one does.
+    Reject,
+    PreferOurs,
+    PreferTheirs,
+}
+
+// A client found in both ledgers, with its account on each side before the
+// merge.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub client_id: ClientId,
+    pub ours: Account,
+    pub theirs: Account,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub conflicts: Vec<MergeConflict>,
+    // Ids stored on both sides for different transactions. The preferred
+    // side's is kept, ours when summing.
+    pub conflicting_transactions: Vec<TransactionId>,
+}
+
+impl MergeReport {
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty() && self.conflicting_transactions.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    // Nothing was merged.
+    Conflicts(MergeReport),
+}
+
+impl Ledger {
+    // Folds a ledger built from another partition of the input into this one,
+    // summing the balances of clients present in both. Transactions already
+    // known here are kept.
+    pub fn merge(&mut self, other: Ledger) {
+        let _ = self.merge_with(other, MergeStrategy::Sum);
+    }
+
+    pub fn merge_with(
+        &mut self,
+        other: Ledger,
+        strategy: MergeStrategy,
+    ) -> Result<MergeReport, MergeError> {
+        let report = self.merge_report(&other);
+        if strategy == MergeStrategy::Reject && !report.is_empty() {
+            return Err(MergeError::Conflicts(report));
+        }
+        let theirs_wins = strategy == MergeStrategy::PreferTheirs;
+        for (client_id, theirs) in other.accounts.iter() {
+            let merged = match (self.accounts.get(&client_id), strategy) {
+                (None, _) | (Some(_), MergeStrategy::PreferTheirs) => theirs,
+                (Some(ours), MergeStrategy::PreferOurs) => ours,
+                (Some(ours), _) => Account::from_parts(
+                    ours.available() + theirs.available(),
+                    ours.held() + theirs.held(),
+                    ours.locked() || theirs.locked(),
+                ),
+            };
+            self.accounts.insert(client_id, merged);
+        }
+        for (transaction_id, transaction) in other.transactions {
+            if theirs_wins || !self.transactions.contains_key(&transaction_id) {
+                self.transactions.insert(transaction_id, transaction);
+            }
+        }
+        let mut open_disputes = other.open_disputes;
+        for conflict in &report.conflicts {
+            let theirs = open_disputes
+                .remove(&conflict.client_id)
+                .unwrap_or_default();
+            match strategy {
+                MergeStrategy::PreferOurs => {}
+                MergeStrategy::PreferTheirs => {
+                    self.open_disputes.insert(conflict.client_id, theirs);
+                }
+                _ => *self.open_disputes.entry(conflict.client_id).or_default() += theirs,
+            }
+        }
+        // the rest only have disputes on their side
+        self.open_disputes.extend(open_disputes);
+        self.archived.extend(other.archived);
+        // both sides' flows happened, whichever account was kept
+        self.totals.add(&other.totals);
+        Ok(report)
+    }
+
+    fn merge_report(&self, other: &Ledger) -> MergeReport {
+        let conflicts = other
+            .accounts
+            .iter()
+            .filter_map(|(client_id, theirs)| {
+                let ours = self.accounts.get(&client_id)?;
+                Some(MergeConflict {
+                    client_id,
+                    ours,
+                    theirs,
+                })
+            })
+            .collect();
+        let conflicting_transactions = other
+            .transactions
+            .iter()
+            .filter(|(transaction_id, theirs)| {
+                self.transactions
+                    .get(*transaction_id)
+                    .is_some_and(|ours| ours != *theirs)
+            })
+            .map(|(transaction_id, _)| *transaction_id)
+            .collect();
+        MergeReport {
+            conflicts,
+            conflicting_transactions,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::Ledger;
+use crate::transactions::{Outcome, Transaction, TransactionId, TransactionResult};
+use std::sync::Arc;
+
+// Hooks run around every applied transaction, in the order the middlewares
+// were added: all of them authorize, then all of them enrich, then any of
+// them may flag it for review, then the transaction is applied and every
+// middleware is notified of the result, including rejections.
+pub trait TransactionMiddleware: Send + Sync {
+    fn authorize(
+        &self,
+        _ledger: &Ledger,
+        _transaction_id: TransactionId,
+        _transaction: &Transaction,
+    ) -> TransactionResult {
+        Ok(())
+    }
+
+    fn enrich(
+        &self,
+        _ledger: &Ledger,
+        _transaction_id: TransactionId,
+        _transaction: &mut Transaction,
+    ) {
+    }
+
+    // Flagged transactions are parked for review rather than applied, see
+    // `Ledger::approve_review`.
+    fn flag(
+        &self,
+        _ledger: &Ledger,
+        _transaction_id: TransactionId,
+        _transaction: &Transaction,
+    ) -> bool {
+        false
+    }
+
+    fn notify(
+        &self,
+        _ledger: &Ledger,
+        _transaction_id: TransactionId,
+        _transaction: &Transaction,
+        _result: &TransactionResult<Outcome>,
+    ) {
+    }
+}
+
+impl Ledger {
+    pub fn add_middleware(&mut self, middleware: Arc<dyn TransactionMiddleware>) {
+        self.middlewares.push(middleware);
+    }
+
+    pub(super) fn apply_through_middlewares(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        let middlewares = self.middlewares.clone();
+        let mut transaction = *transaction;
+        let result = middlewares
+            .iter()
+            .try_for_each(|middleware| middleware.authorize(self, transaction_id, &transaction))
+            .and_then(|()| {
+                for middleware in &middlewares {
+                    middleware.enrich(self, transaction_id, &mut transaction);
+                }
+                if middlewares
+                    .iter()
+                    .any(|middleware| middleware.flag(self, transaction_id, &transaction))
+                {
+                    return Ok(self.park(transaction_id, &transaction, true));
+                }
+                self.commit(transaction_id, &transaction)
+            });
+        for middleware in &middlewares {
+            middleware.notify(self, transaction_id, &transaction, &result);
+        }
+        result
+    }
+}
@@ -1,17 +1,161 @@
 use super::{
-    account::Account, account::ClientId, account::Number, transactions::Operation,
-    transactions::Transaction, transactions::TransactionError, transactions::TransactionId,
-    transactions::TransactionResult, transactions::TransactionState,
+    account::Account, account::AccountResult, account::ClientId, account::Number,
+    transactions::state_machine, transactions::Operation, transactions::Outcome,
+    transactions::SignatureVerifier, transactions::Transaction, transactions::TransactionError,
+    transactions::TransactionId, transactions::TransactionResult, transactions::TransactionState,
 };
 
-use std::collections::HashMap;
+use imbl::{HashMap, OrdMap, OrdSet, Vector};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use window::RollingWindow;
 
-type AccountMap = HashMap<ClientId, Account>;
-type TransactionMap = HashMap<TransactionId, Transaction>;
+mod accounts;
+mod accrual;
+mod actor;
+mod admin;
+mod aging;
+mod alerts;
+mod audit;
+mod batch;
+mod branch;
+mod cases;
+mod clock;
+mod command;
+mod concurrent;
+mod disputes;
+mod dormancy;
+mod escrow;
+mod events;
+mod finalize;
+mod freeze;
+mod groups;
+mod histogram;
+mod history;
+mod journal;
+mod limits;
+mod merge;
+mod middleware;
+mod onboarding;
+mod overflow;
+mod paranoid;
+mod policy;
+mod quarantine;
+mod query;
+mod ranking;
+mod registry;
+mod replay;
+mod retention;
+mod review;
+mod risk;
+mod screening;
+mod shadow;
+mod snapshot;
+mod store;
+mod sweep;
+mod timeline;
+mod totals;
+mod transfer;
+mod window;
+pub use accounts::AccountTable;
+pub use accrual::Accrual;
+pub use actor::{ActorStopped, LedgerActor, LedgerHandle};
+pub use admin::Tombstone;
+pub use aging::{HeldAging, AGE_BUCKETS};
+pub use alerts::{Alert, AlertRules};
+pub use audit::AuditEvent;
+pub use batch::{BatchedStore, DEFAULT_BATCH_SIZE};
+pub use cases::{Case, CaseError, CaseId, CaseState};
+pub use clock::{Timestamp, SECONDS_PER_DAY};
+pub use command::{CommandOutput, LedgerCommand};
+pub use concurrent::ConcurrentLedger;
+pub use disputes::{DisputeEvent, DisputeInfo, DisputeLetter};
+pub use escrow::{Escrow, EscrowError, EscrowId, EscrowState};
+pub use events::{EventFilter, EventStream, LedgerEvent, DEFAULT_EVENT_CAPACITY};
+pub use finalize::{Finalization, Finalizer, PeriodClose};
+pub use freeze::FreezeReason;
+pub use groups::{Group, GroupError, GroupId};
+pub use histogram::Histogram;
+pub use history::{StateChange, StoredTransaction};
+pub use journal::{Journal, JournalEntry, ReplayPoint, JOURNAL_VERSION};
+pub use merge::{MergeConflict, MergeError, MergeReport, MergeStrategy};
+pub use middleware::TransactionMiddleware;
+pub use onboarding::{AccountSettings, Currency};
+pub use policy::{
+    AmountRange, ChargebackExcess, HeldAccrual, IdleSweep, OverflowPolicy, Policy, Tier, TierLimit,
+    TierLimits,
+};
+pub use query::{AccountFilter, AccountsSnapshot, LedgerStats, Page, TransactionFilter};
+pub use ranking::{BalanceIndex, BalanceKey};
+pub use registry::{CustomOperation, CustomTransaction, OperationHandler, OperationRegistry};
+pub use review::{Released, Review};
+pub use risk::RiskProfile;
+pub use screening::{ScreeningOutcome, ScreeningProvider};
+pub use shadow::{Divergence, LedgerBackend, ShadowLedger};
+pub use snapshot::SNAPSHOT_VERSION;
+pub use store::{AccountStore, AsyncAccountStore, AsyncTransactionStore, TransactionStore};
+pub use sweep::Sweep;
+pub use totals::RunningTotals;
+
+type TransactionMap = OrdMap<TransactionId, Transaction>;
+
+// The effect of a transaction, computed against the current state without
+// mutating it so that validation and application share the same checks.
+struct Change {
+    client_id: ClientId,
+    account: Account,
+    transaction_id: TransactionId,
+    transaction: Transaction,
+    // The recipient of a transfer and its account once credited, committed
+    // together with the debited one.
+    credit: Option<(ClientId, Account)>,
+}
 
 pub struct Ledger {
-    accounts: AccountMap,
+    // Everything below that grows with the ledger is a persistent
+    // collection, shared with clones until one of them writes, see
+    // `clone_state`.
+    accounts: AccountTable,
     transactions: TransactionMap,
+    archived: OrdSet<ClientId>,
+    erased_transactions: OrdSet<TransactionId>,
+    tombstones: Vector<Tombstone>,
+    audit_log: Vector<AuditEvent>,
+    signature_verifier: Option<Arc<dyn SignatureVerifier + Send + Sync>>,
+    screening_provider: Option<Arc<dyn ScreeningProvider>>,
+    policy: Policy,
+    open_disputes: HashMap<ClientId, usize>,
+    now: Timestamp,
+    tiers: HashMap<ClientId, Tier>,
+    daily_volume: HashMap<ClientId, (u64, Number)>,
+    withdrawals: HashMap<ClientId, RollingWindow>,
+    journal: Option<Journal>,
+    history: HashMap<TransactionId, Vec<history::StateChange>>,
+    retained: HashMap<ClientId, VecDeque<TransactionId>>,
+    archived_transactions: OrdMap<TransactionId, ClientId>,
+    cases: OrdMap<CaseId, Case>,
+    risk: HashMap<ClientId, risk::RiskProfile>,
+    quarantined: HashMap<ClientId, Number>,
+    groups: OrdMap<GroupId, Group>,
+    escrows: OrdMap<EscrowId, Escrow>,
+    last_activity: HashMap<ClientId, Timestamp>,
+    dormant: OrdSet<ClientId>,
+    overflow_reviews: OrdMap<TransactionId, Transaction>,
+    reviews: OrdMap<ClientId, VecDeque<Review>>,
+    timeline: Option<timeline::Timeline>,
+    opened: OrdSet<ClientId>,
+    settings: HashMap<ClientId, AccountSettings>,
+    accruals: Vector<Accrual>,
+    sweeps: Vector<sweep::Sweep>,
+    screenings: HashMap<TransactionId, ScreeningOutcome>,
+    totals: RunningTotals,
+    frozen: OrdMap<ClientId, FreezeReason>,
+    // When each open dispute was opened and how many days it accrued for.
+    accrued_days: HashMap<TransactionId, (Timestamp, u64)>,
+    events: events::EventBus,
+    middlewares: Vec<Arc<dyn TransactionMiddleware>>,
+    finalizers: Vec<Finalizer>,
+    paranoid: bool,
 }
 
 impl Default for Ledger {
@@ -23,107 +167,477 @@ impl Default for Ledger {
 impl Ledger {
     pub fn new() -> Ledger {
         Ledger {
-            accounts: AccountMap::with_capacity(u16::MAX as usize),
-            transactions: TransactionMap::with_capacity(128),
+            accounts: AccountTable::new(),
+            transactions: TransactionMap::new(),
+            archived: OrdSet::new(),
+            erased_transactions: OrdSet::new(),
+            tombstones: Vector::new(),
+            audit_log: Vector::new(),
+            signature_verifier: None,
+            screening_provider: None,
+            policy: Policy::default(),
+            open_disputes: HashMap::new(),
+            now: Timestamp::default(),
+            tiers: HashMap::new(),
+            daily_volume: HashMap::new(),
+            withdrawals: HashMap::new(),
+            journal: None,
+            history: HashMap::new(),
+            retained: HashMap::new(),
+            archived_transactions: OrdMap::new(),
+            cases: OrdMap::new(),
+            risk: HashMap::new(),
+            quarantined: HashMap::new(),
+            groups: OrdMap::new(),
+            escrows: OrdMap::new(),
+            last_activity: HashMap::new(),
+            dormant: OrdSet::new(),
+            overflow_reviews: OrdMap::new(),
+            reviews: OrdMap::new(),
+            timeline: None,
+            opened: OrdSet::new(),
+            settings: HashMap::new(),
+            accruals: Vector::new(),
+            sweeps: Vector::new(),
+            screenings: HashMap::new(),
+            totals: RunningTotals::default(),
+            frozen: OrdMap::new(),
+            accrued_days: HashMap::new(),
+            events: events::EventBus::default(),
+            middlewares: Vec::new(),
+            finalizers: Vec::new(),
+            paranoid: false,
+        }
+    }
+
+    pub fn with_policy(policy: Policy) -> Ledger {
+        Ledger {
+            policy,
+            ..Ledger::new()
+        }
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    // Takes effect from the next transaction; balances, open disputes and
+    // usage counters are kept as they are.
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+    }
+
+    pub fn open_disputes(&self, client_id: ClientId) -> usize {
+        self.open_disputes.get(&client_id).copied().unwrap_or(0)
+    }
+
+    fn check_dispute_quota(&self, client_id: ClientId) -> TransactionResult {
+        match self.policy.max_open_disputes {
+            Some(max) if self.open_disputes(client_id) >= max => {
+                Err(TransactionError::DisputeQuotaExceeded(client_id))
+            }
+            _ => Ok(()),
         }
     }
 
-    pub fn get_transaction_and_account_mut(
+    fn track_open_disputes(
         &mut self,
-        transaction_id: TransactionId,
         client_id: ClientId,
-    ) -> Result<(&mut Transaction, &mut Account), TransactionError> {
-        let maybe_disputed_transaction = self.transactions.get_mut(&transaction_id);
-        if maybe_disputed_transaction.is_none() {
-            return Err(TransactionError::UnknownTransactionId(transaction_id));
+        previous: Option<TransactionState>,
+        current: TransactionState,
+    ) {
+        let was_open = previous.is_some_and(TransactionState::is_disputed);
+        let is_open = current.is_disputed();
+        if is_open && !was_open {
+            *self.open_disputes.entry(client_id).or_default() += 1;
+        } else if was_open && !is_open {
+            if let Some(count) = self.open_disputes.get_mut(&client_id) {
+                *count -= 1;
+                if *count == 0 {
+                    self.open_disputes.remove(&client_id);
+                }
+            }
         }
-        let maybe_account = self.accounts.get_mut(&client_id);
-        if maybe_account.is_none() {
-            return Err(TransactionError::UnknownClientId(client_id));
+    }
+
+    fn prepare_disputed_transaction(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        err: TransactionError,
+        f: impl FnOnce(&mut Transaction, &mut Account) -> TransactionResult,
+    ) -> Result<Change, TransactionError> {
+        self.check_transaction_archived(transaction_id)?;
+        let mut disputed_transaction = *self
+            .transactions
+            .get(&transaction_id)
+            .ok_or(TransactionError::UnknownTransactionId(transaction_id))?;
+        let client_id = self.account_holder(transaction.client_id());
+        let mut account = self
+            .accounts
+            .get(&client_id)
+            .ok_or(TransactionError::UnknownClientId(transaction.client_id()))?;
+        transaction.check_valid_dispute(
+            transaction_id,
+            &disputed_transaction,
+            self.policy.dispute_withdrawals,
+        )?;
+        state_machine::next_state(disputed_transaction.state(), transaction.operation())
+            .ok_or(err)?;
+        f(&mut disputed_transaction, &mut account)?;
+        Ok(Change {
+            client_id,
+            account,
+            transaction_id,
+            transaction: disputed_transaction,
+            credit: None,
+        })
+    }
+
+    fn check_redispute(&self, transaction_id: TransactionId) -> TransactionResult {
+        let resolved = self
+            .transactions
+            .get(&transaction_id)
+            .is_some_and(|transaction| transaction.state() == TransactionState::Resolved);
+        if resolved && self.policy.reject_redisputes {
+            Err(TransactionError::AlreadyDisputed(transaction_id))
+        } else {
+            Ok(())
         }
-        Ok((maybe_disputed_transaction.unwrap(), maybe_account.unwrap()))
     }
-    pub fn get_or_insert_account_mut(&mut self, client_id: ClientId) -> &mut Account {
-        self.accounts.entry(client_id).or_default()
+
+    fn prepare_new_transaction(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        f: impl FnOnce(&mut Account) -> AccountResult,
+    ) -> Result<Change, TransactionError> {
+        self.id_exists(transaction_id)?;
+        self.check_replay_window(transaction_id)?;
+        let client_id = self.account_holder(transaction.client_id());
+        let mut account = self.accounts.get(&client_id).unwrap_or_default();
+        f(&mut account)
+            .map_err(|err| TransactionError::AccountError(transaction.client_id(), err))?;
+        self.check_tier_limits(transaction, &account)?;
+        Ok(Change {
+            client_id,
+            account,
+            transaction_id,
+            transaction: *transaction,
+            credit: None,
+        })
+    }
+
+    pub fn set_signature_verifier(
+        &mut self,
+        verifier: impl SignatureVerifier + Send + Sync + 'static,
+    ) {
+        self.signature_verifier = Some(Arc::new(verifier));
+    }
+
+    pub fn verifies_signatures(&self) -> bool {
+        self.signature_verifier.is_some()
+    }
+
+    fn verify_signature(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult {
+        let Some(verifier) = &self.signature_verifier else {
+            return Ok(());
+        };
+        match transaction.signature() {
+            Some(signature) if verifier.verify(transaction_id, transaction, signature) => Ok(()),
+            _ => Err(TransactionError::InvalidSignature(transaction_id)),
+        }
     }
 
     fn id_exists(&self, transaction_id: TransactionId) -> TransactionResult {
-        if self.transactions.contains_key(&transaction_id) {
+        if self.transactions.contains_key(&transaction_id)
+            || self.erased_transactions.contains(&transaction_id)
+            || self.archived_transactions.contains_key(&transaction_id)
+            || self.overflow_reviews.contains_key(&transaction_id)
+        {
             Err(TransactionError::RepeatedTransactionId(transaction_id))
         } else {
             Ok(())
         }
     }
-    pub fn apply_transaction(
-        &mut self,
+
+    // What every transaction is checked for, whatever its operation.
+    fn check_submission(
+        &self,
         transaction_id: TransactionId,
         transaction: &Transaction,
     ) -> TransactionResult {
-        if transaction.amount() < Number::ZERO {
+        self.verify_signature(transaction_id, transaction)?;
+        if transaction.amount() < Number::ZERO && transaction.operation() != Operation::Adjustment {
             return Err(TransactionError::InvalidAmount(
                 transaction_id,
                 transaction.amount(),
             ));
         }
+        self.check_archived(transaction.client_id())?;
+        self.check_amount_range(transaction_id, transaction)
+    }
+
+    fn prepare(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> Result<Change, TransactionError> {
+        self.check_submission(transaction_id, transaction)?;
         match transaction.operation() {
             Operation::Deposit => {
-                self.id_exists(transaction_id)?;
-                let account = self.get_or_insert_account_mut(transaction.client_id());
-                account
-                    .deposit(transaction.amount())
-                    .map_err(|err| TransactionError::AccountError(transaction.client_id(), err))?;
-                self.transactions.insert(transaction_id, *transaction);
-                Ok(())
+                self.check_onboarded(transaction.client_id())?;
+                self.check_frozen(transaction.client_id())?;
+                let quarantined = self.is_quarantined(transaction.client_id());
+                let saturate = self.policy.deposit_overflow == OverflowPolicy::Saturate;
+                let mut credited = transaction.amount();
+                let mut change =
+                    self.prepare_new_transaction(transaction_id, transaction, |account| {
+                        if saturate {
+                            credited = account.deposit_saturating(credited);
+                        } else {
+                            account.deposit(credited)?;
+                        }
+                        if quarantined {
+                            account.dispute(credited)?;
+                        }
+                        Ok(())
+                    })?;
+                // Disputes and chargebacks of a saturated deposit only move
+                // what was credited.
+                change.transaction = change.transaction.with_amount(credited);
+                Ok(change)
             }
             Operation::Withdrawal => {
-                self.id_exists(transaction_id)?;
-                let account = self.get_or_insert_account_mut(transaction.client_id());
-                account
-                    .withdraw(transaction.amount())
-                    .map_err(|err| TransactionError::AccountError(transaction.client_id(), err))?;
-                self.transactions.insert(transaction_id, *transaction);
-                Ok(())
+                self.check_onboarded(transaction.client_id())?;
+                self.check_frozen(transaction.client_id())?;
+                self.check_quarantined(transaction.client_id())?;
+                self.check_group_limit(transaction)?;
+                let holder = self.account_holder(transaction.client_id());
+                self.check_dormant(holder)?;
+                let overdraft = self.overdraft_limit(holder);
+                self.prepare_new_transaction(transaction_id, transaction, |account| {
+                    account.overdraw(transaction.amount(), overdraft)
+                })
+            }
+            Operation::Transfer { to } => {
+                self.check_sender(transaction)?;
+                self.prepare_transfer(transaction_id, transaction, to)
+            }
+            Operation::Adjustment => {
+                if !self.policy.allow_adjustments {
+                    return Err(TransactionError::AdjustmentsDisabled(transaction_id));
+                }
+                self.check_onboarded(transaction.client_id())?;
+                self.prepare_new_transaction(transaction_id, transaction, |account| {
+                    account.adjust(transaction.amount())
+                })
+            }
+            Operation::OpenAccount => {
+                if self.opened.contains(&transaction.client_id()) {
+                    return Err(TransactionError::AccountAlreadyOpen(
+                        transaction.client_id(),
+                    ));
+                }
+                self.check_settings(transaction_id, transaction)?;
+                self.prepare_new_transaction(transaction_id, transaction, |_| Ok(()))
             }
             Operation::Dispute => {
-                let (disputed_transaction, account) =
-                    self.get_transaction_and_account_mut(transaction_id, transaction.client_id())?;
-                transaction.check_valid_dispute(transaction_id, disputed_transaction)?;
-                disputed_transaction.state_matches_or(
-                    TransactionState::Ok,
+                self.check_redispute(transaction_id)?;
+                let change = self.prepare_disputed_transaction(
+                    transaction_id,
+                    transaction,
                     TransactionError::AlreadyDisputed(transaction_id),
+                    |disputed_transaction, account| disputed_transaction.dispute(account),
                 )?;
-                disputed_transaction.dispute(account)
-            }
-            Operation::Resolve => {
-                let (disputed_transaction, account) =
-                    self.get_transaction_and_account_mut(transaction_id, transaction.client_id())?;
-                transaction.check_valid_dispute(transaction_id, disputed_transaction)?;
-                disputed_transaction.state_matches_or(
-                    TransactionState::Disputed,
-                    TransactionError::UndisputedTransaction(transaction_id),
-                )?;
-                disputed_transaction.resolve(account)
+                self.check_dispute_quota(transaction.client_id())?;
+                Ok(change)
             }
+            Operation::Resolve => self.prepare_disputed_transaction(
+                transaction_id,
+                transaction,
+                TransactionError::UndisputedTransaction(transaction_id),
+                |disputed_transaction, account| disputed_transaction.resolve(account),
+            ),
+            Operation::Unlock => self.prepare_unlock(transaction_id, transaction),
             Operation::Chargeback => {
-                let (disputed_transaction, account) =
-                    self.get_transaction_and_account_mut(transaction_id, transaction.client_id())?;
-                transaction.check_valid_dispute(transaction_id, disputed_transaction)?;
-                disputed_transaction.state_matches_or(
-                    TransactionState::Disputed,
+                let excess = self.policy.chargeback_excess;
+                self.prepare_disputed_transaction(
+                    transaction_id,
+                    transaction,
                     TransactionError::UndisputedTransaction(transaction_id),
-                )?;
-                disputed_transaction.chargeback(account)
+                    |disputed_transaction, account| {
+                        disputed_transaction.chargeback(account, excess)
+                    },
+                )
             }
         }
     }
+
+    // A resubmitted deposit, withdrawal, adjustment or transfer identical to
+    // the one stored under its id. Anything else reusing the id is an error.
+    fn is_duplicate(&self, transaction_id: TransactionId, transaction: &Transaction) -> bool {
+        let new = matches!(
+            transaction.operation(),
+            Operation::Deposit
+                | Operation::Withdrawal
+                | Operation::Adjustment
+                | Operation::Transfer { .. }
+        );
+        new && self
+            .transactions
+            .get(&transaction_id)
+            .is_some_and(|stored| {
+                stored.client_id() == transaction.client_id()
+                    && stored.operation() == transaction.operation()
+                    && stored.amount() == transaction.amount()
+            })
+    }
+
+    pub fn validate(
+        &self,
+        transaction: &Transaction,
+        transaction_id: TransactionId,
+    ) -> TransactionResult {
+        if self.is_duplicate(transaction_id, transaction) {
+            return Ok(());
+        }
+        match self.prepare(transaction_id, transaction) {
+            Err(err) if self.held_for_review(transaction, &err) => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    pub fn apply_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        // behind a transaction under review, the client's others wait for it
+        if self.awaiting_review(transaction.client_id()) {
+            return Ok(self.park(transaction_id, transaction, false));
+        }
+        self.admit(transaction_id, transaction)
+    }
+
+    fn admit(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        if self.middlewares.is_empty() {
+            return self.commit(transaction_id, transaction);
+        }
+        self.apply_through_middlewares(transaction_id, transaction)
+    }
+
+    fn commit(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        if self.is_duplicate(transaction_id, transaction) {
+            return Ok(Outcome::Deduplicated);
+        }
+        let change = match self.prepare(transaction_id, transaction) {
+            Err(err) if self.held_for_review(transaction, &err) => {
+                self.hold_for_review(transaction_id, transaction);
+                return Ok(Outcome::Queued);
+            }
+            result => result?,
+        };
+        if let Some(outcome) = self.screen(transaction_id, transaction)? {
+            self.screenings.insert(transaction_id, outcome);
+        }
+        self.audit_saturation(transaction_id, transaction, &change.transaction);
+        self.audit_clamped_chargeback(&change);
+        if self.paranoid {
+            let expected = Self::expected_movement(transaction.operation(), &change.transaction);
+            self.check_invariants(transaction_id, transaction, &change, expected);
+        }
+        Ok(self.apply_change(transaction_id, transaction, change))
+    }
+
+    // Stores a prepared change along with everything kept about the
+    // transaction. Nothing can fail past this point.
+    fn apply_change(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        change: Change,
+    ) -> Outcome {
+        let client_id = change.transaction.client_id();
+        self.accounts.insert(change.client_id, change.account);
+        self.record_activity(change.client_id);
+        if let Some((recipient, account)) = change.credit {
+            self.accounts.insert(recipient, account);
+            self.record_activity(recipient);
+        }
+        let previous = self
+            .transactions
+            .insert(change.transaction_id, change.transaction)
+            .map(|previous| previous.state());
+        self.track_open_disputes(client_id, previous, change.transaction.state());
+        self.record_risk(client_id, transaction.operation());
+        self.record_totals(transaction.operation(), &change.transaction);
+        if previous.is_none() {
+            self.record_volume(&change.transaction);
+            self.hold_quarantined(&change.transaction);
+            self.record_time(transaction_id);
+            self.record_opening(&change.transaction);
+            self.retain_transaction(transaction_id, client_id, transaction.operation());
+        }
+        let sequence = self
+            .journal
+            .as_mut()
+            .map(|journal| journal.append(self.now, transaction_id, transaction));
+        self.record_state_change(
+            transaction_id,
+            transaction.operation(),
+            previous,
+            change.transaction.state(),
+            sequence,
+        );
+        self.audit_unlock(transaction, &change);
+        self.events.publish(LedgerEvent::Applied {
+            transaction_id,
+            transaction: *transaction,
+            account: change.account,
+        });
+        Outcome::Applied {
+            new_available: change.account.available(),
+            new_held: change.account.held(),
+        }
+    }
+}
+
+pub struct IntoIter {
+    accounts: accounts::IntoIter,
+    archived: OrdSet<ClientId>,
+}
+
+impl Iterator for IntoIter {
+    type Item = (ClientId, Account);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.accounts
+            .find(|(client_id, _)| !self.archived.contains(client_id))
+    }
 }
 
 impl IntoIterator for Ledger {
-    type Item = <AccountMap as IntoIterator>::Item;
-    type IntoIter = <AccountMap as IntoIterator>::IntoIter;
+    type Item = (ClientId, Account);
+    type IntoIter = IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.accounts.into_iter()
+        IntoIter {
+            accounts: self.accounts.into_iter(),
+            archived: self.archived,
+        }
     }
 }
 
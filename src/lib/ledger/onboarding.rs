@@ -0,0 +1,149 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::{Ledger, Tier};
+use crate::{
+    account::{ClientId, Number},
+    transactions::{Operation, Transaction, TransactionError, TransactionId, TransactionResult},
+};
+
+// An ISO 4217 style code such as `EUR`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub fn as_str(&self) -> &str {
+        // only ever built from ASCII letters
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    // How many decimal places its minor unit has, 2 for cents. Codes not
+    // listed here are assumed to have cents.
+    pub fn exponent(&self) -> u32 {
+        match self.as_str() {
+            "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF"
+            | "UGX" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+            "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+            _ => 2,
+        }
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.as_bytes() {
+            [a, b, c] if value.bytes().all(|byte| byte.is_ascii_alphabetic()) => {
+                Ok(Currency([*a, *b, *c].map(|byte| byte.to_ascii_uppercase())))
+            }
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Currency> for String {
+    fn from(currency: Currency) -> Self {
+        currency.to_string()
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Per-account configuration set when the account is opened. The currency is
+// recorded for whoever consumes the ledger, the tier replaces the client's
+// current one, and the overdraft limit lets withdrawals take the available
+// funds that far below zero.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountSettings {
+    pub currency: Option<Currency>,
+    pub tier: Option<Tier>,
+    pub overdraft_limit: Option<Number>,
+}
+
+// Accounts are normally created by a client's first deposit or withdrawal.
+// With `require_open_account` they have to be opened first, so a mistyped
+// client id is rejected rather than starting a new account.
+impl Ledger {
+    pub fn is_open(&self, client_id: ClientId) -> bool {
+        self.opened.contains(&client_id)
+    }
+
+    pub fn account_settings(&self, client_id: ClientId) -> Option<&AccountSettings> {
+        self.settings.get(&client_id)
+    }
+
+    // An amount given as a count of minor units of the account's currency,
+    // or of the group holder's for group members. Accounts opened without a
+    // currency count in cents.
+    pub fn amount_from_minor_units(&self, client_id: ClientId, units: i64) -> Number {
+        let exponent = self
+            .settings
+            .get(&client_id)
+            .or_else(|| self.settings.get(&self.account_holder(client_id)))
+            .and_then(|settings| settings.currency)
+            .map_or(2, |currency| currency.exponent());
+        Number::new(units, exponent)
+    }
+
+    pub(super) fn overdraft_limit(&self, client_id: ClientId) -> Number {
+        self.settings
+            .get(&client_id)
+            .and_then(|settings| settings.overdraft_limit)
+            .unwrap_or_default()
+    }
+
+    pub(super) fn check_onboarded(&self, client_id: ClientId) -> TransactionResult {
+        if !self.policy.require_open_account
+            || self.is_open(client_id)
+            || self.group_of(client_id).is_some()
+        {
+            Ok(())
+        } else {
+            Err(TransactionError::UnknownClientId(client_id))
+        }
+    }
+
+    pub(super) fn check_settings(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult {
+        match transaction
+            .settings()
+            .and_then(|settings| settings.overdraft_limit)
+        {
+            Some(limit) if limit < Number::ZERO => {
+                Err(TransactionError::InvalidAmount(transaction_id, limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn record_opening(&mut self, transaction: &Transaction) {
+        if transaction.operation() != Operation::OpenAccount {
+            return;
+        }
+        let client_id = transaction.client_id();
+        self.opened.insert(client_id);
+        if let Some(settings) = transaction.settings() {
+            if let Some(tier) = settings.tier {
+                self.set_tier(client_id, tier);
+            }
+            self.settings.insert(client_id, *settings);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use super::{AuditEvent, Ledger, OverflowPolicy};
+use crate::{
+    account::AccountError,
+    transactions::{Operation, Transaction, TransactionError, TransactionId},
+};
+
+// Deposits that would overflow a balance are rejected by default. The policy
+// can instead credit what fits, or set them aside under review so that a
+// single malformed amount is kept for investigation rather than lost.
+impl Ledger {
+    // Deposits set aside by `OverflowPolicy::Review`, oldest id first. Their
+    // ids stay taken until they are dismissed.
+    pub fn overflow_reviews(&self) -> impl Iterator<Item = (TransactionId, &Transaction)> {
+        self.overflow_reviews
+            .iter()
+            .map(|(transaction_id, transaction)| (*transaction_id, transaction))
+    }
+
+    // Drops a deposit under review, handing it back to the caller.
+    pub fn dismiss_overflow_review(
+        &mut self,
+        transaction_id: TransactionId,
+    ) -> Option<Transaction> {
+        self.overflow_reviews.remove(&transaction_id)
+    }
+
+    pub(super) fn held_for_review(
+        &self,
+        transaction: &Transaction,
+        err: &TransactionError,
+    ) -> bool {
+        self.policy.deposit_overflow == OverflowPolicy::Review
+            && transaction.operation() == Operation::Deposit
+            && matches!(
+                err,
+                TransactionError::AccountError(_, AccountError::Overflow { .. })
+            )
+    }
+
+    pub(super) fn hold_for_review(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) {
+        self.overflow_reviews.insert(transaction_id, *transaction);
+        self.audit(AuditEvent::DepositHeldForReview {
+            client_id: transaction.client_id(),
+            transaction_id,
+        });
+    }
+
+    pub(super) fn audit_saturation(
+        &mut self,
+        transaction_id: TransactionId,
+        requested: &Transaction,
+        applied: &Transaction,
+    ) {
+        if requested.operation() == Operation::Deposit && applied.amount() < requested.amount() {
+            self.audit(AuditEvent::DepositSaturated {
+                client_id: requested.client_id(),
+                transaction_id,
+                excess: requested.amount() - applied.amount(),
+            });
+        }
+    }
+}
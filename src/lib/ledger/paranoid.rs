@@ -0,0 +1,83 @@
+use super::{Change, ChargebackExcess, Ledger};
+use crate::{
+    account::Number, transactions::Operation, transactions::Transaction,
+    transactions::TransactionId,
+};
+
+impl Ledger {
+    // Recomputes what each applied transaction should have done to the
+    // balances it touched, and panics with everything involved when the
+    // change disagrees. Meant for soak tests of new policies: every check
+    // looks the touched accounts up once more.
+    pub fn set_paranoid(&mut self, paranoid: bool) {
+        self.paranoid = paranoid;
+    }
+
+    pub fn is_paranoid(&self) -> bool {
+        self.paranoid
+    }
+
+    // By how much a transaction changes the total funds of the accounts it
+    // touches. `applied` is the transaction as stored after the change, so a
+    // dispute, resolve or chargeback sees the deposit or withdrawal it
+    // refers to, with the amount actually credited or charged back.
+    pub(super) fn expected_movement(operation: Operation, applied: &Transaction) -> Number {
+        let amount = applied.amount();
+        let withdrawal = applied.operation() == Operation::Withdrawal;
+        match operation {
+            Operation::Deposit | Operation::Adjustment => amount,
+            Operation::Withdrawal => -amount,
+            Operation::Transfer { .. } | Operation::OpenAccount | Operation::Unlock => Number::ZERO,
+            // A disputed withdrawal holds a credit until it is resolved, and
+            // a charged back one turns that credit into available funds.
+            Operation::Dispute if withdrawal => amount,
+            Operation::Resolve if withdrawal => -amount,
+            Operation::Chargeback if !withdrawal => -amount,
+            Operation::Dispute | Operation::Resolve | Operation::Chargeback => Number::ZERO,
+        }
+    }
+
+    pub(super) fn check_invariants(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        change: &Change,
+        expected: Number,
+    ) {
+        let mut touched = vec![(change.client_id, change.account)];
+        touched.extend(change.credit);
+        let mut moved = Number::ZERO;
+        let mut violations = Vec::new();
+        for (client_id, account) in &touched {
+            let before = self.accounts.get(client_id).unwrap_or_default();
+            moved += account.total() - before.total();
+            // Only `ChargebackExcess::Allow` lets held funds go negative.
+            if account.held() < Number::ZERO
+                && self.policy.chargeback_excess != ChargebackExcess::Allow
+            {
+                violations.push(format!("{client_id:?} holds {}", account.held()));
+            }
+        }
+        if moved != expected {
+            violations.push(format!("balances moved by {moved}, expected {expected}"));
+        }
+        if violations.is_empty() {
+            return;
+        }
+        let before: Vec<_> = touched
+            .iter()
+            .map(|(client_id, _)| (*client_id, self.accounts.get(client_id)))
+            .collect();
+        panic!(
+            "ledger invariants broken by {transaction_id:?}: {}\n\
+             transaction: {transaction:?}\n\
+             stored: {:?}\n\
+             accounts before: {before:?}\n\
+             accounts after: {touched:?}\n\
+             policy: {:?}",
+            violations.join(", "),
+            change.transaction,
+            self.policy,
+        );
+    }
+}
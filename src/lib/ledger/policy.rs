@@ -0,0 +1,162 @@
+use crate::{account::ClientId, account::Number, transactions::Operation};
+
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    #[default]
+    Basic,
+    Verified,
+    Premium,
+}
+
+impl Tier {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tier::Basic => "basic",
+            Tier::Verified => "verified",
+            Tier::Premium => "premium",
+        }
+    }
+}
+
+impl std::str::FromStr for Tier {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "basic" => Ok(Tier::Basic),
+            "verified" => Ok(Tier::Verified),
+            "premium" => Ok(Tier::Premium),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TierLimit {
+    TransactionAmount,
+    DailyVolume,
+    RollingWithdrawals,
+    Balance,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TierLimits {
+    pub max_transaction: Option<Number>,
+    pub max_daily_volume: Option<Number>,
+    pub max_rolling_withdrawals: Option<Number>,
+    pub max_balance: Option<Number>,
+}
+
+// Bounds on the amount of a single transaction of one type. Adjustments are
+// checked by their absolute value.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AmountRange {
+    pub min: Option<Number>,
+    pub max: Option<Number>,
+}
+
+// What happens to a deposit that would take an account's available funds past
+// the largest amount a `Number` can hold.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    #[default]
+    Reject,
+    // Credit up to the limit and record the excess in the audit log.
+    Saturate,
+    // Leave the balance alone and keep the deposit aside for someone to look
+    // at.
+    Review,
+}
+
+// What happens to a chargeback of more than the account holds, which can
+// happen once held funds have also been used for something else.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ChargebackExcess {
+    // Take the whole amount, leaving held funds negative, for the negative
+    // held alert to report.
+    #[default]
+    Allow,
+    // Take only what is held and record the rest in the audit log.
+    Clamp,
+    Reject,
+}
+
+// Interest, or with a negative rate a penalty, on deposits held in dispute
+// for longer than `after_days`: each further day adds `daily_rate` times the
+// held amount to the client's available funds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HeldAccrual {
+    pub after_days: u64,
+    pub daily_rate: Number,
+}
+
+// Where the available funds of accounts idle for `after_days` or more go
+// when swept, as unclaimed property laws require.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IdleSweep {
+    pub after_days: u64,
+    pub sweep_account: ClientId,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    pub max_open_disputes: Option<usize>,
+    pub max_transactions_per_client: Option<usize>,
+    pub allow_adjustments: bool,
+    // Operators can unlock accounts a chargeback locked, see
+    // `Ledger::unlock_account`.
+    pub allow_unlocks: bool,
+    pub dormant_after_days: Option<u64>,
+    pub basic: TierLimits,
+    pub verified: TierLimits,
+    pub premium: TierLimits,
+    pub deposit_amounts: AmountRange,
+    pub withdrawal_amounts: AmountRange,
+    pub adjustment_amounts: AmountRange,
+    pub deposit_overflow: OverflowPolicy,
+    pub chargeback_excess: ChargebackExcess,
+    // Only clients that opened an account, or joined a group, can transact.
+    pub require_open_account: bool,
+    // Resolved transactions can't be disputed a second time.
+    pub reject_redisputes: bool,
+    // Withdrawals can be disputed too. The amount is held as a credit the
+    // client may be owed: resolving drops it and the withdrawal stands,
+    // charging back refunds it to the available funds.
+    pub dispute_withdrawals: bool,
+    pub held_accrual: Option<HeldAccrual>,
+    pub idle_sweep: Option<IdleSweep>,
+    // Apply deposits and withdrawals the screening provider couldn't screen
+    // instead of rejecting them.
+    pub screening_fail_open: bool,
+    // How far below the highest id seen a new transaction's id may be.
+    pub replay_window: Option<u32>,
+}
+
+impl Policy {
+    // Disputes, resolves, chargebacks and unlocks carry no amount of their
+    // own.
+    // Transfers take money out like withdrawals do, and share their range.
+    pub fn amount_range(&self, operation: Operation) -> Option<&AmountRange> {
+        match operation {
+            Operation::Deposit => Some(&self.deposit_amounts),
+            Operation::Withdrawal | Operation::Transfer { .. } => Some(&self.withdrawal_amounts),
+            Operation::Adjustment => Some(&self.adjustment_amounts),
+            Operation::Dispute
+            | Operation::Resolve
+            | Operation::Chargeback
+            | Operation::Unlock
+            | Operation::OpenAccount => None,
+        }
+    }
+
+    pub fn limits(&self, tier: Tier) -> &TierLimits {
+        match tier {
+            Tier::Basic => &self.basic,
+            Tier::Verified => &self.verified,
+            Tier::Premium => &self.premium,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::{AuditEvent, Ledger};
+use crate::{
+    account::ClientId, account::Number, transactions::Operation, transactions::Transaction,
+    transactions::TransactionError, transactions::TransactionResult,
+};
+
+// Quarantined clients keep receiving deposits, but the funds go straight to
+// held until the quarantine is released, and withdrawals are refused. Unlike
+// a chargeback lock this is temporary, and unlike archiving the account keeps
+// taking transactions.
+impl Ledger {
+    pub fn quarantine(&mut self, client_id: ClientId) -> TransactionResult {
+        self.check_archived(client_id)?;
+        if self.quarantined.contains_key(&client_id) {
+            return Err(TransactionError::QuarantinedAccount(client_id));
+        }
+        self.quarantined.insert(client_id, Number::ZERO);
+        self.audit(AuditEvent::AccountQuarantined(client_id));
+        Ok(())
+    }
+
+    // Makes the funds deposited during the quarantine available. Returns how
+    // much was released, nothing if the client wasn't quarantined.
+    pub fn release_quarantine(&mut self, client_id: ClientId) -> Result<Number, TransactionError> {
+        let Some(held) = self.quarantined.get(&client_id).copied() else {
+            return Ok(Number::ZERO);
+        };
+        let holder = self.account_holder(client_id);
+        if let Some(mut account) = self.accounts.get(&holder) {
+            account
+                .resolve(held)
+                .map_err(|err| TransactionError::AccountError(client_id, err))?;
+            self.accounts.insert(holder, account);
+        }
+        self.quarantined.remove(&client_id);
+        self.audit(AuditEvent::QuarantineReleased {
+            client_id,
+            released: held,
+        });
+        Ok(held)
+    }
+
+    pub fn is_quarantined(&self, client_id: ClientId) -> bool {
+        self.quarantined.contains_key(&client_id)
+    }
+
+    // Funds currently held because they were deposited during a quarantine.
+    pub fn quarantined_funds(&self, client_id: ClientId) -> Number {
+        self.quarantined
+            .get(&client_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub(super) fn check_quarantined(&self, client_id: ClientId) -> TransactionResult {
+        if self.is_quarantined(client_id) {
+            Err(TransactionError::QuarantinedAccount(client_id))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(super) fn hold_quarantined(&mut self, transaction: &Transaction) {
+        if transaction.operation() != Operation::Deposit {
+            return;
+        }
+        if let Some(held) = self.quarantined.get_mut(&transaction.client_id()) {
+            *held += transaction.amount();
+        }
+    }
+}
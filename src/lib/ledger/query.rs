@@ -0,0 +1,209 @@
+use super::{AccountTable, EscrowState, Ledger, RunningTotals};
+use crate::{
+    account::Account, account::ClientId, account::Number, transactions::Operation,
+    transactions::Transaction, transactions::TransactionId, transactions::TransactionState,
+};
+use imbl::OrdSet;
+use std::ops::Bound;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountFilter {
+    pub locked: Option<bool>,
+    pub min_total: Option<Number>,
+    pub max_total: Option<Number>,
+}
+
+impl AccountFilter {
+    pub fn matches(&self, account: &Account) -> bool {
+        self.locked.is_none_or(|locked| account.locked() == locked)
+            && self.min_total.is_none_or(|min| account.total() >= min)
+            && self.max_total.is_none_or(|max| account.total() <= max)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionFilter {
+    pub client_id: Option<ClientId>,
+    pub operation: Option<Operation>,
+    pub state: Option<TransactionState>,
+}
+
+impl TransactionFilter {
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.client_id
+            .is_none_or(|client_id| transaction.client_id() == client_id)
+            && self
+                .operation
+                .is_none_or(|operation| transaction.operation() == operation)
+            && self.state.is_none_or(|state| transaction.state() == state)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LedgerStats {
+    pub accounts: usize,
+    pub locked_accounts: usize,
+    pub transactions: usize,
+    pub open_disputes: usize,
+    pub chargebacks: usize,
+    pub resolved_disputes: usize,
+    pub erased_clients: usize,
+    pub available: Number,
+    pub held: Number,
+    pub escrowed: Number,
+    pub watermark: Option<TransactionId>,
+    pub totals: RunningTotals,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Page<K, T> {
+    pub items: Vec<(K, T)>,
+    pub next: Option<K>,
+}
+
+impl<K: Copy, T> Page<K, T> {
+    fn collect(mut iter: impl Iterator<Item = (K, T)>, limit: usize) -> Self {
+        let items: Vec<(K, T)> = iter.by_ref().take(limit).collect();
+        let next = match (items.last(), iter.next()) {
+            (Some((key, _)), Some(_)) => Some(*key),
+            _ => None,
+        };
+        Page { items, next }
+    }
+}
+
+fn after<K>(cursor: Option<K>) -> (Bound<K>, Bound<K>) {
+    match cursor {
+        Some(key) => (Bound::Excluded(key), Bound::Unbounded),
+        None => (Bound::Unbounded, Bound::Unbounded),
+    }
+}
+
+// The ledger's accounts at one point in time, to read from another thread.
+// Taking it is cheap, but the ledger copies its accounts the next time it
+// writes to them while this is still around.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountsSnapshot {
+    accounts: AccountTable,
+    archived: OrdSet<ClientId>,
+}
+
+impl AccountsSnapshot {
+    pub fn len(&self) -> usize {
+        self.accounts.len() - self.archived.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Skips archived accounts, like reports do.
+    pub fn iter(&self) -> impl Iterator<Item = (ClientId, Account)> + '_ {
+        self.accounts
+            .iter()
+            .filter(|(client_id, _)| !self.archived.contains(client_id))
+    }
+}
+
+impl Ledger {
+    pub fn accounts_snapshot(&self) -> AccountsSnapshot {
+        AccountsSnapshot {
+            accounts: self.accounts.clone(),
+            archived: self.archived.clone(),
+        }
+    }
+
+    pub fn account(&self, client_id: ClientId) -> Option<Account> {
+        self.accounts.get(&client_id)
+    }
+
+    pub fn transaction(&self, transaction_id: TransactionId) -> Option<&Transaction> {
+        self.transactions.get(&transaction_id)
+    }
+
+    pub fn accounts<'a>(
+        &'a self,
+        filter: &'a AccountFilter,
+    ) -> impl Iterator<Item = (ClientId, Account)> + 'a {
+        self.accounts
+            .iter()
+            .filter(|(client_id, account)| !self.is_archived(*client_id) && filter.matches(account))
+    }
+
+    pub fn transactions<'a>(
+        &'a self,
+        filter: &'a TransactionFilter,
+    ) -> impl Iterator<Item = (TransactionId, &'a Transaction)> + 'a {
+        self.transactions
+            .iter()
+            .filter(|(_, transaction)| filter.matches(transaction))
+            .map(|(transaction_id, transaction)| (*transaction_id, transaction))
+    }
+
+    pub fn accounts_page(&self, cursor: Option<ClientId>, limit: usize) -> Page<ClientId, Account> {
+        Page::collect(
+            self.accounts
+                .iter_after(cursor)
+                .filter(|(client_id, _)| !self.is_archived(*client_id)),
+            limit,
+        )
+    }
+
+    pub fn transactions_page(
+        &self,
+        cursor: Option<TransactionId>,
+        limit: usize,
+    ) -> Page<TransactionId, &Transaction> {
+        Page::collect(
+            self.transactions
+                .range(after(cursor))
+                .map(|(transaction_id, transaction)| (*transaction_id, transaction)),
+            limit,
+        )
+    }
+
+    pub fn disputes(&self) -> impl Iterator<Item = (TransactionId, &Transaction)> + '_ {
+        self.transactions
+            .iter()
+            .filter(|(_, transaction)| transaction.state().is_disputed())
+            .map(|(transaction_id, transaction)| (*transaction_id, transaction))
+    }
+
+    pub fn stats(&self) -> LedgerStats {
+        let mut stats = LedgerStats {
+            accounts: self.accounts.len(),
+            transactions: self.transactions.len(),
+            erased_clients: self.tombstones.len(),
+            watermark: self.watermark(),
+            totals: self.totals,
+            ..LedgerStats::default()
+        };
+        // Sums past what a `Number` holds stay at the limit, as the running
+        // totals do.
+        for tombstone in &self.tombstones {
+            stats.available = stats.available.saturating_add(tombstone.available);
+            stats.held = stats.held.saturating_add(tombstone.held);
+        }
+        for account in self.accounts.values() {
+            stats.locked_accounts += account.locked() as usize;
+            stats.available = stats.available.saturating_add(account.available());
+            stats.held = stats.held.saturating_add(account.held());
+        }
+        for escrow in self.escrows.values() {
+            if escrow.state == EscrowState::Open {
+                stats.escrowed = stats.escrowed.saturating_add(escrow.amount);
+            }
+        }
+        for transaction in self.transactions.values() {
+            match transaction.state() {
+                TransactionState::Disputed | TransactionState::DisputedWithdrawal => {
+                    stats.open_disputes += 1
+                }
+                TransactionState::Chargedback => stats.chargebacks += 1,
+                TransactionState::Resolved => stats.resolved_disputes += 1,
+                TransactionState::Ok => {}
+            }
+        }
+        stats
+    }
+}
@@ -0,0 +1,105 @@
+use super::Ledger;
+use crate::account::{Account, ClientId, Number};
+use imbl::OrdSet;
+use std::cmp::Reverse;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BalanceKey {
+    Available,
+    Held,
+}
+
+impl BalanceKey {
+    fn of(&self, account: &Account) -> Number {
+        match self {
+            BalanceKey::Available => account.available(),
+            BalanceKey::Held => account.held(),
+        }
+    }
+}
+
+type Ranking = OrdSet<(Number, Reverse<ClientId>)>;
+
+// Accounts ordered by balance, kept in step with the account table so top-N
+// and threshold queries only walk the accounts they return. Ties go to the
+// lower client id.
+#[derive(Clone, Debug, Default)]
+pub struct BalanceIndex {
+    available: Ranking,
+    held: Ranking,
+}
+
+impl BalanceIndex {
+    pub(super) fn update(
+        &mut self,
+        client_id: ClientId,
+        previous: Option<Account>,
+        current: Option<Account>,
+    ) {
+        if let Some(account) = previous {
+            self.available
+                .remove(&(account.available(), Reverse(client_id)));
+            self.held.remove(&(account.held(), Reverse(client_id)));
+        }
+        if let Some(account) = current {
+            self.available
+                .insert((account.available(), Reverse(client_id)));
+            self.held.insert((account.held(), Reverse(client_id)));
+        }
+    }
+
+    fn ranking(&self, key: BalanceKey) -> &Ranking {
+        match key {
+            BalanceKey::Available => &self.available,
+            BalanceKey::Held => &self.held,
+        }
+    }
+}
+
+impl Ledger {
+    pub fn enable_balance_index(&mut self) {
+        self.accounts.enable_index();
+    }
+
+    // Highest balances first.
+    pub fn top_accounts(&self, n: usize, key: BalanceKey) -> Vec<(ClientId, Account)> {
+        self.ranked(key).take(n).collect()
+    }
+
+    // Accounts whose balance is at least `threshold`, highest first.
+    pub fn accounts_above(&self, threshold: Number, key: BalanceKey) -> Vec<(ClientId, Account)> {
+        self.ranked(key)
+            .take_while(|(_, account)| key.of(account) >= threshold)
+            .collect()
+    }
+
+    fn ranked(&self, key: BalanceKey) -> Box<dyn Iterator<Item = (ClientId, Account)> + '_> {
+        let ranked: Box<dyn Iterator<Item = ClientId> + '_> = match self.accounts.index() {
+            Some(index) => Box::new(
+                index
+                    .ranking(key)
+                    .iter()
+                    .rev()
+                    .map(|(_, Reverse(client_id))| *client_id),
+            ),
+            None => {
+                let mut accounts: Vec<(Number, ClientId)> = self
+                    .accounts
+                    .iter()
+                    .map(|(client_id, account)| (key.of(&account), client_id))
+                    .collect();
+                accounts.sort_by_key(|(balance, client_id)| (Reverse(*balance), *client_id));
+                Box::new(accounts.into_iter().map(|(_, client_id)| client_id))
+            }
+        };
+        Box::new(
+            ranked
+                .filter(|client_id| !self.is_archived(*client_id))
+                .filter_map(|client_id| {
+                    self.accounts
+                        .get(&client_id)
+                        .map(|account| (client_id, account))
+                }),
+        )
+    }
+}
@@ -0,0 +1,60 @@
+use super::Ledger;
+use crate::account::{ClientId, Number};
+use crate::transactions::{Operation, Outcome, TransactionId, TransactionResult};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// A row whose type isn't one of the built-in operations.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CustomTransaction {
+    pub client_id: ClientId,
+    pub amount: Number,
+}
+
+// Handlers get the whole ledger so they can look accounts and stored
+// transactions up and apply whatever built-in transactions they map to.
+pub type OperationHandler = dyn Fn(&mut Ledger, TransactionId, &CustomTransaction) -> TransactionResult<Outcome>
+    + Send
+    + Sync;
+
+#[derive(Clone)]
+pub enum CustomOperation {
+    Alias(Operation),
+    Handler(Arc<OperationHandler>),
+}
+
+// Extra operation names accepted in the input, either as another name for a
+// built-in operation ("credit" for deposits) or backed by a handler
+// ("bonus", "cashback").
+#[derive(Clone, Default)]
+pub struct OperationRegistry {
+    operations: HashMap<String, CustomOperation>,
+}
+
+impl OperationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alias(&mut self, name: &str, operation: Operation) {
+        self.operations
+            .insert(name.to_string(), CustomOperation::Alias(operation));
+    }
+
+    pub fn register<F>(&mut self, name: &str, handler: F)
+    where
+        F: Fn(&mut Ledger, TransactionId, &CustomTransaction) -> TransactionResult<Outcome>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.operations.insert(
+            name.to_string(),
+            CustomOperation::Handler(Arc::new(handler)),
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CustomOperation> {
+        self.operations.get(name)
+    }
+}
@@ -0,0 +1,33 @@
+use super::Ledger;
+use crate::transactions::{TransactionError, TransactionId, TransactionResult};
+
+impl Ledger {
+    // The highest transaction id the ledger has seen: stored, archived,
+    // erased or held for review.
+    pub fn watermark(&self) -> Option<TransactionId> {
+        [
+            self.transactions.keys().next_back(),
+            self.archived_transactions.keys().next_back(),
+            self.erased_transactions.get_max(),
+            self.overflow_reviews.keys().next_back(),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .copied()
+    }
+
+    // With `Policy::replay_window` set, new transactions with an id more
+    // than that far below the watermark are taken for stale retransmissions
+    // and rejected, even when the ledger no longer knows the original.
+    pub(super) fn check_replay_window(&self, transaction_id: TransactionId) -> TransactionResult {
+        let (Some(window), Some(watermark)) = (self.policy.replay_window, self.watermark()) else {
+            return Ok(());
+        };
+        if watermark.0.saturating_sub(transaction_id.0) > window {
+            Err(TransactionError::OutsideReplayWindow(transaction_id))
+        } else {
+            Ok(())
+        }
+    }
+}
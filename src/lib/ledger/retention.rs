@@ -0,0 +1,56 @@
+use super::Ledger;
+use crate::{
+    account::ClientId, transactions::Operation, transactions::TransactionError,
+    transactions::TransactionId, transactions::TransactionResult, transactions::TransactionState,
+};
+
+impl Ledger {
+    pub fn is_transaction_archived(&self, transaction_id: TransactionId) -> bool {
+        self.archived_transactions.contains_key(&transaction_id)
+    }
+
+    pub(super) fn check_transaction_archived(
+        &self,
+        transaction_id: TransactionId,
+    ) -> TransactionResult {
+        if self.is_transaction_archived(transaction_id) {
+            Err(TransactionError::ArchivedTransaction(transaction_id))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Keeps at most `max_transactions_per_client` deposits per client in
+    // memory, archiving the oldest ones. Open disputes are never archived.
+    pub(super) fn retain_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        client_id: ClientId,
+        operation: Operation,
+    ) {
+        let Some(max) = self.policy.max_transactions_per_client else {
+            return;
+        };
+        if operation != Operation::Deposit {
+            return;
+        }
+        let retained = self.retained.entry(client_id).or_default();
+        retained.push_back(transaction_id);
+        while retained.len() > max {
+            let transactions = &self.transactions;
+            let Some(position) = retained.iter().position(|id| {
+                transactions
+                    .get(id)
+                    .is_some_and(|transaction| transaction.state() != TransactionState::Disputed)
+            }) else {
+                break;
+            };
+            let Some(archived) = retained.remove(position) else {
+                break;
+            };
+            self.transactions.remove(&archived);
+            self.history.remove(&archived);
+            self.archived_transactions.insert(archived, client_id);
+        }
+    }
+}
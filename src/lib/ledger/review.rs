@@ -0,0 +1,121 @@
+use super::{AuditEvent, Ledger};
+use crate::{
+    account::ClientId, transactions::Outcome, transactions::Transaction,
+    transactions::TransactionError, transactions::TransactionId, transactions::TransactionResult,
+};
+
+// A transaction parked until someone decides on it. Only the first of a
+// client's parked transactions is flagged; the ones after it wait their
+// turn, so the client's transactions are still applied in the order they
+// arrived.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Review {
+    pub transaction_id: TransactionId,
+    pub transaction: Transaction,
+    pub flagged: bool,
+}
+
+pub type Released = Vec<(TransactionId, TransactionResult<Outcome>)>;
+
+impl Ledger {
+    // Parked transactions, by client and then in arrival order.
+    pub fn reviews(&self) -> impl Iterator<Item = &Review> {
+        self.reviews.values().flatten()
+    }
+
+    pub(super) fn awaiting_review(&self, client_id: ClientId) -> bool {
+        self.reviews.contains_key(&client_id)
+    }
+
+    // Flagged transactions go first: they are either the only one parked for
+    // the client or the one a release has just reached.
+    pub(super) fn park(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        flagged: bool,
+    ) -> Outcome {
+        let client_id = transaction.client_id();
+        let review = Review {
+            transaction_id,
+            transaction: *transaction,
+            flagged,
+        };
+        let queue = self.reviews.entry(client_id).or_default();
+        if flagged {
+            queue.push_front(review);
+            self.audit(AuditEvent::TransactionFlagged {
+                client_id,
+                transaction_id,
+            });
+        } else {
+            queue.push_back(review);
+        }
+        Outcome::Queued
+    }
+
+    fn take_flagged(&mut self, transaction_id: TransactionId) -> TransactionResult<Review> {
+        let client_id = self
+            .reviews
+            .iter()
+            .find(|(_, queue)| {
+                queue
+                    .front()
+                    .is_some_and(|review| review.flagged && review.transaction_id == transaction_id)
+            })
+            .map(|(client_id, _)| *client_id)
+            .ok_or(TransactionError::NotUnderReview(transaction_id))?;
+        let queue = self.reviews.get_mut(&client_id).unwrap();
+        Ok(queue.pop_front().unwrap())
+    }
+
+    // Applies a flagged transaction as it was when flagged, then the ones
+    // that were waiting behind it, up to the next one flagged. The results
+    // are listed in the order they were applied, the approved one first.
+    pub fn approve_review(&mut self, transaction_id: TransactionId) -> TransactionResult<Released> {
+        let review = self.take_flagged(transaction_id)?;
+        let client_id = review.transaction.client_id();
+        self.audit(AuditEvent::ReviewApproved {
+            client_id,
+            transaction_id,
+        });
+        let result = self.commit(transaction_id, &review.transaction);
+        let mut released = vec![(transaction_id, result)];
+        released.extend(self.release(client_id));
+        Ok(released)
+    }
+
+    // Drops a flagged transaction and applies the ones that were waiting
+    // behind it, up to the next one flagged.
+    pub fn reject_review(&mut self, transaction_id: TransactionId) -> TransactionResult<Released> {
+        let review = self.take_flagged(transaction_id)?;
+        let client_id = review.transaction.client_id();
+        self.audit(AuditEvent::ReviewRejected {
+            client_id,
+            transaction_id,
+        });
+        Ok(self.release(client_id))
+    }
+
+    fn release(&mut self, client_id: ClientId) -> Released {
+        let mut released = Vec::new();
+        while let Some(review) = self
+            .reviews
+            .get_mut(&client_id)
+            .and_then(|queue| queue.pop_front())
+        {
+            let result = self.admit(review.transaction_id, &review.transaction);
+            released.push((review.transaction_id, result));
+            if self
+                .reviews
+                .get(&client_id)
+                .and_then(|queue| queue.front())
+                .is_some_and(|review| review.flagged)
+            {
+                return released;
+            }
+        }
+        self.reviews.remove(&client_id);
+        released
+    }
+}
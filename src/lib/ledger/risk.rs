@@ -0,0 +1,86 @@
+use std::io::{self, Write};
+
+use super::{window::RollingWindow, Ledger};
+use crate::{account::ClientId, account::Number, transactions::Operation};
+
+// Clients with this many deposits and withdrawals in the last 24 hours get
+// the full velocity component of the score.
+const VELOCITY_LIMIT: u64 = 100;
+const CHARGEBACK_WEIGHT: u64 = 60;
+const DISPUTE_WEIGHT: u64 = 30;
+const VELOCITY_WEIGHT: u64 = 10;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RiskProfile {
+    pub transactions: u64,
+    pub disputes: u64,
+    pub chargebacks: u64,
+    recent: RollingWindow,
+}
+
+impl RiskProfile {
+    fn rate(count: u64, total: u64) -> Number {
+        Number::from(count.min(total)) / Number::from(total.max(1))
+    }
+
+    // From 0 to 100: the share of transactions charged back and disputed,
+    // plus how close the client is to the velocity limit.
+    pub fn score(&self, now: super::Timestamp) -> Number {
+        let velocity =
+            Number::from(VELOCITY_LIMIT).min(self.recent.sum(now)) / Number::from(VELOCITY_LIMIT);
+        let score = Self::rate(self.chargebacks, self.transactions)
+            * Number::from(CHARGEBACK_WEIGHT)
+            + Self::rate(self.disputes, self.transactions) * Number::from(DISPUTE_WEIGHT)
+            + velocity * Number::from(VELOCITY_WEIGHT);
+        score.round_dp(2)
+    }
+}
+
+impl Ledger {
+    pub(super) fn record_risk(&mut self, client_id: ClientId, operation: Operation) {
+        let now = self.now;
+        let profile = self.risk.entry(client_id).or_default();
+        match operation {
+            Operation::Deposit | Operation::Withdrawal | Operation::Transfer { .. } => {
+                profile.transactions += 1;
+                profile.recent.add(now, Number::ONE);
+            }
+            Operation::Dispute => profile.disputes += 1,
+            Operation::Chargeback => profile.chargebacks += 1,
+            Operation::Resolve
+            | Operation::Adjustment
+            | Operation::OpenAccount
+            | Operation::Unlock => {}
+        }
+    }
+
+    pub fn risk_profile(&self, client_id: ClientId) -> Option<&RiskProfile> {
+        self.risk.get(&client_id)
+    }
+
+    pub fn risk_score(&self, client_id: ClientId) -> Number {
+        self.risk
+            .get(&client_id)
+            .map_or(Number::ZERO, |profile| profile.score(self.now))
+    }
+
+    // client,score,transactions,disputes,chargebacks, ordered by client id.
+    pub fn write_risk_scores<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        writeln!(writer, "client,score,transactions,disputes,chargebacks")?;
+        let mut clients: Vec<_> = self.risk.iter().collect();
+        clients.sort_by_key(|(client_id, _)| **client_id);
+        for (client_id, profile) in clients {
+            writeln!(
+                writer,
+                "{},{:.2},{},{},{}",
+                client_id.0,
+                profile.score(self.now),
+                profile.transactions,
+                profile.disputes,
+                profile.chargebacks
+            )?;
+        }
+        writer.flush()
+    }
+}
@@ -0,0 +1,66 @@
+use super::{AuditEvent, Ledger};
+use crate::transactions::{
+    Operation, Transaction, TransactionError, TransactionId, TransactionResult,
+};
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScreeningOutcome {
+    Clear,
+    // The client or the amount pattern matched a watch list.
+    Match,
+    // The provider couldn't answer.
+    Unavailable,
+}
+
+// Screens deposits and withdrawals against sanctions lists and suspicious
+// amount patterns before they're applied, for AML obligations.
+pub trait ScreeningProvider: Send + Sync {
+    fn screen(&self, transaction_id: TransactionId, transaction: &Transaction) -> ScreeningOutcome;
+}
+
+impl Ledger {
+    pub fn set_screening_provider(&mut self, provider: impl ScreeningProvider + 'static) {
+        self.screening_provider = Some(Arc::new(provider));
+    }
+
+    // How the stored transaction fared when it was screened. Transactions
+    // applied without a provider, and restored ones, have no outcome.
+    pub fn screening(&self, transaction_id: TransactionId) -> Option<ScreeningOutcome> {
+        self.screenings.get(&transaction_id).copied()
+    }
+
+    // Matches are rejected. When the provider is unavailable the transaction
+    // is rejected too, unless `Policy::screening_fail_open` lets it through
+    // with that outcome recorded.
+    pub(super) fn screen(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Option<ScreeningOutcome>> {
+        let Some(provider) = &self.screening_provider else {
+            return Ok(None);
+        };
+        if !matches!(
+            transaction.operation(),
+            Operation::Deposit | Operation::Withdrawal
+        ) {
+            return Ok(None);
+        }
+        let outcome = provider.screen(transaction_id, transaction);
+        let error = match outcome {
+            ScreeningOutcome::Clear => return Ok(Some(outcome)),
+            ScreeningOutcome::Unavailable if self.policy.screening_fail_open => {
+                return Ok(Some(outcome))
+            }
+            ScreeningOutcome::Match => TransactionError::ScreeningMatch(transaction_id),
+            ScreeningOutcome::Unavailable => TransactionError::ScreeningUnavailable(transaction_id),
+        };
+        self.audit(AuditEvent::ScreeningRejected {
+            client_id: transaction.client_id(),
+            transaction_id,
+            outcome,
+        });
+        Err(error)
+    }
+}
@@ -0,0 +1,190 @@
+use super::{ConcurrentLedger, Ledger};
+use crate::{
+    account::Account, account::ClientId, transactions::Outcome, transactions::Transaction,
+    transactions::TransactionId, transactions::TransactionResult,
+};
+
+pub trait LedgerBackend {
+    fn apply_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome>;
+    fn account(&self, client_id: ClientId) -> Option<Account>;
+}
+
+impl LedgerBackend for Ledger {
+    fn apply_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        Ledger::apply_transaction(self, transaction_id, transaction)
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<Account> {
+        Ledger::account(self, client_id)
+    }
+}
+
+impl LedgerBackend for ConcurrentLedger {
+    fn apply_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        ConcurrentLedger::apply_transaction(self, transaction_id, transaction)
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<Account> {
+        ConcurrentLedger::account(self, client_id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    Result {
+        transaction_id: TransactionId,
+        primary: TransactionResult<Outcome>,
+        shadow: TransactionResult<Outcome>,
+    },
+    Account {
+        transaction_id: TransactionId,
+        client_id: ClientId,
+        primary: Option<Account>,
+        shadow: Option<Account>,
+    },
+}
+
+// Feeds every transaction to a primary and a shadow ledger and keeps track of
+// where they disagree. Callers only ever see the primary's results.
+pub struct ShadowLedger<P, S> {
+    primary: P,
+    shadow: S,
+    divergences: Vec<Divergence>,
+}
+
+impl<P: LedgerBackend, S: LedgerBackend> ShadowLedger<P, S> {
+    pub fn new(primary: P, shadow: S) -> Self {
+        Self {
+            primary,
+            shadow,
+            divergences: Vec::new(),
+        }
+    }
+
+    pub fn apply_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult<Outcome> {
+        let primary = self.primary.apply_transaction(transaction_id, transaction);
+        let shadow = self.shadow.apply_transaction(transaction_id, transaction);
+        if primary != shadow {
+            self.divergences.push(Divergence::Result {
+                transaction_id,
+                primary: primary.clone(),
+                shadow,
+            });
+        }
+        let client_id = transaction.client_id();
+        let (primary_account, shadow_account) = (
+            self.primary.account(client_id),
+            self.shadow.account(client_id),
+        );
+        if primary_account != shadow_account {
+            self.divergences.push(Divergence::Account {
+                transaction_id,
+                client_id,
+                primary: primary_account,
+                shadow: shadow_account,
+            });
+        }
+        primary
+    }
+
+    pub fn divergences(&self) -> &[Divergence] {
+        &self.divergences
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn shadow(&self) -> &S {
+        &self.shadow
+    }
+
+    pub fn into_inner(self) -> (P, S) {
+        (self.primary, self.shadow)
+    }
+}
+
+#[cfg(test)]
+mod shadow_tests {
+    use super::{Divergence, ShadowLedger};
+    use crate::{
+        account::num, account::Account, account::ClientId, ledger::ConcurrentLedger,
+        ledger::Ledger, ledger::Policy, transactions::Operation, transactions::Outcome,
+        transactions::Transaction, transactions::TransactionError, transactions::TransactionId,
+    };
+
+    #[test]
+    fn report_policy_divergences() {
+        let strict = Policy {
+            max_open_disputes: Some(0),
+            ..Policy::default()
+        };
+        let mut ledger = ShadowLedger::new(Ledger::new(), Ledger::with_policy(strict));
+        let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+        let dispute = Transaction::new(ClientId(1), num!(0), Operation::Dispute);
+        assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+        assert!(ledger.divergences().is_empty());
+        assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+        let mut held = Account::default();
+        held.deposit(num!(5)).unwrap();
+        let available = held;
+        held.dispute(num!(5)).unwrap();
+        assert_eq!(
+            ledger.divergences(),
+            &[
+                Divergence::Result {
+                    transaction_id: TransactionId(1),
+                    primary: Ok(Outcome::Applied {
+                        new_available: num!(0),
+                        new_held: num!(5),
+                    }),
+                    shadow: Err(TransactionError::DisputeQuotaExceeded(ClientId(1))),
+                },
+                Divergence::Account {
+                    transaction_id: TransactionId(1),
+                    client_id: ClientId(1),
+                    primary: Some(held),
+                    shadow: Some(available),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn in_memory_against_concurrent() {
+        let mut ledger = ShadowLedger::new(Ledger::new(), ConcurrentLedger::new(4));
+        let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+        let dispute = Transaction::new(ClientId(2), num!(0), Operation::Dispute);
+        let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+        let res = ledger.apply_transaction(TransactionId(1), &dispute);
+        // the concurrent ledger reports the owner mismatch before the unknown client
+        assert_eq!(res, Err(TransactionError::UnknownClientId(ClientId(2))));
+        assert_eq!(
+            ledger.divergences(),
+            &[Divergence::Result {
+                transaction_id: TransactionId(1),
+                primary: Err(TransactionError::UnknownClientId(ClientId(2))),
+                shadow: Err(TransactionError::MismatchedClientId(
+                    ClientId(2),
+                    ClientId(1)
+                )),
+            }]
+        );
+    }
+}
@@ -0,0 +1,433 @@
+use std::io::{self, BufRead, Write};
+
+use super::{
+    AccountSettings, Escrow, EscrowId, EscrowState, GroupId, Journal, Ledger, Policy,
+    RunningTotals, Timestamp, Tombstone,
+};
+use crate::{
+    account::Account, account::ClientId, transactions::Operation, transactions::Transaction,
+    transactions::TransactionId, transactions::TransactionState,
+};
+
+const HEADER: &str = "crab-snapshot";
+
+pub const SNAPSHOT_VERSION: u32 = 8;
+
+// Each step rewrites a line of the body one version up, starting from
+// version 1. Version 1 had no version in its header and the same lines as
+// version 2; version 3 added the `journal` line, version 4 `settings`,
+// version 5 the `resolved` transaction state, version 6 `frozen`, version
+// 7 `totals` and version 8 the recipient of transfers.
+const MIGRATIONS: [fn(String) -> String; 7] = [
+    |line| line,
+    |line| line,
+    |line| line,
+    |line| line,
+    |line| line,
+    |line| line,
+    |line| line,
+];
+
+// The state needed to carry on where a ledger stopped: the last journal
+// entry it reflects when journaling, balances, stored transactions with their
+// dispute state, reserved ids, archived clients and tiers, account settings,
+// quarantines, freezes, account groups, escrows, last activity, tombstones
+// and running totals. Usage windows, transaction histories, dispute cases, risk
+// profiles and the audit log start over.
+//
+//   crab-snapshot <version>
+//   time <seconds>
+//   journal <sequence>
+//   account <client> <available> <held> <locked>
+//   transaction <tx> <client> <type> <amount> <state> [<to>]
+//   archived <client>
+//   erased <tx>
+//   archived_transaction <tx> <client>
+//   tier <client> <tier>
+//   settings <client> <currency|-> <tier|-> <overdraft limit|->
+//   quarantine <client> <held>
+//   frozen <client> <reason>
+//   group <group> <holder>
+//   member <group> <client> [<withdrawal limit>]
+//   escrow <escrow> <depositor> <beneficiary> <amount> <state>
+//   activity <client> <seconds>
+//   dormant <client>
+//   tombstone <available> <held> <locked> <transactions>
+//   totals <deposited> <withdrawn> <charged back>
+
+fn state_name(state: TransactionState) -> &'static str {
+    match state {
+        TransactionState::Ok => "ok",
+        TransactionState::Disputed => "disputed",
+        TransactionState::Chargedback => "chargedback",
+        TransactionState::Resolved => "resolved",
+        TransactionState::DisputedWithdrawal => "disputed_withdrawal",
+    }
+}
+
+fn operation_name(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Deposit => "deposit",
+        Operation::Withdrawal => "withdrawal",
+        Operation::Dispute => "dispute",
+        Operation::Chargeback => "chargeback",
+        Operation::Resolve => "resolve",
+        Operation::Adjustment => "adjustment",
+        Operation::OpenAccount => "open",
+        Operation::Transfer { .. } => "transfer",
+        Operation::Unlock => "unlock",
+    }
+}
+
+fn malformed(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed snapshot line: {line}"),
+    )
+}
+
+fn read_version(header: Option<String>) -> io::Result<u32> {
+    let header = header.unwrap_or_default();
+    let version = match header.strip_prefix(HEADER) {
+        Some("") => 1,
+        Some(version) => version
+            .strip_prefix(' ')
+            .and_then(|version| version.parse().ok())
+            .filter(|version| *version > 0)
+            .ok_or_else(|| malformed(&header))?,
+        None => return Err(malformed(&header)),
+    };
+    if version > SNAPSHOT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("snapshot version {version} is newer than the supported {SNAPSHOT_VERSION}"),
+        ));
+    }
+    Ok(version)
+}
+
+fn migrate_line(version: u32, line: String) -> String {
+    MIGRATIONS[version as usize - 1..]
+        .iter()
+        .fold(line, |line, migrate| migrate(line))
+}
+
+fn field<T: std::str::FromStr>(fields: &[&str], index: usize, line: &str) -> io::Result<T> {
+    fields
+        .get(index)
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| malformed(line))
+}
+
+// `-` stands for a setting that was left out.
+fn optional_field<T: std::str::FromStr>(
+    fields: &[&str],
+    index: usize,
+    line: &str,
+) -> io::Result<Option<T>> {
+    match fields.get(index) {
+        Some(&"-") => Ok(None),
+        _ => field(fields, index, line).map(Some),
+    }
+}
+
+impl Ledger {
+    pub fn write_snapshot<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        writeln!(writer, "{HEADER} {SNAPSHOT_VERSION}")?;
+        writeln!(writer, "time {}", self.now.0)?;
+        if let Some(journal) = &self.journal {
+            writeln!(writer, "journal {}", journal.last_sequence())?;
+        }
+        for (client_id, account) in self.accounts.iter() {
+            writeln!(
+                writer,
+                "account {} {} {} {}",
+                client_id.0,
+                account.available(),
+                account.held(),
+                account.locked()
+            )?;
+        }
+        for (transaction_id, transaction) in self.transactions.iter() {
+            writeln!(
+                writer,
+                "transaction {} {} {} {} {}{}",
+                transaction_id.0,
+                transaction.client_id().0,
+                operation_name(transaction.operation()),
+                transaction.amount(),
+                state_name(transaction.state()),
+                transaction
+                    .operation()
+                    .recipient()
+                    .map_or(String::new(), |to| format!(" {}", to.0))
+            )?;
+        }
+        for client_id in &self.archived {
+            writeln!(writer, "archived {}", client_id.0)?;
+        }
+        for transaction_id in &self.erased_transactions {
+            writeln!(writer, "erased {}", transaction_id.0)?;
+        }
+        for (transaction_id, client_id) in &self.archived_transactions {
+            writeln!(
+                writer,
+                "archived_transaction {} {}",
+                transaction_id.0, client_id.0
+            )?;
+        }
+        let mut tiers: Vec<_> = self.tiers.iter().collect();
+        tiers.sort_by_key(|(client_id, _)| **client_id);
+        for (client_id, tier) in tiers {
+            writeln!(writer, "tier {} {}", client_id.0, tier.name())?;
+        }
+        let mut settings: Vec<_> = self.settings.iter().collect();
+        settings.sort_by_key(|(client_id, _)| **client_id);
+        for (client_id, settings) in settings {
+            writeln!(
+                writer,
+                "settings {} {} {} {}",
+                client_id.0,
+                settings.currency.map_or("-".to_string(), |c| c.to_string()),
+                settings.tier.map_or("-", |tier| tier.name()),
+                settings
+                    .overdraft_limit
+                    .map_or("-".to_string(), |limit| limit.to_string())
+            )?;
+        }
+        let mut quarantined: Vec<_> = self.quarantined.iter().collect();
+        quarantined.sort_by_key(|(client_id, _)| **client_id);
+        for (client_id, held) in quarantined {
+            writeln!(writer, "quarantine {} {}", client_id.0, held)?;
+        }
+        for (client_id, reason) in &self.frozen {
+            writeln!(writer, "frozen {} {}", client_id.0, reason.name())?;
+        }
+        for (group_id, group) in &self.groups {
+            writeln!(writer, "group {} {}", group_id.0, group.holder.0)?;
+            for (client_id, limit) in &group.members {
+                match limit {
+                    Some(limit) => {
+                        writeln!(writer, "member {} {} {limit}", group_id.0, client_id.0)
+                    }
+                    None => writeln!(writer, "member {} {}", group_id.0, client_id.0),
+                }?;
+            }
+        }
+        for (escrow_id, escrow) in &self.escrows {
+            let state = match escrow.state {
+                EscrowState::Open => "open",
+                EscrowState::Released => "released",
+                EscrowState::Refunded => "refunded",
+            };
+            writeln!(
+                writer,
+                "escrow {} {} {} {} {state}",
+                escrow_id.0, escrow.depositor.0, escrow.beneficiary.0, escrow.amount
+            )?;
+        }
+        let mut activity: Vec<_> = self.last_activity.iter().collect();
+        activity.sort_by_key(|(client_id, _)| **client_id);
+        for (client_id, timestamp) in activity {
+            writeln!(writer, "activity {} {}", client_id.0, timestamp.0)?;
+        }
+        for client_id in &self.dormant {
+            writeln!(writer, "dormant {}", client_id.0)?;
+        }
+        for tombstone in &self.tombstones {
+            writeln!(
+                writer,
+                "tombstone {} {} {} {}",
+                tombstone.available, tombstone.held, tombstone.locked, tombstone.transactions
+            )?;
+        }
+        writeln!(
+            writer,
+            "totals {} {} {}",
+            self.totals.deposited, self.totals.withdrawn, self.totals.charged_back
+        )?;
+        writer.flush()
+    }
+
+    pub fn read_snapshot<R: io::Read>(reader: R, policy: Policy) -> io::Result<Ledger> {
+        let mut lines = io::BufReader::new(reader).lines();
+        let version = read_version(lines.next().transpose()?)?;
+        let mut ledger = Ledger::with_policy(policy);
+        for line in lines {
+            let line = migrate_line(version, line?);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.first().copied() {
+                None => {}
+                Some("time") => ledger.now = Timestamp(field(&fields, 1, &line)?),
+                Some("journal") => {
+                    ledger.journal = Some(Journal::starting_after(field(&fields, 1, &line)?))
+                }
+                Some("account") => {
+                    let account = Account::from_parts(
+                        field(&fields, 2, &line)?,
+                        field(&fields, 3, &line)?,
+                        field(&fields, 4, &line)?,
+                    );
+                    ledger
+                        .accounts
+                        .insert(ClientId(field(&fields, 1, &line)?), account);
+                }
+                Some("transaction") => {
+                    let state = match fields.get(5).copied() {
+                        Some("ok") => TransactionState::Ok,
+                        Some("disputed") => TransactionState::Disputed,
+                        Some("chargedback") => TransactionState::Chargedback,
+                        Some("resolved") => TransactionState::Resolved,
+                        Some("disputed_withdrawal") => TransactionState::DisputedWithdrawal,
+                        _ => return Err(malformed(&line)),
+                    };
+                    let client_id = ClientId(field(&fields, 2, &line)?);
+                    let operation = field::<Operation>(&fields, 3, &line)?.with_recipient(
+                        fields
+                            .get(6)
+                            .map(|_| field(&fields, 6, &line))
+                            .transpose()?
+                            .map(ClientId),
+                    );
+                    let transaction =
+                        Transaction::new(client_id, field(&fields, 4, &line)?, operation)
+                            .with_state(state);
+                    let transaction_id = TransactionId(field(&fields, 1, &line)?);
+                    ledger.transactions.insert(transaction_id, transaction);
+                    ledger.track_open_disputes(client_id, None, state);
+                    if operation == Operation::OpenAccount {
+                        ledger.opened.insert(client_id);
+                    }
+                    if operation == Operation::Deposit
+                        && ledger.policy.max_transactions_per_client.is_some()
+                    {
+                        ledger
+                            .retained
+                            .entry(client_id)
+                            .or_default()
+                            .push_back(transaction_id);
+                    }
+                }
+                Some("archived") => {
+                    ledger.archived.insert(ClientId(field(&fields, 1, &line)?));
+                }
+                Some("erased") => {
+                    ledger
+                        .erased_transactions
+                        .insert(TransactionId(field(&fields, 1, &line)?));
+                }
+                Some("archived_transaction") => {
+                    ledger.archived_transactions.insert(
+                        TransactionId(field(&fields, 1, &line)?),
+                        ClientId(field(&fields, 2, &line)?),
+                    );
+                }
+                Some("tier") => {
+                    ledger.set_tier(
+                        ClientId(field(&fields, 1, &line)?),
+                        field(&fields, 2, &line)?,
+                    );
+                }
+                Some("settings") => {
+                    let settings = AccountSettings {
+                        currency: optional_field(&fields, 2, &line)?,
+                        tier: optional_field(&fields, 3, &line)?,
+                        overdraft_limit: optional_field(&fields, 4, &line)?,
+                    };
+                    ledger
+                        .settings
+                        .insert(ClientId(field(&fields, 1, &line)?), settings);
+                }
+                Some("quarantine") => {
+                    ledger.quarantined.insert(
+                        ClientId(field(&fields, 1, &line)?),
+                        field(&fields, 2, &line)?,
+                    );
+                }
+                Some("frozen") => {
+                    ledger.frozen.insert(
+                        ClientId(field(&fields, 1, &line)?),
+                        field(&fields, 2, &line)?,
+                    );
+                }
+                Some("group") => ledger
+                    .create_group(
+                        GroupId(field(&fields, 1, &line)?),
+                        ClientId(field(&fields, 2, &line)?),
+                    )
+                    .map_err(|_| malformed(&line))?,
+                Some("member") => {
+                    let limit = match fields.get(3) {
+                        Some(_) => Some(field(&fields, 3, &line)?),
+                        None => None,
+                    };
+                    let group_id = GroupId(field(&fields, 1, &line)?);
+                    let client_id = ClientId(field(&fields, 2, &line)?);
+                    // members have no balance of their own, checked when they joined
+                    ledger
+                        .groups
+                        .get_mut(&group_id)
+                        .ok_or_else(|| malformed(&line))?
+                        .members
+                        .insert(client_id, limit);
+                }
+                Some("escrow") => {
+                    let state = match fields.get(5).copied() {
+                        Some("open") => EscrowState::Open,
+                        Some("released") => EscrowState::Released,
+                        Some("refunded") => EscrowState::Refunded,
+                        _ => return Err(malformed(&line)),
+                    };
+                    ledger.escrows.insert(
+                        EscrowId(field(&fields, 1, &line)?),
+                        Escrow {
+                            depositor: ClientId(field(&fields, 2, &line)?),
+                            beneficiary: ClientId(field(&fields, 3, &line)?),
+                            amount: field(&fields, 4, &line)?,
+                            state,
+                        },
+                    );
+                }
+                Some("activity") => {
+                    ledger.last_activity.insert(
+                        ClientId(field(&fields, 1, &line)?),
+                        Timestamp(field(&fields, 2, &line)?),
+                    );
+                }
+                Some("dormant") => {
+                    ledger.dormant.insert(ClientId(field(&fields, 1, &line)?));
+                }
+                Some("totals") => {
+                    ledger.totals = RunningTotals {
+                        deposited: field(&fields, 1, &line)?,
+                        withdrawn: field(&fields, 2, &line)?,
+                        charged_back: field(&fields, 3, &line)?,
+                    }
+                }
+                Some("tombstone") => ledger.tombstones.push_back(Tombstone {
+                    available: field(&fields, 1, &line)?,
+                    held: field(&fields, 2, &line)?,
+                    locked: field(&fields, 3, &line)?,
+                    transactions: field(&fields, 4, &line)?,
+                }),
+                Some(_) => return Err(malformed(&line)),
+            }
+        }
+        Ok(ledger)
+    }
+
+    // Rewrites a snapshot written by an older version in the current format
+    // and returns the version it was written in.
+    pub fn migrate_snapshot<R: io::Read, W: Write>(reader: R, writer: W) -> io::Result<u32> {
+        let mut lines = io::BufReader::new(reader).lines();
+        let version = read_version(lines.next().transpose()?)?;
+        let mut writer = io::BufWriter::new(writer);
+        writeln!(writer, "{HEADER} {SNAPSHOT_VERSION}")?;
+        for line in lines {
+            writeln!(writer, "{}", migrate_line(version, line?))?;
+        }
+        writer.flush()?;
+        Ok(version)
+    }
+}
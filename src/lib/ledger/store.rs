@@ -0,0 +1,119 @@
+use super::accounts::AccountTable;
+use super::TransactionMap;
+use crate::account::{Account, ClientId};
+use crate::transactions::{Transaction, TransactionId};
+use std::collections::BTreeMap;
+use std::future::{ready, Future};
+
+pub trait AccountStore {
+    fn get_account(&self, client_id: ClientId) -> Option<Account>;
+    fn put_account(&mut self, client_id: ClientId, account: Account);
+    fn remove_account(&mut self, client_id: ClientId) -> Option<Account>;
+}
+
+pub trait TransactionStore {
+    fn get_transaction(&self, transaction_id: TransactionId) -> Option<Transaction>;
+    fn put_transaction(&mut self, transaction_id: TransactionId, transaction: Transaction);
+}
+
+// Networked backends (postgres, redis, ...) implement these so lookups don't
+// block the runtime. Anything implementing the sync traits gets them for free
+// with futures that are ready immediately.
+pub trait AsyncAccountStore {
+    fn get_account(&self, client_id: ClientId) -> impl Future<Output = Option<Account>> + Send;
+    fn put_account(
+        &mut self,
+        client_id: ClientId,
+        account: Account,
+    ) -> impl Future<Output = ()> + Send;
+    fn remove_account(
+        &mut self,
+        client_id: ClientId,
+    ) -> impl Future<Output = Option<Account>> + Send;
+}
+
+pub trait AsyncTransactionStore {
+    fn get_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> impl Future<Output = Option<Transaction>> + Send;
+    fn put_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: Transaction,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+impl<S: AccountStore> AsyncAccountStore for S {
+    fn get_account(&self, client_id: ClientId) -> impl Future<Output = Option<Account>> + Send {
+        ready(AccountStore::get_account(self, client_id))
+    }
+
+    fn put_account(
+        &mut self,
+        client_id: ClientId,
+        account: Account,
+    ) -> impl Future<Output = ()> + Send {
+        AccountStore::put_account(self, client_id, account);
+        ready(())
+    }
+
+    fn remove_account(
+        &mut self,
+        client_id: ClientId,
+    ) -> impl Future<Output = Option<Account>> + Send {
+        ready(AccountStore::remove_account(self, client_id))
+    }
+}
+
+impl<S: TransactionStore> AsyncTransactionStore for S {
+    fn get_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> impl Future<Output = Option<Transaction>> + Send {
+        ready(TransactionStore::get_transaction(self, transaction_id))
+    }
+
+    fn put_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: Transaction,
+    ) -> impl Future<Output = ()> + Send {
+        TransactionStore::put_transaction(self, transaction_id, transaction);
+        ready(())
+    }
+}
+
+impl AccountStore for AccountTable {
+    fn get_account(&self, client_id: ClientId) -> Option<Account> {
+        self.get(&client_id)
+    }
+
+    fn put_account(&mut self, client_id: ClientId, account: Account) {
+        self.insert(client_id, account)
+    }
+
+    fn remove_account(&mut self, client_id: ClientId) -> Option<Account> {
+        self.remove(&client_id)
+    }
+}
+
+impl TransactionStore for TransactionMap {
+    fn get_transaction(&self, transaction_id: TransactionId) -> Option<Transaction> {
+        self.get(&transaction_id).copied()
+    }
+
+    fn put_transaction(&mut self, transaction_id: TransactionId, transaction: Transaction) {
+        self.insert(transaction_id, transaction);
+    }
+}
+
+impl TransactionStore for BTreeMap<TransactionId, Transaction> {
+    fn get_transaction(&self, transaction_id: TransactionId) -> Option<Transaction> {
+        self.get(&transaction_id).copied()
+    }
+
+    fn put_transaction(&mut self, transaction_id: TransactionId, transaction: Transaction) {
+        self.insert(transaction_id, transaction);
+    }
+}
@@ -0,0 +1,80 @@
+use super::{AuditEvent, Ledger, Timestamp};
+use crate::account::{ClientId, Number};
+use imbl::Vector;
+
+// Available funds moved off an account left idle past `IdleSweep::after_days`
+// into the sweep account. Both sides of a sweep share its id, so the debit
+// and the credit can be matched in the audit log.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sweep {
+    pub id: u64,
+    pub client_id: ClientId,
+    pub sweep_account: ClientId,
+    pub timestamp: Timestamp,
+    pub idle_days: u64,
+    pub amount: Number,
+}
+
+impl Ledger {
+    pub fn sweeps(&self) -> &Vector<Sweep> {
+        &self.sweeps
+    }
+
+    // Held funds stay where they are until their dispute settles, and locked
+    // or frozen accounts are left alone. Accounts that never had activity
+    // under the ledger's clock don't know how long they've been idle and
+    // aren't swept.
+    pub fn sweep_idle_accounts(&mut self) -> Vec<Sweep> {
+        let Some(policy) = self.policy.idle_sweep else {
+            return Vec::new();
+        };
+        let mut idle: Vec<_> = self
+            .last_activity
+            .keys()
+            .copied()
+            .filter(|client_id| *client_id != policy.sweep_account)
+            .filter(|client_id| self.account_holder(*client_id) == *client_id)
+            .filter(|client_id| !self.is_frozen(*client_id))
+            .map(|client_id| (client_id, self.idle_days(client_id)))
+            .filter(|(_, idle_days)| *idle_days >= policy.after_days)
+            .collect();
+        idle.sort();
+        let mut swept = Vec::new();
+        for (client_id, idle_days) in idle {
+            let Some(mut account) = self.accounts.get(&client_id) else {
+                continue;
+            };
+            let amount = account.available();
+            let mut sweep_account = self.accounts.get(&policy.sweep_account).unwrap_or_default();
+            if amount <= Number::ZERO
+                || account.withdraw(amount).is_err()
+                || sweep_account.deposit(amount).is_err()
+            {
+                continue;
+            }
+            self.accounts.insert(client_id, account);
+            self.accounts.insert(policy.sweep_account, sweep_account);
+            let sweep = Sweep {
+                id: self.sweeps.len() as u64 + 1,
+                client_id,
+                sweep_account: policy.sweep_account,
+                timestamp: self.now,
+                idle_days,
+                amount,
+            };
+            self.sweeps.push_back(sweep);
+            self.audit(AuditEvent::BalanceSwept {
+                sweep: sweep.id,
+                client_id,
+                amount: -amount,
+            });
+            self.audit(AuditEvent::BalanceSwept {
+                sweep: sweep.id,
+                client_id: policy.sweep_account,
+                amount,
+            });
+            swept.push(sweep);
+        }
+        swept
+    }
+}
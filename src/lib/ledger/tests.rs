@@ -1,16 +1,33 @@
 use super::TransactionResult;
 use crate::{
-    account::num, account::AccountError, account::ClientId, account::Number, ledger::Ledger,
-    transactions::Operation, transactions::Transaction, transactions::TransactionError,
-    transactions::TransactionId, transactions::TransactionState,
+    account::num, account::Account, account::AccountError, account::ClientId, account::Number,
+    ledger::AccountFilter, ledger::AccountSettings, ledger::AccountStore, ledger::AccountTable,
+    ledger::Accrual, ledger::Alert, ledger::AlertRules, ledger::AmountRange,
+    ledger::AsyncAccountStore, ledger::AsyncTransactionStore, ledger::AuditEvent,
+    ledger::BalanceKey, ledger::BatchedStore, ledger::CaseError, ledger::CaseId, ledger::CaseState,
+    ledger::ChargebackExcess, ledger::CommandOutput, ledger::DisputeEvent, ledger::DisputeInfo,
+    ledger::EscrowError, ledger::EscrowId, ledger::EscrowState, ledger::EventFilter,
+    ledger::EventStream, ledger::Finalization, ledger::Finalizer, ledger::FreezeReason,
+    ledger::GroupError, ledger::GroupId, ledger::HeldAccrual, ledger::IdleSweep, ledger::Journal,
+    ledger::Ledger, ledger::LedgerCommand, ledger::LedgerEvent, ledger::MergeConflict,
+    ledger::MergeError, ledger::MergeReport, ledger::MergeStrategy, ledger::OverflowPolicy,
+    ledger::Policy, ledger::ReplayPoint, ledger::RunningTotals, ledger::ScreeningOutcome,
+    ledger::ScreeningProvider, ledger::StateChange, ledger::Sweep, ledger::Tier, ledger::TierLimit,
+    ledger::TierLimits, ledger::Timestamp, ledger::Tombstone, ledger::TransactionFilter,
+    ledger::TransactionStore, ledger::JOURNAL_VERSION, ledger::SNAPSHOT_VERSION,
+    manifest::ManifestError, transactions::Operation, transactions::Outcome,
+    transactions::Signature, transactions::SignatureVerifier, transactions::Transaction,
+    transactions::TransactionError, transactions::TransactionId, transactions::TransactionState,
 };
 
+use imbl::vector;
+
 type TransactionList = Vec<(TransactionId, Transaction)>;
 
 fn process_transactions<'a>(
     ledger: &'a mut Ledger,
     transactions: &'a TransactionList,
-) -> impl Iterator<Item = TransactionResult> + 'a {
+) -> impl Iterator<Item = TransactionResult<Outcome>> + 'a {
     transactions.iter().map(move |t| {
         let (id, transaction) = t;
         ledger.apply_transaction(*id, transaction)
@@ -105,6 +122,32 @@ fn simple_withdrawal() {
     assert_eq!(transaction.state(), TransactionState::Ok);
 }
 
+#[test]
+fn apply_reports_outcome() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(3.0), Operation::Deposit);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &deposit),
+        Ok(Outcome::Applied {
+            new_available: num!(3.0),
+            new_held: Number::ZERO,
+        })
+    );
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &dispute),
+        Ok(Outcome::Applied {
+            new_available: Number::ZERO,
+            new_held: num!(3.0),
+        })
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &deposit),
+        Ok(Outcome::Deduplicated)
+    );
+    assert_eq!(ledger.account(ClientId(1)).unwrap().held(), num!(3.0));
+}
+
 #[test]
 fn cant_withdrawal_with_same_id() {
     let mut ledger = Ledger::new();
@@ -120,6 +163,11 @@ fn cant_withdrawal_with_same_id() {
         TransactionId(1),
         &Transaction::new(ClientId(1), num!(0.5), Operation::Withdrawal),
     );
+    assert_eq!(res, Ok(Outcome::Deduplicated));
+    let res = ledger.apply_transaction(
+        TransactionId(1),
+        &Transaction::new(ClientId(1), num!(0.25), Operation::Withdrawal),
+    );
     assert_eq!(
         res.err().unwrap(),
         TransactionError::RepeatedTransactionId(TransactionId(1))
@@ -290,6 +338,63 @@ fn cant_dispute_withdrawal() {
     assert_eq!(transaction.state(), TransactionState::Ok);
 }
 
+#[test]
+fn disputed_withdrawals_hold_a_pending_credit() {
+    let mut ledger = Ledger::with_policy(Policy {
+        dispute_withdrawals: true,
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(50.0), Operation::Deposit);
+    let withdrawal = Transaction::new(ClientId(1), num!(20.0), Operation::Withdrawal);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    let chargeback = Transaction::new(ClientId(1), Number::ZERO, Operation::Chargeback);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &withdrawal)
+        .is_ok());
+
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &dispute),
+        Ok(Outcome::Applied {
+            new_available: num!(10.0),
+            new_held: num!(20.0),
+        })
+    );
+    assert_eq!(
+        ledger.transactions.get(&TransactionId(2)).unwrap().state(),
+        TransactionState::DisputedWithdrawal
+    );
+    assert_eq!(ledger.open_disputes(ClientId(1)), 1);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &resolve),
+        Ok(Outcome::Applied {
+            new_available: num!(10.0),
+            new_held: Number::ZERO,
+        })
+    );
+    assert_eq!(ledger.open_disputes(ClientId(1)), 0);
+
+    assert!(ledger.apply_transaction(TransactionId(3), &dispute).is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &chargeback),
+        Ok(Outcome::Applied {
+            new_available: num!(30.0),
+            new_held: Number::ZERO,
+        })
+    );
+    assert!(!ledger.accounts.get(&ClientId(1)).unwrap().locked());
+    assert_eq!(
+        ledger.transactions.get(&TransactionId(3)).unwrap().state(),
+        TransactionState::Chargedback
+    );
+    assert_eq!(ledger.stats().chargebacks, 1);
+    assert_eq!(ledger.stats().resolved_disputes, 1);
+}
+
 // CHARGEBACK
 #[test]
 fn simple_chargeback() {
@@ -515,7 +620,31 @@ fn simple_resolve() {
     assert!(!ledger.accounts.get(&ClientId(1)).unwrap().locked());
     assert_eq!(ledger.transactions.len(), 2);
     let transaction = ledger.transactions.get(&TransactionId(2)).unwrap();
-    assert_eq!(transaction.state(), TransactionState::Ok);
+    assert_eq!(transaction.state(), TransactionState::Resolved);
+    assert_eq!(transaction.state().without_resolved(), TransactionState::Ok);
+}
+
+#[test]
+fn redisputes_can_be_rejected() {
+    let mut ledger = Ledger::with_policy(Policy {
+        reject_redisputes: true,
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(5.0), Operation::Deposit);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(1), &resolve).is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &dispute),
+        Err(TransactionError::AlreadyDisputed(TransactionId(1)))
+    );
+    assert_eq!(ledger.stats().resolved_disputes, 1);
+
+    ledger.set_policy(Policy::default());
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert_eq!(ledger.stats().open_disputes, 1);
 }
 
 #[test]
@@ -558,3 +687,2934 @@ fn cant_resolve_undisputed_transaction() {
     assert!(!ledger.accounts.get(&ClientId(1)).unwrap().locked());
     assert_eq!(ledger.transactions.len(), 1);
 }
+
+// CASES
+fn disputed_installments(ledger: &mut Ledger) {
+    for id in 1..=3 {
+        let _ = ledger.apply_transaction(
+            TransactionId(id),
+            &Transaction::new(ClientId(1), num!(10), Operation::Deposit),
+        );
+        let _ = ledger.apply_transaction(
+            TransactionId(id),
+            &Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        );
+    }
+}
+
+#[test]
+fn case_settles_as_unit() {
+    let mut ledger = Ledger::new();
+    disputed_installments(&mut ledger);
+    let installments = [TransactionId(1), TransactionId(2)];
+    assert_eq!(
+        ledger.open_case(CaseId(1), ClientId(1), &[]),
+        Err(CaseError::EmptyCase(CaseId(1)))
+    );
+    assert_eq!(
+        ledger.open_case(CaseId(1), ClientId(2), &installments),
+        Err(CaseError::Transaction(TransactionError::UnknownClientId(
+            ClientId(2)
+        )))
+    );
+    assert_eq!(
+        ledger.open_case(CaseId(1), ClientId(1), &installments),
+        Ok(())
+    );
+    assert_eq!(
+        ledger.open_case(
+            CaseId(2),
+            ClientId(1),
+            &[TransactionId(3), TransactionId(2)]
+        ),
+        Err(CaseError::AlreadyInCase(TransactionId(2), CaseId(1)))
+    );
+    assert_eq!(ledger.resolve_case(CaseId(1)), Ok(()));
+    assert_eq!(
+        ledger.chargeback_case(CaseId(1)),
+        Err(CaseError::ClosedCase(CaseId(1)))
+    );
+    assert_eq!(ledger.case(CaseId(1)).unwrap().state, CaseState::Resolved);
+    let account = ledger.accounts.get(&ClientId(1)).unwrap();
+    assert_eq!(account.available(), num!(20));
+    assert_eq!(account.held(), num!(10));
+    assert_eq!(ledger.open_disputes(ClientId(1)), 1);
+}
+
+#[test]
+fn case_not_applied_partially() {
+    let mut ledger = Ledger::new();
+    disputed_installments(&mut ledger);
+    let installments = [TransactionId(1), TransactionId(2), TransactionId(3)];
+    assert_eq!(
+        ledger.open_case(CaseId(1), ClientId(1), &installments),
+        Ok(())
+    );
+    let _ = ledger.apply_transaction(
+        TransactionId(3),
+        &Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve),
+    );
+    assert_eq!(
+        ledger.chargeback_case(CaseId(1)),
+        Err(CaseError::Transaction(
+            TransactionError::UndisputedTransaction(TransactionId(3))
+        ))
+    );
+    let account = ledger.accounts.get(&ClientId(1)).unwrap();
+    assert_eq!(account.held(), num!(20));
+    assert!(!account.locked());
+    assert_eq!(ledger.cases(ClientId(1)).count(), 1);
+    assert_eq!(ledger.case(CaseId(1)).unwrap().state, CaseState::Open);
+}
+
+// RISK
+#[test]
+fn risk_score_follows_activity() {
+    let mut ledger = Ledger::new();
+    for id in 1..=4 {
+        let _ = ledger.apply_transaction(
+            TransactionId(id),
+            &Transaction::new(ClientId(1), num!(10), Operation::Deposit),
+        );
+    }
+    assert_eq!(ledger.risk_score(ClientId(1)), num!(0.4));
+    for (id, operation) in [(1, Operation::Dispute), (2, Operation::Dispute)] {
+        let _ = ledger.apply_transaction(
+            TransactionId(id),
+            &Transaction::new(ClientId(1), Number::ZERO, operation),
+        );
+    }
+    let _ = ledger.apply_transaction(
+        TransactionId(1),
+        &Transaction::new(ClientId(1), Number::ZERO, Operation::Chargeback),
+    );
+    // 1/4 charged back, 2/4 disputed, 4 of 100 transactions in the last day
+    assert_eq!(ledger.risk_score(ClientId(1)), num!(30.4));
+    ledger.advance_time(24 * 60 * 60);
+    assert_eq!(ledger.risk_score(ClientId(1)), num!(30));
+    assert_eq!(ledger.risk_score(ClientId(2)), Number::ZERO);
+
+    let mut csv = Vec::new();
+    ledger.write_risk_scores(&mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "client,score,transactions,disputes,chargebacks\n1,30.00,4,2,1\n"
+    );
+}
+
+// GROUPS
+#[test]
+fn group_members_share_balance() {
+    let mut ledger = Ledger::new();
+    let _ = ledger.apply_transaction(
+        TransactionId(1),
+        &Transaction::new(ClientId(3), num!(1), Operation::Deposit),
+    );
+    assert_eq!(ledger.create_group(GroupId(1), ClientId(1)), Ok(()));
+    assert_eq!(
+        ledger.create_group(GroupId(2), ClientId(1)),
+        Err(GroupError::AlreadyGrouped(ClientId(1), GroupId(1)))
+    );
+    assert_eq!(
+        ledger.add_member(GroupId(1), ClientId(2), Some(num!(15))),
+        Ok(())
+    );
+    assert_eq!(
+        ledger.add_member(GroupId(1), ClientId(3), None),
+        Err(GroupError::OwnBalance(ClientId(3)))
+    );
+    assert_eq!(
+        ledger.add_member(GroupId(9), ClientId(4), None),
+        Err(GroupError::UnknownGroup(GroupId(9)))
+    );
+
+    let transactions: TransactionList = vec![
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), num!(50), Operation::Deposit),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(2), num!(20), Operation::Deposit),
+        ),
+        (
+            TransactionId(4),
+            Transaction::new(ClientId(2), num!(10), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(2), Number::ZERO, Operation::Dispute),
+        ),
+    ];
+    process_transactions(&mut ledger, &transactions).for_each(|res| assert!(res.is_ok()));
+    assert_eq!(
+        ledger.apply_transaction(
+            TransactionId(5),
+            &Transaction::new(ClientId(2), num!(10), Operation::Withdrawal),
+        ),
+        Err(TransactionError::GroupLimitExceeded(ClientId(2)))
+    );
+    assert!(ledger
+        .apply_transaction(
+            TransactionId(5),
+            &Transaction::new(ClientId(1), num!(10), Operation::Withdrawal),
+        )
+        .is_ok());
+    let account = ledger.account(ClientId(1)).unwrap();
+    assert_eq!(account.available(), num!(30));
+    assert_eq!(account.held(), num!(20));
+    assert_eq!(ledger.account(ClientId(2)), None);
+    assert_eq!(ledger.open_disputes(ClientId(2)), 1);
+
+    assert_eq!(ledger.remove_member(GroupId(1), ClientId(2)), Ok(()));
+    assert_eq!(ledger.group_of(ClientId(2)), None);
+    assert!(matches!(
+        ledger.apply_transaction(
+            TransactionId(6),
+            &Transaction::new(ClientId(2), num!(1), Operation::Withdrawal),
+        ),
+        Err(TransactionError::AccountError(ClientId(2), _))
+    ));
+}
+
+// ESCROW
+#[test]
+fn escrow_release_and_refund() {
+    let mut ledger = Ledger::new();
+    assert_eq!(
+        ledger.deposit_escrow(EscrowId(1), ClientId(1), ClientId(2), num!(0)),
+        Err(EscrowError::InvalidAmount(EscrowId(1), num!(0)))
+    );
+    assert_eq!(
+        ledger.deposit_escrow(EscrowId(1), ClientId(1), ClientId(2), num!(30)),
+        Ok(())
+    );
+    assert_eq!(
+        ledger.deposit_escrow(EscrowId(1), ClientId(1), ClientId(2), num!(5)),
+        Err(EscrowError::RepeatedEscrow(EscrowId(1)))
+    );
+    assert_eq!(
+        ledger.deposit_escrow(EscrowId(2), ClientId(1), ClientId(2), num!(5)),
+        Ok(())
+    );
+    assert_eq!(ledger.escrowed(ClientId(2)), num!(35));
+    assert_eq!(ledger.stats().escrowed, num!(35));
+    assert_eq!(ledger.account(ClientId(2)), None);
+
+    assert_eq!(ledger.release_escrow(EscrowId(1)), Ok(()));
+    assert_eq!(
+        ledger.refund_escrow(EscrowId(1)),
+        Err(EscrowError::ClosedEscrow(EscrowId(1)))
+    );
+    assert_eq!(ledger.refund_escrow(EscrowId(2)), Ok(()));
+    assert_eq!(
+        ledger.release_escrow(EscrowId(3)),
+        Err(EscrowError::UnknownEscrow(EscrowId(3)))
+    );
+    assert_eq!(ledger.account(ClientId(2)).unwrap().available(), num!(30));
+    assert_eq!(ledger.account(ClientId(2)).unwrap().held(), Number::ZERO);
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(5));
+    assert_eq!(
+        ledger.escrow(EscrowId(2)).unwrap().state,
+        EscrowState::Refunded
+    );
+    assert_eq!(ledger.stats().escrowed, Number::ZERO);
+}
+
+// TRANSFER
+#[test]
+fn transfers_move_funds_atomically() {
+    let mut ledger = Ledger::new();
+    ledger.enable_journal();
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    ledger
+        .apply_transaction(TransactionId(1), &deposit)
+        .unwrap();
+    let full = Transaction::new(ClientId(3), Number::MAX, Operation::Deposit);
+    ledger.apply_transaction(TransactionId(2), &full).unwrap();
+
+    let transfer = |to, amount| {
+        Transaction::new(
+            ClientId(1),
+            amount,
+            Operation::Transfer { to: ClientId(to) },
+        )
+    };
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &transfer(2, num!(4))),
+        Ok(Outcome::Applied {
+            new_available: num!(6),
+            new_held: Number::ZERO
+        })
+    );
+    assert_eq!(ledger.account(ClientId(2)).unwrap().available(), num!(4));
+    assert!(matches!(
+        ledger.apply_transaction(TransactionId(4), &transfer(2, num!(7))),
+        Err(TransactionError::AccountError(ClientId(1), _))
+    ));
+    // the recipient can't take it, so the sender keeps it
+    assert!(matches!(
+        ledger.apply_transaction(TransactionId(5), &transfer(3, num!(1))),
+        Err(TransactionError::AccountError(ClientId(3), _))
+    ));
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(6));
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &dispute)
+        .is_err());
+
+    let mut snapshot = Vec::new();
+    ledger.write_snapshot(&mut snapshot).unwrap();
+    let restored = Ledger::read_snapshot(snapshot.as_slice(), Policy::default()).unwrap();
+    assert_eq!(
+        restored.transaction(TransactionId(3)).unwrap().operation(),
+        Operation::Transfer { to: ClientId(2) }
+    );
+    let mut journal = Vec::new();
+    ledger.journal().unwrap().write_csv(&mut journal).unwrap();
+    let journal = Journal::read_csv(journal.as_slice()).unwrap();
+    assert_eq!(journal.entries()[2].transaction, transfer(2, num!(4)));
+}
+
+// QUERY
+#[test]
+fn query_accounts_and_disputes() {
+    let mut ledger = Ledger::new();
+    let transactions: Vec<(TransactionId, Transaction)> = vec![
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), num!(10.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(2), num!(5.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(2), num!(1.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(2), Number::ZERO, Operation::Dispute),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(2), Number::ZERO, Operation::Dispute),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(2), Number::ZERO, Operation::Chargeback),
+        ),
+    ];
+    process_transactions(&mut ledger, &transactions).for_each(|res| assert!(res.is_ok()));
+
+    let locked = AccountFilter {
+        locked: Some(true),
+        ..AccountFilter::default()
+    };
+    let locked: Vec<ClientId> = ledger.accounts(&locked).map(|(id, _)| id).collect();
+    assert_eq!(locked, vec![ClientId(2)]);
+
+    let rich = AccountFilter {
+        min_total: Some(num!(6.0)),
+        ..AccountFilter::default()
+    };
+    let rich: Vec<ClientId> = ledger.accounts(&rich).map(|(id, _)| id).collect();
+    assert_eq!(rich, vec![ClientId(1)]);
+
+    let deposits = TransactionFilter {
+        client_id: Some(ClientId(2)),
+        operation: Some(Operation::Deposit),
+        ..TransactionFilter::default()
+    };
+    assert_eq!(ledger.transactions(&deposits).count(), 2);
+
+    let disputes: Vec<TransactionId> = ledger.disputes().map(|(id, _)| id).collect();
+    assert_eq!(disputes, vec![TransactionId(2)]);
+
+    let stats = ledger.stats();
+    assert_eq!(stats.accounts, 2);
+    assert_eq!(stats.locked_accounts, 1);
+    assert_eq!(stats.transactions, 3);
+    assert_eq!(stats.open_disputes, 1);
+    assert_eq!(stats.chargebacks, 1);
+    assert_eq!(stats.available, num!(10.0));
+    assert_eq!(stats.held, num!(5.0));
+    assert_eq!(
+        stats.totals,
+        RunningTotals {
+            deposited: num!(16),
+            withdrawn: Number::ZERO,
+            charged_back: num!(1),
+        }
+    );
+}
+
+#[test]
+fn stats_saturate_at_the_largest_amount() {
+    let mut ledger = Ledger::new();
+    for client in [1, 2] {
+        let deposit = Transaction::new(ClientId(client), Number::MAX, Operation::Deposit);
+        assert!(ledger
+            .apply_transaction(TransactionId(client.into()), &deposit)
+            .is_ok());
+    }
+    assert_eq!(ledger.stats().available, Number::MAX);
+}
+
+#[test]
+fn amount_distribution() {
+    let mut ledger = Ledger::new();
+    let transactions: TransactionList = vec![
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), num!(5.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), num!(50.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(1), num!(20.0), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        ),
+    ];
+    process_transactions(&mut ledger, &transactions).for_each(|res| assert!(res.is_ok()));
+    let bounds = [num!(10), num!(100)];
+    assert_eq!(
+        ledger
+            .amount_distribution(Operation::Deposit, &bounds)
+            .counts,
+        vec![1, 1, 0]
+    );
+    assert_eq!(
+        ledger
+            .amount_distribution(Operation::Withdrawal, &bounds)
+            .counts,
+        vec![0, 1, 0]
+    );
+    let disputes = ledger.amount_distribution(Operation::Dispute, &bounds);
+    assert_eq!(disputes.counts, vec![0, 2, 0]);
+    assert_eq!(disputes.totals[1], num!(100.0));
+    assert_eq!(
+        ledger
+            .amount_distribution(Operation::Chargeback, &bounds)
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn paginate_accounts_across_inserts() {
+    let mut ledger = Ledger::new();
+    for id in [5, 1, 3] {
+        let _ = ledger.apply_transaction(
+            TransactionId(id as u32),
+            &Transaction::new(ClientId(id), Number::ONE, Operation::Deposit),
+        );
+    }
+    let page = ledger.accounts_page(None, 2);
+    let ids: Vec<ClientId> = page.items.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![ClientId(1), ClientId(3)]);
+    assert_eq!(page.next, Some(ClientId(3)));
+
+    // clients inserted behind the cursor must not shift the following pages
+    let cursor = page.next;
+    let _ = ledger.apply_transaction(
+        TransactionId(2),
+        &Transaction::new(ClientId(2), Number::ONE, Operation::Deposit),
+    );
+    let page = ledger.accounts_page(cursor, 2);
+    let ids: Vec<ClientId> = page.items.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![ClientId(5)]);
+    assert_eq!(page.next, None);
+
+    let page = ledger.transactions_page(Some(TransactionId(2)), 10);
+    let ids: Vec<TransactionId> = page.items.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![TransactionId(3), TransactionId(5)]);
+    assert_eq!(page.next, None);
+}
+
+#[test]
+fn failed_activity_does_not_create_accounts() {
+    let mut ledger = Ledger::new();
+    let res = ledger.apply_transaction(
+        TransactionId(1),
+        &Transaction::new(ClientId(7), Number::ONE, Operation::Withdrawal),
+    );
+    assert!(res.is_err());
+    let res = ledger.apply_transaction(
+        TransactionId(2),
+        &Transaction::new(ClientId(8), Number::ZERO, Operation::Dispute),
+    );
+    assert!(res.is_err());
+    assert_eq!(ledger.accounts.len(), 0);
+    assert!(ledger.account(ClientId(7)).is_none());
+    assert_eq!(ledger.into_iter().count(), 0);
+}
+
+#[test]
+fn balance_alerts() {
+    let mut ledger = Ledger::new();
+    let transactions: TransactionList = vec![
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), num!(10), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), num!(8), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(2), num!(5), Operation::Deposit),
+        ),
+    ];
+    process_transactions(&mut ledger, &transactions).for_each(|res| assert!(res.is_ok()));
+    ledger.advance_time(3 * 24 * 60 * 60);
+    let _ = ledger.apply_transaction(
+        TransactionId(4),
+        &Transaction::new(ClientId(2), num!(5), Operation::Deposit),
+    );
+    assert_eq!(
+        ledger.alerts(&AlertRules::default()),
+        vec![(ClientId(1), Alert::NegativeAvailable(num!(-8)))]
+    );
+    let rules = AlertRules {
+        negative_available: false,
+        held_above: Some(num!(9)),
+        dormant_days: Some(2),
+        ..AlertRules::default()
+    };
+    assert_eq!(
+        ledger.alerts(&rules),
+        vec![
+            (ClientId(1), Alert::HeldAbove(num!(10))),
+            (ClientId(1), Alert::Dormant { days: 3 }),
+        ]
+    );
+}
+
+#[test]
+fn transactions_between_times() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(1), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    ledger.enable_timestamps();
+    for (id, time) in [(2, 10), (3, 20), (4, 20), (5, 30)] {
+        ledger.set_time(Timestamp(time));
+        assert!(ledger
+            .apply_transaction(TransactionId(id), &deposit)
+            .is_ok());
+    }
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(3), &dispute).is_ok());
+    let between: Vec<_> = ledger
+        .transactions_between(Timestamp(10), Timestamp(30))
+        .map(|(time, id, transaction)| (time, id, transaction.state()))
+        .collect();
+    assert_eq!(
+        between,
+        [
+            (Timestamp(10), TransactionId(2), TransactionState::Ok),
+            (Timestamp(20), TransactionId(3), TransactionState::Disputed),
+            (Timestamp(20), TransactionId(4), TransactionState::Ok),
+        ]
+    );
+    assert_eq!(
+        ledger
+            .transactions_between(Timestamp(30), Timestamp(10))
+            .count(),
+        0
+    );
+    // Applied before timestamps were enabled.
+    assert_eq!(
+        ledger
+            .transactions_between(Timestamp(0), Timestamp(10))
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn top_accounts_by_balance() {
+    let deposits = [(1, num!(5)), (2, num!(9)), (3, num!(5)), (4, num!(1))];
+    let mut indexed = Ledger::new();
+    indexed.enable_balance_index();
+    let mut scanned = Ledger::new();
+    for (tx, (client, amount)) in deposits.into_iter().enumerate() {
+        let deposit = Transaction::new(ClientId(client), amount, Operation::Deposit);
+        for ledger in [&mut indexed, &mut scanned] {
+            assert!(ledger
+                .apply_transaction(TransactionId(tx as u32), &deposit)
+                .is_ok());
+        }
+    }
+    let dispute = Transaction::new(ClientId(2), Number::ZERO, Operation::Dispute);
+    for ledger in [&mut indexed, &mut scanned] {
+        assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+        let _ = ledger.archive_account(ClientId(4));
+    }
+
+    for ledger in [&indexed, &scanned] {
+        let top: Vec<ClientId> = ledger
+            .top_accounts(2, BalanceKey::Available)
+            .into_iter()
+            .map(|(client_id, _)| client_id)
+            .collect();
+        assert_eq!(top, vec![ClientId(1), ClientId(3)]);
+        assert_eq!(
+            ledger.accounts_above(num!(1), BalanceKey::Held),
+            vec![(ClientId(2), ledger.account(ClientId(2)).unwrap())]
+        );
+        assert_eq!(
+            ledger.accounts_above(num!(0), BalanceKey::Available).len(),
+            3
+        );
+    }
+}
+
+// VALIDATE
+#[test]
+fn validate_does_not_mutate() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(10.0), Operation::Deposit);
+    assert_eq!(ledger.validate(&deposit, TransactionId(1)), Ok(()));
+    assert_eq!(ledger.accounts.len(), 0);
+    assert_eq!(ledger.transactions.len(), 0);
+
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+    let other = Transaction::new(ClientId(1), num!(5.0), Operation::Deposit);
+    assert_eq!(
+        ledger.validate(&other, TransactionId(1)),
+        Err(TransactionError::RepeatedTransactionId(TransactionId(1)))
+    );
+    let withdrawal = Transaction::new(ClientId(1), num!(20.0), Operation::Withdrawal);
+    assert!(matches!(
+        ledger.validate(&withdrawal, TransactionId(2)),
+        Err(TransactionError::AccountError(
+            _,
+            AccountError::Underflow { .. }
+        ))
+    ));
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    assert_eq!(
+        ledger.validate(&resolve, TransactionId(1)),
+        Err(TransactionError::UndisputedTransaction(TransactionId(1)))
+    );
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert_eq!(ledger.validate(&dispute, TransactionId(1)), Ok(()));
+    assert_eq!(
+        ledger.transactions.get(&TransactionId(1)).unwrap().state(),
+        TransactionState::Ok
+    );
+    assert_eq!(
+        ledger.accounts.get(&ClientId(1)).unwrap().held(),
+        Number::ZERO
+    );
+}
+
+#[test]
+fn dispute_details_track_lifecycle() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(5.0), Operation::Deposit);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert_eq!(ledger.dispute_details(TransactionId(1)), None);
+
+    ledger.set_time(Timestamp(10));
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    ledger.set_time(Timestamp(20));
+    assert!(ledger.apply_transaction(TransactionId(1), &resolve).is_ok());
+    // rejected operations don't show up in the history
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &resolve)
+        .is_err());
+    ledger.set_time(Timestamp(30));
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+
+    let event = |timestamp, operation, state| DisputeEvent {
+        timestamp: Timestamp(timestamp),
+        operation,
+        state,
+    };
+    assert_eq!(
+        ledger.dispute_details(TransactionId(1)),
+        Some(DisputeInfo {
+            transaction_id: TransactionId(1),
+            client_id: ClientId(1),
+            state: TransactionState::Disputed,
+            held: num!(5.0),
+            opened_at: Timestamp(30),
+            history: vec![
+                event(10, Operation::Dispute, TransactionState::Disputed),
+                event(20, Operation::Resolve, TransactionState::Resolved),
+                event(30, Operation::Dispute, TransactionState::Disputed),
+            ],
+        })
+    );
+}
+
+#[test]
+fn stored_transactions_keep_their_state_history() {
+    let mut ledger = Ledger::new();
+    ledger.enable_journal();
+    let deposit = Transaction::new(ClientId(1), num!(5.0), Operation::Deposit);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let chargeback = Transaction::new(ClientId(1), Number::ZERO, Operation::Chargeback);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    ledger.set_time(Timestamp(10));
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &dispute)
+        .is_err());
+    ledger.set_time(Timestamp(20));
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &chargeback)
+        .is_ok());
+    assert!(ledger.stored_transaction(TransactionId(2)).is_none());
+
+    let stored = ledger.stored_transaction(TransactionId(1)).unwrap();
+    assert_eq!(stored.transaction().state(), TransactionState::Chargedback);
+    let change = |timestamp, operation, from, to, sequence| StateChange {
+        timestamp: Timestamp(timestamp),
+        operation,
+        from,
+        to,
+        sequence: Some(sequence),
+    };
+    assert_eq!(
+        stored.history(),
+        [
+            change(0, Operation::Deposit, None, TransactionState::Ok, 1),
+            change(
+                10,
+                Operation::Dispute,
+                Some(TransactionState::Ok),
+                TransactionState::Disputed,
+                2
+            ),
+            change(
+                20,
+                Operation::Chargeback,
+                Some(TransactionState::Disputed),
+                TransactionState::Chargedback,
+                3
+            ),
+        ]
+    );
+}
+
+#[test]
+fn long_disputes_accrue_on_held_funds() {
+    const DAY: u64 = 24 * 60 * 60;
+    let mut ledger = Ledger::with_policy(Policy {
+        held_accrual: Some(HeldAccrual {
+            after_days: 2,
+            daily_rate: num!(0.01),
+        }),
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(1000), Operation::Deposit);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+
+    ledger.advance_time(2 * DAY);
+    assert_eq!(ledger.accruals(TransactionId(1)).count(), 0);
+    ledger.set_time(Timestamp(4 * DAY + 10));
+    ledger.advance_time(DAY / 2);
+    ledger.advance_time(DAY);
+    assert_eq!(
+        ledger.accruals(TransactionId(1)).collect::<Vec<_>>(),
+        [
+            &Accrual {
+                transaction_id: TransactionId(1),
+                client_id: ClientId(1),
+                timestamp: Timestamp(4 * DAY + 10),
+                days: 2,
+                amount: num!(20),
+            },
+            &Accrual {
+                transaction_id: TransactionId(1),
+                client_id: ClientId(1),
+                timestamp: Timestamp(5 * DAY + DAY / 2 + 10),
+                days: 1,
+                amount: num!(10),
+            },
+        ]
+    );
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(30));
+    assert_eq!(
+        ledger.audit_log().last(),
+        Some(&AuditEvent::HeldFundsAccrued {
+            client_id: ClientId(1),
+            transaction_id: TransactionId(1),
+            amount: num!(10),
+        })
+    );
+
+    // resolving stops the accrual, and disputing again starts the count over
+    assert!(ledger.apply_transaction(TransactionId(1), &resolve).is_ok());
+    ledger.advance_time(DAY);
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    ledger.advance_time(2 * DAY);
+    assert_eq!(ledger.accruals(TransactionId(1)).count(), 2);
+    ledger.advance_time(DAY);
+    assert_eq!(ledger.accruals(TransactionId(1)).count(), 3);
+
+    // penalties stop at the available funds
+    ledger.set_policy(Policy {
+        held_accrual: Some(HeldAccrual {
+            after_days: 0,
+            daily_rate: num!(-1),
+        }),
+        ..Policy::default()
+    });
+    ledger.advance_time(DAY);
+    let account = ledger.account(ClientId(1)).unwrap();
+    assert_eq!(
+        (account.available(), account.held()),
+        (Number::ZERO, num!(1000))
+    );
+    assert_eq!(
+        ledger.accruals(TransactionId(1)).last().unwrap().amount,
+        num!(-40)
+    );
+}
+
+#[test]
+fn finalize_runs_the_configured_finalizers() {
+    const DAY: u64 = 24 * 60 * 60;
+    let mut ledger = Ledger::with_policy(Policy {
+        held_accrual: Some(HeldAccrual {
+            after_days: 0,
+            daily_rate: num!(0.01),
+        }),
+        ..Policy::default()
+    });
+    ledger.enable_journal();
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    for id in 1..=2 {
+        let deposit = Transaction::new(ClientId(1), num!(100), Operation::Deposit);
+        assert!(ledger
+            .apply_transaction(TransactionId(id), &deposit)
+            .is_ok());
+    }
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    ledger.set_time(Timestamp(31 * DAY));
+    assert!(ledger.apply_transaction(TransactionId(2), &dispute).is_ok());
+    assert_eq!(ledger.finalize(), Finalization::default());
+
+    ledger.add_finalizer(Finalizer::ResolveExpiredDisputes { after_days: 30 });
+    ledger.add_finalizer(Finalizer::AccrueHeldFunds);
+    ledger.add_finalizer(Finalizer::ClosePeriod);
+    ledger.advance_time(DAY / 2);
+    let finalization = ledger.finalize();
+    assert_eq!(finalization.resolved, [TransactionId(1)]);
+    // the day hasn't changed since the clock last accrued
+    assert_eq!(finalization.accruals, 0);
+    let period = finalization.period.unwrap();
+    assert_eq!(period.closed_at, Timestamp(31 * DAY + DAY / 2));
+    assert_eq!(
+        (period.stats.open_disputes, period.stats.held),
+        (1, num!(100))
+    );
+    assert_eq!(
+        ledger.transaction(TransactionId(1)).unwrap().state(),
+        TransactionState::Resolved
+    );
+    assert_eq!(
+        ledger
+            .journal()
+            .unwrap()
+            .entries()
+            .last()
+            .unwrap()
+            .transaction
+            .operation(),
+        Operation::Resolve
+    );
+    assert_eq!(
+        ledger.audit_log().last(),
+        Some(&AuditEvent::PeriodClosed(Timestamp(31 * DAY + DAY / 2)))
+    );
+}
+
+#[test]
+fn sweep_idle_accounts_to_the_sweep_account() {
+    const DAY: u64 = 24 * 60 * 60;
+    let mut ledger = Ledger::with_policy(Policy {
+        idle_sweep: Some(IdleSweep {
+            after_days: 30,
+            sweep_account: ClientId(99),
+        }),
+        ..Policy::default()
+    });
+    for (id, client) in [(1, 1), (2, 1), (3, 3)] {
+        let deposit = Transaction::new(ClientId(client), num!(50), Operation::Deposit);
+        assert!(ledger
+            .apply_transaction(TransactionId(id), &deposit)
+            .is_ok());
+    }
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(2), &dispute).is_ok());
+    assert!(ledger.freeze(ClientId(3), FreezeReason::LegalOrder).is_ok());
+    ledger.set_time(Timestamp(35 * DAY));
+    let deposit = Transaction::new(ClientId(2), num!(10), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(4), &deposit).is_ok());
+
+    let swept = ledger.sweep_idle_accounts();
+    let sweep = Sweep {
+        id: 1,
+        client_id: ClientId(1),
+        sweep_account: ClientId(99),
+        timestamp: Timestamp(35 * DAY),
+        idle_days: 35,
+        amount: num!(50),
+    };
+    assert_eq!(swept, [sweep]);
+    assert_eq!(ledger.sweeps(), &vector![sweep]);
+    // the disputed deposit stays held
+    let account = ledger.account(ClientId(1)).unwrap();
+    assert_eq!(
+        (account.available(), account.held()),
+        (Number::ZERO, num!(50))
+    );
+    assert_eq!(ledger.account(ClientId(99)).unwrap().available(), num!(50));
+    assert_eq!(ledger.account(ClientId(3)).unwrap().available(), num!(50));
+    assert_eq!(
+        ledger.audit_log().skip(ledger.audit_log().len() - 2),
+        vector![
+            AuditEvent::BalanceSwept {
+                sweep: 1,
+                client_id: ClientId(1),
+                amount: num!(-50),
+            },
+            AuditEvent::BalanceSwept {
+                sweep: 1,
+                client_id: ClientId(99),
+                amount: num!(50),
+            },
+        ]
+    );
+    // nothing left to sweep
+    ledger.add_finalizer(Finalizer::SweepIdleAccounts);
+    assert_eq!(ledger.finalize().sweeps, []);
+}
+
+// ADMIN
+#[test]
+fn execute_commands() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    assert_eq!(
+        ledger.execute(LedgerCommand::ApplyTransaction(TransactionId(1), deposit)),
+        Ok(CommandOutput::Applied(Outcome::Applied {
+            new_available: num!(10),
+            new_held: num!(0)
+        }))
+    );
+    assert_eq!(
+        ledger.execute(LedgerCommand::Freeze(ClientId(1), FreezeReason::Compliance)),
+        Ok(CommandOutput::Done)
+    );
+    assert_eq!(
+        ledger.execute(LedgerCommand::CloseAccount(ClientId(2))),
+        Err(TransactionError::UnknownClientId(ClientId(2)))
+    );
+    assert_eq!(
+        ledger.execute(LedgerCommand::CloseAccount(ClientId(1))),
+        Ok(CommandOutput::Done)
+    );
+    let Ok(CommandOutput::Snapshot(snapshot)) = ledger.execute(LedgerCommand::Snapshot) else {
+        panic!("expected a snapshot");
+    };
+    let restored = Ledger::read_snapshot(snapshot.as_slice(), Policy::default()).unwrap();
+    assert_eq!(restored.account(ClientId(1)), ledger.account(ClientId(1)));
+    assert_eq!(
+        restored.freeze_reason(ClientId(1)),
+        Some(FreezeReason::Compliance)
+    );
+    assert_eq!(
+        ledger.execute(LedgerCommand::EraseClient(ClientId(1))),
+        Ok(CommandOutput::Erased(0))
+    );
+}
+
+#[test]
+fn freeze_is_not_a_chargeback_lock() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    let withdrawal = Transaction::new(ClientId(1), num!(1), Operation::Withdrawal);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert_eq!(
+        ledger.freeze(ClientId(2), FreezeReason::Sanctions),
+        Err(TransactionError::UnknownClientId(ClientId(2)))
+    );
+    assert!(ledger.freeze(ClientId(1), FreezeReason::Sanctions).is_ok());
+    assert_eq!(
+        ledger.freeze(ClientId(1), FreezeReason::Compliance),
+        Err(TransactionError::FrozenAccount(ClientId(1)))
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &withdrawal),
+        Err(TransactionError::FrozenAccount(ClientId(1)))
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &deposit),
+        Err(TransactionError::FrozenAccount(ClientId(1)))
+    );
+    // disputes still go through, and the account isn't locked
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert!(!ledger.account(ClientId(1)).unwrap().locked());
+    assert_eq!(
+        ledger.alerts(&AlertRules::default()),
+        [(ClientId(1), Alert::Frozen(FreezeReason::Sanctions))]
+    );
+
+    let mut snapshot = Vec::new();
+    ledger.write_snapshot(&mut snapshot).unwrap();
+    let mut restored = Ledger::read_snapshot(snapshot.as_slice(), Policy::default()).unwrap();
+    assert_eq!(
+        restored.freeze_reason(ClientId(1)),
+        Some(FreezeReason::Sanctions)
+    );
+
+    assert!(restored.unfreeze(ClientId(1)).is_ok());
+    assert!(!restored.is_frozen(ClientId(1)));
+    assert!(restored
+        .apply_transaction(TransactionId(3), &deposit)
+        .is_ok());
+    assert!(restored
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    assert_eq!(
+        restored.audit_log().last(),
+        Some(&AuditEvent::AccountUnfrozen(ClientId(1)))
+    );
+}
+
+#[test]
+fn unlock_charged_back_account() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    let withdrawal = Transaction::new(ClientId(1), num!(1), Operation::Withdrawal);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let chargeback = Transaction::new(ClientId(1), Number::ZERO, Operation::Chargeback);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(2), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &chargeback)
+        .is_ok());
+    assert_eq!(
+        ledger.execute(LedgerCommand::Unlock(ClientId(1))),
+        Err(TransactionError::UnlocksDisabled(ClientId(1)))
+    );
+    assert!(ledger.account(ClientId(1)).unwrap().locked());
+
+    ledger.set_policy(Policy {
+        allow_unlocks: true,
+        ..Policy::default()
+    });
+    assert_eq!(
+        ledger.unlock_account(ClientId(2)),
+        Err(TransactionError::UnknownClientId(ClientId(2)))
+    );
+    assert!(ledger.unlock_account(ClientId(1)).is_ok());
+    assert!(ledger.unlock_account(ClientId(1)).is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &withdrawal),
+        Ok(Outcome::Applied {
+            new_available: num!(9),
+            new_held: Number::ZERO,
+        })
+    );
+    assert_eq!(
+        ledger
+            .audit_log()
+            .iter()
+            .filter(|event| **event == AuditEvent::AccountUnlocked(ClientId(1)))
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn unlocks_are_journaled() {
+    let policy = Policy {
+        allow_unlocks: true,
+        ..Policy::default()
+    };
+    let mut ledger = Ledger::with_policy(policy);
+    ledger.enable_journal();
+    let mut events = ledger.event_stream();
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let chargeback = Transaction::new(ClientId(1), Number::ZERO, Operation::Chargeback);
+    let withdrawal = Transaction::new(ClientId(1), num!(1), Operation::Withdrawal);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(2), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &chargeback)
+        .is_ok());
+    assert!(ledger.unlock_account(ClientId(1)).is_ok());
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &withdrawal)
+        .is_ok());
+
+    let unlock = Transaction::new(ClientId(1), Number::ZERO, Operation::Unlock);
+    let journal = ledger.journal().unwrap();
+    assert_eq!(journal.entries()[4].transaction, unlock);
+    assert_eq!(journal.entries()[4].transaction_id, TransactionId(1));
+    let history = ledger
+        .stored_transaction(TransactionId(1))
+        .unwrap()
+        .history()
+        .to_vec();
+    assert_eq!(history.last().unwrap().operation, Operation::Unlock);
+    assert_eq!(history.last().unwrap().sequence, Some(5));
+    assert!(
+        std::iter::from_fn(|| events.try_next()).any(|event| matches!(
+            event,
+            LedgerEvent::Applied { transaction, account, .. }
+                if transaction == unlock && !account.locked()
+        ))
+    );
+
+    // Replaying the journal unlocks the account before the withdrawal.
+    let mut written = Vec::new();
+    journal.write_csv(&mut written).unwrap();
+    let recovered = Ledger::recover(None, &written[..], policy).unwrap();
+    assert_eq!(recovered.account(ClientId(1)), ledger.account(ClientId(1)));
+    assert_eq!(
+        ledger
+            .state_at(ReplayPoint::Sequence(6))
+            .unwrap()
+            .unwrap()
+            .account(ClientId(1)),
+        ledger.account(ClientId(1))
+    );
+
+    // Only charged back transactions unlock an account.
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &unlock),
+        Err(TransactionError::NotChargedBack(ClientId(1)))
+    );
+}
+
+#[test]
+fn archive_and_restore_account() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(10.0), Operation::Deposit);
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+    let deposit = Transaction::new(ClientId(2), num!(10.0), Operation::Deposit);
+    let _ = ledger.apply_transaction(TransactionId(2), &deposit);
+
+    assert_eq!(
+        ledger.archive_account(ClientId(3)),
+        Err(TransactionError::UnknownClientId(ClientId(3)))
+    );
+    assert_eq!(ledger.archive_account(ClientId(1)), Ok(()));
+    let withdrawal = Transaction::new(ClientId(1), num!(1.0), Operation::Withdrawal);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &withdrawal),
+        Err(TransactionError::ArchivedAccount(ClientId(1)))
+    );
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &dispute),
+        Err(TransactionError::ArchivedAccount(ClientId(1)))
+    );
+    let visible: Vec<ClientId> = ledger
+        .accounts(&AccountFilter::default())
+        .map(|(id, _)| id)
+        .collect();
+    assert_eq!(visible, vec![ClientId(2)]);
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(10.0));
+
+    assert_eq!(ledger.restore_account(ClientId(1)), Ok(()));
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &withdrawal)
+        .is_ok());
+    assert_eq!(ledger.archive_account(ClientId(2)), Ok(()));
+    let reported: Vec<ClientId> = ledger.into_iter().map(|(id, _)| id).collect();
+    assert_eq!(reported, vec![ClientId(1)]);
+}
+
+#[test]
+fn erase_client_keeps_aggregates() {
+    let mut ledger = Ledger::new();
+    let transactions: Vec<(TransactionId, Transaction)> = vec![
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), num!(10.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(2), num!(3.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(1), num!(4.0), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        ),
+    ];
+    process_transactions(&mut ledger, &transactions).for_each(|res| assert!(res.is_ok()));
+
+    assert_eq!(ledger.erase_client(ClientId(1)), Ok(0));
+    assert!(ledger.account(ClientId(1)).is_none());
+    assert_eq!(ledger.account(ClientId(2)).unwrap().available(), num!(3.0));
+    assert_eq!(ledger.transactions.len(), 1);
+    assert_eq!(
+        ledger.tombstones(),
+        &vector![Tombstone {
+            available: num!(-4.0),
+            held: num!(10.0),
+            locked: false,
+            transactions: 2,
+        }]
+    );
+    assert_eq!(
+        ledger.audit_log(),
+        &vector![AuditEvent::ClientErased {
+            tombstone: 0,
+            transactions: 2,
+        }]
+    );
+    let stats = ledger.stats();
+    assert_eq!(stats.erased_clients, 1);
+    assert_eq!(stats.available, num!(-1.0));
+    assert_eq!(stats.held, num!(10.0));
+
+    let deposit = Transaction::new(ClientId(1), num!(1.0), Operation::Deposit);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &deposit),
+        Err(TransactionError::RepeatedTransactionId(TransactionId(1)))
+    );
+    assert_eq!(
+        ledger.erase_client(ClientId(1)),
+        Err(TransactionError::UnknownClientId(ClientId(1)))
+    );
+}
+
+#[test]
+fn erase_client_leaves_no_trace() {
+    let mut ledger = Ledger::with_policy(Policy {
+        deposit_overflow: OverflowPolicy::Review,
+        ..Policy::default()
+    });
+    ledger.enable_journal();
+    ledger.enable_timestamps();
+    let deposit = Transaction::new(ClientId(1), Number::MAX, Operation::Deposit);
+    let top_up = Transaction::new(ClientId(1), Number::ONE, Operation::Deposit);
+    let other = Transaction::new(ClientId(2), num!(5), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(2), &other).is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &top_up),
+        Ok(Outcome::Queued)
+    );
+    assert_eq!(
+        ledger.deposit_escrow(EscrowId(1), ClientId(2), ClientId(1), num!(1)),
+        Ok(())
+    );
+    assert_eq!(
+        ledger.erase_client(ClientId(1)),
+        Err(TransactionError::OpenEscrow(ClientId(1)))
+    );
+    assert_eq!(ledger.refund_escrow(EscrowId(1)), Ok(()));
+    let last_sequence = ledger.journal().unwrap().last_sequence();
+
+    assert_eq!(ledger.erase_client(ClientId(1)), Ok(0));
+    let journal = ledger.journal().unwrap();
+    assert!(journal
+        .entries()
+        .iter()
+        .all(|entry| entry.transaction.client_id() != ClientId(1)));
+    assert_eq!(journal.last_sequence(), last_sequence);
+    assert_eq!(ledger.overflow_reviews().count(), 0);
+    assert_eq!(ledger.escrow(EscrowId(1)), None);
+    assert!(ledger
+        .transactions_between(Timestamp(0), Timestamp(u64::MAX))
+        .all(|(_, _, transaction)| transaction.client_id() != ClientId(1)));
+    assert_eq!(
+        ledger.timeline.as_ref().map(|timeline| timeline.len()),
+        Some(1)
+    );
+    assert_eq!(
+        ledger.audit_log().last(),
+        Some(&AuditEvent::ClientErased {
+            tombstone: 0,
+            transactions: 1,
+        })
+    );
+    // Erased review ids stay taken.
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &other),
+        Err(TransactionError::RepeatedTransactionId(TransactionId(3)))
+    );
+    let deposit = Transaction::new(ClientId(2), num!(1), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(4), &deposit).is_ok());
+    assert_eq!(ledger.journal().unwrap().last_sequence(), last_sequence + 1);
+}
+
+#[test]
+fn quarantine_holds_deposits() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    let withdrawal = Transaction::new(ClientId(1), num!(5), Operation::Withdrawal);
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+    assert_eq!(ledger.quarantine(ClientId(1)), Ok(()));
+    assert_eq!(
+        ledger.quarantine(ClientId(1)),
+        Err(TransactionError::QuarantinedAccount(ClientId(1)))
+    );
+    assert!(ledger.apply_transaction(TransactionId(2), &deposit).is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &withdrawal),
+        Err(TransactionError::QuarantinedAccount(ClientId(1)))
+    );
+    let account = ledger.account(ClientId(1)).unwrap();
+    assert_eq!(account.available(), num!(10));
+    assert_eq!(account.held(), num!(10));
+    assert!(!account.locked());
+    assert_eq!(ledger.quarantined_funds(ClientId(1)), num!(10));
+
+    assert_eq!(ledger.release_quarantine(ClientId(1)), Ok(num!(10)));
+    assert_eq!(ledger.release_quarantine(ClientId(1)), Ok(Number::ZERO));
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &withdrawal)
+        .is_ok());
+    let account = ledger.account(ClientId(1)).unwrap();
+    assert_eq!(account.available(), num!(15));
+    assert_eq!(account.held(), Number::ZERO);
+    assert_eq!(
+        ledger.audit_log(),
+        &vector![
+            AuditEvent::AccountQuarantined(ClientId(1)),
+            AuditEvent::QuarantineReleased {
+                client_id: ClientId(1),
+                released: num!(10)
+            }
+        ]
+    );
+}
+
+// SIGNATURES
+struct FirstByteIsClient;
+
+impl SignatureVerifier for FirstByteIsClient {
+    fn verify(&self, _: TransactionId, transaction: &Transaction, signature: &Signature) -> bool {
+        signature.0[0] as u16 == transaction.client_id().0
+    }
+}
+
+#[test]
+fn reject_invalid_signatures() {
+    let mut ledger = Ledger::new();
+    ledger.set_signature_verifier(FirstByteIsClient);
+    let deposit = Transaction::new(ClientId(1), num!(1.0), Operation::Deposit);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &deposit),
+        Err(TransactionError::InvalidSignature(TransactionId(1)))
+    );
+    let forged = deposit.with_signature(Signature([2; 32]));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &forged),
+        Err(TransactionError::InvalidSignature(TransactionId(1)))
+    );
+    let signed = deposit.with_signature(Signature([1; 32]));
+    assert!(ledger.apply_transaction(TransactionId(1), &signed).is_ok());
+    assert_eq!(
+        ledger.accounts.get(&ClientId(1)).unwrap().available(),
+        num!(1.0)
+    );
+}
+
+// SCREENING
+// Client 2 is on the watch list, amounts of 10000 or more are a pattern to
+// look at, and the provider is down for client 3.
+struct WatchList;
+
+impl ScreeningProvider for WatchList {
+    fn screen(&self, _: TransactionId, transaction: &Transaction) -> ScreeningOutcome {
+        match transaction.client_id().0 {
+            3 => ScreeningOutcome::Unavailable,
+            2 => ScreeningOutcome::Match,
+            _ if transaction.amount() >= num!(10000) => ScreeningOutcome::Match,
+            _ => ScreeningOutcome::Clear,
+        }
+    }
+}
+
+#[test]
+fn screen_deposits_and_withdrawals() {
+    let mut ledger = Ledger::new();
+    ledger.set_screening_provider(WatchList);
+    let deposit = |client, amount| Transaction::new(ClientId(client), amount, Operation::Deposit);
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &deposit(1, num!(50)))
+        .is_ok());
+    assert_eq!(
+        ledger.screening(TransactionId(1)),
+        Some(ScreeningOutcome::Clear)
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &deposit(1, num!(10000))),
+        Err(TransactionError::ScreeningMatch(TransactionId(2)))
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &deposit(2, num!(1))),
+        Err(TransactionError::ScreeningMatch(TransactionId(3)))
+    );
+    assert_eq!(
+        ledger.audit_log().last(),
+        Some(&AuditEvent::ScreeningRejected {
+            client_id: ClientId(2),
+            transaction_id: TransactionId(3),
+            outcome: ScreeningOutcome::Match,
+        })
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(4), &deposit(3, num!(1))),
+        Err(TransactionError::ScreeningUnavailable(TransactionId(4)))
+    );
+    // disputes aren't screened
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert_eq!(
+        ledger.screening(TransactionId(1)),
+        Some(ScreeningOutcome::Clear)
+    );
+
+    ledger.set_policy(Policy {
+        screening_fail_open: true,
+        ..Policy::default()
+    });
+    assert!(ledger
+        .apply_transaction(TransactionId(4), &deposit(3, num!(1)))
+        .is_ok());
+    assert_eq!(
+        ledger.screening(TransactionId(4)),
+        Some(ScreeningOutcome::Unavailable)
+    );
+    assert_eq!(ledger.account(ClientId(2)), None);
+}
+
+// POLICY
+#[test]
+fn reject_ids_outside_the_replay_window() {
+    let mut ledger = Ledger::with_policy(Policy {
+        replay_window: Some(10),
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(1), Operation::Deposit);
+    assert_eq!(ledger.stats().watermark, None);
+    for id in [5, 100, 90] {
+        assert!(ledger
+            .apply_transaction(TransactionId(id), &deposit)
+            .is_ok());
+    }
+    assert_eq!(ledger.stats().watermark, Some(TransactionId(100)));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(89), &deposit),
+        Err(TransactionError::OutsideReplayWindow(TransactionId(89)))
+    );
+    // resubmissions and disputes of stored transactions are still fine
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(5), &deposit),
+        Ok(Outcome::Deduplicated)
+    );
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(5), &dispute).is_ok());
+}
+
+#[test]
+fn dispute_quota() {
+    let mut ledger = Ledger::with_policy(Policy {
+        max_open_disputes: Some(2),
+        ..Policy::default()
+    });
+    for id in 1..=3 {
+        let deposit = Transaction::new(ClientId(1), num!(1.0), Operation::Deposit);
+        let _ = ledger.apply_transaction(TransactionId(id), &deposit);
+    }
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(2), &dispute).is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &dispute),
+        Err(TransactionError::DisputeQuotaExceeded(ClientId(1)))
+    );
+    assert_eq!(ledger.accounts.get(&ClientId(1)).unwrap().held(), num!(2.0));
+    assert_eq!(ledger.open_disputes(ClientId(1)), 2);
+
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    assert!(ledger.apply_transaction(TransactionId(1), &resolve).is_ok());
+    assert_eq!(ledger.open_disputes(ClientId(1)), 1);
+    assert!(ledger.apply_transaction(TransactionId(3), &dispute).is_ok());
+    assert_eq!(ledger.open_disputes(ClientId(1)), 2);
+}
+
+#[test]
+fn reload_policy_keeps_state() {
+    let mut ledger = Ledger::new();
+    for id in 1..=2 {
+        let deposit = Transaction::new(ClientId(1), num!(1.0), Operation::Deposit);
+        assert!(ledger
+            .apply_transaction(TransactionId(id), &deposit)
+            .is_ok());
+    }
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    ledger.set_policy(Policy {
+        max_open_disputes: Some(1),
+        ..Policy::default()
+    });
+    assert_eq!(ledger.policy().max_open_disputes, Some(1));
+    assert_eq!(ledger.open_disputes(ClientId(1)), 1);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &dispute),
+        Err(TransactionError::DisputeQuotaExceeded(ClientId(1)))
+    );
+    assert_eq!(ledger.account(ClientId(1)).unwrap().held(), num!(1.0));
+}
+
+#[test]
+fn overflowing_deposits_are_rejected_by_default() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), Number::MAX - Number::ONE, Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    let top_up = Transaction::new(ClientId(1), Number::ONE, Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(2), &top_up).is_ok());
+    assert_eq!(
+        ledger.account(ClientId(1)).unwrap().available(),
+        Number::MAX
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &top_up),
+        Err(TransactionError::AccountError(
+            ClientId(1),
+            AccountError::Overflow {
+                available: Number::MAX,
+                held: Number::ZERO,
+                transaction_amount: Number::ONE,
+            }
+        ))
+    );
+    assert!(ledger.audit_log().is_empty());
+}
+
+#[test]
+fn overflowing_deposits_saturate() {
+    let mut ledger = Ledger::with_policy(Policy {
+        deposit_overflow: OverflowPolicy::Saturate,
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    let huge = Transaction::new(ClientId(1), Number::MAX, Operation::Deposit);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &huge),
+        Ok(Outcome::Applied {
+            new_available: Number::MAX,
+            new_held: Number::ZERO,
+        })
+    );
+    assert_eq!(
+        ledger.audit_log(),
+        &vector![AuditEvent::DepositSaturated {
+            client_id: ClientId(1),
+            transaction_id: TransactionId(2),
+            excess: num!(10),
+        }]
+    );
+    // Only what was credited is held by a dispute.
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(2), &dispute).is_ok());
+    let account = ledger.account(ClientId(1)).unwrap();
+    assert_eq!(account.available(), num!(10));
+    assert_eq!(account.held(), Number::MAX - num!(10));
+}
+
+#[test]
+fn overflowing_deposits_go_to_review() {
+    let mut ledger = Ledger::with_policy(Policy {
+        deposit_overflow: OverflowPolicy::Review,
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), Number::MAX, Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    let top_up = Transaction::new(ClientId(1), Number::ONE, Operation::Deposit);
+    assert_eq!(ledger.validate(&top_up, TransactionId(2)), Ok(()));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &top_up),
+        Ok(Outcome::Queued)
+    );
+    assert_eq!(
+        ledger.account(ClientId(1)).unwrap().available(),
+        Number::MAX
+    );
+    assert_eq!(
+        ledger.overflow_reviews().collect::<Vec<_>>(),
+        [(TransactionId(2), &top_up)]
+    );
+    assert_eq!(
+        ledger.audit_log(),
+        &vector![AuditEvent::DepositHeldForReview {
+            client_id: ClientId(1),
+            transaction_id: TransactionId(2),
+        }]
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &top_up),
+        Err(TransactionError::RepeatedTransactionId(TransactionId(2)))
+    );
+    // Other errors are still reported.
+    let withdrawal = Transaction::new(ClientId(2), Number::ONE, Operation::Withdrawal);
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &withdrawal)
+        .is_err());
+    assert_eq!(
+        ledger.dismiss_overflow_review(TransactionId(2)),
+        Some(top_up)
+    );
+    assert_eq!(ledger.overflow_reviews().count(), 0);
+}
+
+#[test]
+fn strict_onboarding() {
+    let mut ledger = Ledger::with_policy(Policy {
+        require_open_account: true,
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &deposit),
+        Err(TransactionError::UnknownClientId(ClientId(1)))
+    );
+    assert!(ledger.account(ClientId(1)).is_none());
+    let open = Transaction::new(ClientId(1), Number::ZERO, Operation::OpenAccount);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &open),
+        Ok(Outcome::Applied {
+            new_available: Number::ZERO,
+            new_held: Number::ZERO,
+        })
+    );
+    assert!(ledger.is_open(ClientId(1)));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &open),
+        Err(TransactionError::AccountAlreadyOpen(ClientId(1)))
+    );
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    // Group members use their holder's account.
+    assert!(ledger.create_group(GroupId(1), ClientId(1)).is_ok());
+    assert!(ledger.add_member(GroupId(1), ClientId(2), None).is_ok());
+    let withdrawal = Transaction::new(ClientId(2), num!(1), Operation::Withdrawal);
+    assert!(ledger
+        .apply_transaction(TransactionId(4), &withdrawal)
+        .is_ok());
+
+    let mut snapshot = Vec::new();
+    ledger.write_snapshot(&mut snapshot).unwrap();
+    let restored = Ledger::read_snapshot(snapshot.as_slice(), *ledger.policy()).unwrap();
+    assert!(restored.is_open(ClientId(1)));
+
+    // Without the policy an opening is optional.
+    let mut lenient = Ledger::new();
+    assert!(lenient
+        .apply_transaction(TransactionId(1), &deposit)
+        .is_ok());
+    assert!(!lenient.is_open(ClientId(1)));
+    assert!(lenient.apply_transaction(TransactionId(2), &open).is_ok());
+    assert_eq!(lenient.account(ClientId(1)).unwrap().available(), num!(5));
+}
+
+#[test]
+fn open_account_with_settings() {
+    let mut ledger = Ledger::new();
+    ledger.enable_journal();
+    let settings = AccountSettings {
+        currency: Some("EUR".parse().unwrap()),
+        tier: Some(Tier::Premium),
+        overdraft_limit: Some(num!(10)),
+    };
+    let invalid = Transaction::new(ClientId(1), Number::ZERO, Operation::OpenAccount)
+        .with_settings(AccountSettings {
+            overdraft_limit: Some(num!(-1)),
+            ..settings
+        });
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &invalid),
+        Err(TransactionError::InvalidAmount(TransactionId(1), num!(-1)))
+    );
+    let open =
+        Transaction::new(ClientId(1), Number::ZERO, Operation::OpenAccount).with_settings(settings);
+    assert!(ledger.apply_transaction(TransactionId(1), &open).is_ok());
+    assert_eq!(ledger.account_settings(ClientId(1)), Some(&settings));
+    assert_eq!(ledger.tier(ClientId(1)), Tier::Premium);
+
+    let withdrawal = Transaction::new(ClientId(1), num!(6), Operation::Withdrawal);
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &withdrawal),
+        Err(TransactionError::AccountError(
+            ClientId(1),
+            AccountError::Underflow {
+                available: num!(-6),
+                held: Number::ZERO,
+                transaction_amount: num!(6),
+            }
+        ))
+    );
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(-6));
+
+    let mut snapshot = Vec::new();
+    ledger.write_snapshot(&mut snapshot).unwrap();
+    let restored = Ledger::read_snapshot(snapshot.as_slice(), Policy::default()).unwrap();
+    assert_eq!(restored.account_settings(ClientId(1)), Some(&settings));
+    assert_eq!(restored.tier(ClientId(1)), Tier::Premium);
+    let mut journal = Vec::new();
+    ledger.journal().unwrap().write_csv(&mut journal).unwrap();
+    let replayed = Ledger::recover(None, journal.as_slice(), Policy::default()).unwrap();
+    assert_eq!(replayed.account_settings(ClientId(1)), Some(&settings));
+    assert_eq!(replayed.account(ClientId(1)).unwrap().available(), num!(-6));
+}
+
+#[test]
+fn retention_cap_archives_oldest_deposits() {
+    let mut ledger = Ledger::with_policy(Policy {
+        max_transactions_per_client: Some(2),
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(1.0), Operation::Deposit);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(2), &deposit).is_ok());
+    assert!(ledger.apply_transaction(TransactionId(3), &deposit).is_ok());
+    // the open dispute is kept, so the next oldest deposit goes
+    assert!(!ledger.is_transaction_archived(TransactionId(1)));
+    assert!(ledger.is_transaction_archived(TransactionId(2)));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &dispute),
+        Err(TransactionError::ArchivedTransaction(TransactionId(2)))
+    );
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &deposit),
+        Err(TransactionError::RepeatedTransactionId(TransactionId(2)))
+    );
+    // withdrawals can't be disputed and don't count towards the cap
+    let withdrawal = Transaction::new(ClientId(1), num!(1.0), Operation::Withdrawal);
+    assert!(ledger
+        .apply_transaction(TransactionId(4), &withdrawal)
+        .is_ok());
+    assert!(!ledger.is_transaction_archived(TransactionId(3)));
+    assert_eq!(ledger.account(ClientId(1)).unwrap().total(), num!(2.0));
+
+    assert_eq!(ledger.erase_client(ClientId(1)), Ok(0));
+    assert!(!ledger.is_transaction_archived(TransactionId(2)));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &deposit),
+        Err(TransactionError::RepeatedTransactionId(TransactionId(2)))
+    );
+}
+
+#[test]
+fn tier_limits() {
+    let mut ledger = Ledger::with_policy(Policy {
+        basic: TierLimits {
+            max_transaction: Some(num!(100)),
+            max_daily_volume: Some(num!(150)),
+            max_balance: Some(num!(120)),
+            ..TierLimits::default()
+        },
+        ..Policy::default()
+    });
+    let deposit = |amount| Transaction::new(ClientId(1), amount, Operation::Deposit);
+    let withdrawal = |amount| Transaction::new(ClientId(1), amount, Operation::Withdrawal);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &deposit(num!(101))),
+        Err(TransactionError::TierLimitExceeded(
+            ClientId(1),
+            TierLimit::TransactionAmount
+        ))
+    );
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &deposit(num!(100)))
+        .is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &deposit(num!(30))),
+        Err(TransactionError::TierLimitExceeded(
+            ClientId(1),
+            TierLimit::Balance
+        ))
+    );
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &withdrawal(num!(40)))
+        .is_ok());
+    assert_eq!(ledger.daily_volume(ClientId(1)), num!(140));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &deposit(num!(20))),
+        Err(TransactionError::TierLimitExceeded(
+            ClientId(1),
+            TierLimit::DailyVolume
+        ))
+    );
+
+    // the daily volume starts over on the next day
+    ledger.set_time(Timestamp(24 * 60 * 60));
+    assert_eq!(ledger.daily_volume(ClientId(1)), Number::ZERO);
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &deposit(num!(20)))
+        .is_ok());
+
+    // premium clients have no limits configured
+    ledger.set_tier(ClientId(1), Tier::Premium);
+    assert_eq!(ledger.tier(ClientId(1)), Tier::Premium);
+    assert!(ledger
+        .apply_transaction(TransactionId(4), &deposit(num!(1000)))
+        .is_ok());
+}
+
+#[test]
+fn volume_past_the_largest_amount() {
+    let mut ledger = Ledger::with_policy(Policy {
+        basic: TierLimits {
+            max_daily_volume: Some(Number::MAX),
+            ..TierLimits::default()
+        },
+        ..Policy::default()
+    });
+    let half = Number::MAX / num!(2) + Number::ONE;
+    let deposit = Transaction::new(ClientId(1), half, Operation::Deposit);
+    let withdrawal = Transaction::new(ClientId(1), half, Operation::Withdrawal);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    assert_eq!(ledger.daily_volume(ClientId(1)), Number::MAX);
+    assert!(ledger.apply_transaction(TransactionId(3), &deposit).is_ok());
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), half);
+}
+
+#[test]
+fn rolling_withdrawal_limit() {
+    let mut ledger = Ledger::with_policy(Policy {
+        basic: TierLimits {
+            max_rolling_withdrawals: Some(num!(50)),
+            ..TierLimits::default()
+        },
+        ..Policy::default()
+    });
+    let deposit = Transaction::new(ClientId(1), num!(1000), Operation::Deposit);
+    let withdrawal = Transaction::new(ClientId(1), num!(30), Operation::Withdrawal);
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+    ledger.set_time(Timestamp(20 * 60 * 60));
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    // a new calendar day does not reset the rolling window
+    ledger.set_time(Timestamp(25 * 60 * 60));
+    assert_eq!(ledger.rolling_withdrawals(ClientId(1)), num!(30));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &withdrawal),
+        Err(TransactionError::TierLimitExceeded(
+            ClientId(1),
+            TierLimit::RollingWithdrawals
+        ))
+    );
+    ledger.advance_time(20 * 60 * 60);
+    assert_eq!(ledger.rolling_withdrawals(ClientId(1)), Number::ZERO);
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &withdrawal)
+        .is_ok());
+}
+
+#[test]
+fn adjustments_require_policy() {
+    let adjustment = Transaction::new(ClientId(1), num!(-2.5), Operation::Adjustment);
+    let mut ledger = Ledger::new();
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &adjustment),
+        Err(TransactionError::AdjustmentsDisabled(TransactionId(1)))
+    );
+
+    ledger.set_policy(Policy {
+        allow_adjustments: true,
+        basic: TierLimits {
+            max_daily_volume: Some(num!(10)),
+            ..TierLimits::default()
+        },
+        ..Policy::default()
+    });
+    let _ = ledger.apply_transaction(
+        TransactionId(1),
+        &Transaction::new(ClientId(1), num!(10), Operation::Deposit),
+    );
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &adjustment)
+        .is_ok());
+    assert!(ledger
+        .apply_transaction(
+            TransactionId(3),
+            &Transaction::new(ClientId(1), num!(20), Operation::Adjustment),
+        )
+        .is_ok());
+    assert!(matches!(
+        ledger.apply_transaction(
+            TransactionId(4),
+            &Transaction::new(ClientId(1), num!(-30), Operation::Adjustment),
+        ),
+        Err(TransactionError::AccountError(
+            ClientId(1),
+            AccountError::Underflow { .. }
+        ))
+    ));
+    assert_eq!(
+        ledger.apply_transaction(
+            TransactionId(2),
+            &Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        ),
+        Err(TransactionError::AlreadyDisputed(TransactionId(2)))
+    );
+    let account = ledger.accounts.get(&ClientId(1)).unwrap();
+    assert_eq!(account.available(), num!(27.5));
+    assert_eq!(ledger.daily_volume(ClientId(1)), num!(10));
+}
+
+#[test]
+fn dormant_accounts_need_reactivation() {
+    let mut ledger = Ledger::with_policy(Policy {
+        dormant_after_days: Some(30),
+        ..Policy::default()
+    });
+    let deposit = |amount| Transaction::new(ClientId(1), amount, Operation::Deposit);
+    let withdrawal = Transaction::new(ClientId(1), num!(1), Operation::Withdrawal);
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit(num!(10)));
+    ledger.set_time(Timestamp(10));
+    let _ = ledger.apply_transaction(
+        TransactionId(2),
+        &Transaction::new(ClientId(2), num!(10), Operation::Deposit),
+    );
+    assert_eq!(
+        ledger.dormant_accounts(Timestamp(11)),
+        vec![(ClientId(1), Timestamp(0)), (ClientId(2), Timestamp(10))]
+    );
+    assert_eq!(ledger.dormant_accounts(Timestamp(10)).len(), 1);
+
+    ledger.advance_time(31 * 24 * 60 * 60);
+    assert!(ledger
+        .apply_transaction(TransactionId(3), &deposit(num!(1)))
+        .is_ok());
+    assert!(ledger.is_dormant(ClientId(1)));
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(4), &withdrawal),
+        Err(TransactionError::DormantAccount(ClientId(1)))
+    );
+    assert_eq!(ledger.reactivate(ClientId(1)), Ok(()));
+    assert!(ledger
+        .apply_transaction(TransactionId(4), &withdrawal)
+        .is_ok());
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(10));
+    assert!(ledger.is_dormant(ClientId(2)));
+}
+
+#[test]
+fn amount_range_per_operation() {
+    let mut ledger = Ledger::with_policy(Policy {
+        allow_adjustments: true,
+        withdrawal_amounts: AmountRange {
+            min: Some(num!(0.01)),
+            max: Some(num!(10000)),
+        },
+        adjustment_amounts: AmountRange {
+            min: None,
+            max: Some(num!(5)),
+        },
+        ..Policy::default()
+    });
+    let transaction = |amount, operation| Transaction::new(ClientId(1), amount, operation);
+    assert!(ledger
+        .apply_transaction(
+            TransactionId(1),
+            &transaction(num!(20000), Operation::Deposit)
+        )
+        .is_ok());
+    assert_eq!(
+        ledger.apply_transaction(
+            TransactionId(2),
+            &transaction(num!(0.001), Operation::Withdrawal)
+        ),
+        Err(TransactionError::AmountBelowMinimum(
+            TransactionId(2),
+            num!(0.001)
+        ))
+    );
+    assert_eq!(
+        ledger.apply_transaction(
+            TransactionId(2),
+            &transaction(num!(10000.01), Operation::Withdrawal)
+        ),
+        Err(TransactionError::AmountAboveMaximum(
+            TransactionId(2),
+            num!(10000.01)
+        ))
+    );
+    assert_eq!(
+        ledger.apply_transaction(
+            TransactionId(2),
+            &transaction(num!(-6), Operation::Adjustment)
+        ),
+        Err(TransactionError::AmountAboveMaximum(
+            TransactionId(2),
+            num!(-6)
+        ))
+    );
+    assert!(ledger
+        .apply_transaction(
+            TransactionId(2),
+            &transaction(num!(10000), Operation::Withdrawal)
+        )
+        .is_ok());
+    assert!(ledger
+        .apply_transaction(
+            TransactionId(1),
+            &transaction(Number::ZERO, Operation::Dispute)
+        )
+        .is_ok());
+}
+
+#[test]
+fn chargebacks_exceeding_held_funds() {
+    // 10 disputed, of which only 6 are still held
+    let disputed = |excess| {
+        let mut ledger = Ledger::with_policy(Policy {
+            chargeback_excess: excess,
+            ..Policy::default()
+        });
+        let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+        let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+        assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+        assert!(ledger.apply_transaction(TransactionId(1), &dispute).is_ok());
+        ledger
+            .accounts
+            .insert(ClientId(1), Account::from_parts(num!(4), num!(6), false));
+        ledger
+    };
+    let chargeback = Transaction::new(ClientId(1), Number::ZERO, Operation::Chargeback);
+
+    let mut ledger = disputed(ChargebackExcess::Allow);
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &chargeback)
+        .is_ok());
+    assert_eq!(ledger.account(ClientId(1)).unwrap().held(), num!(-4));
+    assert_eq!(
+        ledger.alerts(&AlertRules::default()),
+        [
+            (ClientId(1), Alert::NegativeHeld(num!(-4))),
+            (ClientId(1), Alert::Locked)
+        ]
+    );
+
+    let mut ledger = disputed(ChargebackExcess::Clamp);
+    assert!(ledger
+        .apply_transaction(TransactionId(1), &chargeback)
+        .is_ok());
+    assert_eq!(ledger.account(ClientId(1)).unwrap().held(), Number::ZERO);
+    assert_eq!(ledger.running_totals().charged_back, num!(6));
+    assert!(ledger.audit_log().contains(&AuditEvent::ChargebackClamped {
+        client_id: ClientId(1),
+        transaction_id: TransactionId(1),
+        excess: num!(4),
+    }));
+
+    let mut ledger = disputed(ChargebackExcess::Reject);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &chargeback),
+        Err(TransactionError::AccountError(
+            ClientId(1),
+            AccountError::Underflow {
+                available: num!(4),
+                held: num!(6),
+                transaction_amount: num!(10),
+            }
+        ))
+    );
+    assert!(!ledger.account(ClientId(1)).unwrap().locked());
+}
+
+// MIDDLEWARE
+#[test]
+fn middleware_chain() {
+    use crate::ledger::TransactionMiddleware;
+    use std::sync::{Arc, Mutex};
+
+    // blocks client 9, rounds amounts down to whole units and records results
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(TransactionId, bool)>>);
+
+    impl TransactionMiddleware for Recorder {
+        fn authorize(
+            &self,
+            _ledger: &Ledger,
+            _transaction_id: TransactionId,
+            transaction: &Transaction,
+        ) -> TransactionResult {
+            match transaction.client_id() {
+                ClientId(9) => Err(TransactionError::UnknownClientId(ClientId(9))),
+                _ => Ok(()),
+            }
+        }
+
+        fn enrich(
+            &self,
+            _ledger: &Ledger,
+            _transaction_id: TransactionId,
+            transaction: &mut Transaction,
+        ) {
+            *transaction = Transaction::new(
+                transaction.client_id(),
+                transaction.amount().floor(),
+                transaction.operation(),
+            );
+        }
+
+        fn notify(
+            &self,
+            _ledger: &Ledger,
+            transaction_id: TransactionId,
+            _transaction: &Transaction,
+            result: &TransactionResult<Outcome>,
+        ) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((transaction_id, result.is_ok()));
+        }
+    }
+
+    let recorder = Arc::new(Recorder::default());
+    let mut ledger = Ledger::new();
+    ledger.add_middleware(recorder.clone());
+    let deposit = Transaction::new(ClientId(1), num!(2.75), Operation::Deposit);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(1), &deposit),
+        Ok(Outcome::Applied {
+            new_available: num!(2),
+            new_held: Number::ZERO,
+        })
+    );
+    let blocked = Transaction::new(ClientId(9), num!(1), Operation::Deposit);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &blocked),
+        Err(TransactionError::UnknownClientId(ClientId(9)))
+    );
+    assert!(ledger.account(ClientId(9)).is_none());
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        vec![(TransactionId(1), true), (TransactionId(2), false)]
+    );
+}
+
+#[test]
+fn review_flagged_transactions() {
+    use crate::ledger::TransactionMiddleware;
+    use std::sync::Arc;
+
+    // flags withdrawals of 100 or more
+    struct LargeWithdrawals;
+
+    impl TransactionMiddleware for LargeWithdrawals {
+        fn flag(
+            &self,
+            _ledger: &Ledger,
+            _transaction_id: TransactionId,
+            transaction: &Transaction,
+        ) -> bool {
+            transaction.operation() == Operation::Withdrawal && transaction.amount() >= num!(100)
+        }
+    }
+
+    let mut ledger = Ledger::new();
+    ledger.add_middleware(Arc::new(LargeWithdrawals));
+    let deposit = Transaction::new(ClientId(1), num!(150), Operation::Deposit);
+    let large = Transaction::new(ClientId(1), num!(120), Operation::Withdrawal);
+    let small = Transaction::new(ClientId(1), num!(40), Operation::Withdrawal);
+    let other = Transaction::new(ClientId(2), num!(5), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(2), &large),
+        Ok(Outcome::Queued)
+    );
+    // waits behind the flagged withdrawal, other clients don't
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(3), &small),
+        Ok(Outcome::Queued)
+    );
+    assert!(matches!(
+        ledger.apply_transaction(TransactionId(4), &other),
+        Ok(Outcome::Applied { .. })
+    ));
+    assert_eq!(
+        ledger
+            .reviews()
+            .map(|review| (review.transaction_id, review.flagged))
+            .collect::<Vec<_>>(),
+        [(TransactionId(2), true), (TransactionId(3), false)]
+    );
+    assert_eq!(
+        ledger.approve_review(TransactionId(3)),
+        Err(TransactionError::NotUnderReview(TransactionId(3)))
+    );
+
+    let released = ledger.approve_review(TransactionId(2)).unwrap();
+    assert_eq!(released.len(), 2);
+    assert!(released[0].1.is_ok());
+    // the large withdrawal went first, leaving too little for the small one
+    assert!(matches!(
+        released[1].1,
+        Err(TransactionError::AccountError(
+            _,
+            AccountError::Underflow { .. }
+        ))
+    ));
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(30));
+    assert_eq!(ledger.reviews().count(), 0);
+
+    let large = Transaction::new(ClientId(1), num!(100), Operation::Withdrawal);
+    assert_eq!(
+        ledger.apply_transaction(TransactionId(5), &large),
+        Ok(Outcome::Queued)
+    );
+    assert_eq!(ledger.reject_review(TransactionId(5)), Ok(vec![]));
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(30));
+    assert!(ledger.audit_log().contains(&AuditEvent::ReviewRejected {
+        client_id: ClientId(1),
+        transaction_id: TransactionId(5),
+    }));
+}
+
+// JOURNAL
+#[test]
+fn time_travel() {
+    let mut ledger = Ledger::new();
+    ledger.enable_journal();
+    let transactions: Vec<(TransactionId, Transaction)> = vec![
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), num!(10.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), num!(20.0), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), num!(4.0), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        ),
+    ];
+    for (i, (id, transaction)) in transactions.iter().enumerate() {
+        ledger.set_time(Timestamp(i as u64 * 100));
+        let _ = ledger.apply_transaction(*id, transaction);
+    }
+    // the rejected withdrawal is not journaled
+    let journal = ledger.journal().unwrap();
+    assert_eq!(journal.last_sequence(), 3);
+
+    let before = ledger.state_at(ReplayPoint::Sequence(1)).unwrap().unwrap();
+    assert_eq!(before.account(ClientId(1)).unwrap().available(), num!(10.0));
+    let before = ledger
+        .state_at(ReplayPoint::Time(Timestamp(250)))
+        .unwrap()
+        .unwrap();
+    assert_eq!(before.account(ClientId(1)).unwrap().available(), num!(6.0));
+    assert_eq!(before.account(ClientId(1)).unwrap().held(), Number::ZERO);
+    let now = ledger.state_at(ReplayPoint::Sequence(3)).unwrap().unwrap();
+    assert_eq!(now.account(ClientId(1)), ledger.account(ClientId(1)));
+
+    let mut file = Vec::new();
+    journal.write_csv(&mut file).unwrap();
+    assert_eq!(Journal::read_csv(file.as_slice()).unwrap(), *journal);
+
+    let (contents, manifest) = journal.export("journal.csv", b"key").unwrap();
+    assert_eq!(
+        Journal::import("journal.csv", &contents, &manifest, b"key").as_ref(),
+        Ok(journal)
+    );
+    assert_eq!(
+        Journal::import(
+            "journal.csv",
+            &contents[..contents.len() - 1],
+            &manifest,
+            b"key"
+        ),
+        Err(ManifestError::ChecksumMismatch("journal.csv".to_string()))
+    );
+}
+
+#[test]
+fn journals_keep_signatures() {
+    let mut ledger = Ledger::new();
+    ledger.set_signature_verifier(FirstByteIsClient);
+    ledger.enable_journal();
+    let deposit = Transaction::new(ClientId(1), num!(1.0), Operation::Deposit)
+        .with_signature(Signature([1; 32]));
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    let journal = ledger.journal().unwrap();
+    let mut file = Vec::new();
+    journal.write_csv(&mut file).unwrap();
+    let read = Journal::read_csv(file.as_slice()).unwrap();
+    assert_eq!(read, *journal);
+    assert_eq!(
+        read.entries()[0].transaction.signature(),
+        Some(&Signature([1; 32]))
+    );
+
+    let forged = "sequence,timestamp,type,client,tx,amount,signature\n1,0,deposit,1,1,1.0,zz\n";
+    let err = Journal::read_csv(forged.as_bytes()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn replay_errors_are_reported() {
+    let journal = "sequence,timestamp,type,client,tx,amount\n\
+        1,0,deposit,1,1,1.0\n\
+        2,0,withdrawal,1,2,5.0\n";
+    let err = Ledger::recover(None, journal.as_bytes(), Policy::default()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let journal = Journal::read_csv(journal.as_bytes()).unwrap();
+    assert!(journal
+        .replay_until(ReplayPoint::Sequence(1), Policy::default())
+        .is_ok());
+    assert!(journal
+        .replay_until(ReplayPoint::Sequence(2), Policy::default())
+        .is_err());
+}
+
+// EVENTS
+#[test]
+fn event_stream_broadcasts() {
+    use std::task::{Context, Poll, Waker};
+
+    let mut ledger = Ledger::new();
+    let mut first = ledger.event_stream();
+    let mut second = ledger.event_stream_with_capacity(1);
+    let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+    let _ = ledger.archive_account(ClientId(1));
+
+    let applied = LedgerEvent::Applied {
+        transaction_id: TransactionId(1),
+        transaction: deposit,
+        account: ledger.account(ClientId(1)).unwrap(),
+    };
+    assert_eq!(first.try_next(), Some(applied));
+    assert_eq!(
+        first.try_next(),
+        Some(LedgerEvent::Audit(AuditEvent::AccountArchived(ClientId(1))))
+    );
+    assert_eq!(first.try_next(), None);
+    assert_eq!(second.lagged(), 1);
+    assert_eq!(
+        second.try_next(),
+        Some(LedgerEvent::Audit(AuditEvent::AccountArchived(ClientId(1))))
+    );
+
+    let mut cx = Context::from_waker(Waker::noop());
+    assert_eq!(first.poll_next(&mut cx), Poll::Pending);
+    drop(ledger);
+    assert_eq!(first.poll_next(&mut cx), Poll::Ready(None));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn event_streams_are_futures_streams() {
+    use futures::StreamExt;
+
+    let mut ledger = Ledger::new();
+    let mut stream = ledger.event_stream();
+    let consumer = tokio::spawn(async move {
+        let mut events = Vec::new();
+        while let Some(event) = StreamExt::next(&mut stream).await {
+            events.push(event);
+        }
+        events
+    });
+    // the consumer is waiting on an empty queue before anything is applied
+    tokio::task::yield_now().await;
+    let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+    let _ = ledger.apply_transaction(TransactionId(1), &deposit);
+    let _ = ledger.archive_account(ClientId(1));
+    let applied = LedgerEvent::Applied {
+        transaction_id: TransactionId(1),
+        transaction: deposit,
+        account: ledger.account(ClientId(1)).unwrap(),
+    };
+    drop(ledger);
+    assert_eq!(
+        consumer.await.unwrap(),
+        [
+            applied,
+            LedgerEvent::Audit(AuditEvent::AccountArchived(ClientId(1)))
+        ]
+    );
+}
+
+#[test]
+fn filtered_event_streams() {
+    use std::time::Duration;
+
+    let mut ledger = Ledger::new();
+    let mut crossings = ledger.filtered_event_stream(EventFilter {
+        threshold: Some(num!(100)),
+        ..Default::default()
+    });
+    let mut debounced = ledger.filtered_event_stream(EventFilter {
+        debounce: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    });
+    let rows = [
+        (1, 1, num!(50), Operation::Deposit),
+        (2, 1, num!(60), Operation::Deposit),
+        (3, 1, num!(10), Operation::Deposit),
+        (4, 1, num!(20), Operation::Withdrawal),
+        (5, 1, num!(1), Operation::Withdrawal),
+        (6, 2, num!(5), Operation::Deposit),
+    ];
+    for (id, client, amount, operation) in rows {
+        let transaction = Transaction::new(ClientId(client), amount, operation);
+        ledger
+            .apply_transaction(TransactionId(id), &transaction)
+            .unwrap();
+    }
+    let _ = ledger.archive_account(ClientId(2));
+
+    let ids = |stream: &mut EventStream| {
+        std::iter::from_fn(|| stream.try_next())
+            .map(|event| match event {
+                LedgerEvent::Applied { transaction_id, .. } => Some(transaction_id.0),
+                LedgerEvent::Audit(_) => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&mut crossings), [Some(2), Some(5), None]);
+    assert_eq!(ids(&mut debounced), [Some(1), Some(6), None]);
+}
+
+#[test]
+fn debounced_crossings_are_not_lost() {
+    use std::time::Duration;
+
+    let mut ledger = Ledger::new();
+    let mut events = ledger.filtered_event_stream(EventFilter {
+        threshold: Some(num!(100)),
+        debounce: Some(Duration::from_millis(20)),
+    });
+    let mut apply = |id, amount, operation| {
+        let transaction = Transaction::new(ClientId(1), amount, operation);
+        ledger
+            .apply_transaction(TransactionId(id), &transaction)
+            .unwrap();
+    };
+    apply(1, num!(150), Operation::Deposit);
+    // crosses back within the interval, so it's held off
+    apply(2, num!(100), Operation::Withdrawal);
+    std::thread::sleep(Duration::from_millis(30));
+    // still below the threshold, where the last delivered event wasn't
+    apply(3, num!(1), Operation::Deposit);
+    apply(4, num!(1), Operation::Deposit);
+
+    let ids: Vec<_> = std::iter::from_fn(|| events.try_next())
+        .filter_map(|event| match event {
+            LedgerEvent::Applied { transaction_id, .. } => Some(transaction_id.0),
+            LedgerEvent::Audit(_) => None,
+        })
+        .collect();
+    assert_eq!(ids, [1, 3]);
+}
+
+// STORE
+#[test]
+fn in_memory_stores_are_async() {
+    use std::collections::BTreeMap;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("in-memory store future was pending"),
+        }
+    }
+
+    let mut accounts = AccountTable::new();
+    let mut account = Account::default();
+    account.deposit(num!(2)).unwrap();
+    ready(AsyncAccountStore::put_account(
+        &mut accounts,
+        ClientId(3),
+        account,
+    ));
+    assert_eq!(
+        AccountStore::get_account(&accounts, ClientId(3)),
+        Some(account)
+    );
+    assert_eq!(
+        ready(AsyncAccountStore::remove_account(
+            &mut accounts,
+            ClientId(3)
+        )),
+        Some(account)
+    );
+    assert_eq!(
+        ready(AsyncAccountStore::get_account(&accounts, ClientId(3))),
+        None
+    );
+
+    let mut transactions = BTreeMap::new();
+    let deposit = Transaction::new(ClientId(3), num!(2), Operation::Deposit);
+    TransactionStore::put_transaction(&mut transactions, TransactionId(1), deposit);
+    assert_eq!(
+        ready(AsyncTransactionStore::get_transaction(
+            &transactions,
+            TransactionId(1)
+        )),
+        Some(deposit)
+    );
+}
+
+#[test]
+fn batched_store_coalesces_writes() {
+    use std::collections::BTreeMap;
+
+    let mut ledger = Ledger::new();
+    ledger.enable_journal();
+    let mut store = BatchedStore::new(AccountTable::new(), BTreeMap::new(), 4);
+    let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(ledger
+        .apply_batched(TransactionId(1), &deposit, &mut store)
+        .is_ok());
+    assert!(ledger
+        .apply_batched(TransactionId(1), &dispute, &mut store)
+        .is_ok());
+    // both writes hit the same account and transaction
+    assert_eq!(store.pending(), 2);
+    assert_eq!(store.flushes(), 0);
+    assert_eq!(
+        AccountStore::get_account(&store, ClientId(1))
+            .unwrap()
+            .held(),
+        num!(5)
+    );
+    let journal = ledger.journal().unwrap();
+    assert_eq!(store.unflushed(journal).len(), 2);
+
+    let withdrawal = Transaction::new(ClientId(2), num!(1), Operation::Withdrawal);
+    assert!(ledger
+        .apply_batched(TransactionId(2), &withdrawal, &mut store)
+        .is_err());
+    let deposit = Transaction::new(ClientId(2), num!(1), Operation::Deposit);
+    assert!(ledger
+        .apply_batched(TransactionId(2), &deposit, &mut store)
+        .is_ok());
+    assert_eq!(store.flushes(), 1);
+    assert_eq!(store.pending(), 0);
+    assert_eq!(store.flushed_sequence(), 3);
+    assert!(store.unflushed(ledger.journal().unwrap()).is_empty());
+
+    let (accounts, transactions) = store.into_inner();
+    assert_eq!(accounts.get(&ClientId(1)), ledger.account(ClientId(1)));
+    assert_eq!(
+        transactions.get(&TransactionId(1)).unwrap().state(),
+        TransactionState::Disputed
+    );
+}
+
+// MERGE
+#[test]
+fn merge_conflict_strategies() {
+    let build = |amount, tx| {
+        let mut ledger = Ledger::new();
+        let deposit = Transaction::new(ClientId(1), amount, Operation::Deposit);
+        assert!(ledger
+            .apply_transaction(TransactionId(tx), &deposit)
+            .is_ok());
+        let other = Transaction::new(ClientId(tx as u16 + 1), amount, Operation::Deposit);
+        assert!(ledger
+            .apply_transaction(TransactionId(tx + 10), &other)
+            .is_ok());
+        ledger
+    };
+    let (ours, theirs) = (build(num!(5), 1), build(num!(3), 2));
+    let expected = MergeReport {
+        conflicts: vec![MergeConflict {
+            client_id: ClientId(1),
+            ours: ours.account(ClientId(1)).unwrap(),
+            theirs: theirs.account(ClientId(1)).unwrap(),
+        }],
+        conflicting_transactions: vec![],
+    };
+
+    let mut rejected = ours.clone();
+    assert_eq!(
+        rejected.merge_with(theirs.clone(), MergeStrategy::Reject),
+        Err(MergeError::Conflicts(expected.clone()))
+    );
+    assert_eq!(rejected, ours);
+
+    let available = |strategy| {
+        let mut merged = ours.clone();
+        assert_eq!(
+            merged.merge_with(theirs.clone(), strategy),
+            Ok(expected.clone())
+        );
+        // clients on one side only are always taken
+        assert_eq!(merged.account(ClientId(3)).unwrap().available(), num!(3));
+        merged.account(ClientId(1)).unwrap().available()
+    };
+    assert_eq!(available(MergeStrategy::Sum), num!(8));
+    assert_eq!(available(MergeStrategy::PreferOurs), num!(5));
+    assert_eq!(available(MergeStrategy::PreferTheirs), num!(3));
+
+    // The same id used for different transactions is reported too.
+    let mut clashing = Ledger::new();
+    let deposit = Transaction::new(ClientId(9), num!(1), Operation::Deposit);
+    assert!(clashing
+        .apply_transaction(TransactionId(1), &deposit)
+        .is_ok());
+    let mut merged = ours.clone();
+    let report = merged.merge_with(clashing, MergeStrategy::Sum).unwrap();
+    assert_eq!(report.conflicting_transactions, [TransactionId(1)]);
+    assert_eq!(
+        merged.transaction(TransactionId(1)).unwrap().client_id(),
+        ClientId(1)
+    );
+}
+
+// BRANCH
+#[test]
+fn clone_state_shares_until_written() {
+    let mut ledger = Ledger::new();
+    let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    let mut branch = ledger.clone_state();
+    assert!(ledger.transactions.ptr_eq(&branch.transactions));
+
+    let withdrawal = Transaction::new(ClientId(1), num!(2), Operation::Withdrawal);
+    assert!(branch
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    assert!(!ledger.transactions.ptr_eq(&branch.transactions));
+    assert_eq!(branch.account(ClientId(1)).unwrap().available(), num!(3));
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(5));
+    assert!(ledger.transaction(TransactionId(2)).is_none());
+    // the original can still take the same transaction independently
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    assert_eq!(ledger.account(ClientId(1)), branch.account(ClientId(1)));
+}
+
+#[test]
+fn branching_costs_the_same_at_any_size() {
+    use std::time::Instant;
+
+    // The quickest of a few rounds of branching off a ledger holding that
+    // many transactions and applying one more to the branch.
+    let cost = |transactions: u32| {
+        let mut ledger = Ledger::new();
+        ledger.enable_journal();
+        ledger.enable_timestamps();
+        for id in 0..transactions {
+            let client_id = ClientId((id % 500) as u16);
+            let deposit = Transaction::new(client_id, num!(1), Operation::Deposit);
+            ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .unwrap();
+        }
+        let deposit = Transaction::new(ClientId(1), num!(1), Operation::Deposit);
+        (0..5)
+            .map(|_| {
+                let start = Instant::now();
+                for _ in 0..50 {
+                    let mut branch = ledger.clone_state();
+                    branch
+                        .apply_transaction(TransactionId(transactions), &deposit)
+                        .unwrap();
+                }
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    let small = cost(500);
+    let large = cost(50_000);
+    // copying what grows with the ledger would make it about 100 times slower
+    assert!(large < small * 10, "{small:?} against {large:?}");
+}
+
+#[test]
+fn ledgers_compare_by_state() {
+    let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+    let mut ledger = Ledger::new();
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    let mut same = Ledger::new();
+    let _ = same.event_stream();
+    assert!(same.apply_transaction(TransactionId(1), &deposit).is_ok());
+    assert_eq!(ledger, same);
+
+    let mut branch = ledger.clone();
+    assert_eq!(branch, ledger);
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    assert!(branch.apply_transaction(TransactionId(1), &dispute).is_ok());
+    assert_ne!(branch, ledger);
+    assert!(format!("{:?}", branch).starts_with("Ledger { accounts: {ClientId(1): Account"));
+}
+
+// SNAPSHOT
+#[test]
+fn snapshot_round_trip() {
+    let policy = Policy {
+        max_open_disputes: Some(2),
+        ..Policy::default()
+    };
+    let mut ledger = Ledger::with_policy(policy);
+    let transactions: TransactionList = vec![
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), num!(10.5), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), num!(2.0), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(ClientId(2), num!(3.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(4),
+            Transaction::new(ClientId(3), num!(1.0), Operation::Deposit),
+        ),
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute),
+        ),
+    ];
+    process_transactions(&mut ledger, &transactions).for_each(|res| assert!(res.is_ok()));
+    ledger.set_tier(ClientId(2), Tier::Premium);
+    ledger.archive_account(ClientId(2)).unwrap();
+    ledger.erase_client(ClientId(3)).unwrap();
+    ledger.quarantine(ClientId(1)).unwrap();
+    ledger.create_group(GroupId(1), ClientId(1)).unwrap();
+    ledger
+        .add_member(GroupId(1), ClientId(5), Some(num!(7)))
+        .unwrap();
+    ledger.set_time(Timestamp(42));
+
+    let mut snapshot = Vec::new();
+    ledger.write_snapshot(&mut snapshot).unwrap();
+    let restored = Ledger::read_snapshot(snapshot.as_slice(), policy).unwrap();
+    assert_eq!(restored.now(), Timestamp(42));
+    assert_eq!(restored.account(ClientId(1)), ledger.account(ClientId(1)));
+    assert_eq!(restored.open_disputes(ClientId(1)), 1);
+    assert_eq!(restored.tier(ClientId(2)), Tier::Premium);
+    assert!(restored.is_archived(ClientId(2)));
+    assert!(restored.is_quarantined(ClientId(1)));
+    assert_eq!(restored.group(GroupId(1)), ledger.group(GroupId(1)));
+    assert_eq!(restored.escrow(EscrowId(1)), ledger.escrow(EscrowId(1)));
+    assert_eq!(restored.tombstones(), ledger.tombstones());
+    assert_eq!(restored.stats(), ledger.stats());
+    assert_eq!(
+        restored.transaction(TransactionId(1)).map(|t| t.state()),
+        Some(TransactionState::Disputed)
+    );
+
+    let mut restored = restored;
+    let deposit = Transaction::new(ClientId(4), num!(1.0), Operation::Deposit);
+    assert_eq!(
+        restored.apply_transaction(TransactionId(4), &deposit),
+        Err(TransactionError::RepeatedTransactionId(TransactionId(4)))
+    );
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    assert!(restored
+        .apply_transaction(TransactionId(1), &resolve)
+        .is_ok());
+    assert_eq!(
+        restored.account(ClientId(1)).unwrap().available(),
+        num!(8.5)
+    );
+
+    assert!(Ledger::read_snapshot("garbage".as_bytes(), policy).is_err());
+    assert!(Ledger::read_snapshot("crab-snapshot\naccount 1".as_bytes(), policy).is_err());
+}
+
+#[test]
+fn older_formats_migrate() {
+    let policy = Policy::default();
+    let v1 = "crab-snapshot\ntime 5\naccount 1 2.5 0 false\n";
+    let ledger = Ledger::read_snapshot(v1.as_bytes(), policy).unwrap();
+    assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(2.5));
+    let mut migrated = Vec::new();
+    assert_eq!(
+        Ledger::migrate_snapshot(v1.as_bytes(), &mut migrated).unwrap(),
+        1
+    );
+    let migrated = String::from_utf8(migrated).unwrap();
+    assert_eq!(
+        migrated,
+        format!("crab-snapshot {SNAPSHOT_VERSION}\ntime 5\naccount 1 2.5 0 false\n")
+    );
+    assert!(Ledger::read_snapshot(migrated.as_bytes(), policy).is_ok());
+    let newer = format!("crab-snapshot {}\n", SNAPSHOT_VERSION + 1);
+    assert!(Ledger::read_snapshot(newer.as_bytes(), policy).is_err());
+
+    let v1 = "sequence,timestamp,type,client,tx,amount\n1,0,deposit,1,1,1.0\n";
+    let journal = Journal::read_csv(v1.as_bytes()).unwrap();
+    assert_eq!(journal.last_sequence(), 1);
+    let mut migrated = Vec::new();
+    assert_eq!(Journal::migrate(v1.as_bytes(), &mut migrated).unwrap(), 1);
+    assert!(migrated.starts_with(format!("# crab-journal {JOURNAL_VERSION}\n").as_bytes()));
+    assert_eq!(Journal::read_csv(migrated.as_slice()).unwrap(), journal);
+    let newer = format!("# crab-journal {}\n", JOURNAL_VERSION + 1);
+    assert!(Journal::read_csv(newer.as_bytes()).is_err());
+}
+
+#[test]
+fn recover_replays_journal_tail() {
+    let policy = Policy::default();
+    let mut ledger = Ledger::new();
+    ledger.enable_journal();
+    let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    let mut snapshot = Vec::new();
+    ledger.write_snapshot(&mut snapshot).unwrap();
+    let withdrawal = Transaction::new(ClientId(1), num!(2), Operation::Withdrawal);
+    assert!(ledger
+        .apply_transaction(TransactionId(2), &withdrawal)
+        .is_ok());
+    let mut journal = Vec::new();
+    ledger.journal().unwrap().write_csv(&mut journal).unwrap();
+
+    let mut recovered =
+        Ledger::recover(Some(snapshot.as_slice()), journal.as_slice(), policy).unwrap();
+    assert_eq!(recovered.account(ClientId(1)).unwrap().available(), num!(3));
+    assert_eq!(recovered.journal(), ledger.journal());
+    assert!(recovered
+        .apply_transaction(TransactionId(3), &deposit)
+        .is_ok());
+    assert_eq!(recovered.journal().unwrap().last_sequence(), 3);
+
+    // Without a snapshot the whole journal is replayed.
+    let replayed = Ledger::recover(None, journal.as_slice(), policy).unwrap();
+    assert_eq!(replayed.account(ClientId(1)).unwrap().available(), num!(3));
+
+    let mut unjournaled = Vec::new();
+    Ledger::new().write_snapshot(&mut unjournaled).unwrap();
+    assert!(Ledger::recover(Some(unjournaled.as_slice()), journal.as_slice(), policy).is_err());
+    let mut ahead = Vec::new();
+    ledger.write_snapshot(&mut ahead).unwrap();
+    let mut behind = Vec::new();
+    Journal::new().write_csv(&mut behind).unwrap();
+    assert!(Ledger::recover(Some(ahead.as_slice()), behind.as_slice(), policy).is_err());
+}
+
+#[test]
+fn paranoid_ledger_checks_every_apply() {
+    let mut ledger = Ledger::with_policy(Policy {
+        chargeback_excess: ChargebackExcess::Clamp,
+        dispute_withdrawals: true,
+        allow_adjustments: true,
+        ..Policy::default()
+    });
+    ledger.set_paranoid(true);
+    assert!(ledger.is_paranoid());
+    let dispute = Transaction::new(ClientId(1), Number::ZERO, Operation::Dispute);
+    let resolve = Transaction::new(ClientId(1), Number::ZERO, Operation::Resolve);
+    let chargeback = Transaction::new(ClientId(1), Number::ZERO, Operation::Chargeback);
+    let transactions: TransactionList = vec![
+        (
+            TransactionId(1),
+            Transaction::new(ClientId(1), num!(10), Operation::Deposit),
+        ),
+        (
+            TransactionId(2),
+            Transaction::new(ClientId(1), num!(3), Operation::Withdrawal),
+        ),
+        (
+            TransactionId(3),
+            Transaction::new(
+                ClientId(1),
+                num!(2),
+                Operation::Transfer { to: ClientId(2) },
+            ),
+        ),
+        (
+            TransactionId(4),
+            Transaction::new(ClientId(1), num!(-1), Operation::Adjustment),
+        ),
+        (TransactionId(2), dispute),
+        (TransactionId(2), resolve),
+        (TransactionId(2), dispute),
+        (TransactionId(2), chargeback),
+        (TransactionId(1), dispute),
+        (TransactionId(1), chargeback),
+    ];
+    for result in process_transactions(&mut ledger, &transactions) {
+        assert!(result.is_ok(), "{result:?}");
+    }
+    assert!(ledger.clone_state().is_paranoid());
+}
+
+#[test]
+#[should_panic(expected = "ledger invariants broken by TransactionId(2)")]
+fn paranoid_ledger_panics_on_negative_held() {
+    let mut ledger = Ledger::new();
+    ledger.set_paranoid(true);
+    let deposit = Transaction::new(ClientId(1), num!(10), Operation::Deposit);
+    assert!(ledger.apply_transaction(TransactionId(1), &deposit).is_ok());
+    ledger
+        .accounts
+        .insert(ClientId(1), Account::from_parts(num!(10), num!(-4), false));
+    ledger.set_policy(Policy {
+        chargeback_excess: ChargebackExcess::Reject,
+        ..Policy::default()
+    });
+    let _ = ledger.apply_transaction(TransactionId(2), &deposit);
+}
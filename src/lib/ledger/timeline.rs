@@ -0,0 +1,38 @@
+use super::{Ledger, Timestamp};
+use crate::transactions::{Transaction, TransactionId};
+use imbl::OrdSet;
+
+// Deposits, withdrawals and adjustments ordered by the ledger time they were
+// applied at, so a time range is read without scanning every transaction.
+// Only recorded once enabled, and not kept in snapshots.
+pub(super) type Timeline = OrdSet<(Timestamp, TransactionId)>;
+
+impl Ledger {
+    pub fn enable_timestamps(&mut self) {
+        self.timeline.get_or_insert_with(Timeline::new);
+    }
+
+    // Transactions applied from `from` up to but excluding `to`, oldest
+    // first. Empty unless timestamps were enabled before they were applied.
+    pub fn transactions_between(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> impl Iterator<Item = (Timestamp, TransactionId, &Transaction)> + '_ {
+        let range = (from < to).then_some((from, TransactionId(0))..(to, TransactionId(0)));
+        self.timeline
+            .iter()
+            .zip(range)
+            .flat_map(|(timeline, range)| timeline.range(range))
+            .filter_map(|(timestamp, transaction_id)| {
+                let transaction = self.transactions.get(transaction_id)?;
+                Some((*timestamp, *transaction_id, transaction))
+            })
+    }
+
+    pub(super) fn record_time(&mut self, transaction_id: TransactionId) {
+        if let Some(timeline) = &mut self.timeline {
+            timeline.insert((self.now, transaction_id));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use super::Ledger;
+use crate::account::Number;
+use crate::transactions::{Operation, Transaction};
+
+// Gross flows through the ledger since it started, kept up to date as
+// transactions are applied, for reconciling what moved rather than the net
+// balances. Deposits count what was credited, chargebacks the amount of the
+// deposit charged back.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunningTotals {
+    pub deposited: Number,
+    pub withdrawn: Number,
+    pub charged_back: Number,
+}
+
+impl RunningTotals {
+    pub(super) fn add(&mut self, other: &RunningTotals) {
+        self.deposited = self.deposited.saturating_add(other.deposited);
+        self.withdrawn = self.withdrawn.saturating_add(other.withdrawn);
+        self.charged_back = self.charged_back.saturating_add(other.charged_back);
+    }
+}
+
+impl Ledger {
+    pub fn running_totals(&self) -> RunningTotals {
+        self.totals
+    }
+
+    // `applied` is the transaction as stored: the deposit with what was
+    // credited, or the deposit a chargeback refers to.
+    pub(super) fn record_totals(&mut self, operation: Operation, applied: &Transaction) {
+        let amount = applied.amount();
+        let flow = match operation {
+            Operation::Deposit => RunningTotals {
+                deposited: amount,
+                ..RunningTotals::default()
+            },
+            Operation::Withdrawal => RunningTotals {
+                withdrawn: amount,
+                ..RunningTotals::default()
+            },
+            Operation::Chargeback => RunningTotals {
+                charged_back: amount,
+                ..RunningTotals::default()
+            },
+            _ => return,
+        };
+        self.totals.add(&flow);
+    }
+}
@@ -0,0 +1,125 @@
+use super::{events::LedgerEvent, Change, Ledger};
+use crate::{
+    account::ClientId, transactions::Outcome, transactions::Transaction,
+    transactions::TransactionError, transactions::TransactionId, transactions::TransactionResult,
+};
+
+// A transfer between clients of two ledgers is split in legs: the sender's
+// ledger debits and stores the transfer, the recipient's only credits its
+// client. Both legs are prepared before either is committed, so a leg turned
+// down on either side leaves both ledgers as they were.
+impl Ledger {
+    pub(super) fn check_sender(&self, transaction: &Transaction) -> TransactionResult {
+        self.check_onboarded(transaction.client_id())?;
+        self.check_frozen(transaction.client_id())?;
+        self.check_quarantined(transaction.client_id())?;
+        self.check_group_limit(transaction)
+    }
+
+    fn check_recipient(&self, to: ClientId) -> TransactionResult {
+        self.check_archived(to)?;
+        self.check_onboarded(to)?;
+        self.check_frozen(to)?;
+        self.check_quarantined(to)
+    }
+
+    fn prepare_debit(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> Result<Change, TransactionError> {
+        let holder = self.account_holder(transaction.client_id());
+        self.check_dormant(holder)?;
+        let overdraft = self.overdraft_limit(holder);
+        self.prepare_new_transaction(transaction_id, transaction, |account| {
+            account.overdraw(transaction.amount(), overdraft)
+        })
+    }
+
+    // Debits the sender as a withdrawal would and credits the recipient as a
+    // deposit would. Nothing changes unless both succeed.
+    pub(super) fn prepare_transfer(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        to: ClientId,
+    ) -> Result<Change, TransactionError> {
+        self.check_recipient(to)?;
+        let mut change = self.prepare_debit(transaction_id, transaction)?;
+        let recipient = self.account_holder(to);
+        let mut account = if recipient == change.client_id {
+            change.account
+        } else {
+            self.accounts.get(&recipient).unwrap_or_default()
+        };
+        account
+            .deposit(transaction.amount())
+            .map_err(|err| TransactionError::AccountError(to, err))?;
+        if recipient == change.client_id {
+            change.account = account;
+        } else {
+            change.credit = Some((recipient, account));
+        }
+        Ok(change)
+    }
+
+    pub(super) fn prepare_transfer_out(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> Result<Change, TransactionError> {
+        self.check_submission(transaction_id, transaction)?;
+        self.check_sender(transaction)?;
+        self.prepare_debit(transaction_id, transaction)
+    }
+
+    pub(super) fn prepare_transfer_in(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        to: ClientId,
+    ) -> Result<Change, TransactionError> {
+        self.id_exists(transaction_id)?;
+        self.check_recipient(to)?;
+        let recipient = self.account_holder(to);
+        let mut account = self.accounts.get(&recipient).unwrap_or_default();
+        account
+            .deposit(transaction.amount())
+            .map_err(|err| TransactionError::AccountError(to, err))?;
+        Ok(Change {
+            client_id: recipient,
+            account,
+            transaction_id,
+            transaction: *transaction,
+            credit: None,
+        })
+    }
+
+    pub(super) fn commit_transfer_out(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        change: Change,
+    ) -> Outcome {
+        if self.paranoid {
+            self.check_invariants(transaction_id, transaction, &change, -transaction.amount());
+        }
+        self.apply_change(transaction_id, transaction, change)
+    }
+
+    // The transfer itself stays with the sender: only the credit is kept
+    // here, as it would be for a recipient in the sender's ledger.
+    pub(super) fn commit_transfer_in(&mut self, change: Change) {
+        let (transaction_id, transaction) = (change.transaction_id, change.transaction);
+        if self.paranoid {
+            self.check_invariants(transaction_id, &transaction, &change, transaction.amount());
+        }
+        self.accounts.insert(change.client_id, change.account);
+        self.record_activity(change.client_id);
+        self.events.publish(LedgerEvent::Applied {
+            transaction_id,
+            transaction,
+            account: change.account,
+        });
+    }
+}
@@ -0,0 +1,69 @@
+use super::Timestamp;
+use crate::account::Number;
+
+const BUCKET_SECONDS: u64 = 60 * 60;
+const BUCKETS: usize = 24;
+
+// Sum over the last 24 hours kept in hourly buckets, so adding and reading
+// are constant time at the cost of hour granularity at the window's edge.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RollingWindow {
+    hours: [u64; BUCKETS],
+    sums: [Number; BUCKETS],
+}
+
+impl RollingWindow {
+    fn hour(now: Timestamp) -> u64 {
+        now.0 / BUCKET_SECONDS
+    }
+
+    pub fn add(&mut self, now: Timestamp, amount: Number) {
+        let hour = Self::hour(now);
+        let bucket = hour as usize % BUCKETS;
+        if self.hours[bucket] != hour {
+            self.hours[bucket] = hour;
+            self.sums[bucket] = Number::ZERO;
+        }
+        // A window past what a `Number` holds stays at the limit.
+        self.sums[bucket] = self.sums[bucket].saturating_add(amount);
+    }
+
+    pub fn sum(&self, now: Timestamp) -> Number {
+        let hour = Self::hour(now);
+        self.hours
+            .iter()
+            .zip(self.sums.iter())
+            .filter(|(bucket_hour, _)| {
+                **bucket_hour <= hour && hour - **bucket_hour < BUCKETS as u64
+            })
+            .fold(Number::ZERO, |total, (_, sum)| total.saturating_add(*sum))
+    }
+}
+
+#[cfg(test)]
+mod window_tests {
+    use super::RollingWindow;
+    use crate::{account::num, account::Number, ledger::Timestamp};
+
+    #[test]
+    fn expire_after_a_day() {
+        let mut window = RollingWindow::default();
+        window.add(Timestamp(0), num!(1));
+        window.add(Timestamp(3_600 * 5), num!(2));
+        window.add(Timestamp(3_600 * 5 + 10), num!(3));
+        assert_eq!(window.sum(Timestamp(3_600 * 23)), num!(6));
+        assert_eq!(window.sum(Timestamp(3_600 * 24)), num!(5));
+        assert_eq!(window.sum(Timestamp(3_600 * 29)), Number::ZERO);
+        window.add(Timestamp(3_600 * 48), num!(4));
+        assert_eq!(window.sum(Timestamp(3_600 * 48)), num!(4));
+    }
+
+    #[test]
+    fn saturate_at_the_largest_amount() {
+        let mut window = RollingWindow::default();
+        window.add(Timestamp(0), Number::MAX);
+        window.add(Timestamp(10), Number::MAX);
+        window.add(Timestamp(3_600), Number::MAX);
+        assert_eq!(window.sum(Timestamp(3_600)), Number::MAX);
+    }
+}
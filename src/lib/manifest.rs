@@ -0,0 +1,211 @@
+use std::fmt::Write;
+
+use hmac::Mac;
+
+use super::digest::{self, Digest};
+
+const HEADER: &str = "crab-manifest v1";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ManifestError {
+    Malformed(String),
+    MissingFile(String),
+    RecordCountMismatch {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+    ChecksumMismatch(String),
+    MissingSignature,
+    InvalidSignature,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub records: usize,
+    pub sha256: Digest,
+}
+
+// Describes a set of exported files so that the receiving side can detect
+// truncated or altered files. The signature is an HMAC-SHA256 over the
+// manifest body using a key shared by both parties.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+    signature: Option<Digest>,
+}
+
+// Records of a CSV file as the csv reader sees them, minus its header, so a
+// quoted field spanning lines is still one record.
+fn count_records(contents: &[u8]) -> usize {
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents)
+        .byte_records()
+        .count()
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) {
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.push(ManifestEntry {
+            name: name.to_string(),
+            records: count_records(contents),
+            sha256: digest::sha256(contents),
+        });
+        self.signature = None;
+    }
+
+    fn body(&self) -> String {
+        let mut body = format!("{HEADER}\n");
+        for entry in &self.entries {
+            let _ = writeln!(
+                body,
+                "{} {} {}",
+                entry.records,
+                digest::to_hex(&entry.sha256),
+                entry.name
+            );
+        }
+        body
+    }
+
+    pub fn sign(&mut self, key: &[u8]) {
+        self.signature = Some(digest::hmac_sha256(key, self.body().as_bytes()));
+    }
+
+    // Compared in constant time, so a forger can't learn the signature byte
+    // by byte.
+    pub fn verify_signature(&self, key: &[u8]) -> Result<(), ManifestError> {
+        let signature = self.signature.ok_or(ManifestError::MissingSignature)?;
+        digest::hmac(key, self.body().as_bytes())
+            .verify_slice(&signature)
+            .map_err(|_| ManifestError::InvalidSignature)
+    }
+
+    pub fn verify_file(&self, name: &str, contents: &[u8]) -> Result<(), ManifestError> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| ManifestError::MissingFile(name.to_string()))?;
+        let records = count_records(contents);
+        if records != entry.records {
+            return Err(ManifestError::RecordCountMismatch {
+                name: name.to_string(),
+                expected: entry.records,
+                actual: records,
+            });
+        }
+        if digest::sha256(contents) != entry.sha256 {
+            return Err(ManifestError::ChecksumMismatch(name.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ManifestError> {
+        let malformed = |line: &str| ManifestError::Malformed(line.to_string());
+        let mut lines = text.lines();
+        match lines.next() {
+            Some(HEADER) => {}
+            line => return Err(malformed(line.unwrap_or_default())),
+        }
+        let mut manifest = Manifest::new();
+        for line in lines {
+            if let Some(signature) = line.strip_prefix("signature ") {
+                manifest.signature =
+                    Some(digest::from_hex(signature).ok_or_else(|| malformed(line))?);
+                continue;
+            }
+            let mut fields = line.splitn(3, ' ');
+            let (Some(records), Some(sha256), Some(name)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(malformed(line));
+            };
+            manifest.entries.push(ManifestEntry {
+                name: name.to_string(),
+                records: records.parse().map_err(|_| malformed(line))?,
+                sha256: digest::from_hex(sha256).ok_or_else(|| malformed(line))?,
+            });
+        }
+        Ok(manifest)
+    }
+}
+
+impl std::fmt::Display for Manifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.body())?;
+        if let Some(signature) = self.signature {
+            writeln!(f, "signature {}", digest::to_hex(&signature))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use super::{Manifest, ManifestError};
+
+    const JOURNAL: &[u8] =
+        b"sequence,timestamp,type,client,tx,amount\n1,0,deposit,1,1,1.0\n2,0,deposit,2,2,2.0\n";
+
+    #[test]
+    fn round_trip_and_verify() {
+        let mut manifest = Manifest::new();
+        manifest.add_file("journal.csv", JOURNAL);
+        manifest.sign(b"secret");
+        let manifest = Manifest::parse(&manifest.to_string()).unwrap();
+        assert_eq!(manifest.entries()[0].records, 2);
+        assert_eq!(manifest.verify_signature(b"secret"), Ok(()));
+        assert_eq!(
+            manifest.verify_signature(b"guess"),
+            Err(ManifestError::InvalidSignature)
+        );
+        assert_eq!(manifest.verify_file("journal.csv", JOURNAL), Ok(()));
+    }
+
+    #[test]
+    fn count_records_across_quoted_newlines() {
+        let mut manifest = Manifest::new();
+        manifest.add_file("letters.csv", b"client,note\n1,\"first\nsecond\"\n2,third");
+        assert_eq!(manifest.entries()[0].records, 2);
+    }
+
+    #[test]
+    fn detect_tampering() {
+        let mut manifest = Manifest::new();
+        manifest.add_file("journal.csv", JOURNAL);
+        let truncated = &JOURNAL[..JOURNAL.len() - 20];
+        assert_eq!(
+            manifest.verify_file("journal.csv", truncated),
+            Err(ManifestError::RecordCountMismatch {
+                name: "journal.csv".to_string(),
+                expected: 2,
+                actual: 1
+            })
+        );
+        let altered = String::from_utf8_lossy(JOURNAL).replace("2.0", "9.0");
+        assert_eq!(
+            manifest.verify_file("journal.csv", altered.as_bytes()),
+            Err(ManifestError::ChecksumMismatch("journal.csv".to_string()))
+        );
+        assert_eq!(
+            manifest.verify_file("other.csv", JOURNAL),
+            Err(ManifestError::MissingFile("other.csv".to_string()))
+        );
+        assert_eq!(
+            manifest.verify_signature(b"secret"),
+            Err(ManifestError::MissingSignature)
+        );
+    }
+}
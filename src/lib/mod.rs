@@ -1,4 +1,30 @@
 pub mod account;
 pub mod app;
+pub mod bench;
+pub mod checkpoint;
+pub mod config;
+pub mod digest;
+pub mod encryption;
+pub mod enrichment;
+pub mod fixed_width;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod health;
+pub mod import;
 pub mod ledger;
+pub mod manifest;
+pub mod outbox;
+pub mod rate_limit;
+pub mod repl;
+pub mod report;
+pub mod schema;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod script;
+pub mod seen;
+pub mod split;
+#[cfg(feature = "templates")]
+pub mod template;
 pub mod transactions;
+pub mod wire;
@@ -0,0 +1,358 @@
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use super::ledger::{Journal, JournalEntry};
+
+// Announces the transactions the ledger accepted to another system, one
+// message per journal entry. The journal file doubles as the outbox: an entry
+// is only delivered once it has been written out with the journal, so
+// consumers never hear of a transaction a crash could still take back, and
+// every transaction that was kept gets announced eventually.
+//
+// Delivery progress is kept in a state file next to the journal. A crash
+// between delivering an entry and saving the state delivers it again, with
+// the same sequence number, which consumers can use to drop the repeat.
+pub trait OutboxSink {
+    fn deliver(&mut self, entry: &JournalEntry) -> io::Result<()>;
+}
+
+impl<F: FnMut(&JournalEntry) -> io::Result<()>> OutboxSink for F {
+    fn deliver(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        self(entry)
+    }
+}
+
+pub fn message_json(entry: &JournalEntry) -> String {
+    serde_json::json!({
+        "sequence": entry.sequence,
+        "timestamp": entry.timestamp.0,
+        "type": entry.transaction.operation(),
+        "client": entry.transaction.client_id().0,
+        "tx": entry.transaction_id.0,
+        "amount": entry.transaction.amount(),
+    })
+    .to_string()
+}
+
+// POSTs each message as JSON to an `http://host:port/path` URL, with the
+// entry's sequence number as `Idempotency-Key`. Any 2xx answer counts as
+// delivered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Webhook {
+    host: String,
+    path: String,
+    timeout: Duration,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid url {url}"));
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (host, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let host = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{host}:80"),
+        };
+        Ok(Webhook {
+            host,
+            path: path.to_string(),
+            timeout: Duration::from_secs(10),
+        })
+    }
+}
+
+impl OutboxSink for Webhook {
+    fn deliver(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        let body = message_json(entry);
+        let mut stream = TcpStream::connect(&self.host)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nIdempotency-Key: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.path,
+            self.host,
+            entry.sequence,
+            body.len()
+        )?;
+        let mut status = String::new();
+        io::BufReader::new(&stream).read_line(&mut status)?;
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "webhook answered {}",
+                status.trim_end()
+            ))),
+        }
+    }
+}
+
+// How many times an entry is tried before a delivery run gives up on it,
+// waiting `backoff` after the first failure and twice as long after each
+// following one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+// `attempts` and `last_error` are about the entry after `delivered`, the
+// next one due.
+//
+//   delivered <sequence>
+//   attempts <count>
+//   error <message>
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryState {
+    pub delivered: u64,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+impl DeliveryState {
+    // A missing file means nothing was delivered yet.
+    pub fn read(path: &str) -> io::Result<Self> {
+        let mut contents = String::new();
+        match fs::File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut contents)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        let malformed = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed outbox state: {line}"),
+            )
+        };
+        let mut state = DeliveryState::default();
+        for line in contents.lines() {
+            match line.split_once(' ') {
+                Some(("delivered", value)) => {
+                    state.delivered = value.parse().map_err(|_| malformed(line))?
+                }
+                Some(("attempts", value)) => {
+                    state.attempts = value.parse().map_err(|_| malformed(line))?
+                }
+                Some(("error", value)) => state.last_error = Some(value.to_string()),
+                _ => return Err(malformed(line)),
+            }
+        }
+        Ok(state)
+    }
+
+    // Written to a temporary file and moved into place, like snapshots.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let partial = format!("{path}.tmp");
+        let mut file = fs::File::create(&partial)?;
+        writeln!(file, "delivered {}", self.delivered)?;
+        writeln!(file, "attempts {}", self.attempts)?;
+        if let Some(error) = &self.last_error {
+            writeln!(file, "error {}", error.replace('\n', " "))?;
+        }
+        file.sync_all()?;
+        fs::rename(partial, path)
+    }
+}
+
+// Where a run delivers its journal once it's written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutboxConfig {
+    pub webhook: String,
+    pub state: String,
+    pub retry: RetryPolicy,
+}
+
+pub struct OutboxWorker<S: OutboxSink> {
+    state_path: String,
+    sink: S,
+    retry: RetryPolicy,
+    state: DeliveryState,
+}
+
+impl<S: OutboxSink> OutboxWorker<S> {
+    pub fn open(state_path: &str, sink: S, retry: RetryPolicy) -> io::Result<Self> {
+        Ok(OutboxWorker {
+            state_path: state_path.to_string(),
+            sink,
+            retry,
+            state: DeliveryState::read(state_path)?,
+        })
+    }
+
+    pub fn state(&self) -> &DeliveryState {
+        &self.state
+    }
+
+    pub fn deliver_file(&mut self, journal: &str) -> io::Result<usize> {
+        self.deliver(&Journal::read_csv(fs::File::open(journal)?)?)
+    }
+
+    // Delivers the entries after the last delivered one, in order, and
+    // returns how many were. Stops with an error at the first entry that
+    // still fails after the retries: it is the first one tried next time.
+    pub fn deliver(&mut self, journal: &Journal) -> io::Result<usize> {
+        let mut delivered = 0;
+        let after = self.state.delivered;
+        let pending = journal
+            .entries()
+            .iter()
+            .filter(|entry| entry.sequence > after);
+        for entry in pending {
+            let mut backoff = self.retry.backoff;
+            let mut tries = 0;
+            while let Err(err) = self.sink.deliver(entry) {
+                tries += 1;
+                self.state.attempts += 1;
+                self.state.last_error = Some(err.to_string());
+                self.state.write(&self.state_path)?;
+                if tries >= self.retry.max_attempts {
+                    return Err(io::Error::other(format!(
+                        "gave up delivering journal entry {} after {tries} attempts: {err}",
+                        entry.sequence
+                    )));
+                }
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            self.state = DeliveryState {
+                delivered: entry.sequence,
+                attempts: 0,
+                last_error: None,
+            };
+            self.state.write(&self.state_path)?;
+            delivered += 1;
+        }
+        Ok(delivered)
+    }
+}
+
+#[cfg(test)]
+mod outbox_tests {
+    use super::{DeliveryState, OutboxWorker, RetryPolicy, Webhook};
+    use crate::account::{num, ClientId};
+    use crate::ledger::{JournalEntry, Ledger};
+    use crate::outbox::OutboxSink;
+    use crate::transactions::{Operation, Transaction, TransactionId};
+    use std::io::{self, BufRead, Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use std::{fs, thread};
+
+    #[test]
+    fn deliver_journal_entries_once() {
+        let dir = std::env::temp_dir().join(format!("crab-outbox-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let state = dir.join("outbox.state").to_string_lossy().into_owned();
+        let _ = fs::remove_file(&state);
+        let mut ledger = Ledger::new();
+        ledger.enable_journal();
+        for id in 1..=3 {
+            let deposit = Transaction::new(ClientId(1), num!(1), Operation::Deposit);
+            ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .unwrap();
+        }
+        let retry = RetryPolicy {
+            max_attempts: 2,
+            backoff: Duration::ZERO,
+        };
+
+        // the second entry fails twice, so the run stops there
+        let mut sent = Vec::new();
+        let mut failures = 0;
+        let sink = |entry: &JournalEntry| {
+            if entry.sequence == 2 && failures < 2 {
+                failures += 1;
+                return Err(io::Error::other("unavailable"));
+            }
+            sent.push(entry.sequence);
+            Ok(())
+        };
+        let mut worker = OutboxWorker::open(&state, sink, retry).unwrap();
+        assert!(worker.deliver(ledger.journal().unwrap()).is_err());
+        assert_eq!(
+            DeliveryState::read(&state).unwrap(),
+            DeliveryState {
+                delivered: 1,
+                attempts: 2,
+                last_error: Some("unavailable".to_string()),
+            }
+        );
+
+        // the next run picks up where it stopped
+        let mut worker = OutboxWorker::open(&state, worker.sink, retry).unwrap();
+        assert_eq!(worker.deliver(ledger.journal().unwrap()).unwrap(), 2);
+        assert_eq!(worker.deliver(ledger.journal().unwrap()).unwrap(), 0);
+        drop(worker);
+        assert_eq!(sent, [1, 2, 3]);
+        assert_eq!(DeliveryState::read(&state).unwrap().delivered, 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn post_to_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line.trim_end().to_string());
+            }
+            let length: usize = head
+                .iter()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            (head, String::from_utf8(body).unwrap())
+        });
+
+        let mut webhook = Webhook::new(&format!("http://{addr}/events")).unwrap();
+        let deposit = Transaction::new(ClientId(7), num!(2.5), Operation::Deposit);
+        let entry = JournalEntry {
+            sequence: 42,
+            timestamp: Default::default(),
+            transaction_id: TransactionId(9),
+            transaction: deposit,
+        };
+        webhook.deliver(&entry).unwrap();
+        let (head, body) = server.join().unwrap();
+        assert_eq!(head[0], "POST /events HTTP/1.1");
+        assert!(head.contains(&"Idempotency-Key: 42".to_string()));
+        assert_eq!(
+            body,
+            r#"{"amount":"2.5","client":7,"sequence":42,"timestamp":0,"tx":9,"type":"deposit"}"#
+        );
+        assert!(Webhook::new("https://example.com").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Token bucket: holds up to `burst` tokens and refills at `rate` tokens per
+// second. Each applied transaction takes one token.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u32) -> Self {
+        Self::with_burst(rate, rate)
+    }
+
+    pub fn with_burst(rate: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: f64::from(rate.max(1)),
+            burst,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // Takes a token at `now`, or returns how long to wait until one is
+    // available.
+    pub fn try_acquire_at(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last_refill = self.last_refill.max(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    pub fn acquire(&mut self) {
+        while let Err(wait) = self.try_acquire_at(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::RateLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn refill_over_time() {
+        let mut limiter = RateLimiter::with_burst(10, 2);
+        let start = Instant::now();
+        assert_eq!(limiter.try_acquire_at(start), Ok(()));
+        assert_eq!(limiter.try_acquire_at(start), Ok(()));
+        let wait = limiter.try_acquire_at(start).unwrap_err();
+        assert!(wait <= Duration::from_millis(100) && wait > Duration::from_millis(90));
+
+        let later = start + Duration::from_millis(100);
+        assert_eq!(limiter.try_acquire_at(later), Ok(()));
+        assert!(limiter.try_acquire_at(later).is_err());
+
+        // Long pauses don't accumulate more than the burst.
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(limiter.try_acquire_at(much_later), Ok(()));
+        assert_eq!(limiter.try_acquire_at(much_later), Ok(()));
+        assert!(limiter.try_acquire_at(much_later).is_err());
+    }
+}
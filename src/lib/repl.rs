@@ -0,0 +1,331 @@
+use std::io::{self, BufRead, Write};
+
+use super::account::{parse_amount, ClientId, Number};
+use super::config::Config;
+use super::ledger::{AccountFilter, CommandOutput, FreezeReason, Ledger, LedgerCommand};
+use super::transactions::{Operation, Outcome, Transaction, TransactionId};
+
+const HELP: &str = "commands:
+  account <client>                          show one account
+  accounts                                  list all accounts
+  disputes                                  list open disputes
+  stats                                     show ledger totals
+  histogram <type> <bound>...               show the distribution of amounts
+  risk <client>                             show a client's risk score
+  simulate <type> <client> <tx> [amount] [to]
+                                            check a transaction without applying it
+  apply <type> <client> <tx> [amount] [to]  apply a transaction (asks for confirmation)
+  reload <config.toml>                      replace the policy with the one in a config file
+  archive <client> | restore <client>       archive or restore an account (asks for confirmation)
+  quarantine <client> | release <client>    hold new deposits and block withdrawals, or release them
+  reactivate <client>                       allow withdrawals from a dormant account again
+  freeze <client> <reason> | unfreeze <client>
+                                            stop deposits and withdrawals for compliance, or lift it
+  unlock <client>                           unlock an account after a chargeback (asks for confirmation)
+  reviews                                   list transactions parked for review
+  approve <tx> | reject <tx>                apply or drop a flagged transaction (asks for confirmation)
+  help | quit";
+
+fn parse_client(argument: Option<&str>) -> Result<ClientId, String> {
+    argument
+        .and_then(|client| client.parse().ok())
+        .map(ClientId)
+        .ok_or_else(|| "expected a client id".to_string())
+}
+
+fn parse_transaction(arguments: &[&str]) -> Result<(TransactionId, Transaction), String> {
+    let [operation, client, tx, rest @ ..] = arguments else {
+        return Err("expected <type> <client> <tx> [amount] [to]".to_string());
+    };
+    let operation: Operation = operation
+        .parse()
+        .map_err(|_| format!("unknown transaction type '{operation}'"))?;
+    let client_id = parse_client(Some(client))?;
+    let transaction_id = tx
+        .parse()
+        .map(TransactionId)
+        .map_err(|_| format!("invalid transaction id '{tx}'"))?;
+    let amount = match rest.first() {
+        Some(amount) => parse_amount(amount).map_err(|err| err.to_string())?,
+        None => Number::ZERO,
+    };
+    let operation = match (operation, rest.get(1)) {
+        (Operation::Transfer { .. }, None) => return Err("expected a recipient".to_string()),
+        (operation, to) => operation.with_recipient(to.and_then(|to| parse_client(Some(to)).ok())),
+    };
+    Ok((
+        transaction_id,
+        Transaction::new(client_id, amount, operation),
+    ))
+}
+
+pub struct Repl<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Repl<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line.trim().to_string())),
+        }
+    }
+
+    fn confirm(&mut self, action: &str) -> io::Result<bool> {
+        write!(self.output, "{action}? [y/N] ")?;
+        self.output.flush()?;
+        Ok(matches!(
+            self.read_line()?.as_deref(),
+            Some("y") | Some("yes")
+        ))
+    }
+
+    pub fn run(&mut self, ledger: &mut Ledger) -> io::Result<()> {
+        loop {
+            write!(self.output, "> ")?;
+            self.output.flush()?;
+            let Some(line) = self.read_line()? else {
+                return Ok(());
+            };
+            let arguments: Vec<&str> = line.split_whitespace().collect();
+            match arguments.as_slice() {
+                [] => {}
+                ["quit"] | ["exit"] => return Ok(()),
+                ["help"] => writeln!(self.output, "{HELP}")?,
+                [command, rest @ ..] => {
+                    if let Err(err) = self.execute(ledger, command, rest) {
+                        writeln!(self.output, "error: {err}")?;
+                    }
+                }
+            }
+        }
+    }
+
+    fn execute(
+        &mut self,
+        ledger: &mut Ledger,
+        command: &str,
+        arguments: &[&str],
+    ) -> Result<(), String> {
+        let output_error = |err: io::Error| err.to_string();
+        match command {
+            "account" => {
+                let client_id = parse_client(arguments.first().copied())?;
+                match ledger.account(client_id) {
+                    Some(account) => writeln!(self.output, "{:?}", account),
+                    None => writeln!(self.output, "no account for client {}", client_id.0),
+                }
+                .map_err(output_error)
+            }
+            "accounts" => {
+                for (client_id, account) in ledger.accounts(&AccountFilter::default()) {
+                    writeln!(self.output, "{}: {:?}", client_id.0, account)
+                        .map_err(output_error)?;
+                }
+                Ok(())
+            }
+            "disputes" => {
+                for (transaction_id, transaction) in ledger.disputes() {
+                    writeln!(
+                        self.output,
+                        "{}: client {} amount {}",
+                        transaction_id.0,
+                        transaction.client_id().0,
+                        transaction.amount()
+                    )
+                    .map_err(output_error)?;
+                }
+                Ok(())
+            }
+            "reviews" => {
+                for review in ledger.reviews() {
+                    writeln!(
+                        self.output,
+                        "{}: client {} {} {}{}",
+                        review.transaction_id.0,
+                        review.transaction.client_id().0,
+                        review.transaction.operation().name(),
+                        review.transaction.amount(),
+                        if review.flagged { " (flagged)" } else { "" }
+                    )
+                    .map_err(output_error)?;
+                }
+                Ok(())
+            }
+            "approve" | "reject" => {
+                let transaction_id = arguments
+                    .first()
+                    .and_then(|tx| tx.parse().ok())
+                    .map(TransactionId)
+                    .ok_or_else(|| "expected a transaction id".to_string())?;
+                if !self.confirm(command).map_err(output_error)? {
+                    return Ok(());
+                }
+                let command = match command {
+                    "approve" => LedgerCommand::ApproveReview(transaction_id),
+                    _ => LedgerCommand::RejectReview(transaction_id),
+                };
+                match ledger.execute(command) {
+                    Ok(CommandOutput::Reviewed(released)) => {
+                        for (transaction_id, result) in released {
+                            match result {
+                                Ok(_) => writeln!(self.output, "{}: applied", transaction_id.0),
+                                Err(err) => {
+                                    writeln!(
+                                        self.output,
+                                        "{}: rejected: {:?}",
+                                        transaction_id.0, err
+                                    )
+                                }
+                            }
+                            .map_err(output_error)?;
+                        }
+                        writeln!(self.output, "done")
+                    }
+                    Ok(_) => writeln!(self.output, "done"),
+                    Err(err) => writeln!(self.output, "rejected: {:?}", err),
+                }
+                .map_err(output_error)
+            }
+            "stats" => writeln!(self.output, "{:?}", ledger.stats()).map_err(output_error),
+            "risk" => {
+                let client_id = parse_client(arguments.first().copied())?;
+                writeln!(self.output, "{:.2}", ledger.risk_score(client_id)).map_err(output_error)
+            }
+            "histogram" => {
+                let [operation, bounds @ ..] = arguments else {
+                    return Err("expected <type> <bound>...".to_string());
+                };
+                let operation: Operation = operation
+                    .parse()
+                    .map_err(|_| format!("unknown transaction type '{operation}'"))?;
+                let bounds = bounds
+                    .iter()
+                    .map(|bound| bound.parse::<Number>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| err.to_string())?;
+                ledger
+                    .amount_distribution(operation, &bounds)
+                    .write_csv(&mut self.output)
+                    .map_err(output_error)
+            }
+            "simulate" => {
+                let (transaction_id, transaction) = parse_transaction(arguments)?;
+                match ledger.validate(&transaction, transaction_id) {
+                    Ok(()) => writeln!(self.output, "ok"),
+                    Err(err) => writeln!(self.output, "rejected: {:?}", err),
+                }
+                .map_err(output_error)
+            }
+            "apply" => {
+                let (transaction_id, transaction) = parse_transaction(arguments)?;
+                if !self.confirm("apply").map_err(output_error)? {
+                    return Ok(());
+                }
+                match ledger.apply_transaction(transaction_id, &transaction) {
+                    Ok(Outcome::Deduplicated) => writeln!(self.output, "already applied"),
+                    Ok(_) => writeln!(self.output, "applied"),
+                    Err(err) => writeln!(self.output, "rejected: {:?}", err),
+                }
+                .map_err(output_error)
+            }
+            "reload" => {
+                let path = arguments
+                    .first()
+                    .ok_or_else(|| "expected a config file".to_string())?;
+                let config = Config::read(path).map_err(|err| format!("{:?}", err))?;
+                if !self.confirm("reload policy").map_err(output_error)? {
+                    return Ok(());
+                }
+                ledger.set_policy(config.policy);
+                writeln!(self.output, "{:?}", ledger.policy()).map_err(output_error)
+            }
+            "freeze" => {
+                let client_id = parse_client(arguments.first().copied())?;
+                let reason: FreezeReason = arguments
+                    .get(1)
+                    .ok_or_else(|| "expected <client> <reason>".to_string())?
+                    .parse()
+                    .map_err(|reason| format!("unknown freeze reason '{reason}'"))?;
+                if !self.confirm(command).map_err(output_error)? {
+                    return Ok(());
+                }
+                match ledger.execute(LedgerCommand::Freeze(client_id, reason)) {
+                    Ok(_) => writeln!(self.output, "done"),
+                    Err(err) => writeln!(self.output, "rejected: {:?}", err),
+                }
+                .map_err(output_error)
+            }
+            "archive" | "restore" | "quarantine" | "release" | "reactivate" | "unfreeze"
+            | "unlock" => {
+                let client_id = parse_client(arguments.first().copied())?;
+                if !self.confirm(command).map_err(output_error)? {
+                    return Ok(());
+                }
+                let command = match command {
+                    "archive" => LedgerCommand::CloseAccount(client_id),
+                    "restore" => LedgerCommand::ReopenAccount(client_id),
+                    "quarantine" => LedgerCommand::Quarantine(client_id),
+                    "reactivate" => LedgerCommand::Reactivate(client_id),
+                    "unfreeze" => LedgerCommand::Unfreeze(client_id),
+                    "unlock" => LedgerCommand::Unlock(client_id),
+                    _ => LedgerCommand::ReleaseQuarantine(client_id),
+                };
+                match ledger.execute(command) {
+                    Ok(_) => writeln!(self.output, "done"),
+                    Err(err) => writeln!(self.output, "rejected: {:?}", err),
+                }
+                .map_err(output_error)
+            }
+            _ => Err(format!("unknown command '{command}', try 'help'")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use super::Repl;
+    use crate::{account::num, account::ClientId, ledger::Ledger};
+
+    fn run(ledger: &mut Ledger, input: &str) -> String {
+        let mut output = Vec::new();
+        Repl::new(input.as_bytes(), &mut output)
+            .run(ledger)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn simulate_and_apply() {
+        let mut ledger = Ledger::new();
+        let output = run(
+            &mut ledger,
+            "simulate deposit 1 1 5.0\napply deposit 1 1 5.0\nn\napply deposit 1 1 5.0\ny\n",
+        );
+        assert_eq!(output, "> ok\n> apply? [y/N] > apply? [y/N] applied\n> ");
+        assert_eq!(ledger.account(ClientId(1)).unwrap().available(), num!(5.0));
+
+        let output = run(
+            &mut ledger,
+            "simulate withdrawal 1 2 9\nbogus\naccount 2\nquit\n",
+        );
+        assert!(output.contains("rejected: AccountError"));
+        assert!(output.contains("error: unknown command 'bogus'"));
+        assert!(output.contains("no account for client 2"));
+    }
+
+    #[test]
+    fn archive_with_confirmation() {
+        let mut ledger = Ledger::new();
+        run(&mut ledger, "apply deposit 1 1 5.0\ny\narchive 1\ny\n");
+        assert!(ledger.is_archived(ClientId(1)));
+        run(&mut ledger, "restore 1\nno\n");
+        assert!(ledger.is_archived(ClientId(1)));
+    }
+}
@@ -0,0 +1,1027 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use super::account::{Account, ClientId, Number};
+use super::digest;
+use super::ledger::{Alert, AlertRules, DisputeLetter, HeldAging, AGE_BUCKETS};
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+// How client ids appear in reports shared outside the system. Hashed ids are
+// an HMAC of the id, so the same key always yields the same pseudonym and
+// reports stay joinable.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum ClientIdFormat {
+    #[default]
+    Plain,
+    Masked,
+    Hashed(Vec<u8>),
+}
+
+impl ClientIdFormat {
+    pub fn format(&self, client_id: ClientId) -> String {
+        match self {
+            ClientIdFormat::Plain => client_id.0.to_string(),
+            ClientIdFormat::Masked => {
+                let digits = client_id.0.to_string();
+                let (hidden, shown) = digits.split_at(digits.len() - 1);
+                "*".repeat(hidden.len()) + shown
+            }
+            ClientIdFormat::Hashed(key) => {
+                digest::to_hex(&digest::hmac_sha256(key, &client_id.0.to_be_bytes())[..8])
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportOptions {
+    pub format: ReportFormat,
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+    pub client_ids: ClientIdFormat,
+    pub alerts: AlertRules,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self {
+            format: ReportFormat::default(),
+            decimal_separator: '.',
+            thousands_separator: None,
+            client_ids: ClientIdFormat::default(),
+            alerts: AlertRules::default(),
+        }
+    }
+}
+
+impl ReportOptions {
+    pub fn european() -> Self {
+        Self {
+            decimal_separator: ',',
+            thousands_separator: Some('.'),
+            ..Self::default()
+        }
+    }
+
+    // A comma decimal separator would clash with the CSV field delimiter, so
+    // those reports fall back to the semicolon spreadsheets expect in that case.
+    fn csv_delimiter(&self) -> u8 {
+        if self.decimal_separator == ',' {
+            b';'
+        } else {
+            b','
+        }
+    }
+
+    pub fn format_number(&self, number: Number) -> String {
+        let formatted = format!("{:.4}", number);
+        let (sign, digits) = match formatted.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", formatted.as_str()),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let mut result = String::with_capacity(formatted.len() + integer.len() / 3);
+        result.push_str(sign);
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                if let Some(separator) = self.thousands_separator {
+                    result.push(separator);
+                }
+            }
+            result.push(digit);
+        }
+        if !fraction.is_empty() {
+            result.push(self.decimal_separator);
+            result.push_str(fraction);
+        }
+        result
+    }
+}
+
+#[derive(serde::Serialize)]
+struct CsvAccountRecord {
+    client: String,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+impl CsvAccountRecord {
+    fn new(client_id: ClientId, account: &Account, options: &ReportOptions) -> Self {
+        Self {
+            client: options.client_ids.format(client_id),
+            available: options.format_number(account.available()),
+            held: options.format_number(account.held()),
+            total: options.format_number(account.total()),
+            locked: account.locked(),
+        }
+    }
+}
+
+// Plain client ids are numbers in JSON rows, masked and hashed ones strings.
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+enum JsonClient {
+    Id(u16),
+    Formatted(String),
+}
+
+impl JsonClient {
+    fn new(client_id: ClientId, options: &ReportOptions) -> Self {
+        match options.client_ids {
+            ClientIdFormat::Plain => JsonClient::Id(client_id.0),
+            _ => JsonClient::Formatted(options.client_ids.format(client_id)),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonAccountRecord {
+    client: JsonClient,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+#[derive(serde::Serialize)]
+struct JsonAlertRecord<'a> {
+    client: JsonClient,
+    alert: &'a str,
+    value: String,
+}
+
+// The age buckets are keys of their own, in order.
+struct JsonHeldAgingRecord {
+    client: JsonClient,
+    amounts: Vec<String>,
+}
+
+impl serde::Serialize for JsonHeldAgingRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.amounts.len() + 1))?;
+        map.serialize_entry("client", &self.client)?;
+        for (name, amount) in AGE_BUCKETS.iter().chain(&["total"]).zip(&self.amounts) {
+            map.serialize_entry(name, amount)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonDisputeLetterRecord<'a> {
+    client: JsonClient,
+    tx: u32,
+    status: &'a str,
+    amount: String,
+    opened: String,
+    date: String,
+}
+
+// One JSON object per line.
+fn write_json_row<W: io::Write>(writer: &mut W, row: &impl serde::Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, row)?;
+    writeln!(writer)
+}
+
+const DEFAULT_BUFFER_CAPACITY: usize = 64 * 1024;
+
+enum Sink<W: io::Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json(io::BufWriter<W>),
+}
+
+// Writes report rows as they are produced, so a report never needs to be
+// assembled in memory before reaching its destination.
+pub struct ReportWriter<W: io::Write> {
+    sink: Sink<W>,
+    options: ReportOptions,
+    flush_interval: Option<usize>,
+    pending_rows: usize,
+}
+
+impl<W: io::Write> ReportWriter<W> {
+    pub fn new(writer: W, options: &ReportOptions) -> Self {
+        Self::with_capacity(DEFAULT_BUFFER_CAPACITY, writer, options)
+    }
+
+    pub fn with_capacity(capacity: usize, writer: W, options: &ReportOptions) -> Self {
+        Self::build(capacity, writer, options, true)
+    }
+
+    fn build(capacity: usize, writer: W, options: &ReportOptions, headers: bool) -> Self {
+        let sink = match options.format {
+            ReportFormat::Csv => Sink::Csv(Box::new(
+                csv::WriterBuilder::new()
+                    .delimiter(options.csv_delimiter())
+                    .has_headers(headers)
+                    .buffer_capacity(capacity)
+                    .from_writer(writer),
+            )),
+            ReportFormat::Json => Sink::Json(io::BufWriter::with_capacity(capacity, writer)),
+        };
+        Self {
+            sink,
+            options: options.clone(),
+            flush_interval: None,
+            pending_rows: 0,
+        }
+    }
+
+    pub fn flush_every(mut self, rows: usize) -> Self {
+        self.flush_interval = Some(rows.max(1));
+        self
+    }
+
+    pub fn write_account(&mut self, client_id: ClientId, account: &Account) -> io::Result<()> {
+        let record = CsvAccountRecord::new(client_id, account, &self.options);
+        match &mut self.sink {
+            Sink::Csv(writer) => writer.serialize(record)?,
+            Sink::Json(writer) => write_json_row(
+                writer,
+                &JsonAccountRecord {
+                    client: JsonClient::new(client_id, &self.options),
+                    available: record.available,
+                    held: record.held,
+                    total: record.total,
+                    locked: record.locked,
+                },
+            )?,
+        }
+        self.pending_rows += 1;
+        if self
+            .flush_interval
+            .is_some_and(|interval| self.pending_rows >= interval)
+        {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn write_accounts<I>(&mut self, accounts: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = (ClientId, Account)>,
+    {
+        for (client_id, account) in accounts {
+            self.write_account(client_id, &account)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        match &self.sink {
+            Sink::Csv(writer) => writer.get_ref(),
+            Sink::Json(writer) => writer.get_ref(),
+        }
+    }
+
+    pub fn into_inner(self) -> io::Result<W> {
+        match self.sink {
+            Sink::Csv(writer) => writer.into_inner().map_err(|err| err.into_error()),
+            Sink::Json(writer) => writer.into_inner().map_err(|err| err.into_error()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.pending_rows = 0;
+        match &mut self.sink {
+            Sink::Csv(writer) => writer.flush(),
+            Sink::Json(writer) => writer.flush(),
+        }
+    }
+}
+
+pub fn write_report<W, I>(writer: W, accounts: I, options: &ReportOptions) -> io::Result<()>
+where
+    W: io::Write,
+    I: IntoIterator<Item = (ClientId, Account)>,
+{
+    let mut writer = ReportWriter::new(writer, options);
+    writer.write_accounts(accounts)?;
+    writer.flush()
+}
+
+// One row per alert: client,alert,value. Locked accounts have no value.
+pub fn write_alerts<W: io::Write>(
+    writer: W,
+    alerts: &[(ClientId, Alert)],
+    options: &ReportOptions,
+) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(writer);
+    let delimiter = options.csv_delimiter() as char;
+    if options.format == ReportFormat::Csv {
+        writeln!(writer, "client{delimiter}alert{delimiter}value")?;
+    }
+    for (client_id, alert) in alerts {
+        let client = options.client_ids.format(*client_id);
+        let (name, value) = match alert {
+            Alert::NegativeAvailable(amount) => {
+                ("negative_available", options.format_number(*amount))
+            }
+            Alert::NegativeHeld(amount) => ("negative_held", options.format_number(*amount)),
+            Alert::Locked => ("locked", String::new()),
+            Alert::Frozen(reason) => ("frozen", reason.name().to_string()),
+            Alert::HeldAbove(amount) => ("held", options.format_number(*amount)),
+            Alert::Dormant { days } => ("dormant", days.to_string()),
+        };
+        match options.format {
+            ReportFormat::Csv => writeln!(writer, "{client}{delimiter}{name}{delimiter}{value}")?,
+            ReportFormat::Json => write_json_row(
+                &mut writer,
+                &JsonAlertRecord {
+                    client: JsonClient::new(*client_id, options),
+                    alert: name,
+                    value,
+                },
+            )?,
+        }
+    }
+    writer.flush()
+}
+
+// One row per client with held funds, then one for all of them with
+// `total` as the client: client,0-30,31-60,61-90,90+,total.
+pub fn write_held_aging<W: io::Write>(
+    writer: W,
+    aging: &[(ClientId, HeldAging)],
+    options: &ReportOptions,
+) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(writer);
+    let delimiter = options.csv_delimiter() as char;
+    if options.format == ReportFormat::Csv {
+        write!(writer, "client")?;
+        for bucket in AGE_BUCKETS {
+            write!(writer, "{delimiter}{bucket}")?;
+        }
+        writeln!(writer, "{delimiter}total")?;
+    }
+    let total = HeldAging::sum(aging.iter().map(|(_, aging)| aging));
+    let clients = aging
+        .iter()
+        .map(|(client_id, aging)| (Some(*client_id), aging));
+    for (client_id, aging) in clients.chain([(None, &total)]) {
+        let client = client_id.map_or("total".to_string(), |client_id| {
+            options.client_ids.format(client_id)
+        });
+        let amounts = aging.buckets.into_iter().chain([aging.total()]);
+        match options.format {
+            ReportFormat::Csv => {
+                write!(writer, "{client}")?;
+                for amount in amounts {
+                    write!(writer, "{delimiter}{}", options.format_number(amount))?;
+                }
+                writeln!(writer)?;
+            }
+            ReportFormat::Json => write_json_row(
+                &mut writer,
+                &JsonHeldAgingRecord {
+                    client: match client_id {
+                        Some(client_id) => JsonClient::new(client_id, options),
+                        None => JsonClient::Formatted(client),
+                    },
+                    amounts: amounts
+                        .map(|amount| options.format_number(amount))
+                        .collect(),
+                },
+            )?,
+        }
+    }
+    writer.flush()
+}
+
+// One row per dispute status change, for customer letters:
+// client,tx,status,amount,opened,date. Dates are YYYY-MM-DD.
+pub fn write_dispute_letters<W: io::Write>(
+    writer: W,
+    letters: &[DisputeLetter],
+    options: &ReportOptions,
+) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(writer);
+    let delimiter = options.csv_delimiter() as char;
+    if options.format == ReportFormat::Csv {
+        writeln!(
+            writer,
+            "client{delimiter}tx{delimiter}status{delimiter}amount{delimiter}opened{delimiter}date"
+        )?;
+    }
+    for letter in letters {
+        let client = options.client_ids.format(letter.client_id);
+        let (tx, status) = (letter.transaction_id.0, letter.status.name());
+        let amount = options.format_number(letter.amount);
+        let (opened, date) = (letter.opened_at.date(), letter.changed_at.date());
+        match options.format {
+            ReportFormat::Csv => writeln!(
+                writer,
+                "{client}{delimiter}{tx}{delimiter}{status}{delimiter}{amount}{delimiter}{opened}{delimiter}{date}"
+            )?,
+            ReportFormat::Json => write_json_row(
+                &mut writer,
+                &JsonDisputeLetterRecord {
+                    client: JsonClient::new(letter.client_id, options),
+                    tx,
+                    status,
+                    amount,
+                    opened,
+                    date,
+                },
+            )?,
+        }
+    }
+    writer.flush()
+}
+
+const ROWS_PER_SHARD: usize = 64 * 1024;
+
+fn format_shard(
+    accounts: &[(ClientId, Account)],
+    options: &ReportOptions,
+    headers: bool,
+) -> io::Result<Vec<u8>> {
+    let capacity = accounts.len() * 64;
+    let mut writer = ReportWriter::build(capacity, Vec::with_capacity(capacity), options, headers);
+    writer.write_accounts(accounts.iter().copied())?;
+    writer.flush()?;
+    Ok(writer.get_ref().clone())
+}
+
+// Formats shards of accounts on `threads` threads and writes them in their
+// original order, so the output is identical to `write_report`. At most
+// `threads` shards are held in memory at a time.
+pub fn write_report_parallel<W: io::Write>(
+    mut writer: W,
+    accounts: &[(ClientId, Account)],
+    options: &ReportOptions,
+    threads: usize,
+) -> io::Result<()> {
+    let threads = threads.max(1);
+    #[cfg(feature = "rayon")]
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(io::Error::other)?;
+    let mut headers = true;
+    for batch in accounts.chunks(threads * ROWS_PER_SHARD) {
+        #[cfg(feature = "rayon")]
+        let shards = pool.install(|| format_shards(batch, options, headers))?;
+        #[cfg(not(feature = "rayon"))]
+        let shards = format_shards(batch, options, headers)?;
+        for shard in shards {
+            writer.write_all(&shard)?;
+        }
+        headers = false;
+    }
+    writer.flush()
+}
+
+#[cfg(feature = "rayon")]
+fn format_shards(
+    batch: &[(ClientId, Account)],
+    options: &ReportOptions,
+    headers: bool,
+) -> io::Result<Vec<Vec<u8>>> {
+    use rayon::prelude::*;
+    batch
+        .par_chunks(ROWS_PER_SHARD)
+        .enumerate()
+        .map(|(i, shard)| format_shard(shard, options, headers && i == 0))
+        .collect()
+}
+
+// One scoped thread per shard without rayon.
+#[cfg(not(feature = "rayon"))]
+fn format_shards(
+    batch: &[(ClientId, Account)],
+    options: &ReportOptions,
+    headers: bool,
+) -> io::Result<Vec<Vec<u8>>> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = batch
+            .chunks(ROWS_PER_SHARD)
+            .enumerate()
+            .map(|(i, shard)| {
+                let headers = headers && i == 0;
+                scope.spawn(move || format_shard(shard, options, headers))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("report shard panicked"))
+            .collect()
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct CsvReportRecord {
+    client: u16,
+    available: Number,
+    held: Number,
+    locked: bool,
+}
+
+// Reads back a report written with the default options; the total column is
+// derived from the balances and not checked.
+pub fn read_report<R: io::Read>(reader: R) -> io::Result<Vec<(ClientId, Account)>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut accounts = Vec::new();
+    for record in reader.deserialize::<CsvReportRecord>() {
+        let record = record.map_err(io::Error::other)?;
+        accounts.push((
+            ClientId(record.client),
+            Account::from_parts(record.available, record.held, record.locked),
+        ));
+    }
+    accounts.sort_by_key(|(client_id, _)| *client_id);
+    Ok(accounts)
+}
+
+#[derive(serde::Deserialize)]
+struct CsvPortfolioRecord {
+    client: u16,
+    portfolio: String,
+}
+
+// Reads a client,portfolio mapping. A client listed twice belongs to the
+// last portfolio given.
+pub fn read_portfolios<R: io::Read>(reader: R) -> io::Result<BTreeMap<ClientId, String>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut portfolios = BTreeMap::new();
+    for record in reader.deserialize::<CsvPortfolioRecord>() {
+        let record = record.map_err(io::Error::other)?;
+        portfolios.insert(ClientId(record.client), record.portfolio);
+    }
+    Ok(portfolios)
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PortfolioRollup {
+    pub accounts: usize,
+    pub available: Number,
+    pub held: Number,
+    pub locked: usize,
+}
+
+// Totals per portfolio. Clients missing from the mapping are rolled up
+// under `None`.
+pub fn portfolio_rollups<I>(
+    accounts: I,
+    portfolios: &BTreeMap<ClientId, String>,
+) -> BTreeMap<Option<String>, PortfolioRollup>
+where
+    I: IntoIterator<Item = (ClientId, Account)>,
+{
+    let mut rollups: BTreeMap<Option<String>, PortfolioRollup> = BTreeMap::new();
+    for (client_id, account) in accounts {
+        let rollup = rollups
+            .entry(portfolios.get(&client_id).cloned())
+            .or_default();
+        rollup.accounts += 1;
+        rollup.available += account.available();
+        rollup.held += account.held();
+        rollup.locked += account.locked() as usize;
+    }
+    rollups
+}
+
+// One row per portfolio: portfolio,accounts,available,held,total,locked.
+// Unassigned clients come first, with an empty portfolio.
+pub fn write_portfolio_rollups<W: io::Write>(
+    writer: W,
+    rollups: &BTreeMap<Option<String>, PortfolioRollup>,
+    options: &ReportOptions,
+) -> io::Result<()> {
+    let rows = rollups.iter().map(|(portfolio, rollup)| {
+        (
+            portfolio.as_deref().unwrap_or_default(),
+            rollup,
+            options.format_number(rollup.available),
+            options.format_number(rollup.held),
+            options.format_number(rollup.available + rollup.held),
+        )
+    });
+    if options.format == ReportFormat::Json {
+        let mut writer = io::BufWriter::new(writer);
+        for (portfolio, rollup, available, held, total) in rows {
+            let row = serde_json::json!({
+                "portfolio": portfolio,
+                "accounts": rollup.accounts,
+                "available": available,
+                "held": held,
+                "total": total,
+                "locked": rollup.locked,
+            });
+            writeln!(writer, "{row}")?;
+        }
+        return writer.flush();
+    }
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.csv_delimiter())
+        .from_writer(writer);
+    writer.write_record([
+        "portfolio",
+        "accounts",
+        "available",
+        "held",
+        "total",
+        "locked",
+    ])?;
+    for (portfolio, rollup, available, held, total) in rows {
+        writer.write_record([
+            portfolio,
+            &rollup.accounts.to_string(),
+            &available,
+            &held,
+            &total,
+            &rollup.locked.to_string(),
+        ])?;
+    }
+    writer.flush()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReportDifference {
+    Removed(ClientId, Account),
+    Added(ClientId, Account),
+    Changed {
+        client_id: ClientId,
+        before: Account,
+        after: Account,
+    },
+}
+
+impl std::fmt::Display for ReportDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |account: &Account| {
+            format!(
+                "available {} held {} locked {}",
+                account.available(),
+                account.held(),
+                account.locked()
+            )
+        };
+        match self {
+            ReportDifference::Removed(client_id, account) => {
+                write!(f, "- {}: {}", client_id.0, describe(account))
+            }
+            ReportDifference::Added(client_id, account) => {
+                write!(f, "+ {}: {}", client_id.0, describe(account))
+            }
+            ReportDifference::Changed {
+                client_id,
+                before,
+                after,
+            } => write!(
+                f,
+                "~ {}: {} -> {}",
+                client_id.0,
+                describe(before),
+                describe(after)
+            ),
+        }
+    }
+}
+
+// Compares two reports client by client. Balances that differ by no more
+// than `tolerance` are considered equal.
+pub fn diff_reports(
+    before: &[(ClientId, Account)],
+    after: &[(ClientId, Account)],
+    tolerance: Number,
+) -> Vec<ReportDifference> {
+    let close = |a: Number, b: Number| (a - b).abs() <= tolerance;
+    let after: BTreeMap<ClientId, Account> = after.iter().copied().collect();
+    let mut differences = Vec::new();
+    let mut seen = BTreeSet::new();
+    for &(client_id, before) in before {
+        seen.insert(client_id);
+        match after.get(&client_id) {
+            None => differences.push(ReportDifference::Removed(client_id, before)),
+            Some(&after)
+                if !close(before.available(), after.available())
+                    || !close(before.held(), after.held())
+                    || before.locked() != after.locked() =>
+            {
+                differences.push(ReportDifference::Changed {
+                    client_id,
+                    before,
+                    after,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (client_id, account) in after {
+        if !seen.contains(&client_id) {
+            differences.push(ReportDifference::Added(client_id, account));
+        }
+    }
+    differences.sort_by_key(|difference| match difference {
+        ReportDifference::Removed(client_id, _)
+        | ReportDifference::Added(client_id, _)
+        | ReportDifference::Changed { client_id, .. } => *client_id,
+    });
+    differences
+}
+
+#[cfg(test)]
+mod report_tests {
+    use super::{
+        diff_reports, portfolio_rollups, read_portfolios, read_report, write_alerts,
+        write_dispute_letters, write_held_aging, write_portfolio_rollups, write_report,
+        write_report_parallel, ClientIdFormat, ReportDifference, ReportFormat, ReportOptions,
+        ReportWriter,
+    };
+    use crate::account::{num, Account, ClientId};
+    use crate::ledger::{Alert, Ledger, Timestamp};
+    use crate::transactions::{Operation, Transaction, TransactionId};
+
+    #[test]
+    fn alerts_report() {
+        let alerts = [
+            (ClientId(1), Alert::NegativeAvailable(num!(-1234.5))),
+            (ClientId(2), Alert::Locked),
+            (ClientId(3), Alert::Dormant { days: 90 }),
+        ];
+        let mut output = Vec::new();
+        write_alerts(&mut output, &alerts, &ReportOptions::european()).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client;alert;value\n1;negative_available;-1.234,5000\n2;locked;\n3;dormant;90\n"
+        );
+        let options = ReportOptions {
+            format: ReportFormat::Json,
+            ..ReportOptions::default()
+        };
+        let mut output = Vec::new();
+        write_alerts(&mut output, &alerts[1..2], &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"client\":2,\"alert\":\"locked\",\"value\":\"\"}\n"
+        );
+    }
+
+    #[test]
+    fn held_aging_report() {
+        const DAY: u64 = 24 * 60 * 60;
+        let mut ledger = Ledger::new();
+        for (id, client, amount) in [(1, 1, num!(10)), (2, 1, num!(20)), (3, 2, num!(5))] {
+            let deposit = Transaction::new(ClientId(client), amount, Operation::Deposit);
+            assert!(ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .is_ok());
+        }
+        // disputed 95, 45 and 30 days before the report
+        for (id, client, day) in [(1, 1, 0), (2, 1, 50), (3, 2, 65)] {
+            ledger.set_time(Timestamp(day * DAY));
+            let dispute = Transaction::new(ClientId(client), num!(0), Operation::Dispute);
+            assert!(ledger
+                .apply_transaction(TransactionId(id), &dispute)
+                .is_ok());
+        }
+        ledger.set_time(Timestamp(95 * DAY + DAY / 2));
+
+        let mut output = Vec::new();
+        write_held_aging(&mut output, &ledger.held_aging(), &ReportOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,0-30,31-60,61-90,90+,total
+\
+             1,0.0000,20.0000,0.0000,10.0000,30.0000
+\
+             2,5.0000,0.0000,0.0000,0.0000,5.0000
+\
+             total,5.0000,20.0000,0.0000,10.0000,35.0000
+"
+        );
+        let options = ReportOptions {
+            format: ReportFormat::Json,
+            ..ReportOptions::default()
+        };
+        let mut output = Vec::new();
+        write_held_aging(&mut output, &ledger.held_aging()[1..], &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"client\":2,\"0-30\":\"5.0000\",\"31-60\":\"0.0000\",\"61-90\":\"0.0000\",\"90+\":\"0.0000\",\"total\":\"5.0000\"}\n\
+             {\"client\":\"total\",\"0-30\":\"5.0000\",\"31-60\":\"0.0000\",\"61-90\":\"0.0000\",\"90+\":\"0.0000\",\"total\":\"5.0000\"}\n"
+        );
+    }
+
+    #[test]
+    fn dispute_letters_feed() {
+        const DAY: u64 = 24 * 60 * 60;
+        let mut ledger = Ledger::new();
+        for (id, amount) in [(1, num!(10)), (2, num!(2.5))] {
+            let deposit = Transaction::new(ClientId(1), amount, Operation::Deposit);
+            assert!(ledger
+                .apply_transaction(TransactionId(id), &deposit)
+                .is_ok());
+        }
+        // 2024-03-01, then resolved and disputed again ten days later
+        ledger.set_time(Timestamp(19_783 * DAY));
+        for (id, operation) in [(1, Operation::Dispute), (2, Operation::Dispute)] {
+            let row = Transaction::new(ClientId(1), num!(0), operation);
+            assert!(ledger.apply_transaction(TransactionId(id), &row).is_ok());
+        }
+        ledger.advance_time(10 * DAY);
+        for (id, operation) in [(1, Operation::Resolve), (2, Operation::Chargeback)] {
+            let row = Transaction::new(ClientId(1), num!(0), operation);
+            assert!(ledger.apply_transaction(TransactionId(id), &row).is_ok());
+        }
+
+        let mut output = Vec::new();
+        write_dispute_letters(
+            &mut output,
+            &ledger.dispute_letters(),
+            &ReportOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,tx,status,amount,opened,date\n\
+             1,1,disputed,10.0000,2024-03-01,2024-03-01\n\
+             1,2,disputed,2.5000,2024-03-01,2024-03-01\n\
+             1,1,resolved,10.0000,2024-03-01,2024-03-11\n\
+             1,2,chargedback,2.5000,2024-03-01,2024-03-11\n"
+        );
+    }
+
+    #[test]
+    fn portfolio_rollup_report() {
+        let portfolios =
+            read_portfolios("client,portfolio\n1,retail\n2,retail\n3,\"smb, east\"\n".as_bytes())
+                .unwrap();
+        let locked = Account::from_parts(num!(3), num!(1), true);
+        let accounts = [
+            (ClientId(1), Account::from_parts(num!(10), num!(0), false)),
+            (ClientId(2), Account::from_parts(num!(2.5), num!(1), false)),
+            (ClientId(3), locked),
+            (ClientId(4), Account::from_parts(num!(1), num!(0), false)),
+        ];
+        let rollups = portfolio_rollups(accounts, &portfolios);
+        let mut output = Vec::new();
+        write_portfolio_rollups(&mut output, &rollups, &ReportOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "portfolio,accounts,available,held,total,locked\n\
+             ,1,1.0000,0.0000,1.0000,0\n\
+             retail,2,12.5000,1.0000,13.5000,0\n\
+             \"smb, east\",1,3.0000,1.0000,4.0000,1\n"
+        );
+        assert!(read_portfolios("client,portfolio\nx,retail\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn format_numbers() {
+        let default = ReportOptions::default();
+        assert_eq!(default.format_number(num!(1234.5678)), "1234.5678");
+        assert_eq!(default.format_number(num!(-1.5)), "-1.5000");
+
+        let european = ReportOptions::european();
+        assert_eq!(european.format_number(num!(1234.5678)), "1.234,5678");
+        assert_eq!(european.format_number(num!(-1234567)), "-1.234.567,0000");
+        assert_eq!(european.format_number(num!(123)), "123,0000");
+    }
+
+    #[test]
+    fn european_reports() {
+        let mut account = Account::default();
+        account.deposit(num!(1234.5)).unwrap();
+        let accounts = [(ClientId(1), account)];
+
+        let mut csv = Vec::new();
+        write_report(&mut csv, accounts, &ReportOptions::european()).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "client;available;held;total;locked\n1;1.234,5000;0,0000;1.234,5000;false\n"
+        );
+
+        let options = ReportOptions {
+            format: ReportFormat::Json,
+            ..ReportOptions::european()
+        };
+        let mut json = Vec::new();
+        write_report(&mut json, accounts, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"client\":1,\"available\":\"1.234,5000\",\"held\":\"0,0000\",\"total\":\"1.234,5000\",\"locked\":false}\n"
+        );
+
+        // Separators are escaped like any other string contents.
+        let options = ReportOptions {
+            thousands_separator: Some('"'),
+            ..options
+        };
+        let mut json = Vec::new();
+        write_report(&mut json, accounts, &options).unwrap();
+        let row: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(row["available"], "1\"234,5000");
+    }
+
+    #[test]
+    fn flush_every_n_rows() {
+        let header_and_two_rows = "client,available,held,total,locked\n1,0.0000,0.0000,0.0000,false\n2,0.0000,0.0000,0.0000,false\n";
+        let mut writer =
+            ReportWriter::with_capacity(1024, Vec::new(), &ReportOptions::default()).flush_every(2);
+        writer
+            .write_account(ClientId(1), &Account::default())
+            .unwrap();
+        assert!(writer.get_ref().is_empty());
+        writer
+            .write_account(ClientId(2), &Account::default())
+            .unwrap();
+        assert_eq!(writer.get_ref().as_slice(), header_and_two_rows.as_bytes());
+        writer
+            .write_account(ClientId(3), &Account::default())
+            .unwrap();
+        assert_eq!(writer.get_ref().as_slice(), header_and_two_rows.as_bytes());
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref().len(), header_and_two_rows.len() + 29);
+    }
+
+    #[test]
+    fn diff_with_tolerance() {
+        let before = "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n2,2.0000,0.0000,2.0000,false\n3,3.0000,0.0000,3.0000,false\n";
+        let after = "client,available,held,total,locked\n3,3.0000,0.0000,3.0000,true\n1,1.00001,0.0000,1.00001,false\n4,4.0000,0.0000,4.0000,false\n";
+        let before = read_report(before.as_bytes()).unwrap();
+        let after = read_report(after.as_bytes()).unwrap();
+
+        let differences = diff_reports(&before, &after, num!(0.0001));
+        assert_eq!(
+            differences,
+            vec![
+                ReportDifference::Removed(ClientId(2), before[1].1),
+                ReportDifference::Changed {
+                    client_id: ClientId(3),
+                    before: before[2].1,
+                    after: after[1].1
+                },
+                ReportDifference::Added(ClientId(4), after[2].1),
+            ]
+        );
+        assert_eq!(
+            differences[1].to_string(),
+            "~ 3: available 3.0000 held 0.0000 locked false -> available 3.0000 held 0.0000 locked true"
+        );
+        assert_eq!(diff_reports(&before, &after, num!(0)).len(), 4);
+    }
+
+    #[test]
+    fn pseudonymize_client_ids() {
+        assert_eq!(ClientIdFormat::Masked.format(ClientId(1234)), "***4");
+        assert_eq!(ClientIdFormat::Masked.format(ClientId(7)), "7");
+        let hashed = ClientIdFormat::Hashed(b"key".to_vec());
+        assert_eq!(hashed.format(ClientId(1)).len(), 16);
+        assert_eq!(hashed.format(ClientId(1)), hashed.format(ClientId(1)));
+        assert_ne!(hashed.format(ClientId(1)), hashed.format(ClientId(2)));
+        assert_ne!(
+            hashed.format(ClientId(1)),
+            ClientIdFormat::Hashed(b"other".to_vec()).format(ClientId(1))
+        );
+
+        let options = ReportOptions {
+            format: ReportFormat::Json,
+            client_ids: ClientIdFormat::Masked,
+            ..ReportOptions::default()
+        };
+        let mut json = Vec::new();
+        write_report(&mut json, [(ClientId(42), Account::default())], &options).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"client\":\"*2\",\"available\":\"0.0000\",\"held\":\"0.0000\",\"total\":\"0.0000\",\"locked\":false}\n"
+        );
+    }
+
+    #[test]
+    fn parallel_report_matches_sequential() {
+        let accounts: Vec<(ClientId, Account)> = (0..u16::MAX)
+            .map(|id| {
+                let mut account = Account::default();
+                account
+                    .deposit(num!(1.5) * rust_decimal::Decimal::from(id))
+                    .unwrap();
+                (ClientId(id), account)
+            })
+            .collect();
+        for options in [
+            ReportOptions::default(),
+            ReportOptions {
+                format: ReportFormat::Json,
+                ..ReportOptions::default()
+            },
+        ] {
+            let mut sequential = Vec::new();
+            write_report(&mut sequential, accounts.iter().copied(), &options).unwrap();
+            let mut parallel = Vec::new();
+            write_report_parallel(&mut parallel, &accounts, &options, 3).unwrap();
+            assert!(sequential == parallel);
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::fmt;
+
+use serde_json::{json, Map, Value};
+
+use super::account::parse_amount;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FieldKind {
+    Integer { max: u64 },
+    // Decimal amounts, written as strings so they keep their exact value.
+    Amount,
+    Boolean,
+    // Plain client ids in reports, or pseudonyms when they are masked or
+    // hashed.
+    ClientId,
+    Hex,
+    // Three letter currency codes.
+    Currency,
+    OneOf(&'static [&'static str]),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+// The columns of a CSV file, or the keys of one JSON line, in order.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Schema {
+    pub title: &'static str,
+    pub fields: &'static [Field],
+}
+
+pub const TRANSACTION_INPUT: Schema = Schema {
+    title: "transaction",
+    fields: &[
+        Field {
+            name: "type",
+            kind: FieldKind::OneOf(&[
+                "deposit",
+                "withdrawal",
+                "dispute",
+                "resolve",
+                "chargeback",
+                "adjustment",
+                "open",
+            ]),
+            required: true,
+            description: "operation to apply",
+        },
+        Field {
+            name: "client",
+            kind: FieldKind::Integer {
+                max: u16::MAX as u64,
+            },
+            required: true,
+            description: "client id",
+        },
+        Field {
+            name: "tx",
+            kind: FieldKind::Integer {
+                max: u32::MAX as u64,
+            },
+            required: false,
+            description: "transaction id, derived from the row when missing",
+        },
+        Field {
+            name: "amount",
+            kind: FieldKind::Amount,
+            required: false,
+            description: "deposits, withdrawals and adjustments only",
+        },
+        Field {
+            name: "signature",
+            kind: FieldKind::Hex,
+            required: false,
+            description: "hex signature, checked when a verifier is set",
+        },
+        Field {
+            name: "currency",
+            kind: FieldKind::Currency,
+            required: false,
+            description: "currency of an account being opened",
+        },
+        Field {
+            name: "tier",
+            kind: FieldKind::OneOf(&["basic", "verified", "premium"]),
+            required: false,
+            description: "tier of an account being opened",
+        },
+        Field {
+            name: "overdraft_limit",
+            kind: FieldKind::Amount,
+            required: false,
+            description: "how far below zero withdrawals may take an account being opened",
+        },
+    ],
+};
+
+pub const ACCOUNT_OUTPUT: Schema = Schema {
+    title: "account",
+    fields: &[
+        Field {
+            name: "client",
+            kind: FieldKind::ClientId,
+            required: true,
+            description: "client id, or its pseudonym",
+        },
+        Field {
+            name: "available",
+            kind: FieldKind::Amount,
+            required: true,
+            description: "funds available for withdrawal",
+        },
+        Field {
+            name: "held",
+            kind: FieldKind::Amount,
+            required: true,
+            description: "funds held by disputes",
+        },
+        Field {
+            name: "total",
+            kind: FieldKind::Amount,
+            required: true,
+            description: "available plus held",
+        },
+        Field {
+            name: "locked",
+            kind: FieldKind::Boolean,
+            required: true,
+            description: "frozen after a chargeback",
+        },
+    ],
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaError {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl FieldKind {
+    fn to_json_schema(self) -> Value {
+        match self {
+            FieldKind::Integer { max } => json!({"type": "integer", "minimum": 0, "maximum": max}),
+            FieldKind::Amount => {
+                json!({"type": ["string", "number"], "pattern": "^-?[0-9]+(\\.[0-9]+)?$"})
+            }
+            FieldKind::Boolean => json!({"type": "boolean"}),
+            FieldKind::ClientId => json!({"type": ["integer", "string"]}),
+            FieldKind::Hex => json!({"type": "string", "pattern": "^([0-9a-fA-F]{2})*$"}),
+            FieldKind::Currency => json!({"type": "string", "pattern": "^[A-Za-z]{3}$"}),
+            FieldKind::OneOf(values) => json!({"type": "string", "enum": values}),
+        }
+    }
+
+    fn check(self, value: &Value) -> Result<(), String> {
+        let valid = match (self, value) {
+            (FieldKind::Integer { max }, Value::Number(number)) => {
+                number.as_u64().is_some_and(|number| number <= max)
+            }
+            (FieldKind::Amount, Value::String(amount)) => parse_amount(amount).is_ok(),
+            (FieldKind::Amount, Value::Number(amount)) => parse_amount(&amount.to_string()).is_ok(),
+            (FieldKind::Boolean, Value::Bool(_)) => true,
+            (FieldKind::ClientId, Value::Number(number)) => number
+                .as_u64()
+                .is_some_and(|number| number <= u16::MAX as u64),
+            (FieldKind::ClientId, Value::String(_)) => true,
+            (FieldKind::Hex, Value::String(hex)) => {
+                hex.len() % 2 == 0 && hex.chars().all(|c| c.is_ascii_hexdigit())
+            }
+            (FieldKind::Currency, Value::String(code)) => {
+                code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic())
+            }
+            (FieldKind::OneOf(values), Value::String(value)) => values.contains(&value.as_str()),
+            _ => false,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("expected {}, got {value}", self.to_json_schema()))
+        }
+    }
+}
+
+impl Schema {
+    pub fn to_json_schema(&self) -> Value {
+        let mut properties = Map::new();
+        for field in self.fields {
+            let mut property = field.kind.to_json_schema();
+            property["description"] = json!(field.description);
+            properties.insert(field.name.to_string(), property);
+        }
+        let required: Vec<_> = self
+            .fields
+            .iter()
+            .filter(|field| field.required)
+            .map(|field| field.name)
+            .collect();
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": self.title,
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false,
+        })
+    }
+
+    // Null counts as missing, like an empty CSV column.
+    pub fn validate(&self, value: &Value) -> Result<(), Vec<SchemaError>> {
+        let error = |field: &str, message: String| SchemaError {
+            field: field.to_string(),
+            message,
+        };
+        let Value::Object(object) = value else {
+            return Err(vec![error("", format!("expected an object, got {value}"))]);
+        };
+        let mut errors = Vec::new();
+        for field in self.fields {
+            match object.get(field.name) {
+                None | Some(Value::Null) if field.required => {
+                    errors.push(error(field.name, "missing".to_string()))
+                }
+                None | Some(Value::Null) => {}
+                Some(value) => {
+                    if let Err(message) = field.kind.check(value) {
+                        errors.push(error(field.name, message));
+                    }
+                }
+            }
+        }
+        for key in object.keys() {
+            if !self.fields.iter().any(|field| field.name == key) {
+                errors.push(error(key, "unknown field".to_string()));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Checks a file of JSON lines, returning the errors of every line that
+    // doesn't match, numbered from 1.
+    pub fn validate_lines<R: std::io::BufRead>(
+        &self,
+        reader: R,
+    ) -> std::io::Result<Vec<(usize, SchemaError)>> {
+        let mut errors = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let result = match serde_json::from_str::<Value>(&line) {
+                Ok(value) => self.validate(&value),
+                Err(err) => Err(vec![SchemaError {
+                    field: String::new(),
+                    message: err.to_string(),
+                }]),
+            };
+            if let Err(line_errors) = result {
+                errors.extend(line_errors.into_iter().map(|err| (index + 1, err)));
+            }
+        }
+        Ok(errors)
+    }
+}
+
+#[cfg(test)]
+mod schema_tests {
+    use super::{SchemaError, ACCOUNT_OUTPUT, TRANSACTION_INPUT};
+    use serde_json::json;
+
+    #[test]
+    fn emit_json_schema() {
+        let schema = TRANSACTION_INPUT.to_json_schema();
+        assert_eq!(schema["title"], "transaction");
+        assert_eq!(schema["required"], json!(["type", "client"]));
+        assert_eq!(schema["properties"]["client"]["maximum"], 65535);
+        assert_eq!(schema["properties"]["type"]["enum"][5], "adjustment");
+        assert_eq!(
+            ACCOUNT_OUTPUT.to_json_schema()["properties"]["locked"]["type"],
+            "boolean"
+        );
+    }
+
+    #[test]
+    fn validate_json() {
+        let deposit = json!({"type": "deposit", "client": 1, "tx": 7, "amount": "1.5"});
+        assert_eq!(TRANSACTION_INPUT.validate(&deposit), Ok(()));
+        let dispute = json!({"type": "dispute", "client": 1, "tx": 7, "amount": null});
+        assert_eq!(TRANSACTION_INPUT.validate(&dispute), Ok(()));
+        let errors = TRANSACTION_INPUT
+            .validate(&json!({"type": "refund", "client": 70000, "memo": "x"}))
+            .unwrap_err();
+        let fields: Vec<_> = errors.iter().map(|err| err.field.as_str()).collect();
+        assert_eq!(fields, ["type", "client", "memo"]);
+
+        let lines = "{\"client\":1,\"available\":\"1.0000\",\"held\":\"0\",\"total\":\"1\",\"locked\":false}\n\nnot json\n";
+        let errors = ACCOUNT_OUTPUT.validate_lines(lines.as_bytes()).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 3);
+        assert!(matches!(&errors[0].1, SchemaError { field, .. } if field.is_empty()));
+    }
+}
@@ -0,0 +1,317 @@
+use std::fs;
+
+use toml_edit::{Document, Item};
+
+use super::account::{ClientId, Number};
+use super::config::{amount, boolean, integer, invalid, section, string, Config, ConfigError};
+use super::ledger::{Finalizer, Ledger, Policy, SECONDS_PER_DAY};
+use super::transactions::{Operation, Transaction, TransactionId};
+
+// End-to-end scenarios written down as TOML rather than code: transactions,
+// clock advances and checks run in order against a ledger, so behaviour that
+// depends on time, like dispute expiry, held accrual or dormancy, can be
+// described step by step. Enabled with the `fixtures` feature.
+//
+//   [policy]                    # as in the configuration file
+//   dormant_after_days = 30
+//   [finalize]                  # likewise
+//   resolve_disputes_after_days = 10
+//   [[step]]
+//   apply = "deposit"
+//   client = 1
+//   tx = 1
+//   amount = "10"
+//   [[step]]
+//   apply = "withdrawal"
+//   client = 1
+//   tx = 2
+//   amount = "20"
+//   rejected = true             # the ledger has to reject it
+//   [[step]]
+//   advance_days = 11           # or advance_seconds
+//   [[step]]
+//   finalize = true
+//   [[step]]
+//   expect_client = 1
+//   available = "10"            # any of available, held and locked
+//   [[step]]
+//   expect_tx = 1
+//   state = "resolved"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Script {
+    pub policy: Policy,
+    pub finalizers: Vec<Finalizer>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Apply {
+        transaction_id: TransactionId,
+        transaction: Transaction,
+        rejected: bool,
+    },
+    // In seconds.
+    AdvanceTime(u64),
+    Finalize,
+    ExpectAccount {
+        client_id: ClientId,
+        available: Option<Number>,
+        held: Option<Number>,
+        locked: Option<bool>,
+    },
+    // The state by its name, see `TransactionState::name`.
+    ExpectState {
+        transaction_id: TransactionId,
+        state: String,
+    },
+}
+
+// The first step that didn't go as written, numbered from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepFailure {
+    pub step: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for StepFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step {}: {}", self.step, self.message)
+    }
+}
+
+fn step(item: &Item) -> Result<Step, ConfigError> {
+    if let Some(operation) = string(item, "apply")? {
+        let operation: Operation = operation.parse().map_err(|_| invalid("apply"))?;
+        let client_id = ClientId(integer(item, "client")?.ok_or_else(|| invalid("client"))?);
+        let operation = operation.with_recipient(integer(item, "to")?.map(ClientId));
+        let amount = amount(item, "amount")?.unwrap_or_default();
+        return Ok(Step::Apply {
+            transaction_id: TransactionId(integer(item, "tx")?.ok_or_else(|| invalid("tx"))?),
+            transaction: Transaction::new(client_id, amount, operation),
+            rejected: boolean(item, "rejected")?.unwrap_or_default(),
+        });
+    }
+    if let Some(seconds) = integer(item, "advance_seconds")? {
+        return Ok(Step::AdvanceTime(seconds));
+    }
+    if let Some(days) = integer::<u64>(item, "advance_days")? {
+        return Ok(Step::AdvanceTime(days.saturating_mul(SECONDS_PER_DAY)));
+    }
+    if boolean(item, "finalize")?.unwrap_or_default() {
+        return Ok(Step::Finalize);
+    }
+    if let Some(client_id) = integer(item, "expect_client")? {
+        return Ok(Step::ExpectAccount {
+            client_id: ClientId(client_id),
+            available: amount(item, "available")?,
+            held: amount(item, "held")?,
+            locked: boolean(item, "locked")?,
+        });
+    }
+    if let Some(transaction_id) = integer(item, "expect_tx")? {
+        return Ok(Step::ExpectState {
+            transaction_id: TransactionId(transaction_id),
+            state: string(item, "state")?.ok_or_else(|| invalid("state"))?,
+        });
+    }
+    Err(invalid("step"))
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config = Config::parse(text)?;
+        let document: Document = text
+            .parse()
+            .map_err(|err: toml_edit::TomlError| ConfigError::Syntax(err.to_string()))?;
+        let steps = match section(document.as_item(), "step") {
+            Item::None => Vec::new(),
+            steps => steps
+                .as_array_of_tables()
+                .ok_or_else(|| invalid("step"))?
+                .iter()
+                .map(|table| step(&Item::Table(table.clone())))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Script {
+            policy: config.policy,
+            finalizers: config.finalizers,
+            steps,
+        })
+    }
+
+    pub fn read(path: &str) -> Result<Self, ConfigError> {
+        Self::parse(&fs::read_to_string(path).map_err(|err| ConfigError::Io(err.to_string()))?)
+    }
+
+    // Runs the steps against a new ledger with the script's policy and
+    // finalizers, returning the ledger for further checks.
+    pub fn run(&self) -> Result<Ledger, StepFailure> {
+        let mut ledger = Ledger::with_policy(self.policy);
+        for finalizer in &self.finalizers {
+            ledger.add_finalizer(*finalizer);
+        }
+        self.run_on(ledger)
+    }
+
+    // Runs the steps against `ledger`, as it is configured.
+    pub fn run_on(&self, mut ledger: Ledger) -> Result<Ledger, StepFailure> {
+        for (index, step) in self.steps.iter().enumerate() {
+            run_step(&mut ledger, step).map_err(|message| StepFailure {
+                step: index + 1,
+                message,
+            })?;
+        }
+        Ok(ledger)
+    }
+}
+
+fn expect<T: PartialEq + std::fmt::Debug>(
+    what: &str,
+    expected: Option<T>,
+    actual: T,
+) -> Result<(), String> {
+    match expected {
+        Some(expected) if expected != actual => {
+            Err(format!("expected {what} {expected:?}, got {actual:?}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn run_step(ledger: &mut Ledger, step: &Step) -> Result<(), String> {
+    match step {
+        Step::Apply {
+            transaction_id,
+            transaction,
+            rejected,
+        } => match (
+            ledger.apply_transaction(*transaction_id, transaction),
+            *rejected,
+        ) {
+            (Ok(_), false) | (Err(_), true) => Ok(()),
+            (Ok(outcome), true) => Err(format!(
+                "expected {transaction_id:?} to be rejected, got {outcome:?}"
+            )),
+            (Err(err), false) => Err(format!("{transaction_id:?} was rejected: {err:?}")),
+        },
+        Step::AdvanceTime(seconds) => {
+            ledger.advance_time(*seconds);
+            Ok(())
+        }
+        Step::Finalize => {
+            ledger.finalize();
+            Ok(())
+        }
+        Step::ExpectAccount {
+            client_id,
+            available,
+            held,
+            locked,
+        } => {
+            let account = ledger
+                .account(*client_id)
+                .ok_or_else(|| format!("no account for {client_id:?}"))?;
+            expect("available", *available, account.available())?;
+            expect("held", *held, account.held())?;
+            expect("locked", *locked, account.locked())
+        }
+        Step::ExpectState {
+            transaction_id,
+            state,
+        } => {
+            let transaction = ledger
+                .transaction(*transaction_id)
+                .ok_or_else(|| format!("no transaction {transaction_id:?}"))?;
+            expect("state", Some(state.as_str()), transaction.state().name())
+        }
+    }
+}
+
+#[cfg(test)]
+mod script_tests {
+    use super::{Script, Step, StepFailure};
+    use crate::account::num;
+    use crate::ledger::Finalizer;
+
+    #[test]
+    fn expired_disputes_are_resolved() {
+        let script = Script::parse(
+            r#"
+            [finalize]
+            resolve_disputes_after_days = 10
+            [[step]]
+            apply = "deposit"
+            client = 1
+            tx = 1
+            amount = "10"
+            [[step]]
+            apply = "dispute"
+            client = 1
+            tx = 1
+            [[step]]
+            advance_days = 5
+            [[step]]
+            finalize = true
+            [[step]]
+            expect_tx = 1
+            state = "disputed"
+            [[step]]
+            advance_days = 6
+            [[step]]
+            finalize = true
+            [[step]]
+            expect_tx = 1
+            state = "resolved"
+            [[step]]
+            expect_client = 1
+            available = "10"
+            held = "0"
+            locked = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            script.finalizers,
+            [Finalizer::ResolveExpiredDisputes { after_days: 10 }]
+        );
+        assert_eq!(script.steps[2], Step::AdvanceTime(5 * 24 * 60 * 60));
+        let ledger = script.run().unwrap();
+        assert_eq!(ledger.now().day(), 11);
+    }
+
+    #[test]
+    fn report_the_failing_step() {
+        let script = Script::parse(
+            r#"
+            [policy]
+            dormant_after_days = 30
+            [[step]]
+            apply = "deposit"
+            client = 1
+            tx = 1
+            amount = "10"
+            [[step]]
+            advance_days = 31
+            [[step]]
+            apply = "withdrawal"
+            client = 1
+            tx = 2
+            amount = "1"
+            rejected = true
+            [[step]]
+            expect_client = 1
+            available = "9"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            script.run().err(),
+            Some(StepFailure {
+                step: 4,
+                message: format!("expected available {:?}, got {:?}", num!(9), num!(10)),
+            })
+        );
+        assert!(Script::parse("[[step]]\nwait = 1\n").is_err());
+    }
+}
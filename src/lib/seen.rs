@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+use super::ledger::{Ledger, TransactionMiddleware};
+use super::transactions::{
+    Operation, Outcome, Transaction, TransactionError, TransactionId, TransactionResult,
+};
+
+// Answers "maybe seen" or "certainly not seen" from memory. False positives
+// only cost a read from the file behind it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    const HASHES: u64 = 7;
+
+    // About ten bits per id keeps false positives near one percent until the
+    // filter holds `capacity` ids.
+    fn with_capacity(capacity: usize) -> Self {
+        let words = (capacity.max(1 << 12) * 10).div_ceil(64);
+        BloomFilter {
+            bits: vec![0; words],
+        }
+    }
+
+    fn positions(&self, id: u32) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64 * 64;
+        let first = mix(id as u64);
+        let second = mix(first) | 1;
+        (0..Self::HASHES).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    fn insert(&mut self, id: u32) {
+        for bit in self.positions(id).collect::<Vec<_>>() {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    fn may_contain(&self, id: u32) -> bool {
+        self.positions(id)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// Every transaction id ever applied, kept in a file so it outlives the
+// process and whatever the ledger forgets: snapshots that pruned old
+// transactions, ledgers rebuilt from scratch. The file is a bitmap with one
+// bit per id, so looking an id up reads a single byte and the file only grows
+// as far as the largest id seen.
+pub struct SeenIds {
+    // None for a read-only set whose file doesn't exist yet.
+    file: Option<fs::File>,
+    len: u64,
+    bloom: BloomFilter,
+    count: usize,
+    // Bytes of the bitmap changed in memory only, for read-only sets.
+    unwritten: Option<HashMap<u64, u8>>,
+}
+
+impl SeenIds {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Self::load(Some(file), None)
+    }
+
+    // Checks ids against the file but keeps the ones inserted in memory, for
+    // validating a file without marking its ids as seen.
+    pub fn open_read_only(path: &str) -> io::Result<Self> {
+        let file = match fs::File::open(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            file => Some(file?),
+        };
+        Self::load(file, Some(HashMap::new()))
+    }
+
+    fn load(mut file: Option<fs::File>, unwritten: Option<HashMap<u64, u8>>) -> io::Result<Self> {
+        let mut bitmap = Vec::new();
+        if let Some(file) = &mut file {
+            file.read_to_end(&mut bitmap)?;
+        }
+        let ids = bitmap.iter().enumerate().flat_map(|(byte, bits)| {
+            (0..8)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| (byte * 8 + bit) as u32)
+        });
+        let count = bitmap.iter().map(|bits| bits.count_ones() as usize).sum();
+        // Room to double before the filter gets crowded.
+        let mut bloom = BloomFilter::with_capacity(count * 2);
+        for id in ids {
+            bloom.insert(id);
+        }
+        Ok(SeenIds {
+            file,
+            len: bitmap.len() as u64,
+            bloom,
+            count,
+            unwritten,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn read_byte(&mut self, offset: u64) -> io::Result<u8> {
+        if let Some(byte) = self
+            .unwritten
+            .as_ref()
+            .and_then(|unwritten| unwritten.get(&offset))
+        {
+            return Ok(*byte);
+        }
+        let Some(file) = self.file.as_mut().filter(|_| offset < self.len) else {
+            return Ok(0);
+        };
+        let mut byte = [0];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    pub fn contains(&mut self, transaction_id: TransactionId) -> io::Result<bool> {
+        let id = transaction_id.0;
+        if !self.bloom.may_contain(id) {
+            return Ok(false);
+        }
+        Ok(self.read_byte(id as u64 / 8)? & (1 << (id % 8)) != 0)
+    }
+
+    // Written straight to the file, so a process that dies right after still
+    // has it on the next start. `sync` makes it survive the machine too.
+    pub fn insert(&mut self, transaction_id: TransactionId) -> io::Result<()> {
+        let id = transaction_id.0;
+        let offset = id as u64 / 8;
+        let byte = self.read_byte(offset)?;
+        let bit = 1 << (id % 8);
+        if byte & bit != 0 {
+            return Ok(());
+        }
+        match (&mut self.unwritten, &mut self.file) {
+            (None, Some(file)) => {
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(&[byte | bit])?;
+                self.len = self.len.max(offset + 1);
+            }
+            (unwritten, _) => {
+                unwritten
+                    .get_or_insert_with(HashMap::new)
+                    .insert(offset, byte | bit);
+            }
+        }
+        self.bloom.insert(id);
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn sync(&self) -> io::Result<()> {
+        match (&self.unwritten, &self.file) {
+            (None, Some(file)) => file.sync_data(),
+            _ => Ok(()),
+        }
+    }
+}
+
+// At-most-once mode: a deposit, withdrawal, adjustment or account opening
+// whose id is in the seen set is rejected as a repeat when the ledger no
+// longer has anything under that id, and ids are added as the ledger accepts
+// them. Ids the ledger still holds are left to it, so identical resubmissions
+// are still deduplicated. Disputes, resolves and chargebacks refer to
+// existing ids and pass through.
+pub struct AtMostOnce {
+    seen: Mutex<SeenIds>,
+}
+
+impl AtMostOnce {
+    pub fn new(seen: SeenIds) -> Self {
+        AtMostOnce {
+            seen: Mutex::new(seen),
+        }
+    }
+
+    pub fn sync(&self) -> io::Result<()> {
+        self.seen.lock().unwrap().sync()
+    }
+
+    fn creates_id(transaction: &Transaction) -> bool {
+        matches!(
+            transaction.operation(),
+            Operation::Deposit
+                | Operation::Withdrawal
+                | Operation::Adjustment
+                | Operation::OpenAccount
+                | Operation::Transfer { .. }
+        )
+    }
+}
+
+impl TransactionMiddleware for AtMostOnce {
+    // A set that can't be read is treated as having seen the id: refusing a
+    // transaction is the safe side of at-most-once.
+    fn authorize(
+        &self,
+        ledger: &Ledger,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+    ) -> TransactionResult {
+        if !Self::creates_id(transaction) || ledger.transaction(transaction_id).is_some() {
+            return Ok(());
+        }
+        match self.seen.lock().unwrap().contains(transaction_id) {
+            Ok(false) => Ok(()),
+            Ok(true) | Err(_) => Err(TransactionError::RepeatedTransactionId(transaction_id)),
+        }
+    }
+
+    fn notify(
+        &self,
+        _ledger: &Ledger,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        result: &TransactionResult<Outcome>,
+    ) {
+        if Self::creates_id(transaction) && matches!(result, Ok(Outcome::Applied { .. })) {
+            if let Err(err) = self.seen.lock().unwrap().insert(transaction_id) {
+                eprintln!(
+                    "error: cannot record transaction {}: {err}",
+                    transaction_id.0
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod seen_tests {
+    use super::{AtMostOnce, SeenIds};
+    use crate::account::{num, ClientId};
+    use crate::ledger::Ledger;
+    use crate::transactions::{Operation, Outcome, Transaction, TransactionError, TransactionId};
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn replays_are_rejected_after_a_restart() {
+        let dir = std::env::temp_dir().join(format!("crab-seen-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("seen.ids").to_string_lossy().into_owned();
+        let _ = fs::remove_file(&path);
+        let deposit = Transaction::new(ClientId(1), num!(5), Operation::Deposit);
+
+        let mut seen = SeenIds::open(&path).unwrap();
+        for id in [3, 70_000, 9] {
+            seen.insert(TransactionId(id)).unwrap();
+        }
+        assert!(seen.contains(TransactionId(70_000)).unwrap());
+        assert!(!seen.contains(TransactionId(4)).unwrap());
+        assert!(!seen.contains(TransactionId(u32::MAX)).unwrap());
+        drop(seen);
+
+        // a fresh ledger, as if rebuilt from a snapshot without transaction 3
+        let mut ledger = Ledger::new();
+        let at_most_once = Arc::new(AtMostOnce::new(SeenIds::open(&path).unwrap()));
+        ledger.add_middleware(at_most_once.clone());
+        assert_eq!(
+            ledger.apply_transaction(TransactionId(3), &deposit),
+            Err(TransactionError::RepeatedTransactionId(TransactionId(3)))
+        );
+        ledger
+            .apply_transaction(TransactionId(4), &deposit)
+            .unwrap();
+        assert_eq!(
+            ledger.apply_transaction(TransactionId(4), &deposit),
+            Ok(Outcome::Deduplicated)
+        );
+        let dispute = Transaction::new(ClientId(1), num!(0), Operation::Dispute);
+        ledger
+            .apply_transaction(TransactionId(4), &dispute)
+            .unwrap();
+        at_most_once.sync().unwrap();
+        drop(ledger);
+        drop(at_most_once);
+
+        let mut seen = SeenIds::open(&path).unwrap();
+        assert_eq!(seen.len(), 4);
+        assert!(seen.contains(TransactionId(4)).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
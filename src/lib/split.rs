@@ -0,0 +1,122 @@
+use std::{fs, io, path::Path, path::PathBuf};
+
+use super::account::ClientId;
+
+// Which of `shards` files a client's rows go to. The same client always lands
+// in the same shard, whatever else is in the input.
+pub fn shard_of(client_id: ClientId, shards: usize) -> usize {
+    let hash = u64::from(client_id.0).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    ((hash >> 32) % shards.max(1) as u64) as usize
+}
+
+// `transactions.csv` split in shards is written to `transactions-0.csv`,
+// `transactions-1.csv` and so on, next to it.
+pub fn shard_path(path: &Path, shard: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{shard}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{shard}"),
+    };
+    path.with_file_name(name)
+}
+
+// Copies each record of a CSV input to the shard of its client, after the
+// input's header, so every shard can be run on its own with all of a client's
+// rows in their original order. Records are rewritten whole, quoted fields
+// included. Rows without a readable client go to the first shard, to be
+// rejected there, and transfers go with the sender. Rows without an id get
+// theirs derived from the shard rather than the input, see `IdGenerator`.
+// Returns how many rows each shard got.
+pub fn split_csv<R: io::Read, W: io::Write>(
+    reader: R,
+    delimiter: u8,
+    shards: &mut [W],
+) -> io::Result<Vec<u64>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(reader);
+    let headers = reader.byte_headers()?.clone();
+    let client = headers.iter().position(|header| header == b"client");
+    let mut writers: Vec<_> = shards
+        .iter_mut()
+        .map(|shard| {
+            csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .flexible(true)
+                .from_writer(shard)
+        })
+        .collect();
+    for writer in &mut writers {
+        writer.write_byte_record(&headers)?;
+    }
+    let mut rows = vec![0; writers.len()];
+    let mut record = csv::ByteRecord::new();
+    while reader.read_byte_record(&mut record)? {
+        let client_id = client
+            .and_then(|column| record.get(column))
+            .and_then(|field| std::str::from_utf8(field).ok())
+            .and_then(|field| field.trim().parse().ok())
+            .map(ClientId);
+        let shard = client_id.map_or(0, |client_id| shard_of(client_id, writers.len()));
+        writers[shard].write_byte_record(&record)?;
+        rows[shard] += 1;
+    }
+    for writer in &mut writers {
+        writer.flush()?;
+    }
+    Ok(rows)
+}
+
+// Splits the file at `path` into `shards` files named by `shard_path`.
+pub fn split_file(path: &str, shards: usize, delimiter: u8) -> io::Result<Vec<u64>> {
+    if shards == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "splitting needs at least one shard",
+        ));
+    }
+    let reader = io::BufReader::new(fs::File::open(path)?);
+    let mut files = (0..shards)
+        .map(|shard| fs::File::create(shard_path(Path::new(path), shard)).map(io::BufWriter::new))
+        .collect::<io::Result<Vec<_>>>()?;
+    split_csv(reader, delimiter, &mut files)
+}
+
+#[cfg(test)]
+mod split_tests {
+    use super::{shard_of, shard_path, split_csv};
+    use crate::account::ClientId;
+    use std::path::Path;
+
+    #[test]
+    fn clients_keep_their_rows_in_order() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,1.0\n\
+                     deposit,2,2,2.0\n\
+                     withdrawal,1,3,0.5\n\
+                     dispute,2,2,\n\
+                     deposit,x,4,1.0\n";
+        let mut shards = vec![Vec::new(); 4];
+        let rows = split_csv(input.as_bytes(), b',', &mut shards).unwrap();
+        assert_eq!(rows.iter().sum::<u64>(), 5);
+        let one = String::from_utf8(shards[shard_of(ClientId(1), 4)].clone()).unwrap();
+        assert!(one.starts_with("type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,3,0.5\n"));
+        let two = String::from_utf8(shards[shard_of(ClientId(2), 4)].clone()).unwrap();
+        assert!(two.contains("deposit,2,2,2.0\ndispute,2,2,\n"));
+        let first = String::from_utf8(shards[0].clone()).unwrap();
+        assert!(first.ends_with("deposit,x,4,1.0\n"));
+        for shard in &shards {
+            assert!(shard.starts_with(b"type,client,tx,amount\n"));
+        }
+    }
+
+    #[test]
+    fn shards_are_named_after_the_input() {
+        assert_eq!(
+            shard_path(Path::new("data/transactions.csv"), 3),
+            Path::new("data/transactions-3.csv")
+        );
+        assert_eq!(shard_path(Path::new("feed"), 0), Path::new("feed-0"));
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use std::io::{self, Write};
+
+use minijinja::{Environment, Error, ErrorKind, UndefinedBehavior, Value};
+use serde::Serialize;
+
+use super::account::{ClientId, Number};
+use super::ledger::{AccountFilter, Ledger};
+use super::report::ReportOptions;
+
+// Reports in whatever layout a minijinja template describes, for formats the
+// built-in CSV and JSON writers don't cover:
+//
+//   {{ total }}                          a value, formatted like the report
+//   {{ account.client|pad(12, ">") }}    right-aligned in exactly 12
+//                                        characters; left without ">",
+//                                        longer values are cut
+//   {{ account.client|xml }}             with XML special characters escaped
+//   {% for account in accounts %}...{% endfor %}
+//   {% for dispute in disputes %}...{% endfor %}
+//
+// A newline right after a block tag is dropped, so loops can sit on lines of
+// their own. The top-level values are `account_count`, `dispute_count`,
+// `total_available`, `total_held` and `total`, the running totals
+// `deposited`, `withdrawn` and `charged_back`, and the `accounts` and
+// `disputes` lists. Accounts have `client`, `available`, `held`, `total` and
+// `locked`; disputes have `tx`, `client`, `held`, `opened` and `state`.
+// Anything else is an error when the report is rendered.
+#[derive(Debug)]
+pub struct Template {
+    env: Environment<'static>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    Io(String),
+    Syntax(String),
+}
+
+const NAME: &str = "report";
+
+#[derive(Serialize)]
+struct AccountRow {
+    client: String,
+    available: String,
+    held: String,
+    total: String,
+    // "true" or "false" as in the report, where minijinja would print True.
+    locked: String,
+}
+
+#[derive(Serialize)]
+struct DisputeRow {
+    tx: u32,
+    client: String,
+    held: String,
+    opened: u64,
+    state: String,
+}
+
+#[derive(Serialize)]
+struct Report {
+    account_count: usize,
+    dispute_count: usize,
+    total_available: String,
+    total_held: String,
+    total: String,
+    deposited: String,
+    withdrawn: String,
+    charged_back: String,
+    accounts: Vec<AccountRow>,
+    disputes: Vec<DisputeRow>,
+}
+
+fn pad(value: &Value, width: usize, align: Option<&str>) -> Result<String, Error> {
+    let value: String = value.to_string().chars().take(width).collect();
+    match align.unwrap_or("<") {
+        "<" => Ok(format!("{value:<width$}")),
+        ">" => Ok(format!("{value:>width$}")),
+        align => Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("alignment must be \"<\" or \">\", not {align:?}"),
+        )),
+    }
+}
+
+fn xml(value: &Value) -> String {
+    let value = value.to_string();
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_keep_trailing_newline(true);
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.add_filter("pad", pad);
+        env.add_filter("xml", xml);
+        env.add_template_owned(NAME, source.to_string())
+            .map_err(|err| TemplateError::Syntax(err.to_string()))?;
+        Ok(Template { env })
+    }
+
+    pub fn read(path: &str) -> Result<Self, TemplateError> {
+        let source = fs::read_to_string(path).map_err(|err| TemplateError::Io(err.to_string()))?;
+        Self::parse(&source)
+    }
+
+    pub fn render<W: Write>(
+        &self,
+        mut writer: W,
+        ledger: &Ledger,
+        options: &ReportOptions,
+    ) -> io::Result<()> {
+        let stats = ledger.stats();
+        let number = |number: Number| options.format_number(number);
+        let client = |client_id: ClientId| options.client_ids.format(client_id);
+        let accounts: Vec<AccountRow> = ledger
+            .accounts(&AccountFilter::default())
+            .map(|(client_id, account)| AccountRow {
+                client: client(client_id),
+                available: number(account.available()),
+                held: number(account.held()),
+                total: number(account.total()),
+                locked: account.locked().to_string(),
+            })
+            .collect();
+        let disputes = ledger
+            .disputes()
+            .filter_map(|(transaction_id, _)| {
+                let dispute = ledger.dispute_details(transaction_id)?;
+                Some(DisputeRow {
+                    tx: transaction_id.0,
+                    client: client(dispute.client_id),
+                    held: number(dispute.held),
+                    opened: dispute.opened_at.0,
+                    state: format!("{:?}", dispute.state).to_lowercase(),
+                })
+            })
+            .collect();
+        let report = Report {
+            account_count: accounts.len(),
+            dispute_count: stats.open_disputes,
+            total_available: number(stats.available),
+            total_held: number(stats.held),
+            total: number(stats.available + stats.held),
+            deposited: number(stats.totals.deposited),
+            withdrawn: number(stats.totals.withdrawn),
+            charged_back: number(stats.totals.charged_back),
+            accounts,
+            disputes,
+        };
+        let output = self
+            .env
+            .get_template(NAME)
+            .and_then(|template| template.render(report))
+            .map_err(io::Error::other)?;
+        writer.write_all(output.as_bytes())?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod template_tests {
+    use super::Template;
+    use crate::account::{num, ClientId};
+    use crate::fixtures::Scenario;
+    use crate::report::ReportOptions;
+    use crate::transactions::TransactionId;
+
+    #[test]
+    fn render_fixed_width_and_xml() {
+        let ledger = Scenario::new()
+            .deposit(ClientId(1), num!(12.5))
+            .deposit(ClientId(22), num!(3))
+            .dispute(TransactionId(2))
+            .into_ledger();
+        let render = |source: &str| {
+            let mut out = Vec::new();
+            Template::parse(source)
+                .unwrap()
+                .render(&mut out, &ledger, &ReportOptions::default())
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+
+        let fixed = "HDR{{ account_count|pad(4, \">\") }}\n{% for account in accounts %}\nACC{{ account.client|pad(5) }}{{ account.available|pad(10, \">\") }}{{ account.locked|pad(1) }}\n{% endfor %}\n";
+        assert_eq!(
+            render(fixed).unwrap(),
+            "HDR   2\nACC1       12.5000f\nACC22       0.0000f\n"
+        );
+        assert_eq!(
+            render("{{ deposited }}/{{ withdrawn }}").unwrap(),
+            "15.5000/0.0000"
+        );
+        let xml = "<disputes count=\"{{ dispute_count }}\">{% for d in disputes %}<d tx=\"{{ d.tx }}\" held=\"{{ d.held|xml }}\"/>{% endfor %}</disputes>";
+        assert_eq!(
+            render(xml).unwrap(),
+            "<disputes count=\"1\"><d tx=\"2\" held=\"3.0000\"/></disputes>"
+        );
+
+        assert_eq!(
+            render("{{ \"<a & 'b'>\"|xml }}").unwrap(),
+            "&lt;a &amp; &apos;b&apos;&gt;"
+        );
+
+        assert!(render("{% for account in accounts %}{{ account.tx }}{% endfor %}").is_err());
+        assert!(render("{{ total|pad(4, \"^\") }}").is_err());
+        assert!(Template::parse("{% for account in accounts %}").is_err());
+        assert!(Template::parse("a {{ total").is_err());
+    }
+}
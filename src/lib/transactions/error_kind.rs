@@ -0,0 +1,95 @@
+use super::TransactionError;
+use crate::account::AccountError;
+
+// What a consumer can do about a rejected transaction, without matching every
+// error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    // May go through if retried later: what it refers to hasn't arrived yet,
+    // the funds or limits aren't there yet, or a provider was unavailable.
+    Recoverable,
+    // The transaction itself is wrong and will be rejected however often it
+    // is retried.
+    InvalidInput,
+    // The ledger's state rules the transaction out until someone changes it:
+    // a repeated id, a frozen or archived account, a settled dispute.
+    StateConflict,
+    // The ledger can't represent the result.
+    Internal,
+}
+
+impl TransactionError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            TransactionError::UnknownTransactionId(_)
+            | TransactionError::UnknownClientId(_)
+            | TransactionError::AccountError(_, AccountError::Underflow { .. })
+            | TransactionError::DisputeQuotaExceeded(_)
+            | TransactionError::TierLimitExceeded(..)
+            | TransactionError::GroupLimitExceeded(_)
+            | TransactionError::ScreeningUnavailable(_) => ErrorKind::Recoverable,
+            TransactionError::MismatchedClientId(..)
+            | TransactionError::InvalidAmount(..)
+            | TransactionError::InvalidSignature(_)
+            | TransactionError::AdjustmentsDisabled(_)
+            | TransactionError::UnlocksDisabled(_)
+            | TransactionError::AmountBelowMinimum(..)
+            | TransactionError::AmountAboveMaximum(..)
+            | TransactionError::ScreeningMatch(_) => ErrorKind::InvalidInput,
+            TransactionError::RepeatedTransactionId(_)
+            | TransactionError::AlreadyDisputed(_)
+            | TransactionError::UndisputedTransaction(_)
+            | TransactionError::AccountError(_, AccountError::FrozenAccount(_))
+            | TransactionError::ArchivedAccount(_)
+            | TransactionError::ArchivedTransaction(_)
+            | TransactionError::QuarantinedAccount(_)
+            | TransactionError::DormantAccount(_)
+            | TransactionError::AccountAlreadyOpen(_)
+            | TransactionError::FrozenAccount(_)
+            | TransactionError::OutsideReplayWindow(_)
+            | TransactionError::NotUnderReview(_)
+            | TransactionError::NotChargedBack(_)
+            | TransactionError::OpenEscrow(_) => ErrorKind::StateConflict,
+            TransactionError::AccountError(_, AccountError::Overflow { .. }) => ErrorKind::Internal,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.kind() == ErrorKind::Recoverable
+    }
+}
+
+#[cfg(test)]
+mod error_kind_tests {
+    use super::ErrorKind;
+    use crate::account::{num, Account, AccountError, ClientId};
+    use crate::transactions::{TransactionError, TransactionId};
+
+    #[test]
+    fn classify_errors() {
+        let dispute_first = TransactionError::UnknownTransactionId(TransactionId(1));
+        assert_eq!(dispute_first.kind(), ErrorKind::Recoverable);
+        assert!(dispute_first.is_retryable());
+        let repeated = TransactionError::RepeatedTransactionId(TransactionId(1));
+        assert_eq!(repeated.kind(), ErrorKind::StateConflict);
+        assert!(!repeated.is_retryable());
+        assert_eq!(
+            TransactionError::InvalidAmount(TransactionId(1), num!(-1)).kind(),
+            ErrorKind::InvalidInput
+        );
+        let overflow = AccountError::Overflow {
+            available: num!(1),
+            held: num!(0),
+            transaction_amount: num!(1),
+        };
+        assert_eq!(
+            TransactionError::AccountError(ClientId(1), overflow).kind(),
+            ErrorKind::Internal
+        );
+        let locked = AccountError::FrozenAccount(Account::default());
+        assert_eq!(
+            TransactionError::AccountError(ClientId(1), locked).kind(),
+            ErrorKind::StateConflict
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use super::TransactionId;
+use crate::digest::Digest;
+use sha2::{Digest as _, Sha256};
+
+// A transaction id derived from where a record came from, for feeds that
+// don't carry ids of their own. Replaying the same file yields the same ids,
+// so the ledger's duplicate detection keeps working.
+pub fn derive_id(file: &str, row: u64, record: &[u8]) -> (TransactionId, Digest) {
+    let mut hasher = Sha256::new();
+    hasher.update(file.as_bytes());
+    hasher.update([0]);
+    hasher.update(row.to_be_bytes());
+    hasher.update(record);
+    let digest: Digest = hasher.finalize().into();
+    let id = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    (TransactionId(id), digest)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdCollision {
+    pub transaction_id: TransactionId,
+    pub file: String,
+    pub row: u64,
+}
+
+impl std::fmt::Display for IdCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "derived id {} for {}:{} is already used by another record",
+            self.transaction_id.0, self.file, self.row
+        )
+    }
+}
+
+// Remembers which record every derived id was given to. Deriving an id again
+// for the same record returns it unchanged; two different records hashing to
+// the same id is reported rather than silently merged.
+#[derive(Debug, Default)]
+pub struct IdGenerator {
+    assigned: HashMap<TransactionId, Digest>,
+}
+
+impl IdGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn assign(
+        &mut self,
+        file: &str,
+        row: u64,
+        record: &[u8],
+    ) -> Result<TransactionId, IdCollision> {
+        let (transaction_id, source) = derive_id(file, row, record);
+        match self.assigned.insert(transaction_id, source) {
+            Some(previous) if previous != source => {
+                self.assigned.insert(transaction_id, previous);
+                Err(IdCollision {
+                    transaction_id,
+                    file: file.to_string(),
+                    row,
+                })
+            }
+            _ => Ok(transaction_id),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.assigned.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assigned.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod ids_tests {
+    use super::{derive_id, IdCollision, IdGenerator};
+    use crate::transactions::TransactionId;
+
+    #[test]
+    fn ids_are_stable() {
+        let (first, _) = derive_id("feed.csv", 1, b"deposit,1,,1.0");
+        assert_eq!(first, derive_id("feed.csv", 1, b"deposit,1,,1.0").0);
+        assert_ne!(first, derive_id("feed.csv", 2, b"deposit,1,,1.0").0);
+        assert_ne!(first, derive_id("other.csv", 1, b"deposit,1,,1.0").0);
+        assert_ne!(first, derive_id("feed.csv", 1, b"deposit,1,,2.0").0);
+
+        let mut generator = IdGenerator::new();
+        assert_eq!(
+            generator.assign("feed.csv", 1, b"deposit,1,,1.0"),
+            Ok(first)
+        );
+        assert_eq!(
+            generator.assign("feed.csv", 1, b"deposit,1,,1.0"),
+            Ok(first)
+        );
+        assert_eq!(generator.len(), 1);
+    }
+
+    #[test]
+    fn detect_collisions() {
+        let mut generator = IdGenerator::new();
+        // plant a different record under the id the next one will get
+        let (id, _) = derive_id("feed.csv", 7, b"withdrawal,2,,5.0");
+        generator.assigned.insert(id, [0; 32]);
+        assert_eq!(
+            generator.assign("feed.csv", 7, b"withdrawal,2,,5.0"),
+            Err(IdCollision {
+                transaction_id: id,
+                file: "feed.csv".to_string(),
+                row: 7
+            })
+        );
+        assert_eq!(generator.assigned[&id], [0; 32]);
+        assert_ne!(id, TransactionId(0));
+    }
+}
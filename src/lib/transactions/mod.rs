@@ -0,0 +1,356 @@
+use super::account::{Account, ClientId, Number};
+use crate::account::AccountError;
+use crate::ledger::{AccountSettings, ChargebackExcess, TierLimit};
+
+mod error_kind;
+mod ids;
+mod signature;
+pub mod state_machine;
+
+pub use error_kind::ErrorKind;
+pub use ids::{derive_id, IdCollision, IdGenerator};
+pub use signature::{Signature, SignatureVerifier};
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub struct TransactionId(pub u32);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransactionError {
+    RepeatedTransactionId(TransactionId),
+    UnknownTransactionId(TransactionId),
+    UnknownClientId(ClientId),
+    MismatchedClientId(ClientId, ClientId),
+    AlreadyDisputed(TransactionId),
+    UndisputedTransaction(TransactionId),
+    AccountError(ClientId, AccountError),
+    InvalidAmount(TransactionId, Number),
+    ArchivedAccount(ClientId),
+    InvalidSignature(TransactionId),
+    DisputeQuotaExceeded(ClientId),
+    TierLimitExceeded(ClientId, TierLimit),
+    ArchivedTransaction(TransactionId),
+    AdjustmentsDisabled(TransactionId),
+    QuarantinedAccount(ClientId),
+    GroupLimitExceeded(ClientId),
+    DormantAccount(ClientId),
+    AmountBelowMinimum(TransactionId, Number),
+    AmountAboveMaximum(TransactionId, Number),
+    AccountAlreadyOpen(ClientId),
+    FrozenAccount(ClientId),
+    ScreeningMatch(TransactionId),
+    ScreeningUnavailable(TransactionId),
+    OutsideReplayWindow(TransactionId),
+    NotUnderReview(TransactionId),
+    UnlocksDisabled(ClientId),
+    // The client has no charged back transaction to unlock the account by.
+    NotChargedBack(ClientId),
+    // Erasure waits until the client's open escrows are released or refunded.
+    OpenEscrow(ClientId),
+}
+pub type TransactionResult<T = ()> = Result<T, TransactionError>;
+
+// What became of an accepted transaction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    // The account's balances after the transaction.
+    Applied {
+        new_available: Number,
+        new_held: Number,
+    },
+    // The same deposit, withdrawal or adjustment was already applied with
+    // this id, so nothing changed.
+    Deduplicated,
+    // Accepted, to be applied later by whoever queued it.
+    Queued,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Chargeback,
+    Resolve,
+    Adjustment,
+    OpenAccount,
+    // Moves the amount from the client's account to `to`'s, both or neither.
+    Transfer { to: ClientId },
+    // Lifts the lock a chargeback left, referring to the charged back
+    // transaction like the chargeback did. See `Ledger::unlock_account`.
+    Unlock,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Deposit => "deposit",
+            Operation::Withdrawal => "withdrawal",
+            Operation::Dispute => "dispute",
+            Operation::Chargeback => "chargeback",
+            Operation::Resolve => "resolve",
+            Operation::Adjustment => "adjustment",
+            Operation::OpenAccount => "open",
+            Operation::Transfer { .. } => "transfer",
+            Operation::Unlock => "unlock",
+        }
+    }
+
+    // Sets the recipient of a transfer, which is kept apart from the
+    // operation's name wherever transactions are written out.
+    pub fn with_recipient(self, to: Option<ClientId>) -> Self {
+        match (self, to) {
+            (Operation::Transfer { .. }, Some(to)) => Operation::Transfer { to },
+            (operation, _) => operation,
+        }
+    }
+
+    pub fn recipient(&self) -> Option<ClientId> {
+        match self {
+            Operation::Transfer { to } => Some(*to),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Operation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "deposit" => Ok(Operation::Deposit),
+            "withdrawal" => Ok(Operation::Withdrawal),
+            "dispute" => Ok(Operation::Dispute),
+            "chargeback" => Ok(Operation::Chargeback),
+            "resolve" => Ok(Operation::Resolve),
+            "adjustment" => Ok(Operation::Adjustment),
+            "open" => Ok(Operation::OpenAccount),
+            "unlock" => Ok(Operation::Unlock),
+            // The recipient is read separately, see `with_recipient`.
+            "transfer" => Ok(Operation::Transfer {
+                to: ClientId::default(),
+            }),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+impl serde::Serialize for Operation {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Operation {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        name.parse()
+            .map_err(|name| serde::de::Error::custom(format!("unknown operation '{name}'")))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TransactionState {
+    #[default]
+    Ok,
+    Disputed,
+    Chargedback,
+    // Disputed and then resolved, with the funds back to available.
+    Resolved,
+    // A withdrawal the client disputes, with its amount held as a pending
+    // credit until it is resolved or charged back, see
+    // `Policy::dispute_withdrawals`.
+    DisputedWithdrawal,
+}
+
+impl TransactionState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionState::Ok => "ok",
+            TransactionState::Disputed => "disputed",
+            TransactionState::Chargedback => "chargedback",
+            TransactionState::Resolved => "resolved",
+            TransactionState::DisputedWithdrawal => "disputed_withdrawal",
+        }
+    }
+
+    // Under an open dispute, of a deposit or of a withdrawal.
+    pub fn is_disputed(self) -> bool {
+        matches!(
+            self,
+            TransactionState::Disputed | TransactionState::DisputedWithdrawal
+        )
+    }
+
+    // The state as it was before resolved disputes had their own, for
+    // consumers that only know the other three.
+    pub fn without_resolved(self) -> Self {
+        match self {
+            TransactionState::Resolved => TransactionState::Ok,
+            state => state,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    client_id: ClientId,
+    amount: Number,
+    state: TransactionState,
+    operation: Operation,
+    signature: Option<Signature>,
+    // Only carried by account openings.
+    settings: Option<AccountSettings>,
+}
+
+impl Transaction {
+    // Amounts are kept without trailing zeros, so `1.5` and `1.5000` are the
+    // same transaction wherever it's written out, hashed or compared.
+    pub fn new(client_id: ClientId, amount: Number, operation: Operation) -> Self {
+        Self {
+            amount: amount.normalize(),
+            client_id,
+            operation,
+            state: TransactionState::default(),
+            signature: None,
+            settings: None,
+        }
+    }
+    pub fn with_signature(mut self, signature: Signature) -> Self {
+        self.signature = Some(signature);
+        self
+    }
+    pub fn with_settings(mut self, settings: AccountSettings) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+    pub(crate) fn with_amount(mut self, amount: Number) -> Self {
+        self.amount = amount.normalize();
+        self
+    }
+    pub(crate) fn with_state(mut self, state: TransactionState) -> Self {
+        self.state = state;
+        self
+    }
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+    pub fn amount(&self) -> Number {
+        self.amount
+    }
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+    pub fn state(&self) -> TransactionState {
+        self.state
+    }
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+    pub fn settings(&self) -> Option<&AccountSettings> {
+        self.settings.as_ref()
+    }
+
+    // A disputed withdrawal holds its amount as a credit the client may be
+    // owed, rather than taking it from the available funds.
+    pub fn dispute(&mut self, account: &mut Account) -> TransactionResult {
+        if self.operation == Operation::Withdrawal {
+            account
+                .dispute_withdrawal(self.amount)
+                .map_err(|err| TransactionError::AccountError(self.client_id(), err))?;
+            self.state = TransactionState::DisputedWithdrawal;
+            return Ok(());
+        }
+        account
+            .dispute(self.amount)
+            .map_err(|err| TransactionError::AccountError(self.client_id(), err))?;
+        self.state = TransactionState::Disputed;
+        Ok(())
+    }
+
+    // A resolved withdrawal stands, and the credit held for it is dropped.
+    pub fn resolve(&mut self, account: &mut Account) -> TransactionResult {
+        let result = if self.state == TransactionState::DisputedWithdrawal {
+            account.resolve_withdrawal(self.amount)
+        } else {
+            account.resolve(self.amount)
+        };
+        result.map_err(|err| TransactionError::AccountError(self.client_id(), err))?;
+        self.state = TransactionState::Resolved;
+        Ok(())
+    }
+
+    // With `ChargebackExcess::Clamp`, the amount becomes what was actually
+    // taken. A charged back withdrawal is refunded to the client, whose
+    // account is left unlocked.
+    pub fn chargeback(
+        &mut self,
+        account: &mut Account,
+        excess: ChargebackExcess,
+    ) -> TransactionResult {
+        if self.state == TransactionState::DisputedWithdrawal {
+            // The pending credit becomes available, as a resolved deposit's
+            // held funds do.
+            account
+                .resolve(self.amount)
+                .map_err(|err| TransactionError::AccountError(self.client_id(), err))?;
+            self.state = TransactionState::Chargedback;
+            return Ok(());
+        }
+        if account.held() < self.amount {
+            match excess {
+                ChargebackExcess::Allow => {}
+                ChargebackExcess::Clamp => self.amount = account.held().max(Number::ZERO),
+                ChargebackExcess::Reject => {
+                    return Err(TransactionError::AccountError(
+                        self.client_id(),
+                        AccountError::Underflow {
+                            available: account.available(),
+                            held: account.held(),
+                            transaction_amount: self.amount,
+                        },
+                    ))
+                }
+            }
+        }
+        account.chargeback(self.amount);
+        self.state = TransactionState::Chargedback;
+        Ok(())
+    }
+
+    pub fn state_matches_or(
+        &self,
+        state: TransactionState,
+        err: TransactionError,
+    ) -> TransactionResult {
+        if self.state == state {
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
+    // Only deposits can be disputed, and withdrawals too under
+    // `Policy::dispute_withdrawals`.
+    pub fn check_valid_dispute(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        disputable_withdrawals: bool,
+    ) -> TransactionResult {
+        let disputable = match transaction.operation {
+            Operation::Deposit => true,
+            Operation::Withdrawal => disputable_withdrawals,
+            _ => false,
+        };
+        if !disputable {
+            return Err(TransactionError::AlreadyDisputed(transaction_id));
+        }
+        if self.client_id != transaction.client_id {
+            return Err(TransactionError::MismatchedClientId(
+                self.client_id,
+                transaction.client_id,
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use super::{Transaction, TransactionId};
+use crate::digest;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Signature(pub [u8; 32]);
+
+impl Signature {
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        digest::from_hex(hex).map(Self)
+    }
+
+    pub fn to_hex(&self) -> String {
+        digest::to_hex(&self.0)
+    }
+}
+
+pub trait SignatureVerifier {
+    fn verify(
+        &self,
+        transaction_id: TransactionId,
+        transaction: &Transaction,
+        signature: &Signature,
+    ) -> bool;
+}
+
+#[cfg(test)]
+mod signature_tests {
+    use super::Signature;
+
+    #[test]
+    fn parse_hex() {
+        let hex = "00ff".repeat(16);
+        let signature = Signature::from_hex(&hex).unwrap();
+        assert_eq!(signature.0[0], 0x00);
+        assert_eq!(signature.0[1], 0xff);
+        assert_eq!(signature.to_hex(), hex);
+        assert_eq!(Signature::from_hex("00ff"), None);
+        assert_eq!(Signature::from_hex(&"zz".repeat(32)), None);
+    }
+}
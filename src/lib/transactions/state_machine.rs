@@ -0,0 +1,190 @@
+use std::fmt::Write;
+
+use super::{Operation, TransactionState};
+use crate::ledger::{ChargebackExcess, Policy};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub from: TransactionState,
+    pub operation: Operation,
+    pub to: TransactionState,
+}
+
+pub const TRANSITIONS: &[Transition] = &[
+    Transition {
+        from: TransactionState::Ok,
+        operation: Operation::Dispute,
+        to: TransactionState::Disputed,
+    },
+    Transition {
+        from: TransactionState::Disputed,
+        operation: Operation::Resolve,
+        to: TransactionState::Resolved,
+    },
+    Transition {
+        from: TransactionState::Disputed,
+        operation: Operation::Chargeback,
+        to: TransactionState::Chargedback,
+    },
+    Transition {
+        from: TransactionState::Resolved,
+        operation: Operation::Dispute,
+        to: TransactionState::Disputed,
+    },
+    // Only taken by withdrawals, see `Policy::dispute_withdrawals`. Deposits
+    // match the dispute above first.
+    Transition {
+        from: TransactionState::Ok,
+        operation: Operation::Dispute,
+        to: TransactionState::DisputedWithdrawal,
+    },
+    Transition {
+        from: TransactionState::DisputedWithdrawal,
+        operation: Operation::Resolve,
+        to: TransactionState::Resolved,
+    },
+    Transition {
+        from: TransactionState::DisputedWithdrawal,
+        operation: Operation::Chargeback,
+        to: TransactionState::Chargedback,
+    },
+    // Lifts the account's lock and leaves the transaction as it is, see
+    // `Policy::allow_unlocks`.
+    Transition {
+        from: TransactionState::Chargedback,
+        operation: Operation::Unlock,
+        to: TransactionState::Chargedback,
+    },
+];
+
+pub fn next_state(from: TransactionState, operation: Operation) -> Option<TransactionState> {
+    TRANSITIONS
+        .iter()
+        .find(|transition| transition.from == from && transition.operation == operation)
+        .map(|transition| transition.to)
+}
+
+// Whether transactions can take the transition under `policy`, as decided
+// when a dispute, resolve, chargeback or unlock is applied.
+fn allowed(transition: &Transition, policy: &Policy) -> bool {
+    let withdrawal = transition.from == TransactionState::DisputedWithdrawal
+        || transition.to == TransactionState::DisputedWithdrawal;
+    match (transition.from, transition.operation) {
+        _ if withdrawal => policy.dispute_withdrawals,
+        (TransactionState::Resolved, Operation::Dispute) => !policy.reject_redisputes,
+        (_, Operation::Unlock) => policy.allow_unlocks,
+        _ => true,
+    }
+}
+
+fn label(transition: &Transition, policy: &Policy) -> String {
+    let operation = format!("{:?}", transition.operation);
+    // Only deposits charge back held funds.
+    if transition.from != TransactionState::Disputed
+        || transition.operation != Operation::Chargeback
+    {
+        return operation;
+    }
+    match policy.chargeback_excess {
+        ChargebackExcess::Allow => operation,
+        ChargebackExcess::Clamp => format!("{operation} (clamped to held)"),
+        ChargebackExcess::Reject => format!("{operation} (up to held)"),
+    }
+}
+
+// The transitions `policy` allows, for Graphviz.
+pub fn to_dot(policy: &Policy) -> String {
+    let mut dot = String::from("digraph transaction_state {\n");
+    dot.push_str("    start [shape=point];\n");
+    let _ = writeln!(
+        dot,
+        "    start -> {:?} [label=\"{:?}\"];",
+        TransactionState::default(),
+        Operation::Deposit
+    );
+    if policy.dispute_withdrawals {
+        let _ = writeln!(
+            dot,
+            "    start -> {:?} [label=\"{:?}\"];",
+            TransactionState::default(),
+            Operation::Withdrawal
+        );
+    }
+    for transition in TRANSITIONS
+        .iter()
+        .filter(|transition| allowed(transition, policy))
+    {
+        let _ = writeln!(
+            dot,
+            "    {:?} -> {:?} [label=\"{}\"];",
+            transition.from,
+            transition.to,
+            label(transition, policy)
+        );
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod state_machine_tests {
+    use super::{next_state, to_dot};
+    use crate::ledger::{ChargebackExcess, Policy};
+    use crate::transactions::{Operation, TransactionState};
+
+    #[test]
+    fn transitions() {
+        assert_eq!(
+            next_state(TransactionState::Ok, Operation::Dispute),
+            Some(TransactionState::Disputed)
+        );
+        assert_eq!(next_state(TransactionState::Ok, Operation::Resolve), None);
+        assert_eq!(
+            next_state(TransactionState::Chargedback, Operation::Dispute),
+            None
+        );
+    }
+
+    #[test]
+    fn dot_output() {
+        assert_eq!(
+            to_dot(&Policy::default()),
+            "digraph transaction_state {
+    start [shape=point];
+    start -> Ok [label=\"Deposit\"];
+    Ok -> Disputed [label=\"Dispute\"];
+    Disputed -> Resolved [label=\"Resolve\"];
+    Disputed -> Chargedback [label=\"Chargeback\"];
+    Resolved -> Disputed [label=\"Dispute\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn dot_output_follows_the_policy() {
+        let policy = Policy {
+            allow_unlocks: true,
+            chargeback_excess: ChargebackExcess::Clamp,
+            reject_redisputes: true,
+            dispute_withdrawals: true,
+            ..Policy::default()
+        };
+        assert_eq!(
+            to_dot(&policy),
+            "digraph transaction_state {
+    start [shape=point];
+    start -> Ok [label=\"Deposit\"];
+    start -> Ok [label=\"Withdrawal\"];
+    Ok -> Disputed [label=\"Dispute\"];
+    Disputed -> Resolved [label=\"Resolve\"];
+    Disputed -> Chargedback [label=\"Chargeback (clamped to held)\"];
+    Ok -> DisputedWithdrawal [label=\"Dispute\"];
+    DisputedWithdrawal -> Resolved [label=\"Resolve\"];
+    DisputedWithdrawal -> Chargedback [label=\"Chargeback\"];
+    Chargedback -> Chargedback [label=\"Unlock\"];
+}
+"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use std::io::{self, Read, Write};
+
+use prost::Message;
+
+use super::account::{Account, ClientId, Number};
+use super::ledger::AccountSettings;
+use super::transactions::{Operation, Signature, Transaction, TransactionId};
+
+// The messages of proto/crab.proto, declared by hand with prost's derives to
+// stay free of a build script. Fields holding their default value are left
+// out, and unknown fields are skipped, as protobuf does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WireError {
+    // Not a protobuf message, or one ending early.
+    Malformed(prost::DecodeError),
+    // A field number holding an invalid value.
+    InvalidField(u32),
+}
+
+pub type WireResult<T> = Result<T, WireError>;
+
+// Messages here are a few dozen bytes long, so a length past this is a
+// corrupt stream rather than a message worth allocating for.
+pub const MAX_MESSAGE_LEN: u64 = 64 * 1024;
+
+#[derive(Clone, PartialEq, Message)]
+struct WireAmount {
+    #[prost(sint64, tag = "1")]
+    units: i64,
+    #[prost(uint32, tag = "2")]
+    scale: u32,
+    #[prost(bytes = "vec", tag = "3")]
+    wide_units: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+enum WireOperation {
+    Deposit = 0,
+    Withdrawal = 1,
+    Dispute = 2,
+    Resolve = 3,
+    Chargeback = 4,
+    Adjustment = 5,
+    Open = 6,
+    Transfer = 7,
+    Unlock = 8,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct WireTransaction {
+    #[prost(enumeration = "WireOperation", tag = "1")]
+    r#type: i32,
+    #[prost(uint32, tag = "2")]
+    client: u32,
+    #[prost(uint32, tag = "3")]
+    tx: u32,
+    #[prost(message, optional, tag = "4")]
+    amount: Option<WireAmount>,
+    #[prost(bytes = "vec", tag = "5")]
+    signature: Vec<u8>,
+    #[prost(string, tag = "6")]
+    currency: String,
+    #[prost(string, tag = "7")]
+    tier: String,
+    #[prost(message, optional, tag = "8")]
+    overdraft_limit: Option<WireAmount>,
+    #[prost(uint32, tag = "9")]
+    to: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct WireAccount {
+    #[prost(uint32, tag = "1")]
+    client: u32,
+    #[prost(message, optional, tag = "2")]
+    available: Option<WireAmount>,
+    #[prost(message, optional, tag = "3")]
+    held: Option<WireAmount>,
+    #[prost(bool, tag = "4")]
+    locked: bool,
+}
+
+// Amounts are always written, even zero ones, so they can be told apart
+// from a missing amount.
+fn to_wire(amount: Number) -> Option<WireAmount> {
+    let (units, wide_units) = match i64::try_from(amount.mantissa()) {
+        Ok(units) => (units, Vec::new()),
+        Err(_) => (0, amount.mantissa().to_be_bytes().to_vec()),
+    };
+    Some(WireAmount {
+        units,
+        scale: amount.scale(),
+        wide_units,
+    })
+}
+
+fn from_wire(field: u32, amount: Option<WireAmount>) -> WireResult<Number> {
+    let Some(amount) = amount else {
+        return Ok(Number::ZERO);
+    };
+    let units = match amount.wide_units.as_slice() {
+        [] => amount.units as i128,
+        wide => i128::from_be_bytes(
+            wide.try_into()
+                .map_err(|_| WireError::InvalidField(field))?,
+        ),
+    };
+    Number::try_from_i128_with_scale(units, amount.scale)
+        .map_err(|_| WireError::InvalidField(field))
+}
+
+fn id<T: TryFrom<u32>>(field: u32, value: u32) -> WireResult<T> {
+    T::try_from(value).map_err(|_| WireError::InvalidField(field))
+}
+
+fn operation_to_wire(operation: Operation) -> WireOperation {
+    match operation {
+        Operation::Deposit => WireOperation::Deposit,
+        Operation::Withdrawal => WireOperation::Withdrawal,
+        Operation::Dispute => WireOperation::Dispute,
+        Operation::Resolve => WireOperation::Resolve,
+        Operation::Chargeback => WireOperation::Chargeback,
+        Operation::Adjustment => WireOperation::Adjustment,
+        Operation::OpenAccount => WireOperation::Open,
+        Operation::Transfer { .. } => WireOperation::Transfer,
+        Operation::Unlock => WireOperation::Unlock,
+    }
+}
+
+// Transfers get their recipient from a field of its own.
+fn operation_from_wire(operation: WireOperation, to: ClientId) -> Operation {
+    match operation {
+        WireOperation::Deposit => Operation::Deposit,
+        WireOperation::Withdrawal => Operation::Withdrawal,
+        WireOperation::Dispute => Operation::Dispute,
+        WireOperation::Resolve => Operation::Resolve,
+        WireOperation::Chargeback => Operation::Chargeback,
+        WireOperation::Adjustment => Operation::Adjustment,
+        WireOperation::Open => Operation::OpenAccount,
+        WireOperation::Transfer => Operation::Transfer { to },
+        WireOperation::Unlock => Operation::Unlock,
+    }
+}
+
+pub fn encode_transaction(transaction_id: TransactionId, transaction: &Transaction) -> Vec<u8> {
+    let has_amount = matches!(
+        transaction.operation(),
+        Operation::Deposit
+            | Operation::Withdrawal
+            | Operation::Adjustment
+            | Operation::Transfer { .. }
+    );
+    let settings = transaction.settings().copied().unwrap_or_default();
+    WireTransaction {
+        r#type: operation_to_wire(transaction.operation()) as i32,
+        client: transaction.client_id().0 as u32,
+        tx: transaction_id.0,
+        amount: has_amount.then(|| to_wire(transaction.amount())).flatten(),
+        signature: transaction
+            .signature()
+            .map(|signature| signature.0.to_vec())
+            .unwrap_or_default(),
+        currency: settings
+            .currency
+            .map(|currency| currency.as_str().to_string())
+            .unwrap_or_default(),
+        tier: settings
+            .tier
+            .map(|tier| tier.name().to_string())
+            .unwrap_or_default(),
+        overdraft_limit: settings.overdraft_limit.and_then(to_wire),
+        to: transaction
+            .operation()
+            .recipient()
+            .map_or(0, |to| to.0 as u32),
+    }
+    .encode_to_vec()
+}
+
+pub fn decode_transaction(input: &[u8]) -> WireResult<(TransactionId, Transaction)> {
+    let message = WireTransaction::decode(input).map_err(WireError::Malformed)?;
+    let operation =
+        WireOperation::try_from(message.r#type).map_err(|_| WireError::InvalidField(1))?;
+    let operation = operation_from_wire(operation, ClientId(id(9, message.to)?));
+    let client_id = ClientId(id(2, message.client)?);
+    let amount = from_wire(4, message.amount)?;
+    let mut transaction = Transaction::new(client_id, amount, operation);
+    if !message.signature.is_empty() {
+        let bytes = message
+            .signature
+            .try_into()
+            .map_err(|_| WireError::InvalidField(5))?;
+        transaction = transaction.with_signature(Signature(bytes));
+    }
+    let settings = AccountSettings {
+        currency: (!message.currency.is_empty())
+            .then(|| message.currency.parse())
+            .transpose()
+            .map_err(|_| WireError::InvalidField(6))?,
+        tier: (!message.tier.is_empty())
+            .then(|| message.tier.parse())
+            .transpose()
+            .map_err(|_| WireError::InvalidField(7))?,
+        overdraft_limit: message
+            .overdraft_limit
+            .map(|limit| from_wire(8, Some(limit)))
+            .transpose()?,
+    };
+    if settings != AccountSettings::default() {
+        transaction = transaction.with_settings(settings);
+    }
+    Ok((TransactionId(message.tx), transaction))
+}
+
+pub fn encode_account(client_id: ClientId, account: &Account) -> Vec<u8> {
+    WireAccount {
+        client: client_id.0 as u32,
+        available: to_wire(account.available()),
+        held: to_wire(account.held()),
+        locked: account.locked(),
+    }
+    .encode_to_vec()
+}
+
+pub fn decode_account(input: &[u8]) -> WireResult<(ClientId, Account)> {
+    let message = WireAccount::decode(input).map_err(WireError::Malformed)?;
+    let account = Account::from_parts(
+        from_wire(2, message.available)?,
+        from_wire(3, message.held)?,
+        message.locked,
+    );
+    Ok((ClientId(id(1, message.client)?), account))
+}
+
+pub fn write_delimited<W: Write>(mut writer: W, message: &[u8]) -> io::Result<()> {
+    let mut length = Vec::with_capacity(5);
+    prost::encoding::encode_varint(message.len() as u64, &mut length);
+    writer.write_all(&length)?;
+    writer.write_all(message)
+}
+
+// The messages of a length-delimited stream, one at a time.
+pub struct DelimitedReader<R> {
+    reader: R,
+}
+
+impl<R: Read> DelimitedReader<R> {
+    pub fn new(reader: R) -> Self {
+        DelimitedReader { reader }
+    }
+
+    // None at the end of the stream. A stream ending inside a message, or a
+    // length over `MAX_MESSAGE_LEN`, is an error.
+    pub fn next_message(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut length = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0];
+            if self.reader.read(&mut byte)? == 0 {
+                return match shift {
+                    0 => Ok(None),
+                    _ => Err(io::ErrorKind::UnexpectedEof.into()),
+                };
+            }
+            length |= ((byte[0] & 0x7f) as u64) << shift;
+            if byte[0] < 0x80 {
+                if length > MAX_MESSAGE_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "message of {length} bytes is over the {MAX_MESSAGE_LEN} byte limit"
+                        ),
+                    ));
+                }
+                let mut message = vec![0; length as usize];
+                self.reader.read_exact(&mut message)?;
+                return Ok(Some(message));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid message length",
+        ))
+    }
+}
+
+impl<R: Read> Iterator for DelimitedReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message().transpose()
+    }
+}
+
+#[cfg(test)]
+mod wire_tests {
+    use super::{
+        decode_account, decode_transaction, encode_account, encode_transaction, write_delimited,
+        DelimitedReader, WireError, MAX_MESSAGE_LEN,
+    };
+    use crate::account::{num, Account, ClientId, Number};
+    use crate::ledger::{AccountSettings, Tier};
+    use crate::transactions::{Operation, Signature, Transaction, TransactionId};
+
+    #[test]
+    fn round_trip_messages() {
+        let deposit = Transaction::new(ClientId(7), num!(-12.5), Operation::Adjustment)
+            .with_signature(Signature([9; 32]));
+        let open = Transaction::new(ClientId(300), Number::ZERO, Operation::OpenAccount)
+            .with_settings(AccountSettings {
+                currency: Some("EUR".parse().unwrap()),
+                tier: Some(Tier::Premium),
+                overdraft_limit: Some(Number::MAX),
+            });
+        let dispute = Transaction::new(ClientId(7), Number::ZERO, Operation::Dispute);
+        let transfer = Transaction::new(
+            ClientId(7),
+            num!(3),
+            Operation::Transfer { to: ClientId(8) },
+        );
+        let account = Account::from_parts(num!(1.2345), num!(0), true);
+
+        let mut stream = Vec::new();
+        for (id, transaction) in [(1, deposit), (70_000, open), (1, dispute), (2, transfer)] {
+            let message = encode_transaction(TransactionId(id), &transaction);
+            assert_eq!(
+                decode_transaction(&message),
+                Ok((TransactionId(id), transaction))
+            );
+            write_delimited(&mut stream, &message).unwrap();
+        }
+        let messages: Vec<_> = DelimitedReader::new(stream.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(messages.len(), 4);
+        // type, client and tx
+        assert_eq!(messages[2], [0x08, 0x02, 0x10, 0x07, 0x18, 0x01]);
+
+        let message = encode_account(ClientId(4), &account);
+        assert_eq!(decode_account(&message), Ok((ClientId(4), account)));
+
+        // unknown fields are skipped
+        let mut extended = messages[2].clone();
+        extended.extend([0x78, 0x05, 0x82, 0x01, 0x01, 0xff]);
+        assert_eq!(
+            decode_transaction(&extended),
+            Ok((TransactionId(1), dispute))
+        );
+        assert!(matches!(
+            decode_transaction(&messages[0][..5]),
+            Err(WireError::Malformed(_))
+        ));
+        assert_eq!(
+            decode_transaction(&[0x08, 0x09]),
+            Err(WireError::InvalidField(1))
+        );
+        assert!(DelimitedReader::new([0x05, 0x01].as_slice())
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn oversized_lengths_are_invalid() {
+        let mut stream = Vec::new();
+        prost::encoding::encode_varint(MAX_MESSAGE_LEN + 1, &mut stream);
+        stream.extend([0; 16]);
+        let err = DelimitedReader::new(stream.as_slice())
+            .next_message()
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut stream = Vec::new();
+        write_delimited(&mut stream, &[0; MAX_MESSAGE_LEN as usize]).unwrap();
+        let message = DelimitedReader::new(stream.as_slice())
+            .next_message()
+            .unwrap();
+        assert_eq!(
+            message.map(|message| message.len()),
+            Some(MAX_MESSAGE_LEN as usize)
+        );
+    }
+
+    #[test]
+    fn amounts_at_any_scale_encode_alike() {
+        let short = Transaction::new(ClientId(1), num!(1.5), Operation::Deposit);
+        let long = Transaction::new(ClientId(1), num!(1.5000), Operation::Deposit);
+        assert_eq!(long.amount().scale(), 1);
+        assert_eq!(
+            encode_transaction(TransactionId(1), &short),
+            encode_transaction(TransactionId(1), &long)
+        );
+    }
+}
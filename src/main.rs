@@ -1,15 +1,560 @@
-use clap::Parser;
-use crab::app;
+use std::{fs, io, process, sync::Arc, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use crab::account::{AmountLocale, Number};
+use crab::app::{self, InputFormat, ProcessOptions, QueueEvent, RowRetry, RunOptions};
+use crab::bench::{self, BenchOptions};
+use crab::checkpoint::SnapshotSchedule;
+use crab::config::Config;
+use crab::encryption::Keyring;
+use crab::fixed_width::Layout;
+use crab::health::{self, HealthMonitor};
+use crab::ledger::{AlertRules, Journal, Ledger, OperationRegistry, Policy, ReplayPoint};
+use crab::outbox::{OutboxConfig, RetryPolicy};
+use crab::rate_limit::RateLimiter;
+use crab::repl::Repl;
+use crab::report::{self, ClientIdFormat, ReportFormat, ReportOptions};
+use crab::schema::{self, Schema};
+use crab::split;
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+impl From<Format> for ReportFormat {
+    fn from(value: Format) -> Self {
+        match value {
+            Format::Csv => ReportFormat::Csv,
+            Format::Json => ReportFormat::Json,
+        }
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum InputKind {
+    Csv,
+    Protobuf,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum SchemaKind {
+    Input,
+    Output,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Load transactions (or a journal) and inspect the ledger interactively
+    Repl {
+        filename: String,
+        #[arg(long, default_value_t = false)]
+        journal: bool,
+    },
+    /// Check every row of a transactions file without producing a report
+    Validate { filename: String },
+    /// Compare two account reports client by client
+    Diff {
+        before: String,
+        after: String,
+        /// Balance differences up to this amount are ignored
+        #[arg(long, default_value_t = Number::ZERO)]
+        tolerance: Number,
+    },
+    /// Deliver the journal entries a webhook hasn't received yet
+    Deliver {
+        journal: String,
+        #[arg(long, value_name = "URL")]
+        webhook: String,
+        /// Where delivery progress is kept [default: <journal>.outbox]
+        #[arg(long, value_name = "FILE")]
+        state: Option<String>,
+        /// Give up on an entry after this many attempts [default: 5]
+        #[arg(long)]
+        max_attempts: Option<u32>,
+    },
+    /// Print the JSON Schema of transactions or report rows
+    Schema {
+        #[arg(value_enum)]
+        kind: SchemaKind,
+        /// Check a file of JSON lines against the schema instead
+        #[arg(long, value_name = "FILE")]
+        check: Option<String>,
+    },
+    /// Apply generated transactions in memory and print throughput and latency
+    Bench {
+        #[arg(long, default_value_t = 1000)]
+        clients: u16,
+        #[arg(long, default_value_t = 1_000_000)]
+        transactions: u64,
+        /// Share of rows disputing an earlier deposit
+        #[arg(long, default_value_t = 0.01)]
+        dispute_rate: f64,
+        /// Runs with the same seed generate the same transactions
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+    },
+    /// Split a transactions file into one file per shard of clients
+    Split {
+        filename: String,
+        #[arg(long)]
+        shards: usize,
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+    },
+}
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Arguments {
-    filename: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    filename: Option<String>,
     #[arg(short, long, default_value_t = false)]
     debug: bool,
+    /// Read settings from a TOML file; flags take precedence over it
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
+    /// Report format [default: csv]
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// [default: .]
+    #[arg(long)]
+    decimal_separator: Option<char>,
+    #[arg(long)]
+    thousands_separator: Option<char>,
+    /// Render the report through this template file (needs the templates feature)
+    #[arg(long, value_name = "FILE")]
+    template: Option<String>,
+    /// Replace client ids in the report with their last digit
+    #[arg(long, conflicts_with = "hash_client_ids")]
+    mask_client_ids: bool,
+    /// Replace client ids in the report with an HMAC keyed with this value
+    #[arg(long, value_name = "KEY")]
+    hash_client_ids: Option<String>,
+    /// Write rows that could not be applied to this file, with the reason
+    #[arg(long, value_name = "FILE")]
+    dead_letters: Option<String>,
+    /// Format the report on this many threads [default: 1]
+    #[arg(long)]
+    report_threads: Option<usize>,
+    /// Record applied transactions and write them to this file
+    #[arg(long, value_name = "FILE")]
+    journal: Option<String>,
+    /// Write a snapshot of the ledger to this file at the end of the run
+    #[arg(long, value_name = "FILE")]
+    snapshot: Option<String>,
+    /// Also write the snapshot every this many rows while running
+    #[arg(long, value_name = "ROWS", requires = "snapshot")]
+    snapshot_every: Option<u64>,
+    /// Also write the snapshot every this many seconds while running
+    #[arg(long, value_name = "SECONDS", requires = "snapshot")]
+    snapshot_interval: Option<u64>,
+    /// Encrypt the journal and snapshots with this key, 64 hex digits (needs
+    /// the encryption feature)
+    #[arg(long, value_name = "KEY")]
+    encryption_key: Option<String>,
+    /// Also read files encrypted with this key, rewriting them under the new one
+    #[arg(long, value_name = "KEY", requires = "encryption_key")]
+    previous_encryption_key: Option<String>,
+    /// Start from the snapshot and replay the journal entries written after it
+    #[arg(long)]
+    recover: bool,
+    /// POST each journal entry to this URL once the journal is written
+    #[arg(long, value_name = "URL")]
+    outbox_webhook: Option<String>,
+    /// Keep the outbox delivery progress in this file [default: <journal>.outbox]
+    #[arg(long, value_name = "FILE")]
+    outbox_state: Option<String>,
+    /// Reject transaction ids applied by any earlier run, kept in this file
+    #[arg(long, value_name = "FILE")]
+    seen_ids: Option<String>,
+    /// Write each client's risk score to this file at the end of the run
+    #[arg(long, value_name = "FILE")]
+    risk_scores: Option<String>,
+    /// Apply disputes, resolves and chargebacks from a case-management export
+    #[arg(long, value_name = "FILE")]
+    import_disputes: Option<String>,
+    /// Write imported dispute rows that could not be applied here [default: stderr]
+    #[arg(long, value_name = "FILE", requires = "import_disputes")]
+    reconciliation: Option<String>,
+    /// Write accounts matching the alert rules to this file
+    #[arg(long, value_name = "FILE")]
+    alerts: Option<String>,
+    /// Write held funds by dispute age to this file
+    #[arg(long, value_name = "FILE")]
+    held_aging: Option<String>,
+    /// Write every dispute status change in the run to this file, for customer letters
+    #[arg(long, value_name = "FILE")]
+    dispute_letters: Option<String>,
+    /// Map clients to portfolios with this client,portfolio file
+    #[arg(long, value_name = "FILE")]
+    portfolios: Option<String>,
+    /// Write totals per portfolio to this file
+    #[arg(long, value_name = "FILE")]
+    portfolio_report: Option<String>,
+    /// Also alert on accounts holding more than this amount
+    #[arg(long, value_name = "AMOUNT")]
+    alert_held_above: Option<Number>,
+    /// Also alert on accounts without activity for this many days
+    #[arg(long, value_name = "DAYS")]
+    alert_dormant_days: Option<u64>,
+    /// Answer /healthz and /readyz on this address while running
+    #[arg(long, value_name = "ADDR")]
+    health_addr: Option<String>,
+    /// Apply at most this many transactions per second
+    #[arg(long)]
+    max_rate: Option<u32>,
+    /// Hold at most this many rows between the reader and the ledger
+    #[arg(long, value_name = "ROWS")]
+    queue_capacity: Option<usize>,
+    /// Read the input as fixed-width records laid out as in this TOML file
+    #[arg(long, value_name = "FILE", conflicts_with = "input_format")]
+    layout: Option<String>,
+    /// Input format [default: csv]
+    #[arg(long, value_enum)]
+    input_format: Option<InputKind>,
+    /// Read amounts as whole numbers of the account currency's minor units
+    #[arg(long)]
+    minor_units: bool,
+    /// Accept amounts written the way this locale does: en (1,234.56) or eu
+    /// (1.234,56)
+    #[arg(long, value_name = "LOCALE")]
+    amount_locale: Option<AmountLocale>,
+    /// Field separator of CSV input, such as ';' [default: ,]
+    #[arg(long, value_name = "CHAR")]
+    delimiter: Option<char>,
+}
+
+fn repl(filename: &String, journal: bool, debug: bool) -> io::Result<()> {
+    let mut ledger = if journal {
+        Journal::read_csv(fs::File::open(filename)?)?
+            .replay_until(ReplayPoint::Sequence(u64::MAX), Policy::default())?
+    } else {
+        app::process_file(filename, debug)
+    };
+    Repl::new(io::stdin().lock(), io::stdout().lock()).run(&mut ledger)
+}
+
+// Exits with 1 when the reports differ, like diff(1).
+fn diff(before: &String, after: &String, tolerance: Number) -> io::Result<bool> {
+    let before = report::read_report(fs::File::open(before)?)?;
+    let after = report::read_report(fs::File::open(after)?)?;
+    let differences = report::diff_reports(&before, &after, tolerance);
+    for difference in &differences {
+        println!("{difference}");
+    }
+    Ok(differences.is_empty())
+}
+
+// Prints the schema, or the lines of `check` that don't match it.
+fn check_schema(schema: &Schema, check: Option<&str>) -> io::Result<bool> {
+    let Some(path) = check else {
+        println!("{:#}", schema.to_json_schema());
+        return Ok(true);
+    };
+    let errors = schema.validate_lines(io::BufReader::new(fs::File::open(path)?))?;
+    for (line, error) in &errors {
+        println!("line {line}: {error}");
+    }
+    Ok(errors.is_empty())
+}
+
+fn outbox_config(
+    webhook: String,
+    state: Option<String>,
+    max_attempts: Option<u32>,
+    journal: &str,
+) -> OutboxConfig {
+    let retry = RetryPolicy::default();
+    OutboxConfig {
+        webhook,
+        state: state.unwrap_or_else(|| format!("{journal}.outbox")),
+        retry: RetryPolicy {
+            max_attempts: max_attempts.unwrap_or(retry.max_attempts),
+            ..retry
+        },
+    }
 }
 
 fn main() {
     let args = Arguments::parse();
-    app::app(&args.filename, args.debug);
+    match &args.command {
+        Some(Command::Repl { filename, journal }) => {
+            if let Err(err) = repl(filename, *journal, args.debug) {
+                eprintln!("error: {err}");
+            }
+            return;
+        }
+        Some(Command::Diff {
+            before,
+            after,
+            tolerance,
+        }) => match diff(before, after, *tolerance) {
+            Ok(true) => process::exit(0),
+            Ok(false) => process::exit(1),
+            Err(err) => {
+                eprintln!("error: {err}");
+                process::exit(2);
+            }
+        },
+        Some(Command::Deliver {
+            journal,
+            webhook,
+            state,
+            max_attempts,
+        }) => {
+            let outbox = outbox_config(webhook.clone(), state.clone(), *max_attempts, journal);
+            match app::deliver(journal, &outbox) {
+                Ok(delivered) => {
+                    println!("delivered {delivered} entries");
+                    process::exit(0);
+                }
+                Err(err) => {
+                    eprintln!("error: {err}");
+                    process::exit(1);
+                }
+            }
+        }
+        Some(Command::Schema { kind, check }) => {
+            let schema = match kind {
+                SchemaKind::Input => schema::TRANSACTION_INPUT,
+                SchemaKind::Output => schema::ACCOUNT_OUTPUT,
+            };
+            process::exit(match check_schema(&schema, check.as_deref()) {
+                Ok(true) => 0,
+                Ok(false) => 1,
+                Err(err) => {
+                    eprintln!("error: {err}");
+                    2
+                }
+            });
+        }
+        // Run below with the options of a real run.
+        Some(Command::Validate { .. }) => {}
+        Some(Command::Bench {
+            clients,
+            transactions,
+            dispute_rate,
+            seed,
+        }) => {
+            let options = BenchOptions {
+                clients: *clients,
+                transactions: *transactions,
+                dispute_rate: *dispute_rate,
+                seed: *seed,
+            };
+            print!("{}", bench::run(options, Ledger::new()));
+            return;
+        }
+        Some(Command::Split {
+            filename,
+            shards,
+            delimiter,
+        }) => {
+            let delimiter = u8::try_from(*delimiter).unwrap_or_else(|_| {
+                eprintln!("error: the delimiter must be an ASCII character");
+                process::exit(2);
+            });
+            match split::split_file(filename, *shards, delimiter) {
+                Ok(rows) => {
+                    for (shard, rows) in rows.iter().enumerate() {
+                        println!(
+                            "{} {rows}",
+                            split::shard_path(std::path::Path::new(filename), shard).display()
+                        );
+                    }
+                    process::exit(0);
+                }
+                Err(err) => {
+                    eprintln!("error: {err}");
+                    process::exit(2);
+                }
+            }
+        }
+        None => {}
+    }
+    let validating = matches!(args.command, Some(Command::Validate { .. }));
+    let config = match &args.config {
+        Some(path) => Config::read(path).unwrap_or_else(|err| {
+            eprintln!("error: invalid config {path}: {:?}", err);
+            process::exit(2);
+        }),
+        None => Config::default(),
+    };
+    let client_ids = match args.hash_client_ids {
+        Some(key) => Some(ClientIdFormat::Hashed(key.into_bytes())),
+        None if args.mask_client_ids => Some(ClientIdFormat::Masked),
+        None => config.client_ids,
+    };
+    let report = ReportOptions {
+        format: args
+            .format
+            .map(ReportFormat::from)
+            .or(config.format)
+            .unwrap_or_default(),
+        decimal_separator: args
+            .decimal_separator
+            .or(config.decimal_separator)
+            .unwrap_or('.'),
+        thousands_separator: args.thousands_separator.or(config.thousands_separator),
+        client_ids: client_ids.unwrap_or_default(),
+        alerts: AlertRules {
+            held_above: args.alert_held_above.or(config.alert_rules.held_above),
+            dormant_days: args.alert_dormant_days.or(config.alert_rules.dormant_days),
+            ..config.alert_rules
+        },
+    };
+    let dead_letters = args
+        .dead_letters
+        .or(config.dead_letters)
+        .filter(|_| !validating);
+    let dead_letters = match dead_letters.as_deref().map(fs::File::create) {
+        Some(Ok(file)) => Some(Box::new(io::BufWriter::new(file)) as Box<dyn io::Write + Send>),
+        Some(Err(err)) => {
+            eprintln!("error: {err}");
+            process::exit(2);
+        }
+        None => None,
+    };
+    // Signals stop the run the way POST /shutdown does, with or without the
+    // health server.
+    let mut monitor = HealthMonitor::new(config.max_queue_depth, config.max_journal_lag)
+        .with_export_options(report.clone());
+    if let Some(path) = &args.config {
+        monitor = monitor.with_config(path.as_str());
+    }
+    let monitor = Arc::new(monitor);
+    if let Err(err) = monitor.shutdown_on_signals() {
+        eprintln!("error: cannot handle signals: {err}");
+        process::exit(2);
+    }
+    if let Some(addr) = args
+        .health_addr
+        .or(config.health_addr)
+        .filter(|_| !validating)
+    {
+        if let Err(err) = health::serve(addr.as_str(), monitor.clone()) {
+            eprintln!("error: cannot listen on {addr}: {err}");
+            process::exit(2);
+        }
+    }
+    let health = Some(monitor);
+    let debug = args.debug || config.debug.unwrap_or(false);
+    let queue_observer = debug.then(|| {
+        Box::new(|event| {
+            if let QueueEvent::Dropped { line, dropped } = event {
+                eprintln!("error: queue full, dropped line {line} ({dropped} so far)");
+            }
+        }) as Box<dyn FnMut(QueueEvent) + Send>
+    });
+    let mut operations = OperationRegistry::new();
+    for (name, operation) in &config.operation_aliases {
+        operations.alias(name, *operation);
+    }
+    let input_format = match (args.input_format, args.layout.or(config.layout)) {
+        (Some(InputKind::Csv), _) => InputFormat::Csv,
+        (Some(InputKind::Protobuf), _) => InputFormat::Protobuf,
+        (None, Some(path)) => InputFormat::FixedWidth(Layout::read(&path).unwrap_or_else(|err| {
+            eprintln!("error: invalid layout {path}: {:?}", err);
+            process::exit(2);
+        })),
+        (None, None) => config.input_format.unwrap_or_default(),
+    };
+    let delimiter = args.delimiter.map(|delimiter| {
+        u8::try_from(delimiter).unwrap_or_else(|_| {
+            eprintln!("error: the delimiter must be an ASCII character");
+            process::exit(2);
+        })
+    });
+    let journal = args.journal.or(config.journal);
+    let previous_key = args
+        .previous_encryption_key
+        .or(config.previous_encryption_key);
+    let keyring = args.encryption_key.or(config.encryption_key).map(|key| {
+        Keyring::from_hex(&key, previous_key.as_deref()).unwrap_or_else(|err| {
+            eprintln!("error: {err}");
+            process::exit(2);
+        })
+    });
+    let outbox = args
+        .outbox_webhook
+        .or(config.outbox_webhook)
+        .map(|webhook| {
+            outbox_config(
+                webhook,
+                args.outbox_state.or(config.outbox_state),
+                config.outbox_max_attempts,
+                journal.as_deref().unwrap_or_default(),
+            )
+        });
+    let options = RunOptions {
+        process: ProcessOptions {
+            debug,
+            rate_limiter: args.max_rate.or(config.max_rate).map(RateLimiter::new),
+            dead_letters,
+            retry: RowRetry {
+                max_retries: config.max_retries.unwrap_or(0),
+                backoff: Duration::from_millis(config.retry_backoff_ms.unwrap_or(100)),
+            },
+            health,
+            operations,
+            queue_capacity: args.queue_capacity.or(config.queue_capacity),
+            drop_when_full: config.drop_when_full.unwrap_or(false),
+            queue_observer,
+            snapshotter: None,
+            input_format,
+            delimiter: delimiter.or(config.delimiter),
+            minor_units: args.minor_units || config.minor_units.unwrap_or(false),
+            amount_locale: args.amount_locale.or(config.amount_locale),
+            enricher: None,
+        },
+        report,
+        report_threads: args.report_threads.or(config.report_threads).unwrap_or(1),
+        policy: config.policy,
+        journal,
+        snapshot: args.snapshot.or(config.snapshot),
+        keyring,
+        snapshot_schedule: SnapshotSchedule {
+            every: args.snapshot_every.or(config.snapshot_every),
+            interval: args
+                .snapshot_interval
+                .or(config.snapshot_interval)
+                .map(Duration::from_secs),
+        },
+        recover: args.recover,
+        risk_scores: args.risk_scores,
+        groups: config.groups,
+        alerts: args.alerts.or(config.alerts),
+        held_aging: args.held_aging.or(config.held_aging),
+        dispute_letters: args.dispute_letters.or(config.dispute_letters),
+        portfolios: args.portfolios.or(config.portfolios),
+        portfolio_report: args.portfolio_report.or(config.portfolio_report),
+        disputes: args.import_disputes,
+        reconciliation: args.reconciliation,
+        outbox,
+        seen_ids: args.seen_ids.or(config.seen_ids),
+        template: args.template.or(config.template),
+        finalizers: config.finalizers,
+    };
+    if let Some(Command::Validate { filename }) = &args.command {
+        match app::validate_file(filename, options) {
+            Ok(errors) => {
+                for error in &errors {
+                    println!("{error}");
+                }
+                process::exit(if errors.is_empty() { 0 } else { 1 });
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                process::exit(2);
+            }
+        }
+    }
+    if let Err(err) = app::app(&args.filename.unwrap_or_default(), options) {
+        eprintln!("error: {err}");
+        process::exit(2);
+    }
 }